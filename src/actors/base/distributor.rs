@@ -0,0 +1,68 @@
+use std::{fmt, marker::PhantomData};
+
+use anyhow::anyhow;
+use bastion::prelude::{Distributor, Message, SendError};
+
+/// Distributor only sending `M` and expecting `R` back.
+///
+/// Sending any other message or asking for any other reply does not compile, where a plain
+/// `Distributor` hands it to the actor's `on_fallback` or waits for a reply that never comes.
+pub struct TypedDistributor<M, R> {
+    distributor: Distributor,
+    contract: PhantomData<fn(M) -> R>,
+}
+
+impl<M, R> TypedDistributor<M, R>
+where
+    M: Message,
+    R: Message,
+{
+    /// Typed view of the distributor named `name`
+    pub fn named(name: impl AsRef<str>) -> Self {
+        Self::new(Distributor::named(name))
+    }
+
+    /// Typed view of `distributor`
+    pub fn new(distributor: Distributor) -> Self {
+        Self {
+            distributor,
+            contract: PhantomData,
+        }
+    }
+
+    /// Untyped distributor, e.g. to send messages outside the contract
+    pub fn distributor(&self) -> Distributor {
+        self.distributor
+    }
+
+    /// Send `message` to one recipient without waiting for a reply
+    pub fn tell_one(&self, message: M) -> Result<(), SendError> {
+        self.distributor.tell_one(message)
+    }
+
+    /// Ask one recipient, a reply that never comes reads as `SendError::Other`
+    pub async fn request(&self, message: M) -> Result<R, SendError> {
+        self.distributor
+            .request(message)
+            .await
+            .unwrap_or_else(|e| Err(SendError::Other(anyhow!("couldn't receive reply: {e:?}"))))
+    }
+}
+
+impl<M, R> Clone for TypedDistributor<M, R> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<M, R> Copy for TypedDistributor<M, R> {}
+
+impl<M, R> fmt::Debug for TypedDistributor<M, R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TypedDistributor")
+            .field("distributor", &self.distributor)
+            .field("message", &std::any::type_name::<M>())
+            .field("reply", &std::any::type_name::<R>())
+            .finish()
+    }
+}
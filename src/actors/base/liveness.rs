@@ -0,0 +1,155 @@
+//! Liveness of the children of an actor built with a heartbeat tick.
+//!
+//! Every tick rolls the count of messages each child processed, handlers count them with
+//! `processed`. A monitor child next to the actor's groups answers `LivenessQuery`, so a child
+//! stuck in its handler shows up from outside: its heartbeat ages and it processes nothing while
+//! the monitor still answers.
+
+use std::{
+    future::Future,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use bastion::prelude::{BastionContext, Distributor, MessageHandler};
+use serde::Serialize;
+use tracing::warn;
+
+use super::{
+    ticker::Ticker,
+    tree::{ChildStatus, Tracker},
+    TypedDistributor,
+};
+
+tokio::task_local! {
+    // Tracker and id of the child whose handler is polled
+    static CURRENT: (Arc<Tracker>, String);
+}
+
+/// Ask the liveness monitor of an actor for a `LivenessReport`
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+pub struct LivenessQuery;
+
+// Sent to the monitor on every heartbeat tick
+#[derive(Debug, Clone, Copy)]
+struct LivenessTick;
+
+/// Liveness of one child
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct ChildLiveness {
+    pub id: String,
+    pub status: ChildStatus,
+    /// Time since the handler last ran, i.e. handled a message or a tick
+    pub since_heartbeat: Duration,
+    /// Messages processed since the last tick
+    pub processed: u64,
+    /// Messages processed between the two last ticks
+    pub processed_last_tick: u64,
+}
+
+impl ChildLiveness {
+    /// Running but silent for longer than `max_silence`, e.g. blocked in a handler
+    pub fn is_stuck(&self, max_silence: Duration) -> bool {
+        self.status == ChildStatus::Running && self.since_heartbeat > max_silence
+    }
+}
+
+/// Liveness of every started child of an actor
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct LivenessReport {
+    /// Type of the actor state
+    pub actor: String,
+    /// Name the actor was built with
+    pub instance: Option<String>,
+    /// Heartbeat tick the counts are rolled on
+    pub tick: Duration,
+    /// Time since the last tick
+    pub since_tick: Duration,
+    pub children: Vec<ChildLiveness>,
+}
+
+impl LivenessReport {
+    /// Running children silent for longer than `max_silence`
+    pub fn stuck(&self, max_silence: Duration) -> Vec<&ChildLiveness> {
+        self.children
+            .iter()
+            .filter(|child| child.is_stuck(max_silence))
+            .collect()
+    }
+}
+
+/// Count one message processed by the child whose handler is running, nothing happens outside
+/// of a handler
+pub fn processed() {
+    let _ = CURRENT.try_with(|(tracker, id)| tracker.processed(id));
+}
+
+/// Run `handler` as the handler of child `id`, `processed` counts for it
+pub(crate) async fn scope<F>(tracker: Arc<Tracker>, id: String, handler: F) -> F::Output
+where
+    F: Future,
+{
+    CURRENT.scope((tracker, id), handler).await
+}
+
+/// Distributor of the liveness monitor of an actor with state type `actor`, named `instance`
+pub(crate) fn distributor(
+    actor: &str,
+    instance: Option<&str>,
+) -> TypedDistributor<LivenessQuery, LivenessReport> {
+    TypedDistributor::new(Distributor::named(format!(
+        "liveness:{}",
+        instance.unwrap_or(actor)
+    )))
+}
+
+/// Handler of the monitor child: rolls the counts on every tick and answers `LivenessQuery`
+pub(crate) async fn monitor(
+    ctx: BastionContext,
+    tracker: Arc<Tracker>,
+    tick: Duration,
+    actor: &'static str,
+    instance: Option<String>,
+) -> Result<(), ()> {
+    let own = distributor(actor, instance.as_deref()).distributor();
+    let _ticker = Ticker::spawn(tick, own, || LivenessTick);
+    let mut ticked = Instant::now();
+    loop {
+        MessageHandler::new(ctx.recv().await?)
+            .on_tell(|_: LivenessTick, _| {
+                ticked = Instant::now();
+                let children = tracker.tick();
+                record(actor, instance.as_deref(), &children);
+            })
+            .on_question(|_: LivenessQuery, sender| {
+                let report = LivenessReport {
+                    actor: actor.to_owned(),
+                    instance: instance.clone(),
+                    tick,
+                    since_tick: ticked.elapsed(),
+                    children: tracker.liveness(),
+                };
+                if sender.reply(report).is_err() {
+                    warn!("[LIVENESS] Cannot reply to a liveness query");
+                }
+            })
+            .on_fallback(|unknown, _| warn!("[LIVENESS] Unknown message: {unknown:?}"));
+    }
+}
+
+// Heartbeat age and messages processed during the last tick of every child
+fn record(actor: &'static str, instance: Option<&str>, children: &[ChildLiveness]) {
+    #[cfg(feature = "metrics")]
+    for child in children {
+        let labels = [
+            ("actor", actor.to_owned()),
+            ("instance", instance.unwrap_or_default().to_owned()),
+            ("child", child.id.clone()),
+        ];
+        metrics::gauge!("actor_heartbeat_age_seconds", &labels)
+            .set(child.since_heartbeat.as_secs_f64());
+        metrics::gauge!("actor_messages_per_tick", &labels).set(child.processed_last_tick as f64);
+    }
+    #[cfg(not(feature = "metrics"))]
+    let _ = (actor, instance, children);
+}
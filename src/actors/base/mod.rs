@@ -1,18 +1,31 @@
+/// Distributors typed with the messages they send and the replies they expect
+pub mod distributor;
+/// Per-child liveness on every heartbeat tick
+pub mod liveness;
 /// Actor state (wrap aggregates or data structs)
 pub mod state;
+/// Test helpers for actors
+pub mod testkit;
+/// Periodic messages for actors
+pub mod ticker;
+/// Supervision tree reports
+pub mod tree;
 
-use std::{ops::Deref, time::Duration};
+use std::{any::type_name, ops::Deref, sync::Arc, time::Duration};
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use async_trait::async_trait;
 use bastion::{
-    prelude::{BastionContext, Dispatcher, Distributor},
+    prelude::{BastionContext, ChildrenRef, Dispatcher, Distributor},
     resizer::OptimalSizeExploringResizer,
     supervisor::{RestartStrategy, SupervisionStrategy, SupervisorRef},
     Bastion, Callbacks,
 };
 
+pub use distributor::TypedDistributor;
+use liveness::{LivenessQuery, LivenessReport};
 use state::State;
+use tree::{SupervisorReport, Tracker, Tree};
 
 /// TActor to be implemented for states
 #[async_trait]
@@ -51,7 +64,17 @@ pub trait TActor: Default + Send + Sync + 'static {
         None
     }
 
-    /// Heartbeat interval
+    /// Distributor for children of the instance named at build time, so several instances
+    /// don't share one
+    fn with_named_distributor(_name: &str) -> Option<Distributor> {
+        Self::with_distributor()
+    }
+
+    /// Called on the state of every child before its handler runs, with the instance name given
+    /// at build time
+    fn with_instance_name(&mut self, _name: &str) {}
+
+    /// Heartbeat interval, also the tick liveness counts are rolled on
     fn with_heartbeat_tick() -> Option<Duration> {
         None
     }
@@ -70,12 +93,35 @@ pub trait TActor: Default + Send + Sync + 'static {
     fn with_resizer() -> Option<OptimalSizeExploringResizer> {
         None
     }
+
+    // For the read children group
+
+    /// Handler for the children of the read group, only answering questions
+    async fn read_handler(&mut self, ctx: BastionContext) -> Result<(), ()> {
+        self.handler(ctx).await
+    }
+
+    /// Distributor for the read children group
+    fn with_read_distributor() -> Option<Distributor> {
+        None
+    }
+
+    /// Distributor for the read children group of the instance named at build time
+    fn with_named_read_distributor(_name: &str) -> Option<Distributor> {
+        Self::with_read_distributor()
+    }
 }
 
+/// Builds the state of every child in the read group
+type ReaderInit<S> = Arc<dyn Fn() -> S + Send + Sync>;
+
 /// Core actor with state inside that implemented TActor
 #[derive(Debug)]
 pub struct Actor<S> {
-    __supervisor: SupervisorRef,
+    /// Supervisor with the main children group, then the read group when there is one
+    tree: Arc<Tree>,
+    /// Liveness monitor and its distributor when built with a heartbeat tick
+    liveness: Option<(ChildrenRef, TypedDistributor<LivenessQuery, LivenessReport>)>,
     state: State<S>,
 }
 
@@ -87,6 +133,45 @@ where
     pub fn builder() -> ActorBuilder<S> {
         ActorBuilder::default()
     }
+
+    /// Stop every child of the actor, they leave their distributors once stopped.
+    ///
+    /// Children are stopped one by one as well as through their group, groups only handle
+    /// messages once Bastion is started.
+    pub fn stop(&self) -> Result<()> {
+        let monitor = self.liveness.as_ref().map(|(monitor, _)| monitor);
+        for children in self.tree.groups.iter().chain(monitor) {
+            for child in children.elems() {
+                child
+                    .stop()
+                    .map_err(|()| anyhow!("cannot reach the child {:?}", child.id()))?;
+            }
+            // Not started groups keep it for later
+            let _ = children.stop();
+        }
+        Ok(())
+    }
+
+    /// Supervisor, children groups and the status, restarts and last heartbeat of every child
+    pub fn tree(&self) -> SupervisorReport {
+        self.tree.report()
+    }
+
+    /// Distributor of the liveness monitor answering `LivenessQuery`, `None` without a heartbeat
+    /// tick
+    pub fn liveness(&self) -> Option<TypedDistributor<LivenessQuery, LivenessReport>> {
+        self.liveness.as_ref().map(|(_, distributor)| *distributor)
+    }
+}
+
+impl<S> Clone for Actor<S> {
+    fn clone(&self) -> Self {
+        Self {
+            tree: self.tree.clone(),
+            liveness: self.liveness.clone(),
+            state: self.state.clone(),
+        }
+    }
 }
 
 impl<S> Deref for Actor<S> {
@@ -113,6 +198,9 @@ pub struct ActorBuilder<S> {
     name: Option<String>,
     redundancy: Option<usize>,
     resizer: Option<OptimalSizeExploringResizer>,
+    // For the read children group
+    readers: Option<(usize, ReaderInit<S>)>,
+    read_distributor: Option<Distributor>,
 }
 
 impl<S> Default for ActorBuilder<S>
@@ -134,6 +222,8 @@ where
             name: Default::default(),
             redundancy: Default::default(),
             resizer: Default::default(),
+            readers: Default::default(),
+            read_distributor: Default::default(),
         }
     }
 }
@@ -182,9 +272,14 @@ where
         // Because state does not implement Copy trait and Fn cannot handle mutable value so pure state S must be wrap by State<S>
         let state = self.state;
         let weak_state = state.downgrade();
+        // Instance name, it picks the distributors and is handed to every child's state
+        let instance = self.name.clone().or_else(S::with_name);
+        let tracker = Arc::new(Tracker::default());
+        let heartbeat_tick = self.heartbeat_tick.or_else(S::with_heartbeat_tick);
 
         // Create children behaviour
-        supervisor
+        let mut children_groups = vec![];
+        let main = supervisor
             .children(|mut children| {
                 // Implements config from TActor
                 if let Some(callbacks) = S::with_children_callbacks() {
@@ -193,7 +288,11 @@ where
                 if let Some(dispatcher) = S::with_dispatcher() {
                     children = children.with_dispatcher(dispatcher);
                 }
-                if let Some(distributor) = S::with_distributor() {
+                let distributor = match &instance {
+                    Some(name) => S::with_named_distributor(name),
+                    None => S::with_distributor(),
+                };
+                if let Some(distributor) = distributor {
                     children = children.with_distributor(distributor);
                 }
                 if let Some(interval) = S::with_heartbeat_tick() {
@@ -233,21 +332,80 @@ where
                 }
 
                 // Main handler, this one will be called if the actor is down by error or crash
+                let instance = instance.clone();
+                let tracker = tracker.clone();
                 children.with_exec(move |ctx| {
                     let weak_state = weak_state.clone();
                     let state = State::upgrade(weak_state);
-                    async move {
-                        {
-                            let mut write = state.write().await;
-                            write.handler(ctx).await
+                    let instance = instance.clone();
+                    let id = ctx.current().id().to_string();
+                    tracker.clone().run(id, async move {
+                        let mut write = state.write().await;
+                        if let Some(name) = &instance {
+                            write.with_instance_name(name);
                         }
-                    }
+                        write.handler(ctx).await
+                    })
                 })
             })
             .unwrap();
+        children_groups.push(main);
+
+        // Read children group next to the main one, every child owns its state so reads run
+        // concurrently while the main group keeps writes in order
+        if let Some((redundancy, init)) = self.readers {
+            let tracker = tracker.clone();
+            let instance = instance.clone();
+            let distributor = self.read_distributor.or_else(|| match &instance {
+                Some(name) => S::with_named_read_distributor(name),
+                None => S::with_read_distributor(),
+            });
+            let readers = supervisor
+                .children(|mut children| {
+                    if let Some(distributor) = distributor {
+                        children = children.with_distributor(distributor);
+                    }
+                    children.with_redundancy(redundancy).with_exec(move |ctx| {
+                        let mut state = init();
+                        if let Some(name) = &instance {
+                            state.with_instance_name(name);
+                        }
+                        let id = ctx.current().id().to_string();
+                        tracker
+                            .clone()
+                            .run(id, async move { state.read_handler(ctx).await })
+                    })
+                })
+                .unwrap();
+            children_groups.push(readers);
+        }
+
+        // Monitor outside the groups it watches, it answers while they are stuck
+        let liveness = heartbeat_tick.map(|tick| {
+            let distributor = liveness::distributor(type_name::<S>(), instance.as_deref());
+            let tracker = tracker.clone();
+            let instance = instance.clone();
+            let monitor = supervisor
+                .children(|children| {
+                    children
+                        .with_distributor(distributor.distributor())
+                        .with_exec(move |ctx| {
+                            liveness::monitor(
+                                ctx,
+                                tracker.clone(),
+                                tick,
+                                type_name::<S>(),
+                                instance.clone(),
+                            )
+                        })
+                })
+                .unwrap();
+            (monitor, distributor)
+        });
 
         Ok(Actor {
-            __supervisor: supervisor,
+            tree: Tree::register::<S>(supervisor, children_groups, instance, tracker),
+            liveness,
             state,
         })
     }
@@ -289,7 +447,7 @@ where
         self
     }
 
-    /// Heartbeat interval
+    /// Heartbeat interval, also the tick liveness counts are rolled on
     pub fn with_heartbeat_tick(mut self, interval: Duration) -> Self {
         self.heartbeat_tick = Some(interval);
         self
@@ -312,4 +470,22 @@ where
         self.resizer = Some(resizer);
         self
     }
+
+    // For the read children group
+
+    /// Run a read children group of `redundancy` children, each with the state built by `init`
+    pub fn with_readers(
+        mut self,
+        redundancy: usize,
+        init: impl Fn() -> S + Send + Sync + 'static,
+    ) -> Self {
+        self.readers = Some((redundancy, Arc::new(init)));
+        self
+    }
+
+    /// Distributor for the read children group
+    pub fn with_read_distributor(mut self, distributor: Distributor) -> Self {
+        self.read_distributor = Some(distributor);
+        self
+    }
 }
@@ -57,6 +57,12 @@ impl<S> State<S> {
     }
 }
 
+impl<S> Clone for State<S> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
 /// Weak state being used when actor handler call it
 #[derive(Debug)]
 pub struct WeakState<S>(Weak<RwLock<S>>);
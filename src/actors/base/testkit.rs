@@ -0,0 +1,161 @@
+use std::{sync::OnceLock, time::Duration};
+
+use bastion::{
+    prelude::{ChildrenRef, Distributor, Message, MessageHandler, SendError, SignedMessage},
+    Bastion,
+};
+use thiserror::Error;
+use tokio::{
+    runtime::Runtime,
+    sync::mpsc::{self, UnboundedReceiver},
+    time::{self, Instant},
+};
+
+/// How long a probe gets to subscribe to its distributor
+const START_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Failures of test kit expectations
+#[derive(Debug, Error)]
+pub enum TestKitError {
+    #[error("nothing matching arrived within {0:?}")]
+    Timeout(Duration),
+    #[error("cannot send: {0:?}")]
+    Send(SendError),
+    #[error("the probe stopped")]
+    Closed,
+    #[error("cannot start the probe")]
+    Start,
+}
+
+/// Child subscribed to a distributor recording every message it receives.
+///
+/// Point a `MachineContext` or a `Ticker` at the probe's distributor to assert on what they emit
+/// without running the actor under test.
+pub struct Probe {
+    distributor: Distributor,
+    received: UnboundedReceiver<SignedMessage>,
+    children: ChildrenRef,
+}
+
+impl Probe {
+    /// Start a probe receiving everything sent to `name`, returns once it is subscribed
+    pub async fn named(name: impl AsRef<str>) -> Result<Self, TestKitError> {
+        let distributor = Distributor::named(name);
+        let (sender, received) = mpsc::unbounded_channel();
+        let (ready, mut started) = mpsc::unbounded_channel();
+        let children = Bastion::children(|children| {
+            children
+                .with_distributor(distributor)
+                .with_exec(move |ctx| {
+                    let sender = sender.clone();
+                    let ready = ready.clone();
+                    async move {
+                        let _ = ready.send(());
+                        loop {
+                            let msg = ctx.recv().await?;
+                            if sender.send(msg).is_err() {
+                                return Ok(());
+                            }
+                        }
+                    }
+                })
+        })
+        .map_err(|_| TestKitError::Start)?;
+        time::timeout(START_TIMEOUT, started.recv())
+            .await
+            .ok()
+            .flatten()
+            .ok_or(TestKitError::Start)?;
+
+        Ok(Self {
+            distributor,
+            received,
+            children,
+        })
+    }
+
+    /// Distributor the probe listens on
+    pub fn distributor(&self) -> Distributor {
+        self.distributor
+    }
+
+    /// Wait for the next `M`, skipping messages of other types
+    pub async fn expect<M: Message>(&mut self, timeout: Duration) -> Result<M, TestKitError> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let msg = time::timeout_at(deadline, self.received.recv())
+                .await
+                .map_err(|_| TestKitError::Timeout(timeout))?
+                .ok_or(TestKitError::Closed)?;
+            if let Some(message) = downcast::<M>(msg) {
+                return Ok(message);
+            }
+        }
+    }
+
+    /// Succeed when no `M` arrives within `within`
+    pub async fn expect_none<M: Message>(&mut self, within: Duration) -> Result<(), M> {
+        match self.expect::<M>(within).await {
+            Ok(message) => Err(message),
+            Err(_) => Ok(()),
+        }
+    }
+}
+
+impl Drop for Probe {
+    fn drop(&mut self) {
+        let _ = self.children.stop();
+    }
+}
+
+/// Process-wide runtime for tests.
+///
+/// Bastion keeps the runtime it is first used from, so tests starting actors must all run on
+/// this one, with `runtime().block_on(..)` or `runtime().enter()`, instead of `#[tokio::test]`.
+pub fn runtime() -> &'static Runtime {
+    static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+    RUNTIME.get_or_init(|| Runtime::new().expect("cannot start the test runtime"))
+}
+
+/// Ask `distributor` a question, failing when no reply arrives within `timeout`
+pub async fn ask<R: Message>(
+    distributor: Distributor,
+    question: impl Message,
+    timeout: Duration,
+) -> Result<R, TestKitError> {
+    time::timeout(timeout, distributor.request(question))
+        .await
+        .map_err(|_| TestKitError::Timeout(timeout))?
+        .map_err(|_| TestKitError::Closed)?
+        .map_err(TestKitError::Send)
+}
+
+// Told and asked messages alike, the answer sender of a question is dropped
+fn downcast<M: Message>(msg: SignedMessage) -> Option<M> {
+    MessageHandler::new(msg)
+        .on_tell(|message: M, _| Some(message))
+        .on_question(|message: M, _| Some(message))
+        .on_fallback(|_, _| None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn probes_see_told_messages() {
+        runtime().block_on(async {
+            let mut probe = Probe::named("testkit_probe").await.unwrap();
+
+            probe.distributor().tell_one("unrelated").unwrap();
+            probe.distributor().tell_one(42_u32).unwrap();
+
+            let told = probe.expect::<u32>(Duration::from_secs(1)).await.unwrap();
+            assert_eq!(42, told);
+            assert!(probe
+                .expect_none::<u32>(Duration::from_millis(50))
+                .await
+                .is_ok());
+        });
+    }
+}
@@ -0,0 +1,35 @@
+use std::{sync::Arc, thread, time::Duration};
+
+use bastion::prelude::{Distributor, Message};
+use tracing::warn;
+
+/// Sends a message to a distributor on a fixed interval until dropped
+#[derive(Debug)]
+pub struct Ticker {
+    _alive: Arc<()>,
+}
+
+impl Ticker {
+    /// Start ticking, `message` builds the message sent on each tick
+    pub fn spawn<M, F>(interval: Duration, distributor: Distributor, message: F) -> Self
+    where
+        M: Message,
+        F: Fn() -> M + Send + 'static,
+    {
+        let alive = Arc::new(());
+        let weak = Arc::downgrade(&alive);
+
+        thread::spawn(move || loop {
+            thread::sleep(interval);
+            // Owner dropped the ticker (e.g. the handler restarted)
+            if weak.strong_count() == 0 {
+                break;
+            }
+            if let Err(e) = distributor.tell_one(message()) {
+                warn!("[TICKER] Cannot send tick: {e:?}");
+            }
+        });
+
+        Self { _alive: alive }
+    }
+}
@@ -0,0 +1,278 @@
+//! Supervision tree introspection.
+//!
+//! Every actor keeps track of its children while their handlers run: whether they are running,
+//! how many times they were restarted and when they last made progress. `Actor::tree` reports a
+//! single actor, the crate's `supervision_tree` every actor still held somewhere.
+
+use std::{
+    any::type_name,
+    collections::HashMap,
+    future::{poll_fn, Future},
+    pin::pin,
+    sync::{Arc, Mutex, Weak},
+    time::{Duration, Instant},
+};
+
+use bastion::{prelude::ChildrenRef, supervisor::SupervisorRef};
+use serde::Serialize;
+
+use super::liveness::{self, ChildLiveness};
+
+/// Every actor built, dropped ones are pruned when reporting
+static ACTORS: Mutex<Vec<Weak<Tree>>> = Mutex::new(vec![]);
+
+/// What a child is doing
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+pub enum ChildStatus {
+    /// Not started yet
+    Pending,
+    Running,
+    /// Stopped or returned normally
+    Stopped,
+    /// Returned an error, the supervisor restarts it unless it gave up
+    Failed,
+}
+
+/// One child of a group
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct ChildReport {
+    pub id: String,
+    pub status: ChildStatus,
+    pub restarts: u32,
+    /// Time since the handler last ran, i.e. handled a message or a tick. `None` until started.
+    pub since_heartbeat: Option<Duration>,
+}
+
+/// Role of a children group in its actor
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+pub enum GroupRole {
+    Main,
+    Read,
+}
+
+/// One children group of a supervisor
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct GroupReport {
+    pub id: String,
+    pub role: GroupRole,
+    pub children: Vec<ChildReport>,
+}
+
+/// Supervisor of an actor and its children groups
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct SupervisorReport {
+    pub id: String,
+    /// Type of the actor state
+    pub actor: String,
+    /// Name the actor was built with
+    pub instance: Option<String>,
+    pub groups: Vec<GroupReport>,
+}
+
+impl SupervisorReport {
+    /// Every child is running and ran within `max_silence`
+    pub fn is_healthy(&self, max_silence: Duration) -> bool {
+        self.groups
+            .iter()
+            .flat_map(|group| &group.children)
+            .all(|child| {
+                child.status == ChildStatus::Running
+                    && child
+                        .since_heartbeat
+                        .is_some_and(|since| since <= max_silence)
+            })
+    }
+}
+
+#[derive(Debug)]
+struct Track {
+    status: ChildStatus,
+    starts: u32,
+    heartbeat: Instant,
+    // Messages counted since the last tick, and between the two last ticks
+    processed: u64,
+    processed_last_tick: u64,
+}
+
+/// Status, starts, last heartbeat and processed messages of the children of an actor by id
+#[derive(Debug, Default)]
+pub(crate) struct Tracker(Mutex<HashMap<String, Track>>);
+
+impl Tracker {
+    /// Run the handler of child `id`, a heartbeat is taken every time it is polled
+    pub(crate) async fn run<F>(self: Arc<Self>, id: String, handler: F) -> Result<(), ()>
+    where
+        F: Future<Output = Result<(), ()>>,
+    {
+        self.update(&id, |track| {
+            track.status = ChildStatus::Running;
+            track.starts += 1;
+        });
+        // Children stopped by their supervisor are dropped without returning
+        let mut exit = Exit {
+            tracker: &self,
+            id: &id,
+            status: ChildStatus::Stopped,
+        };
+        let mut handler = pin!(handler);
+        let heartbeats = poll_fn(|cx| {
+            self.update(&id, |_| ());
+            handler.as_mut().poll(cx)
+        });
+        let result = liveness::scope(self.clone(), id.clone(), heartbeats).await;
+        if result.is_err() {
+            exit.status = ChildStatus::Failed;
+        }
+        result
+    }
+
+    // Apply `change` to the track of `id`, taking a heartbeat
+    fn update(&self, id: &str, change: impl FnOnce(&mut Track)) {
+        let mut tracks = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        let track = tracks.entry(id.to_owned()).or_insert(Track {
+            status: ChildStatus::Pending,
+            starts: 0,
+            heartbeat: Instant::now(),
+            processed: 0,
+            processed_last_tick: 0,
+        });
+        track.heartbeat = Instant::now();
+        change(track);
+    }
+
+    /// Count one message processed by child `id`
+    pub(crate) fn processed(&self, id: &str) {
+        self.update(id, |track| track.processed += 1);
+    }
+
+    /// Start a new tick, returning the liveness of every started child over the one ending
+    pub(crate) fn tick(&self) -> Vec<ChildLiveness> {
+        let mut tracks = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        for track in tracks.values_mut() {
+            track.processed_last_tick = std::mem::take(&mut track.processed);
+        }
+        Self::liveness_of(&tracks)
+    }
+
+    /// Liveness of every started child, by id
+    pub(crate) fn liveness(&self) -> Vec<ChildLiveness> {
+        Self::liveness_of(&self.0.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+
+    fn liveness_of(tracks: &HashMap<String, Track>) -> Vec<ChildLiveness> {
+        let mut children: Vec<_> = tracks
+            .iter()
+            .map(|(id, track)| ChildLiveness {
+                id: id.clone(),
+                status: track.status,
+                since_heartbeat: track.heartbeat.elapsed(),
+                processed: track.processed,
+                processed_last_tick: track.processed_last_tick,
+            })
+            .collect();
+        children.sort_by(|a, b| a.id.cmp(&b.id));
+        children
+    }
+
+    fn report(&self, id: String) -> ChildReport {
+        let tracks = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        match tracks.get(&id) {
+            Some(track) => ChildReport {
+                id,
+                status: track.status,
+                restarts: track.starts.saturating_sub(1),
+                since_heartbeat: Some(track.heartbeat.elapsed()),
+            },
+            None => ChildReport {
+                id,
+                status: ChildStatus::Pending,
+                restarts: 0,
+                since_heartbeat: None,
+            },
+        }
+    }
+}
+
+// Records how a child's handler ended
+struct Exit<'a> {
+    tracker: &'a Tracker,
+    id: &'a str,
+    status: ChildStatus,
+}
+
+impl Drop for Exit<'_> {
+    fn drop(&mut self) {
+        let status = self.status;
+        self.tracker.update(self.id, |track| track.status = status);
+    }
+}
+
+/// Supervisor and children groups of an actor, the main group first
+#[derive(Debug)]
+pub(crate) struct Tree {
+    supervisor: SupervisorRef,
+    pub(crate) groups: Vec<ChildrenRef>,
+    actor: &'static str,
+    instance: Option<String>,
+    tracker: Arc<Tracker>,
+}
+
+impl Tree {
+    /// Tree of an actor with state `S`, listed by `report_all` while held
+    pub(crate) fn register<S>(
+        supervisor: SupervisorRef,
+        groups: Vec<ChildrenRef>,
+        instance: Option<String>,
+        tracker: Arc<Tracker>,
+    ) -> Arc<Self> {
+        let tree = Arc::new(Self {
+            supervisor,
+            groups,
+            actor: type_name::<S>(),
+            instance,
+            tracker,
+        });
+        let mut actors = ACTORS.lock().unwrap_or_else(|e| e.into_inner());
+        actors.retain(|actor| actor.strong_count() > 0);
+        actors.push(Arc::downgrade(&tree));
+        tree
+    }
+
+    pub(crate) fn report(&self) -> SupervisorReport {
+        let groups = self
+            .groups
+            .iter()
+            .enumerate()
+            .map(|(i, group)| GroupReport {
+                id: group.id().to_string(),
+                role: if i == 0 {
+                    GroupRole::Main
+                } else {
+                    GroupRole::Read
+                },
+                children: group
+                    .elems()
+                    .iter()
+                    .map(|child| self.tracker.report(child.id().to_string()))
+                    .collect(),
+            })
+            .collect();
+        SupervisorReport {
+            id: self.supervisor.id().to_string(),
+            actor: self.actor.to_owned(),
+            instance: self.instance.clone(),
+            groups,
+        }
+    }
+}
+
+/// Report of every actor still held, in the order they were built
+pub(crate) fn report_all() -> Vec<SupervisorReport> {
+    let actors: Vec<_> = ACTORS
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .iter()
+        .filter_map(Weak::upgrade)
+        .collect();
+    actors.iter().map(|tree| tree.report()).collect()
+}
@@ -0,0 +1,172 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use bastion::{
+    prelude::{BastionContext, Distributor, Message, MessageHandler},
+    run,
+};
+use chrono::Utc;
+use cqrs_es::{Aggregate, EventEnvelope};
+use tracing::{error, info_span, warn};
+
+use super::{
+    base::{liveness, TActor},
+    machine::forward,
+};
+
+/// Metadata key holding the RFC 3339 time an event was applied
+pub const TIMESTAMP_METADATA: &str = "timestamp";
+
+/// Aggregate that can be hosted by `CqrsActor`
+pub trait CqrsAggregate:
+    'static + Aggregate<Command: Message, Event: Message, Error: Message, Services: Default>
+{
+    /// Distributor commands and events of the aggregate go through
+    fn distributor() -> Distributor;
+}
+
+/// Command → handle → event → apply loop shared by every cqrs actor.
+///
+/// Commands are handled against the aggregate and the resulting events are sent back through
+/// the distributor, so they are applied in mailbox order like any other message.
+pub struct CqrsContext<A: CqrsAggregate> {
+    services: A::Services,
+    distributor: Distributor,
+    sequence: usize,
+}
+
+impl<A: CqrsAggregate> CqrsContext<A> {
+    /// Init new context sending events through `distributor`
+    pub fn new(services: A::Services, distributor: Distributor) -> Self {
+        Self {
+            services,
+            distributor,
+            sequence: 0,
+        }
+    }
+
+    /// Sequence number of the last applied event
+    pub fn sequence(&self) -> usize {
+        self.sequence
+    }
+
+    /// Handle a command and forward the resulting events to the actor
+    pub fn execute(&self, aggregate: &A, command: A::Command) -> Result<(), A::Error> {
+        let _span =
+            info_span!("cqrs.execute", aggregate = %A::aggregate_type(), ?command).entered();
+        let events = run!(aggregate.handle(command, &self.services))?;
+        forward(&self.distributor, &A::aggregate_type(), events);
+        Ok(())
+    }
+
+    /// Handle a command and apply the resulting events right away instead of sending them behind
+    /// the messages already queued, `on_applied` runs after each event is applied
+    pub fn execute_now<F>(
+        &mut self,
+        aggregate: &mut A,
+        command: A::Command,
+        mut on_applied: F,
+    ) -> Result<(), A::Error>
+    where
+        F: FnMut(&EventEnvelope<A>),
+    {
+        let _span =
+            info_span!("cqrs.execute", aggregate = %A::aggregate_type(), ?command).entered();
+        let events = run!(aggregate.handle(command, &self.services))?;
+        for event in events {
+            let envelope = self.apply(aggregate, event);
+            on_applied(&envelope);
+        }
+        Ok(())
+    }
+
+    /// Apply an event to the aggregate and wrap it for views
+    pub fn apply(&mut self, aggregate: &mut A, event: A::Event) -> EventEnvelope<A> {
+        aggregate.apply(event.clone());
+        self.sequence += 1;
+        EventEnvelope {
+            aggregate_id: A::aggregate_type(),
+            sequence: self.sequence,
+            payload: event,
+            metadata: HashMap::from([(TIMESTAMP_METADATA.to_owned(), Utc::now().to_rfc3339())]),
+        }
+    }
+
+    /// Match commands (told or asked) and events, `on_applied` runs after an event is applied.
+    ///
+    /// Unmatched messages are left in the returned handler for the caller to match.
+    pub fn dispatch<F>(
+        &mut self,
+        aggregate: &mut A,
+        handler: MessageHandler<()>,
+        on_applied: F,
+    ) -> MessageHandler<()>
+    where
+        F: FnOnce(&EventEnvelope<A>),
+    {
+        handler
+            .on_tell(|command: A::Command, _| {
+                if let Err(e) = self.execute(aggregate, command) {
+                    error!("[{}] Rejected command: {e}", A::aggregate_type());
+                }
+            })
+            .on_question(|command: A::Command, sender| {
+                // The caller may be gone already
+                let _ = sender.reply(self.execute(aggregate, command));
+            })
+            .on_tell(|event: A::Event, _| {
+                let envelope = self.apply(aggregate, event);
+                on_applied(&envelope);
+            })
+    }
+}
+
+/// Generic actor hosting a user-defined cqrs-es aggregate under bastion supervision
+pub struct CqrsActor<A: CqrsAggregate> {
+    aggregate: A,
+    context: CqrsContext<A>,
+}
+
+impl<A: CqrsAggregate> Default for CqrsActor<A> {
+    fn default() -> Self {
+        Self::new(A::default())
+    }
+}
+
+impl<A: CqrsAggregate> CqrsActor<A> {
+    /// Init new actor state from an aggregate
+    pub fn new(aggregate: A) -> Self {
+        Self {
+            aggregate,
+            context: CqrsContext::new(A::Services::default(), A::distributor()),
+        }
+    }
+
+    /// Hosted aggregate
+    pub fn aggregate(&self) -> &A {
+        &self.aggregate
+    }
+}
+
+#[async_trait]
+impl<A: CqrsAggregate> TActor for CqrsActor<A> {
+    fn with_distributor() -> Option<Distributor> {
+        Some(A::distributor())
+    }
+
+    async fn handler(&mut self, ctx: BastionContext) -> Result<(), ()> {
+        loop {
+            self.context
+                .dispatch(
+                    &mut self.aggregate,
+                    MessageHandler::new(ctx.recv().await?),
+                    |_| {},
+                )
+                .on_fallback(|unknown, _| {
+                    warn!("[{}] Unknown message: {unknown:?}", A::aggregate_type())
+                });
+            liveness::processed();
+        }
+    }
+}
@@ -0,0 +1,201 @@
+use std::{fmt::Display, marker::PhantomData};
+
+use bastion::prelude::{Distributor, Message, MessageHandler};
+use chrono::{DateTime, Utc};
+use tracing::{error, info_span};
+
+/// State changed only by the events its commands produce
+pub trait StateMachine: 'static {
+    type Command: Message;
+    type Event: Message + Clone;
+    type Error: Message + Display;
+
+    /// Name of the machine in logs and spans
+    fn machine_type() -> String;
+
+    /// Validate a command against the current state and return the events it produces
+    fn handle(&self, command: Self::Command) -> Result<Vec<Self::Event>, Self::Error>;
+
+    /// Change the state, events are never rejected
+    fn apply(&mut self, event: Self::Event);
+}
+
+/// Event applied to a machine
+#[derive(Debug, Clone, PartialEq)]
+pub struct Applied<E> {
+    /// Position of the event among the ones applied by the context
+    pub sequence: usize,
+    pub event: E,
+    pub at: DateTime<Utc>,
+}
+
+/// Command → handle → event → apply loop of an actor hosting a `StateMachine`.
+///
+/// Commands are handled against the machine and the resulting events are sent back through the
+/// distributor, so they are applied in mailbox order like any other message.
+pub struct MachineContext<M: StateMachine> {
+    distributor: Distributor,
+    sequence: usize,
+    machine: PhantomData<fn(M)>,
+}
+
+/// Tell `events` to the actor behind `distributor`, logging those it cannot take under `name`
+pub(crate) fn forward<E: Message>(
+    distributor: &Distributor,
+    name: &str,
+    events: impl IntoIterator<Item = E>,
+) {
+    for e in events {
+        // The actor may be stopping or restarting, the event is lost but the handler goes on
+        if let Err(e) = distributor.tell_one(e) {
+            error!("[{name}] Cannot forward event: {e:?}");
+        }
+    }
+}
+
+impl<M: StateMachine> MachineContext<M> {
+    /// Init new context sending events through `distributor`
+    pub fn new(distributor: Distributor) -> Self {
+        Self {
+            distributor,
+            sequence: 0,
+            machine: PhantomData,
+        }
+    }
+
+    /// Sequence number of the last applied event
+    pub fn sequence(&self) -> usize {
+        self.sequence
+    }
+
+    /// Handle a command and forward the resulting events to the actor
+    pub fn execute(&self, machine: &M, command: M::Command) -> Result<(), M::Error> {
+        let _span = info_span!("machine.execute", machine = %M::machine_type(), ?command).entered();
+        forward(
+            &self.distributor,
+            &M::machine_type(),
+            machine.handle(command)?,
+        );
+        Ok(())
+    }
+
+    /// Handle a command and apply the resulting events right away instead of sending them behind
+    /// the messages already queued, `on_applied` runs after each event is applied
+    pub fn execute_now<F>(
+        &mut self,
+        machine: &mut M,
+        command: M::Command,
+        mut on_applied: F,
+    ) -> Result<(), M::Error>
+    where
+        F: FnMut(&Applied<M::Event>),
+    {
+        let _span = info_span!("machine.execute", machine = %M::machine_type(), ?command).entered();
+        for event in machine.handle(command)? {
+            let applied = self.apply(machine, event);
+            on_applied(&applied);
+        }
+        Ok(())
+    }
+
+    /// Apply an event to the machine and stamp it
+    pub fn apply(&mut self, machine: &mut M, event: M::Event) -> Applied<M::Event> {
+        machine.apply(event.clone());
+        self.sequence += 1;
+        Applied {
+            sequence: self.sequence,
+            event,
+            at: Utc::now(),
+        }
+    }
+
+    /// Match commands (told or asked) and events, `on_applied` runs after an event is applied.
+    ///
+    /// Unmatched messages are left in the returned handler for the caller to match.
+    pub fn dispatch<F>(
+        &mut self,
+        machine: &mut M,
+        handler: MessageHandler<()>,
+        on_applied: F,
+    ) -> MessageHandler<()>
+    where
+        F: FnOnce(&Applied<M::Event>),
+    {
+        handler
+            .on_tell(|command: M::Command, _| {
+                if let Err(e) = self.execute(machine, command) {
+                    error!("[{}] Rejected command: {e}", M::machine_type());
+                }
+            })
+            .on_question(|command: M::Command, sender| {
+                // The caller may be gone already
+                let _ = sender.reply(self.execute(machine, command));
+            })
+            .on_tell(|event: M::Event, _| {
+                let applied = self.apply(machine, event);
+                on_applied(&applied);
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::actors::base::testkit::runtime;
+
+    #[derive(Debug, Default)]
+    struct Counter(u32);
+
+    #[derive(Debug, Clone)]
+    struct Add(u32);
+
+    impl StateMachine for Counter {
+        type Command = Add;
+        type Event = Add;
+        type Error = String;
+
+        fn machine_type() -> String {
+            "counter".to_owned()
+        }
+
+        fn handle(&self, command: Add) -> Result<Vec<Add>, String> {
+            match command.0 {
+                0 => Err("nothing to add".to_owned()),
+                _ => Ok(vec![command]),
+            }
+        }
+
+        fn apply(&mut self, event: Add) {
+            self.0 += event.0;
+        }
+    }
+
+    #[test]
+    fn commands_are_applied_in_sequence() {
+        let mut context = MachineContext::new(Distributor::named("machine-test"));
+        let mut counter = Counter::default();
+        let mut sequences = vec![];
+
+        for n in [2, 0, 3] {
+            let _ = context.execute_now(&mut counter, Add(n), |applied| {
+                sequences.push(applied.sequence)
+            });
+        }
+        assert_eq!(5, counter.0);
+        assert_eq!(vec![1, 2], sequences);
+        assert_eq!(2, context.sequence());
+    }
+
+    #[test]
+    fn events_without_actor_are_dropped() {
+        let _runtime = runtime().enter();
+        let context = MachineContext::new(Distributor::named("machine-test-missing"));
+        let counter = Counter::default();
+
+        assert_eq!(Ok(()), context.execute(&counter, Add(1)));
+        assert_eq!(
+            Err("nothing to add".to_owned()),
+            context.execute(&counter, Add(0))
+        );
+    }
+}
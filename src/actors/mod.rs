@@ -1,2 +1,7 @@
 /// Base actor implementation
 pub mod base;
+/// Generic actor hosting cqrs-es aggregates
+#[cfg(feature = "cqrs")]
+pub mod cqrs;
+/// Command and event state machine hosted by actors
+pub mod machine;
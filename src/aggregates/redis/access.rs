@@ -0,0 +1,143 @@
+use std::time::Duration;
+
+use bastion::prelude::AnswerSender;
+use r2d2::Pool;
+use redis::RedisResult;
+use serde::{Deserialize, Serialize};
+use tokio::task;
+
+use super::{
+    connection::RedisConnection,
+    error::RedisError,
+    node,
+    pool::{checkout, RedisManager},
+    trace, Redis,
+};
+
+/// Keys whose access statistics are read
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub enum AccessTarget {
+    Keys(Vec<String>),
+    /// Up to this many random keys, spread over the masters with `RANDOMKEY`
+    Sample(usize),
+}
+
+impl Default for AccessTarget {
+    fn default() -> Self {
+        Self::Keys(vec![])
+    }
+}
+
+/// How recently or how often a key was accessed, depending on the eviction policy
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum Access {
+    /// `OBJECT IDLETIME`, with an LRU or no eviction policy
+    Idle(Duration),
+    /// `OBJECT FREQ`, the logarithmic access counter of LFU policies
+    Frequency(u64),
+}
+
+/// Access statistics of one key
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct KeyAccess {
+    pub key: String,
+    pub access: Access,
+}
+
+/// Question replied with `Result<Vec<KeyAccess>, RedisError>`, missing keys are left out.
+///
+/// Reading the statistics does not count as an access.
+#[derive(Default, Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RedisAccessQuery {
+    pub target: AccessTarget,
+}
+
+/// Read access statistics from a blocking task on its own pooled connection
+pub(crate) fn spawn(
+    pool: &Pool<RedisManager>,
+    redis: &Redis,
+    event: RedisAccessQuery,
+    sender: AnswerSender,
+) {
+    let pool = pool.clone();
+    let urls = redis.urls.clone();
+    let (hash_trace_keys, actor) = (redis.hash_trace_keys, redis.own_distributor());
+    task::spawn_blocking(move || {
+        let result = checkout(&pool)
+            .map_err(|e| RedisError::Command(e.to_string()))
+            .and_then(|mut conn| {
+                let keys = match event.target {
+                    AccessTarget::Keys(keys) => keys,
+                    AccessTarget::Sample(count) => sample(&mut conn, &urls, count)
+                        .map_err(|e| RedisError::Command(e.to_string()))?,
+                };
+                let label = format!("{} keys", keys.len());
+                trace::command("object", &label, hash_trace_keys, || {
+                    access(&mut conn, &keys)
+                })
+                .map_err(|e| RedisError::Command(e.to_string()))
+            });
+        if let Err(e) = &result {
+            Redis::report_error(actor, "Access", e);
+        }
+        // The caller may be gone already
+        let _ = sender.reply(result);
+    });
+}
+
+// Random distinct keys, an equal share from every master
+fn sample(conn: &mut RedisConnection, urls: &[String], count: usize) -> RedisResult<Vec<String>> {
+    let masters: Vec<_> = node::nodes(conn, urls)?
+        .into_iter()
+        .filter(|node| !node.slots.is_empty())
+        .collect();
+    let share = count.div_ceil(masters.len().max(1));
+
+    let mut keys = vec![];
+    for master in masters {
+        let mut node_conn = master.client(urls)?.get_connection()?;
+        for _ in 0..share {
+            if keys.len() == count {
+                break;
+            }
+            match redis::cmd("RANDOMKEY").query::<Option<String>>(&mut node_conn)? {
+                Some(key) if !keys.contains(&key) => keys.push(key),
+                Some(_) => {}
+                // Empty node
+                None => break,
+            }
+        }
+    }
+    Ok(keys)
+}
+
+fn access(conn: &mut RedisConnection, keys: &[String]) -> RedisResult<Vec<KeyAccess>> {
+    let mut stats = vec![];
+    // Switched to `OBJECT FREQ` once a node reports an LFU policy, the policy is cluster wide
+    let mut lfu = false;
+    for key in keys {
+        if !lfu {
+            match redis::cmd("OBJECT")
+                .arg("IDLETIME")
+                .arg(key)
+                .query::<Option<u64>>(conn)
+            {
+                Ok(idle) => {
+                    stats.extend(idle.map(|idle| KeyAccess {
+                        key: key.clone(),
+                        access: Access::Idle(Duration::from_secs(idle)),
+                    }));
+                    continue;
+                }
+                Err(e) if e.to_string().contains("LFU") => lfu = true,
+                Err(e) => return Err(e),
+            }
+        }
+        let frequency: Option<u64> = redis::cmd("OBJECT").arg("FREQ").arg(key).query(conn)?;
+        stats.extend(frequency.map(|frequency| KeyAccess {
+            key: key.clone(),
+            access: Access::Frequency(frequency),
+        }));
+    }
+    Ok(stats)
+}
@@ -0,0 +1,227 @@
+//! Diagnostics HTTP endpoint.
+//!
+//! A companion actor serving read-only JSON reports of the Redis actor for operators without
+//! metrics infrastructure:
+//!
+//! - `GET /health`: `RedisHealth`, answered with 503 while unhealthy
+//! - `GET /pool`: `PoolStatsReport`
+//! - `GET /topology`: the `ClusterNode`s seen by the actor
+//! - `GET /errors`: `ErrorStats`
+
+use std::time::Duration;
+
+use async_trait::async_trait;
+use bastion::{
+    prelude::{BastionContext, Message},
+    supervisor::{ActorRestartStrategy, RestartPolicy, RestartStrategy},
+};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    time::timeout,
+};
+use tracing::{error, info, warn};
+
+use crate::actors::base::TActor;
+
+use super::{
+    error::{ErrorStats, RedisError, RedisErrorStatsQuery},
+    health::{RedisHealth, RedisHealthQuery},
+    node::{ClusterNode, RedisTopologyQuery},
+    pool::{PoolStatsReport, RedisPoolStats},
+    Redis,
+};
+
+/// How long the Redis actor gets to answer a report question
+const ASK_TIMEOUT: Duration = Duration::from_secs(5);
+/// Largest request head read, the rest is ignored
+const MAX_REQUEST: usize = 4096;
+
+/// Admin endpoint settings, the actor state
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct AdminHttp {
+    /// Address the endpoint listens on
+    pub addr: String,
+}
+
+impl Default for AdminHttp {
+    fn default() -> Self {
+        Self {
+            addr: "127.0.0.1:9187".to_owned(),
+        }
+    }
+}
+
+/// Report served by the endpoint
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Endpoint {
+    Health,
+    Pool,
+    Topology,
+    Errors,
+}
+
+impl Endpoint {
+    /// Endpoint of an HTTP request line, e.g. `GET /health HTTP/1.1`
+    fn route(request_line: &str) -> Result<Self, (u16, &'static str)> {
+        let mut parts = request_line.split_whitespace();
+        let (method, target) = match (parts.next(), parts.next()) {
+            (Some(method), Some(target)) => (method, target),
+            _ => return Err((400, "Bad Request")),
+        };
+        if method != "GET" {
+            return Err((405, "Method Not Allowed"));
+        }
+        match target.split('?').next().unwrap_or_default() {
+            "/health" => Ok(Self::Health),
+            "/pool" => Ok(Self::Pool),
+            "/topology" => Ok(Self::Topology),
+            "/errors" => Ok(Self::Errors),
+            _ => Err((404, "Not Found")),
+        }
+    }
+
+    /// Ask the Redis actor for the report, as a status line and a JSON body
+    async fn respond(self) -> (u16, &'static str, String) {
+        match self {
+            Self::Health => match ask::<RedisHealth>(RedisHealthQuery).await {
+                Some(health) if health.is_healthy() => json(200, "OK", &health),
+                Some(health) => json(503, "Service Unavailable", &health),
+                None => unavailable(),
+            },
+            Self::Pool => match ask::<PoolStatsReport>(RedisPoolStats).await {
+                Some(report) => json(200, "OK", &report),
+                None => unavailable(),
+            },
+            Self::Topology => {
+                match ask::<Result<Vec<ClusterNode>, RedisError>>(RedisTopologyQuery).await {
+                    Some(Ok(nodes)) => json(200, "OK", &nodes),
+                    Some(Err(e)) => json(502, "Bad Gateway", &e.to_string()),
+                    None => unavailable(),
+                }
+            }
+            Self::Errors => match ask::<ErrorStats>(RedisErrorStatsQuery).await {
+                Some(errors) => json(200, "OK", &errors),
+                None => unavailable(),
+            },
+        }
+    }
+}
+
+// `None` when the actor is down or too slow to answer
+async fn ask<R: Message>(question: impl Message) -> Option<R> {
+    match timeout(ASK_TIMEOUT, Redis::distributor().request::<R>(question)).await {
+        Ok(Ok(Ok(reply))) => Some(reply),
+        Ok(Ok(Err(e))) => {
+            warn!("[ADMIN] Cannot ask the redis actor: {e:?}");
+            None
+        }
+        Ok(Err(_)) => None,
+        Err(_) => {
+            warn!("[ADMIN] The redis actor did not answer within {ASK_TIMEOUT:?}");
+            None
+        }
+    }
+}
+
+fn json(status: u16, reason: &'static str, body: &impl Serialize) -> (u16, &'static str, String) {
+    let body = serde_json::to_string(body).expect("reports serialize to JSON");
+    (status, reason, body)
+}
+
+fn unavailable() -> (u16, &'static str, String) {
+    json(
+        503,
+        "Service Unavailable",
+        &"the redis actor is unreachable",
+    )
+}
+
+fn response(status: u16, reason: &str, body: &str) -> String {
+    format!(
+        "HTTP/1.1 {status} {reason}\r\n\
+         Content-Type: application/json\r\n\
+         Content-Length: {}\r\n\
+         Connection: close\r\n\r\n{body}",
+        body.len()
+    )
+}
+
+async fn serve(mut stream: TcpStream) -> std::io::Result<()> {
+    let mut head = vec![0; MAX_REQUEST];
+    let read = stream.read(&mut head).await?;
+    let head = String::from_utf8_lossy(&head[..read]);
+    let request_line = head.lines().next().unwrap_or_default();
+
+    let (status, reason, body) = match Endpoint::route(request_line) {
+        Ok(endpoint) => endpoint.respond().await,
+        Err((status, reason)) => json(status, reason, &reason),
+    };
+    stream
+        .write_all(response(status, reason, &body).as_bytes())
+        .await?;
+    stream.shutdown().await
+}
+
+#[async_trait]
+impl TActor for AdminHttp {
+    /// Retry binding a few times, e.g. while a previous listener releases the port
+    fn with_restart_strategy() -> Option<RestartStrategy> {
+        Some(RestartStrategy::new(
+            RestartPolicy::Tries(5),
+            ActorRestartStrategy::LinearBackOff {
+                timeout: Duration::from_secs(1),
+            },
+        ))
+    }
+
+    async fn handler(&mut self, _: BastionContext) -> Result<(), ()> {
+        let listener = TcpListener::bind(&self.addr).await.map_err(|e| {
+            error!(addr = self.addr, error = %e, "[ADMIN] Cannot listen");
+        })?;
+        info!(addr = self.addr, "[ADMIN] Listening");
+
+        loop {
+            match listener.accept().await {
+                Ok((stream, _)) => {
+                    tokio::spawn(async move {
+                        if let Err(e) = serve(stream).await {
+                            warn!(error = %e, "[ADMIN] Cannot answer request");
+                        }
+                    });
+                }
+                Err(e) => warn!(error = %e, "[ADMIN] Cannot accept connection"),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn request_lines_are_routed() {
+        assert_eq!(
+            Ok(Endpoint::Health),
+            Endpoint::route("GET /health HTTP/1.1")
+        );
+        assert_eq!(
+            Ok(Endpoint::Topology),
+            Endpoint::route("GET /topology?x=1 HTTP/1.1")
+        );
+        assert_eq!(Err((404, "Not Found")), Endpoint::route("GET / HTTP/1.1"));
+        assert_eq!(
+            Err((405, "Method Not Allowed")),
+            Endpoint::route("POST /pool HTTP/1.1")
+        );
+        assert_eq!(Err((400, "Bad Request")), Endpoint::route(""));
+
+        assert_eq!(
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: 2\r\n\
+             Connection: close\r\n\r\n{}",
+            response(200, "OK", "{}")
+        );
+    }
+}
@@ -0,0 +1,202 @@
+use std::{
+    fmt::{self, Debug},
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use chrono::{DateTime, Utc};
+use redis::RedisResult;
+use serde::{Deserialize, Serialize};
+use tracing::{error, info};
+
+use super::connection::{self, RedisConnection};
+
+/// One mutating command as recorded by the audit log
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct AuditEntry {
+    pub op: String,
+    pub key: String,
+    /// Payload size in bytes
+    pub size: usize,
+    /// Tag supplied by the caller, if any
+    pub caller: Option<String>,
+    pub ok: bool,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Destination of audit entries
+pub trait AuditSink: Debug + Send + Sync {
+    fn record(&self, entry: &AuditEntry);
+}
+
+/// Opt-in audit log, disabled by default
+#[derive(Clone, Default)]
+pub struct AuditLog(Option<Arc<dyn AuditSink>>);
+
+impl AuditLog {
+    /// Enable auditing into `sink`
+    pub fn new(sink: impl AuditSink + 'static) -> Self {
+        Self(Some(Arc::new(sink)))
+    }
+
+    pub(crate) fn record(&self, op: &str, key: &str, size: usize, caller: Option<&str>, ok: bool) {
+        if let Some(sink) = &self.0 {
+            sink.record(&AuditEntry {
+                op: op.to_owned(),
+                key: key.to_owned(),
+                size,
+                caller: caller.map(ToOwned::to_owned),
+                ok,
+                timestamp: Utc::now(),
+            });
+        }
+    }
+}
+
+impl Debug for AuditLog {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("AuditLog").field(&self.0).finish()
+    }
+}
+
+impl PartialEq for AuditLog {
+    fn eq(&self, other: &Self) -> bool {
+        match (&self.0, &other.0) {
+            (Some(a), Some(b)) => Arc::ptr_eq(a, b),
+            (None, None) => true,
+            _ => false,
+        }
+    }
+}
+
+/// Emits entries as `redis_audit` tracing events
+#[derive(Debug, Default)]
+pub struct TracingAuditSink;
+
+impl AuditSink for TracingAuditSink {
+    fn record(&self, entry: &AuditEntry) {
+        info!(
+            target: "redis_audit",
+            op = entry.op,
+            key = entry.key,
+            size = entry.size,
+            caller = entry.caller,
+            ok = entry.ok,
+            timestamp = %entry.timestamp,
+        );
+    }
+}
+
+/// Appends entries to a file as JSON lines
+#[derive(Debug)]
+pub struct FileAuditSink(Mutex<File>);
+
+impl FileAuditSink {
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self(Mutex::new(file)))
+    }
+}
+
+impl AuditSink for FileAuditSink {
+    fn record(&self, entry: &AuditEntry) {
+        let mut file = self.0.lock().unwrap();
+        let written = serde_json::to_writer(&mut *file, entry)
+            .map_err(io::Error::from)
+            .and_then(|_| file.write_all(b"\n"));
+        if let Err(e) = written {
+            error!(error = %e, "cannot write audit entry");
+        }
+    }
+}
+
+/// Appends entries to a capped Redis stream with `XADD`
+pub struct StreamAuditSink {
+    conn: Mutex<RedisConnection>,
+    stream: String,
+    max_len: usize,
+}
+
+impl StreamAuditSink {
+    /// Connect to the cluster holding the stream, it keeps roughly the last `max_len` entries
+    pub fn connect(
+        urls: Vec<String>,
+        stream: impl Into<String>,
+        max_len: usize,
+    ) -> RedisResult<Self> {
+        let conn = connection::connect(&urls)?;
+        Ok(Self {
+            conn: Mutex::new(conn),
+            stream: stream.into(),
+            max_len,
+        })
+    }
+}
+
+impl Debug for StreamAuditSink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StreamAuditSink")
+            .field("stream", &self.stream)
+            .field("max_len", &self.max_len)
+            .finish()
+    }
+}
+
+impl AuditSink for StreamAuditSink {
+    fn record(&self, entry: &AuditEntry) {
+        let mut conn = self.conn.lock().unwrap();
+        let result: RedisResult<String> = redis::cmd("XADD")
+            .arg(&self.stream)
+            .arg("MAXLEN")
+            .arg("~")
+            .arg(self.max_len)
+            .arg("*")
+            .arg("op")
+            .arg(&entry.op)
+            .arg("key")
+            .arg(&entry.key)
+            .arg("size")
+            .arg(entry.size)
+            .arg("caller")
+            .arg(entry.caller.as_deref().unwrap_or_default())
+            .arg("ok")
+            .arg(entry.ok)
+            .arg("timestamp")
+            .arg(entry.timestamp.to_rfc3339())
+            .query(&mut *conn);
+        if let Err(e) = result {
+            error!(error = %e, "cannot write audit entry");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use super::*;
+
+    #[test]
+    fn file_sink_appends_json_lines() {
+        let path = env::temp_dir().join(format!("redis-audit-{}.jsonl", std::process::id()));
+        let audit = AuditLog::new(FileAuditSink::open(&path).unwrap());
+
+        audit.record("set", "hello", 2, Some("tests"), true);
+        audit.record("set", "world", 5, None, false);
+
+        let lines = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let entries: Vec<AuditEntry> = lines
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(2, entries.len());
+        assert_eq!(
+            ("hello", Some("tests")),
+            (entries[0].key.as_str(), entries[0].caller.as_deref())
+        );
+        assert!(!entries[1].ok);
+    }
+}
@@ -0,0 +1,396 @@
+use std::{
+    collections::HashMap,
+    fmt::{self, Debug},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use redis::{Cmd, Commands, ErrorKind, RedisResult, Value};
+
+use super::{connection::RedisConnection, Ttl};
+
+/// Data commands the actor runs, implemented by the cluster connection and `MemoryBackend`
+pub trait RedisBackend: Send {
+    /// `GET`, `None` for a missing key
+    fn get(&mut self, key: &str) -> RedisResult<Option<Vec<u8>>>;
+
+    /// `GETEX ... PX`, `GET` re-applying `ttl` to an existing key
+    fn getex(&mut self, key: &str, ttl: Duration) -> RedisResult<Option<Vec<u8>>>;
+
+    /// `SET`, expiring the key after `ttl` when set. The TTL is part of the same command, so a
+    /// written key always carries it.
+    fn set(&mut self, key: &str, value: &[u8], ttl: Option<Duration>) -> RedisResult<()>;
+
+    /// `EXISTS` of one key
+    fn exists(&mut self, key: &str) -> RedisResult<bool>;
+
+    /// `DEL` of one key, whether it existed
+    fn del(&mut self, key: &str) -> RedisResult<bool>;
+
+    /// `GETRANGE` with inclusive, possibly negative offsets
+    fn getrange(&mut self, key: &str, start: isize, end: isize) -> RedisResult<Vec<u8>>;
+
+    /// `PEXPIRE`, whether the key exists
+    fn expire(&mut self, key: &str, ttl: Duration) -> RedisResult<bool>;
+
+    /// `PTTL`
+    fn ttl(&mut self, key: &str) -> RedisResult<Ttl>;
+
+    fn ping(&mut self) -> RedisResult<()>;
+
+    /// Any other command, the in-memory backend only runs the data commands above
+    fn command(&mut self, cmd: &Cmd) -> RedisResult<Value>;
+
+    /// The cluster connection behind the backend, for calls beyond single commands
+    fn connection(&mut self) -> Option<&mut RedisConnection> {
+        None
+    }
+}
+
+/// `SET key value [PX ttl]`
+pub(crate) fn set_cmd(key: &str, value: &[u8], ttl: Option<Duration>) -> Cmd {
+    let mut cmd = redis::cmd("SET");
+    cmd.arg(key).arg(value);
+    if let Some(ttl) = ttl {
+        // PX 0 is rejected by the server
+        cmd.arg("PX").arg((ttl.as_millis() as u64).max(1));
+    }
+    cmd
+}
+
+impl RedisBackend for RedisConnection {
+    fn get(&mut self, key: &str) -> RedisResult<Option<Vec<u8>>> {
+        Commands::get(self, key)
+    }
+
+    fn getex(&mut self, key: &str, ttl: Duration) -> RedisResult<Option<Vec<u8>>> {
+        redis::cmd("GETEX")
+            .arg(key)
+            .arg("PX")
+            .arg((ttl.as_millis() as u64).max(1))
+            .query(self)
+    }
+
+    fn set(&mut self, key: &str, value: &[u8], ttl: Option<Duration>) -> RedisResult<()> {
+        set_cmd(key, value, ttl).query(self)
+    }
+
+    fn exists(&mut self, key: &str) -> RedisResult<bool> {
+        Commands::exists(self, key)
+    }
+
+    fn del(&mut self, key: &str) -> RedisResult<bool> {
+        Commands::del(self, key)
+    }
+
+    fn getrange(&mut self, key: &str, start: isize, end: isize) -> RedisResult<Vec<u8>> {
+        Commands::getrange(self, key, start, end)
+    }
+
+    fn expire(&mut self, key: &str, ttl: Duration) -> RedisResult<bool> {
+        Commands::pexpire(self, key, ttl.as_millis() as usize)
+    }
+
+    fn ttl(&mut self, key: &str) -> RedisResult<Ttl> {
+        let ttl: i64 = Commands::pttl(self, key)?;
+        Ok(match ttl {
+            -2 => Ttl::NoKey,
+            ttl if ttl < 0 => Ttl::NoExpiry,
+            ttl => Ttl::Expires(Duration::from_millis(ttl as u64)),
+        })
+    }
+
+    fn ping(&mut self) -> RedisResult<()> {
+        redis::cmd("PING").query::<String>(self).map(|_| ())
+    }
+
+    fn command(&mut self, cmd: &Cmd) -> RedisResult<Value> {
+        cmd.query(self)
+    }
+
+    fn connection(&mut self) -> Option<&mut RedisConnection> {
+        Some(self)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Entry {
+    value: Vec<u8>,
+    expires_at: Option<Instant>,
+}
+
+/// In-memory backend with TTLs for tests, clones share the same data
+#[derive(Debug, Clone, Default)]
+pub struct MemoryBackend(Arc<Mutex<HashMap<String, Entry>>>);
+
+impl MemoryBackend {
+    /// Lock the data, every command on this backend waits until the guard is dropped
+    #[cfg(test)]
+    pub(crate) fn stall(&self) -> impl Drop + '_ {
+        self.0.lock().unwrap()
+    }
+
+    /// Run `f` on the live entry of `key`, dropping it first when expired
+    fn with_entry<T>(&self, key: &str, f: impl FnOnce(Option<&mut Entry>) -> T) -> T {
+        let mut data = self.0.lock().unwrap();
+        if data
+            .get(key)
+            .and_then(|entry| entry.expires_at)
+            .is_some_and(|at| at <= Instant::now())
+        {
+            data.remove(key);
+        }
+        f(data.get_mut(key))
+    }
+
+    /// Live keys matching a `SCAN MATCH` glob-style pattern
+    pub(crate) fn keys(&self, pattern: &str) -> Vec<String> {
+        let now = Instant::now();
+        self.0
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, entry)| entry.expires_at.is_none_or(|at| at > now))
+            .filter(|(key, _)| glob_match(pattern.as_bytes(), key.as_bytes()))
+            .map(|(key, _)| key.clone())
+            .collect()
+    }
+}
+
+/// Redis glob matching: `*`, `?`, `[abc]`, `[^a-z]` and `\` escapes
+pub(crate) fn glob_match(pattern: &[u8], text: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => text.is_empty(),
+        Some((b'*', rest)) => (0..=text.len()).any(|skip| glob_match(rest, &text[skip..])),
+        Some((b'?', rest)) => !text.is_empty() && glob_match(rest, &text[1..]),
+        Some((b'[', rest)) => {
+            let Some((&c, text)) = text.split_first() else {
+                return false;
+            };
+            let (negated, mut class) = match rest.split_first() {
+                Some((b'^', class)) => (true, class),
+                _ => (false, rest),
+            };
+            let mut matched = false;
+            loop {
+                match class {
+                    // Unterminated class, like Redis match up to the end
+                    [] => break,
+                    [b']', after @ ..] => {
+                        class = after;
+                        break;
+                    }
+                    [b'\\', escaped, after @ ..] => {
+                        matched |= *escaped == c;
+                        class = after;
+                    }
+                    [start, b'-', end, after @ ..] if *end != b']' => {
+                        let (low, high) = if start <= end {
+                            (start, end)
+                        } else {
+                            (end, start)
+                        };
+                        matched |= (*low..=*high).contains(&c);
+                        class = after;
+                    }
+                    [single, after @ ..] => {
+                        matched |= *single == c;
+                        class = after;
+                    }
+                }
+            }
+            matched != negated && glob_match(class, text)
+        }
+        Some((b'\\', [escaped, rest @ ..])) => {
+            text.first() == Some(escaped) && glob_match(rest, &text[1..])
+        }
+        Some((literal, rest)) => text.first() == Some(literal) && glob_match(rest, &text[1..]),
+    }
+}
+
+impl RedisBackend for MemoryBackend {
+    fn get(&mut self, key: &str) -> RedisResult<Option<Vec<u8>>> {
+        Ok(self.with_entry(key, |entry| entry.map(|entry| entry.value.clone())))
+    }
+
+    fn getex(&mut self, key: &str, ttl: Duration) -> RedisResult<Option<Vec<u8>>> {
+        Ok(self.with_entry(key, |entry| {
+            entry.map(|entry| {
+                entry.expires_at = Some(Instant::now() + ttl);
+                entry.value.clone()
+            })
+        }))
+    }
+
+    fn set(&mut self, key: &str, value: &[u8], ttl: Option<Duration>) -> RedisResult<()> {
+        // SET without a TTL clears the previous one
+        self.0.lock().unwrap().insert(
+            key.to_owned(),
+            Entry {
+                value: value.to_vec(),
+                expires_at: ttl.map(|ttl| Instant::now() + ttl),
+            },
+        );
+        Ok(())
+    }
+
+    fn exists(&mut self, key: &str) -> RedisResult<bool> {
+        Ok(self.with_entry(key, |entry| entry.is_some()))
+    }
+
+    fn del(&mut self, key: &str) -> RedisResult<bool> {
+        let existed = self.with_entry(key, |entry| entry.is_some());
+        self.0.lock().unwrap().remove(key);
+        Ok(existed)
+    }
+
+    fn getrange(&mut self, key: &str, start: isize, end: isize) -> RedisResult<Vec<u8>> {
+        let value = self.get(key)?.unwrap_or_default();
+        let len = value.len() as isize;
+        let offset = |i: isize| if i < 0 { (len + i).max(0) } else { i };
+        let (start, end) = (offset(start), offset(end).min(len - 1));
+        if len == 0 || start > end {
+            return Ok(vec![]);
+        }
+        Ok(value[start as usize..=end as usize].to_vec())
+    }
+
+    fn expire(&mut self, key: &str, ttl: Duration) -> RedisResult<bool> {
+        Ok(self.with_entry(key, |entry| match entry {
+            Some(entry) => {
+                entry.expires_at = Some(Instant::now() + ttl);
+                true
+            }
+            None => false,
+        }))
+    }
+
+    fn ttl(&mut self, key: &str) -> RedisResult<Ttl> {
+        Ok(self.with_entry(key, |entry| match entry {
+            Some(Entry {
+                expires_at: Some(at),
+                ..
+            }) => Ttl::Expires(at.saturating_duration_since(Instant::now())),
+            Some(_) => Ttl::NoExpiry,
+            None => Ttl::NoKey,
+        }))
+    }
+
+    fn ping(&mut self) -> RedisResult<()> {
+        Ok(())
+    }
+
+    fn command(&mut self, cmd: &Cmd) -> RedisResult<Value> {
+        let name = match cmd.args_iter().next() {
+            Some(redis::Arg::Simple(name)) => String::from_utf8_lossy(name).into_owned(),
+            _ => String::new(),
+        };
+        Err((
+            ErrorKind::ClientError,
+            "command not supported by the in-memory backend",
+            name,
+        )
+            .into())
+    }
+}
+
+/// Backend selected at init, the cluster at the configured urls by default
+#[derive(Clone, Default)]
+pub struct Backend(Option<MemoryBackend>);
+
+impl Backend {
+    /// Serve every data command from memory, no cluster is contacted
+    pub fn memory(backend: MemoryBackend) -> Self {
+        Self(Some(backend))
+    }
+
+    pub(crate) fn as_memory(&self) -> Option<&MemoryBackend> {
+        self.0.as_ref()
+    }
+}
+
+impl Debug for Backend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.0 {
+            Some(_) => f.write_str("Backend::Memory"),
+            None => f.write_str("Backend::Cluster"),
+        }
+    }
+}
+
+impl PartialEq for Backend {
+    fn eq(&self, other: &Self) -> bool {
+        match (&self.0, &other.0) {
+            (Some(a), Some(b)) => Arc::ptr_eq(&a.0, &b.0),
+            (None, None) => true,
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::typed::args;
+    use super::*;
+
+    #[test]
+    fn memory_keys_expire() {
+        let mut backend = MemoryBackend::default();
+        backend.set("greeting", b"hello", None).unwrap();
+        assert_eq!(
+            b"ell".to_vec(),
+            backend.getrange("greeting", 1, -2).unwrap()
+        );
+
+        assert_eq!(Ttl::NoExpiry, backend.ttl("greeting").unwrap());
+        assert!(backend.expire("greeting", Duration::from_secs(60)).unwrap());
+        assert!(
+            matches!(backend.ttl("greeting").unwrap(), Ttl::Expires(ttl) if ttl > Duration::from_secs(59))
+        );
+
+        backend.getex("greeting", Duration::from_secs(120)).unwrap();
+        assert!(
+            matches!(backend.ttl("greeting").unwrap(), Ttl::Expires(ttl) if ttl > Duration::from_secs(119))
+        );
+
+        assert!(backend.expire("greeting", Duration::ZERO).unwrap());
+        assert_eq!(Ttl::NoKey, backend.ttl("greeting").unwrap());
+        assert_eq!(None, backend.get("greeting").unwrap());
+        assert!(!backend.exists("greeting").unwrap());
+        assert!(!backend.del("greeting").unwrap());
+    }
+
+    #[test]
+    fn set_applies_the_ttl_with_the_value() {
+        assert_eq!(
+            vec!["SET", "k", "v", "PX", "1500"],
+            args(&set_cmd("k", b"v", Some(Duration::from_millis(1500))))
+        );
+        assert_eq!(vec!["SET", "k", "v"], args(&set_cmd("k", b"v", None)));
+
+        let mut backend = MemoryBackend::default();
+        backend
+            .set("greeting", b"hello", Some(Duration::from_secs(60)))
+            .unwrap();
+        assert!(
+            matches!(backend.ttl("greeting").unwrap(), Ttl::Expires(ttl) if ttl > Duration::from_secs(59))
+        );
+        backend.set("greeting", b"hi", None).unwrap();
+        assert_eq!(Ttl::NoExpiry, backend.ttl("greeting").unwrap());
+    }
+
+    #[test]
+    fn memory_keys_match_globs() {
+        let mut backend = MemoryBackend::default();
+        for key in ["user:1", "user:22", "session:1", "user:x"] {
+            backend.set(key, b"", None).unwrap();
+        }
+
+        let mut keys = backend.keys("user:[0-9]*");
+        keys.sort();
+        assert_eq!(vec!["user:1", "user:22"], keys);
+        assert_eq!(vec!["user:x"], backend.keys("user:[^0-9]"));
+        assert_eq!(vec!["session:1"], backend.keys("s?ssion:\\1"));
+        assert!(backend.keys("user").is_empty());
+    }
+}
@@ -0,0 +1,286 @@
+//! Big-key scanning.
+//!
+//! Keys matching a pattern are scanned on every master and measured with `MEMORY USAGE`. When
+//! the command is disabled or renamed, as on some managed offerings, strings are measured with
+//! `STRLEN` and collections by their number of elements instead. Keys above the thresholds are
+//! reported biggest first, before a single `GET` or `DEL` on them stalls the node.
+
+use std::{cmp::Reverse, ops::ControlFlow};
+
+use bastion::prelude::AnswerSender;
+use r2d2::Pool;
+use redis::{Connection, ErrorKind, RedisResult};
+use serde::{Deserialize, Serialize};
+use tokio::task;
+
+use super::{
+    backend::{MemoryBackend, RedisBackend},
+    connection::RedisConnection,
+    error::RedisError,
+    pool::{checkout, RedisManager},
+    scan::{self, Throttle},
+    trace, Redis,
+};
+
+/// Keys requested per `SCAN` and measured per pipeline
+const BATCH: usize = 100;
+
+/// Question scanning for keys above a size, replied with `Result<BigKeyReport, RedisError>`.
+///
+/// Keys are found with `SCAN`, master after master, until `sample` of them are measured. Keys
+/// written while the scan runs may be missed.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RedisBigKeyScan {
+    /// Glob-style pattern of the keys measured
+    pub pattern: String,
+    /// Keys measured at most
+    pub sample: usize,
+    /// Keys using more bytes are reported
+    pub max_bytes: u64,
+    /// Collections with more elements are reported when `MEMORY USAGE` is not available
+    pub max_elements: u64,
+    /// Keys measured per second at most, unthrottled when unset
+    #[serde(default)]
+    pub per_second: Option<u32>,
+}
+
+impl Default for RedisBigKeyScan {
+    fn default() -> Self {
+        Self {
+            pattern: "*".to_owned(),
+            sample: 10_000,
+            max_bytes: 1024 * 1024,
+            max_elements: 10_000,
+            per_second: None,
+        }
+    }
+}
+
+/// Size of a key
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum KeySize {
+    /// `MEMORY USAGE` with its overhead, or `STRLEN` of a string
+    Bytes(u64),
+    /// Length of a list, set, sorted set, hash or stream
+    Elements(u64),
+}
+
+impl KeySize {
+    fn value(self) -> u64 {
+        match self {
+            Self::Bytes(size) | Self::Elements(size) => size,
+        }
+    }
+}
+
+/// One key above the thresholds
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct BigKey {
+    pub key: String,
+    pub size: KeySize,
+}
+
+/// Outcome of a scan
+#[derive(Default, Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct BigKeyReport {
+    /// Keys measured
+    pub scanned: u64,
+    /// Keys above the thresholds, biggest first
+    pub keys: Vec<BigKey>,
+}
+
+/// Scan from a blocking task on its own pooled connection
+pub(crate) fn spawn(
+    pool: &Pool<RedisManager>,
+    redis: &Redis,
+    event: RedisBigKeyScan,
+    sender: AnswerSender,
+) {
+    let pool = pool.clone();
+    let urls = redis.urls.clone();
+    let (hash_trace_keys, actor) = (redis.hash_trace_keys, redis.own_distributor());
+    task::spawn_blocking(move || {
+        let result = checkout(&pool)
+            .map_err(|e| RedisError::Command(e.to_string()))
+            .and_then(|mut conn| {
+                trace::command("memory", &event.pattern, hash_trace_keys, || {
+                    big_keys(&mut conn, &urls, &event)
+                })
+                .map_err(|e| RedisError::Command(e.to_string()))
+            });
+        if let Err(e) = &result {
+            Redis::report_error(actor, "BigKeys", e);
+        }
+        // The caller may be gone already
+        let _ = sender.reply(result);
+    });
+}
+
+/// Measure the values of the in-memory backend, every one is a string
+pub(crate) fn run_memory(
+    backend: &mut MemoryBackend,
+    event: RedisBigKeyScan,
+    sender: AnswerSender,
+) {
+    let result = backend
+        .keys(&event.pattern)
+        .into_iter()
+        .take(event.sample)
+        .map(|key| {
+            let size = backend
+                .get(&key)?
+                .map(|value| KeySize::Bytes(value.len() as u64));
+            Ok((key, size))
+        })
+        .collect::<RedisResult<Vec<_>>>()
+        .map(|sizes| report(sizes, &event))
+        .map_err(|e| RedisError::Command(e.to_string()));
+    // The caller may be gone already
+    let _ = sender.reply(result);
+}
+
+fn big_keys(
+    conn: &mut RedisConnection,
+    urls: &[String],
+    event: &RedisBigKeyScan,
+) -> RedisResult<BigKeyReport> {
+    let mut throttle = Throttle::new(event.per_second);
+    let mut sizes = vec![];
+    // Switched to lengths once a node refuses `MEMORY USAGE`
+    let mut memory_usage = true;
+    scan::scan_masters(conn, urls, &event.pattern, BATCH, |_, node_conn, keys| {
+        let keys = &keys[..keys.len().min(event.sample - sizes.len())];
+        let measured = if memory_usage {
+            match usage(node_conn, keys) {
+                Err(e) if unavailable(&e) => {
+                    memory_usage = false;
+                    lengths(node_conn, keys)?
+                }
+                measured => measured?,
+            }
+        } else {
+            lengths(node_conn, keys)?
+        };
+        sizes.extend(keys.iter().cloned().zip(measured));
+        throttle.wait(keys.len());
+        Ok(if sizes.len() < event.sample {
+            ControlFlow::Continue(())
+        } else {
+            ControlFlow::Break(())
+        })
+    })?;
+    Ok(report(sizes, event))
+}
+
+// `MEMORY USAGE` of keys held by one node, `None` for keys deleted since the scan saw them
+fn usage(node_conn: &mut Connection, keys: &[String]) -> RedisResult<Vec<Option<KeySize>>> {
+    let mut pipe = redis::pipe();
+    for key in keys {
+        pipe.cmd("MEMORY").arg("USAGE").arg(key);
+    }
+    let usage: Vec<Option<u64>> = pipe.query(node_conn)?;
+    Ok(usage
+        .into_iter()
+        .map(|bytes| bytes.map(KeySize::Bytes))
+        .collect())
+}
+
+// Length of keys held by one node, by type
+fn lengths(node_conn: &mut Connection, keys: &[String]) -> RedisResult<Vec<Option<KeySize>>> {
+    let mut types = redis::pipe();
+    for key in keys {
+        types.cmd("TYPE").arg(key);
+    }
+    let types: Vec<String> = types.query(node_conn)?;
+
+    let mut pipe = redis::pipe();
+    let mut measured = vec![];
+    for (key, kind) in keys.iter().zip(&types) {
+        let (command, bytes) = match kind.as_str() {
+            "string" => ("STRLEN", true),
+            "list" => ("LLEN", false),
+            "set" => ("SCARD", false),
+            "zset" => ("ZCARD", false),
+            "hash" => ("HLEN", false),
+            "stream" => ("XLEN", false),
+            // Deleted since the scan saw it, or a module type
+            _ => {
+                measured.push(None);
+                continue;
+            }
+        };
+        pipe.cmd(command).arg(key);
+        measured.push(Some(bytes));
+    }
+    let mut lengths = pipe.query::<Vec<u64>>(node_conn)?.into_iter();
+    Ok(measured
+        .into_iter()
+        .map(|bytes| {
+            let bytes = bytes?;
+            let length = lengths.next()?;
+            Some(if bytes {
+                KeySize::Bytes(length)
+            } else {
+                KeySize::Elements(length)
+            })
+        })
+        .collect())
+}
+
+// Whether the node refused `MEMORY USAGE` rather than failed
+fn unavailable(error: &redis::RedisError) -> bool {
+    matches!(
+        error.kind(),
+        ErrorKind::ResponseError | ErrorKind::ExtensionError
+    )
+}
+
+fn report(sizes: Vec<(String, Option<KeySize>)>, event: &RedisBigKeyScan) -> BigKeyReport {
+    let scanned = sizes.len() as u64;
+    let mut keys: Vec<BigKey> = sizes
+        .into_iter()
+        .filter_map(|(key, size)| Some(BigKey { key, size: size? }))
+        .filter(|big| match big.size {
+            KeySize::Bytes(bytes) => bytes > event.max_bytes,
+            KeySize::Elements(elements) => elements > event.max_elements,
+        })
+        .collect();
+    keys.sort_by_key(|big| Reverse(big.size.value()));
+    BigKeyReport { scanned, keys }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_above_either_threshold_are_reported_biggest_first() {
+        let scan = RedisBigKeyScan {
+            max_bytes: 100,
+            max_elements: 10,
+            ..Default::default()
+        };
+        let sizes = vec![
+            ("small".to_owned(), Some(KeySize::Bytes(100))),
+            ("blob".to_owned(), Some(KeySize::Bytes(4096))),
+            ("queue".to_owned(), Some(KeySize::Elements(500))),
+            ("set".to_owned(), Some(KeySize::Elements(10))),
+            ("gone".to_owned(), None),
+        ];
+        let report = report(sizes, &scan);
+        assert_eq!(5, report.scanned);
+        assert_eq!(
+            vec![
+                BigKey {
+                    key: "blob".to_owned(),
+                    size: KeySize::Bytes(4096)
+                },
+                BigKey {
+                    key: "queue".to_owned(),
+                    size: KeySize::Elements(500)
+                }
+            ],
+            report.keys
+        );
+    }
+}
@@ -0,0 +1,184 @@
+use std::time::Duration;
+
+use redis::{ErrorKind, RedisResult};
+use serde::{Deserialize, Serialize};
+
+use super::backend::RedisBackend;
+
+/// Prefix marking a value as the manifest of a chunked value
+const MANIFEST_MAGIC: &[u8] = b"\x00redis-actor:chunked\x00";
+
+/// Bytes read from the head of a key to detect a manifest before overwriting it
+const MANIFEST_PEEK: isize = 255;
+
+/// Large-value chunking settings, values above `threshold` bytes are split into
+/// `key:chunk:N` keys of `chunk_size` bytes and `key` holds a manifest.
+///
+/// Writes are not atomic, a read racing a write of the same key may see a mix of both.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ChunkingConfig {
+    pub threshold: usize,
+    pub chunk_size: usize,
+}
+
+impl Default for ChunkingConfig {
+    fn default() -> Self {
+        Self {
+            threshold: 1 << 20,
+            chunk_size: 512 << 10,
+        }
+    }
+}
+
+/// Stored under the original key in place of a chunked value
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+struct Manifest {
+    chunks: usize,
+    len: usize,
+}
+
+impl Manifest {
+    fn parse(value: &[u8]) -> Option<Self> {
+        serde_json::from_slice(value.strip_prefix(MANIFEST_MAGIC)?).ok()
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut value = MANIFEST_MAGIC.to_vec();
+        value.extend(serde_json::to_vec(self).expect("manifest serializes"));
+        value
+    }
+}
+
+pub(crate) fn chunk_key(key: &str, n: usize) -> String {
+    format!("{key}:chunk:{n}")
+}
+
+/// Replace a manifest read by `GET` with the reassembled value
+pub(crate) fn resolve(
+    conn: &mut dyn RedisBackend,
+    key: &str,
+    value: Vec<u8>,
+) -> RedisResult<Vec<u8>> {
+    let Some(manifest) = Manifest::parse(&value) else {
+        return Ok(value);
+    };
+    let mut value = Vec::with_capacity(manifest.len);
+    for n in 0..manifest.chunks {
+        let chunk = conn.get(&chunk_key(key, n))?;
+        let chunk = chunk.ok_or((ErrorKind::TypeError, "chunk of a chunked value is missing"))?;
+        value.extend(chunk);
+    }
+    if value.len() != manifest.len {
+        return Err((ErrorKind::TypeError, "chunked value has the wrong length").into());
+    }
+    Ok(value)
+}
+
+/// `SET`, splitting the value into chunks above the threshold and removing the chunks an
+/// overwritten value no longer needs. Chunks and manifest are written with the same `ttl`.
+pub(crate) fn set(
+    conn: &mut dyn RedisBackend,
+    key: &str,
+    value: &[u8],
+    ttl: Option<Duration>,
+    config: ChunkingConfig,
+) -> RedisResult<()> {
+    let head = conn.getrange(key, 0, MANIFEST_PEEK)?;
+    let old_chunks = Manifest::parse(&head).map_or(0, |manifest| manifest.chunks);
+
+    let mut chunks = 0;
+    if value.len() > config.threshold {
+        // Chunks first, the manifest then only points at complete chunks
+        for (n, chunk) in value.chunks(config.chunk_size.max(1)).enumerate() {
+            conn.set(&chunk_key(key, n), chunk, ttl)?;
+            chunks += 1;
+        }
+        let manifest = Manifest {
+            chunks,
+            len: value.len(),
+        };
+        conn.set(key, &manifest.encode(), ttl)?;
+    } else {
+        conn.set(key, value, ttl)?;
+    }
+
+    remove_chunks(conn, key, chunks..old_chunks)
+}
+
+/// `GETEX` re-applying `ttl` to the key and, for a chunked value, to every chunk before
+/// resolving it
+pub(crate) fn getex(
+    conn: &mut dyn RedisBackend,
+    key: &str,
+    ttl: Duration,
+) -> RedisResult<Option<Vec<u8>>> {
+    let Some(value) = conn.getex(key, ttl)? else {
+        return Ok(None);
+    };
+    if let Some(manifest) = Manifest::parse(&value) {
+        for n in 0..manifest.chunks {
+            conn.expire(&chunk_key(key, n), ttl)?;
+        }
+    }
+    resolve(conn, key, value).map(Some)
+}
+
+/// `PEXPIRE` of a key and, for a chunked value, of every chunk, whether the key exists
+pub(crate) fn expire(conn: &mut dyn RedisBackend, key: &str, ttl: Duration) -> RedisResult<bool> {
+    let head = conn.getrange(key, 0, MANIFEST_PEEK)?;
+    if let Some(manifest) = Manifest::parse(&head) {
+        // Chunks first, they never outlive the manifest pointing at them
+        for n in 0..manifest.chunks {
+            conn.expire(&chunk_key(key, n), ttl)?;
+        }
+    }
+    conn.expire(key, ttl)
+}
+
+/// Keys holding the chunks of the value at `key` in order, `None` for a value not chunked
+pub(crate) fn chunk_keys(
+    conn: &mut dyn RedisBackend,
+    key: &str,
+) -> RedisResult<Option<Vec<String>>> {
+    let head = conn.getrange(key, 0, MANIFEST_PEEK)?;
+    Ok(Manifest::parse(&head)
+        .map(|manifest| (0..manifest.chunks).map(|n| chunk_key(key, n)).collect()))
+}
+
+/// Remove every chunk of the chunked value at `key`, the key itself is kept
+pub(crate) fn remove_all_chunks(conn: &mut dyn RedisBackend, key: &str) -> RedisResult<()> {
+    let head = conn.getrange(key, 0, MANIFEST_PEEK)?;
+    match Manifest::parse(&head) {
+        Some(manifest) => remove_chunks(conn, key, 0..manifest.chunks),
+        None => Ok(()),
+    }
+}
+
+fn remove_chunks(
+    conn: &mut dyn RedisBackend,
+    key: &str,
+    chunks: std::ops::Range<usize>,
+) -> RedisResult<()> {
+    // One key per command, chunks live in different slots
+    for n in chunks {
+        conn.del(&chunk_key(key, n))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn manifests_are_recognized() {
+        let manifest = Manifest {
+            chunks: 3,
+            len: 1_200_000,
+        };
+
+        assert_eq!(Some(manifest), Manifest::parse(&manifest.encode()));
+        assert_eq!(None, Manifest::parse(br#"{"chunks":3,"len":1200000}"#));
+        assert_eq!("report:chunk:2", chunk_key("report", 2));
+    }
+}
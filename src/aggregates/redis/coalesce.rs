@@ -0,0 +1,184 @@
+//! Coalescing of identical concurrent reads.
+//!
+//! The first caller reading a key runs the read. Callers asking for the same key while it is in
+//! flight wait for it and share its result instead of sending their own, so a hot key read by
+//! many callers at once costs one `GET`. A caller joining a flight may see a value written after
+//! the read was sent missing, like any read racing a write.
+
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Condvar, Mutex},
+};
+
+/// Reads in flight by key, sharing their result of type `V` with the callers waiting on them
+pub struct Coalescer<V> {
+    flights: Mutex<BTreeMap<String, Arc<Flight<V>>>>,
+}
+
+enum Landing<V> {
+    Pending,
+    Done(V),
+    /// The read panicked, waiting callers run their own
+    Abandoned,
+}
+
+struct Flight<V> {
+    landing: Mutex<Landing<V>>,
+    landed: Condvar,
+}
+
+impl<V: Clone> Coalescer<V> {
+    pub const fn new() -> Self {
+        Self {
+            flights: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Run `read` for `key`, or wait for the read of `key` already in flight and return its
+    /// result
+    pub fn run(&self, key: &str, read: impl FnOnce() -> V) -> V {
+        let (flight, leader) = {
+            let mut flights = self.flights.lock().unwrap();
+            match flights.get(key) {
+                Some(flight) => (flight.clone(), false),
+                None => {
+                    let flight = Arc::new(Flight {
+                        landing: Mutex::new(Landing::Pending),
+                        landed: Condvar::new(),
+                    });
+                    flights.insert(key.to_owned(), flight.clone());
+                    (flight, true)
+                }
+            }
+        };
+        if !leader {
+            super::metrics::record_coalesced_query();
+            return flight.wait().unwrap_or_else(read);
+        }
+
+        let _landing = Land {
+            coalescer: self,
+            key,
+            flight: &flight,
+        };
+        let value = read();
+        *flight.landing.lock().unwrap() = Landing::Done(value.clone());
+        value
+    }
+
+    /// Keys with a read in flight
+    pub fn in_flight(&self) -> usize {
+        self.flights.lock().unwrap().len()
+    }
+}
+
+impl<V: Clone> Default for Coalescer<V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<V: Clone> Flight<V> {
+    // Result of the flight, `None` when it was abandoned
+    fn wait(&self) -> Option<V> {
+        let landing = self.landing.lock().unwrap();
+        let landing = self
+            .landed
+            .wait_while(landing, |landing| matches!(landing, Landing::Pending))
+            .unwrap();
+        match &*landing {
+            Landing::Done(value) => Some(value.clone()),
+            _ => None,
+        }
+    }
+}
+
+// Ends the flight of the leader, even when its read panics
+struct Land<'a, V> {
+    coalescer: &'a Coalescer<V>,
+    key: &'a str,
+    flight: &'a Arc<Flight<V>>,
+}
+
+impl<V> Drop for Land<'_, V> {
+    fn drop(&mut self) {
+        // Callers arriving from now on start a new read
+        self.coalescer
+            .flights
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(self.key);
+        let mut landing = self
+            .flight
+            .landing
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        if matches!(*landing, Landing::Pending) {
+            *landing = Landing::Abandoned;
+        }
+        self.flight.landed.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{atomic::AtomicUsize, atomic::Ordering, Barrier},
+        thread,
+        time::Duration,
+    };
+
+    use super::*;
+
+    #[test]
+    fn concurrent_reads_of_a_key_run_once() {
+        let coalescer = Coalescer::new();
+        let reads = AtomicUsize::new(0);
+        let started = Barrier::new(2);
+
+        thread::scope(|scope| {
+            let leader = scope.spawn(|| {
+                coalescer.run("hot", || {
+                    started.wait();
+                    // Long enough for the others to join
+                    thread::sleep(Duration::from_millis(200));
+                    reads.fetch_add(1, Ordering::SeqCst)
+                })
+            });
+            started.wait();
+            let followers: Vec<_> = (0..4)
+                .map(|_| {
+                    scope.spawn(|| coalescer.run("hot", || reads.fetch_add(1, Ordering::SeqCst)))
+                })
+                .collect();
+            assert_eq!(0, leader.join().unwrap());
+            for follower in followers {
+                assert_eq!(0, follower.join().unwrap());
+            }
+        });
+        assert_eq!(1, reads.load(Ordering::SeqCst));
+        assert_eq!(0, coalescer.in_flight());
+        // The flight is over, the next read goes out
+        assert_eq!(7, coalescer.run("hot", || 7));
+    }
+
+    #[test]
+    fn a_panicking_read_lets_waiters_read_themselves() {
+        let coalescer = Coalescer::new();
+        let started = Barrier::new(2);
+
+        thread::scope(|scope| {
+            let leader = scope.spawn(|| {
+                coalescer.run("hot", || -> u8 {
+                    started.wait();
+                    thread::sleep(Duration::from_millis(200));
+                    panic!("read failed")
+                })
+            });
+            started.wait();
+            assert_eq!(2, coalescer.run("hot", || 2));
+            assert!(leader.join().is_err());
+        });
+        assert_eq!(0, coalescer.in_flight());
+    }
+}
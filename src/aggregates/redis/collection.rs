@@ -0,0 +1,240 @@
+use bytes::Bytes;
+use redis::{from_redis_value, Cmd, RedisResult, Value};
+use serde::{Deserialize, Serialize};
+
+use super::typed::{items, TypedCommand};
+
+/// Type of a collection key
+#[derive(Default, Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum CollectionKind {
+    #[default]
+    List,
+    Set,
+    SortedSet,
+    Hash,
+}
+
+/// Number of items of a collection (`LLEN`, `SCARD`, `ZCARD` or `HLEN`), 0 for a missing key
+#[derive(Default, Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RedisLen {
+    pub key: String,
+    pub kind: CollectionKind,
+}
+
+impl TypedCommand for RedisLen {
+    type Reply = u64;
+    const OP: &'static str = "len";
+    const READ_ONLY: bool = true;
+
+    fn key(&self) -> &str {
+        &self.key
+    }
+
+    fn cmd(&self) -> Cmd {
+        let name = match self.kind {
+            CollectionKind::List => "LLEN",
+            CollectionKind::Set => "SCARD",
+            CollectionKind::SortedSet => "ZCARD",
+            CollectionKind::Hash => "HLEN",
+        };
+        let mut cmd = redis::cmd(name);
+        cmd.arg(&self.key);
+        cmd
+    }
+
+    fn parse(&self, value: &Value) -> RedisResult<u64> {
+        from_redis_value(value)
+    }
+}
+
+/// `LPOS`, replied with the indexes of matching elements, at most one without `count`
+#[derive(Default, Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RedisLPos {
+    pub key: String,
+    pub element: Bytes,
+    /// Skip to the nth match, negative ranks search from the tail
+    #[serde(default)]
+    pub rank: Option<i64>,
+    /// Number of matches returned, 0 for all of them
+    #[serde(default)]
+    pub count: Option<usize>,
+    /// Number of elements compared at most
+    #[serde(default)]
+    pub max_len: Option<usize>,
+}
+
+impl TypedCommand for RedisLPos {
+    type Reply = Vec<u64>;
+    const OP: &'static str = "lpos";
+    const READ_ONLY: bool = true;
+
+    fn key(&self) -> &str {
+        &self.key
+    }
+
+    fn cmd(&self) -> Cmd {
+        let mut cmd = redis::cmd("LPOS");
+        cmd.arg(&self.key).arg(&self.element[..]);
+        if let Some(rank) = self.rank {
+            cmd.arg("RANK").arg(rank);
+        }
+        if let Some(count) = self.count {
+            cmd.arg("COUNT").arg(count);
+        }
+        if let Some(max_len) = self.max_len {
+            cmd.arg("MAXLEN").arg(max_len);
+        }
+        cmd
+    }
+
+    fn parse(&self, value: &Value) -> RedisResult<Vec<u64>> {
+        items(self.count, value)
+    }
+}
+
+/// `LSET` of the element at `index`, negative indexes count from the tail
+#[derive(Default, Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RedisLSet {
+    pub key: String,
+    pub index: i64,
+    pub element: Bytes,
+}
+
+impl TypedCommand for RedisLSet {
+    type Reply = ();
+    const OP: &'static str = "lset";
+    const READ_ONLY: bool = false;
+
+    fn key(&self) -> &str {
+        &self.key
+    }
+
+    fn cmd(&self) -> Cmd {
+        let mut cmd = redis::cmd("LSET");
+        cmd.arg(&self.key).arg(self.index).arg(&self.element[..]);
+        cmd
+    }
+
+    fn parse(&self, value: &Value) -> RedisResult<()> {
+        from_redis_value(value)
+    }
+}
+
+/// `LREM`, replied with the number of elements removed.
+///
+/// A positive `count` removes from the head, a negative one from the tail and 0 removes all.
+#[derive(Default, Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RedisLRem {
+    pub key: String,
+    pub count: i64,
+    pub element: Bytes,
+}
+
+impl TypedCommand for RedisLRem {
+    type Reply = u64;
+    const OP: &'static str = "lrem";
+    const READ_ONLY: bool = false;
+    const IDEMPOTENT: bool = false;
+
+    fn key(&self) -> &str {
+        &self.key
+    }
+
+    fn cmd(&self) -> Cmd {
+        let mut cmd = redis::cmd("LREM");
+        cmd.arg(&self.key).arg(self.count).arg(&self.element[..]);
+        cmd
+    }
+
+    fn parse(&self, value: &Value) -> RedisResult<u64> {
+        from_redis_value(value)
+    }
+}
+
+/// Side of the pivot `LINSERT` inserts on
+#[derive(Default, Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum ListPosition {
+    #[default]
+    Before,
+    After,
+}
+
+/// `LINSERT` next to the first `pivot`, replied with the new length, -1 when the pivot is
+/// missing and 0 when the key is
+#[derive(Default, Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RedisLInsert {
+    pub key: String,
+    pub position: ListPosition,
+    pub pivot: Bytes,
+    pub element: Bytes,
+}
+
+impl TypedCommand for RedisLInsert {
+    type Reply = i64;
+    const OP: &'static str = "linsert";
+    const READ_ONLY: bool = false;
+    const IDEMPOTENT: bool = false;
+
+    fn key(&self) -> &str {
+        &self.key
+    }
+
+    fn cmd(&self) -> Cmd {
+        let position = match self.position {
+            ListPosition::Before => "BEFORE",
+            ListPosition::After => "AFTER",
+        };
+        let mut cmd = redis::cmd("LINSERT");
+        cmd.arg(&self.key)
+            .arg(position)
+            .arg(&self.pivot[..])
+            .arg(&self.element[..]);
+        cmd
+    }
+
+    fn parse(&self, value: &Value) -> RedisResult<i64> {
+        from_redis_value(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aggregates::redis::typed::args;
+
+    #[test]
+    fn lengths_use_the_command_of_the_kind() {
+        let len = RedisLen {
+            key: "jobs".to_owned(),
+            kind: CollectionKind::SortedSet,
+        };
+        assert_eq!(vec!["ZCARD", "jobs"], args(&len.cmd()));
+    }
+
+    #[test]
+    fn list_edits_are_built_and_parsed() {
+        let lpos = RedisLPos {
+            key: "pending".to_owned(),
+            element: Bytes::from("job-7"),
+            rank: Some(-1),
+            ..Default::default()
+        };
+        assert_eq!(
+            vec!["LPOS", "pending", "job-7", "RANK", "-1"],
+            args(&lpos.cmd())
+        );
+        assert_eq!(vec![3], lpos.parse(&Value::Int(3)).unwrap());
+        assert!(lpos.parse(&Value::Nil).unwrap().is_empty());
+
+        let insert = RedisLInsert {
+            key: "pending".to_owned(),
+            position: ListPosition::After,
+            pivot: Bytes::from("job-7"),
+            element: Bytes::from("job-8"),
+        };
+        assert_eq!(
+            vec!["LINSERT", "pending", "AFTER", "job-7", "job-8"],
+            args(&insert.cmd())
+        );
+    }
+}
@@ -1,6 +1,85 @@
+use redis::{ConnectionInfo, IntoConnectionInfo};
+
+use super::error::RedisError;
+
 /// Commands for redis actor
 #[derive(Debug)]
 pub enum RedisCommand {
+    /// Move to other urls, sent by `reconnect` through `RedisControl::Reconnect`
     ReconnectRedisServer { urls: Vec<String> },
+    /// The connection to `urls` is open, data commands are served
     ConnectRedisServer { urls: Vec<String> },
+    /// The connection was lost, data commands are refused while it is retried
+    DisconnectRedisServer { error: String },
+    /// Connection retries ran out, nothing is retried until the next reconnect
+    AbandonRedisServer { error: String, attempts: u32 },
+    /// Move to the secondary `urls`, sent by the writer once the cluster stayed unhealthy
+    FailOverRedisServer { urls: Vec<String> },
+}
+
+impl RedisCommand {
+    /// Check the command payload before any event is produced
+    pub fn validate(&self) -> Result<(), RedisError> {
+        match self {
+            RedisCommand::ReconnectRedisServer { urls }
+            | RedisCommand::ConnectRedisServer { urls }
+            | RedisCommand::FailOverRedisServer { urls } => validate_urls(urls),
+            RedisCommand::DisconnectRedisServer { .. }
+            | RedisCommand::AbandonRedisServer { .. } => Ok(()),
+        }
+    }
+}
+
+/// Every url must parse and all of them must share the same credentials
+pub fn validate_urls(urls: &[String]) -> Result<(), RedisError> {
+    if urls.is_empty() {
+        return Err(RedisError::EmptyUrls);
+    }
+
+    let infos = urls
+        .iter()
+        .map(|url| {
+            url.as_str()
+                .into_connection_info()
+                .map_err(|e| RedisError::MalformedUrl {
+                    url: url.clone(),
+                    reason: e.to_string(),
+                })
+        })
+        .collect::<Result<Vec<ConnectionInfo>, _>>()?;
+
+    let auth = |info: &ConnectionInfo| (info.redis.username.clone(), info.redis.password.clone());
+    if infos.iter().any(|info| auth(info) != auth(&infos[0])) {
+        return Err(RedisError::ConflictingAuth);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn urls_are_validated() {
+        assert_eq!(Err(RedisError::EmptyUrls), validate_urls(&[]));
+        assert!(matches!(
+            validate_urls(&["not a url".to_owned()]),
+            Err(RedisError::MalformedUrl { .. })
+        ));
+        assert_eq!(
+            Err(RedisError::ConflictingAuth),
+            validate_urls(&[
+                "redis://:first@127.0.0.1:30001".to_owned(),
+                "redis://:second@127.0.0.1:30002".to_owned(),
+            ])
+        );
+        assert_eq!(
+            Ok(()),
+            validate_urls(&[
+                "redis://127.0.0.1:30001".to_owned(),
+                "redis://127.0.0.1:30002".to_owned(),
+            ])
+        );
+    }
 }
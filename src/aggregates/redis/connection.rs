@@ -0,0 +1,43 @@
+//! Connection of the actor, picked at compile time.
+//!
+//! With the `cluster` feature the actor talks to a Redis Cluster: connections discover the nodes
+//! from the configured urls and follow slot redirections. Without it, to the single node at the
+//! first url, and the cluster client is not compiled in.
+
+use redis::RedisResult;
+
+/// Connection to the configured Redis, a cluster connection with the `cluster` feature
+#[cfg(feature = "cluster")]
+pub type RedisConnection = redis::cluster::ClusterConnection;
+/// Connection to the configured Redis, a single node without the `cluster` feature
+#[cfg(not(feature = "cluster"))]
+pub type RedisConnection = redis::Connection;
+
+/// Pipeline sent on a `RedisConnection`
+#[cfg(feature = "cluster")]
+pub(crate) type Pipeline = redis::cluster::ClusterPipeline;
+#[cfg(not(feature = "cluster"))]
+pub(crate) type Pipeline = redis::Pipeline;
+
+/// Connect to the cluster behind `urls`
+#[cfg(feature = "cluster")]
+pub(crate) fn connect(urls: &[String]) -> RedisResult<RedisConnection> {
+    redis::cluster::ClusterClientBuilder::new(urls.to_vec())
+        .build()?
+        .get_connection()
+}
+
+/// Connect to the node at the first of `urls`
+#[cfg(not(feature = "cluster"))]
+pub(crate) fn connect(urls: &[String]) -> RedisResult<RedisConnection> {
+    let url = urls.first().ok_or((
+        redis::ErrorKind::InvalidClientConfig,
+        "no url to connect to",
+    ))?;
+    redis::Client::open(url.as_str())?.get_connection()
+}
+
+/// Empty pipeline
+pub(crate) fn pipe() -> Pipeline {
+    Pipeline::new()
+}
@@ -0,0 +1,78 @@
+//! Priority lane for control messages.
+//!
+//! Control messages skip the mailbox: the writer of every instance owns a channel it checks
+//! before taking the next message, so a reconnect or a shutdown is handled right after the
+//! message in progress however many data commands are queued.
+
+use std::{
+    collections::HashMap,
+    sync::{Mutex, OnceLock},
+};
+
+use bastion::prelude::Distributor;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{
+    mpsc::{self, UnboundedReceiver, UnboundedSender},
+    oneshot,
+};
+
+use super::{error::RedisError, Redis};
+
+/// Message handled by the writer ahead of its mailbox
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum RedisControl {
+    /// Move to `urls`, e.g. the same nodes with rotated credentials
+    Reconnect { urls: Vec<String> },
+    /// Send the writes batched for the pipeline now
+    Flush,
+    /// Stop the writer, queued messages are dropped and batched writes sent first
+    Shutdown,
+}
+
+/// Control message waiting for the writer, answered once handled
+#[derive(Debug)]
+pub(crate) struct ControlRequest {
+    pub(crate) control: RedisControl,
+    reply: oneshot::Sender<Result<(), RedisError>>,
+}
+
+impl ControlRequest {
+    pub(crate) fn reply(self, result: Result<(), RedisError>) {
+        // The caller may be gone already
+        let _ = self.reply.send(result);
+    }
+}
+
+type Lanes = Mutex<HashMap<Distributor, UnboundedSender<ControlRequest>>>;
+
+// Lane of every running writer by its distributor
+fn lanes() -> &'static Lanes {
+    static LANES: OnceLock<Lanes> = OnceLock::new();
+    LANES.get_or_init(Default::default)
+}
+
+/// Open the lane of the writer answering on `actor`, replacing the one of a previous run
+pub(crate) fn open(actor: Distributor) -> UnboundedReceiver<ControlRequest> {
+    let (sender, lane) = mpsc::unbounded_channel();
+    lanes()
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .insert(actor, sender);
+    lane
+}
+
+/// Send `control` to the writer of the instance built with `name` and wait until it is handled,
+/// `RedisError::NotReady` when the writer is not running
+pub async fn send(name: Option<&str>, control: RedisControl) -> Result<(), RedisError> {
+    let (reply, handled) = oneshot::channel();
+    let request = ControlRequest { control, reply };
+    let sent = lanes()
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .get(&Redis::distributor_named(name))
+        .map(|lane| lane.send(request));
+    match sent {
+        Some(Ok(())) => handled.await.unwrap_or(Err(RedisError::NotReady)),
+        _ => Err(RedisError::NotReady),
+    }
+}
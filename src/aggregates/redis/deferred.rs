@@ -0,0 +1,135 @@
+//! Writes held while the connection is not ready.
+//!
+//! Inserts arriving before the actor is `Initialized`, or while it reconnects, are kept in a
+//! bounded queue and run in arrival order once the connection comes up. Asked inserts are only
+//! answered when they run. Past the bound, or when no queue is configured, asked inserts are
+//! refused with `RedisError::NotReady` and told ones are dropped and reported.
+
+use std::collections::VecDeque;
+
+use bastion::prelude::AnswerSender;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use super::{error::RedisError, metrics, multi::RedisMultiInsert, Blocking, Redis, RedisInsert};
+
+/// Pending write queue settings
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct PendingWritesConfig {
+    /// Inserts held at most, later ones are refused
+    pub capacity: usize,
+}
+
+impl Default for PendingWritesConfig {
+    fn default() -> Self {
+        Self { capacity: 1024 }
+    }
+}
+
+/// Inserts waiting for the connection, oldest first
+#[derive(Default)]
+pub(crate) struct PendingWrites(VecDeque<(RedisInsert, Option<AnswerSender>)>);
+
+impl PendingWrites {
+    /// Hold an insert, refusing it when the queue is full or disabled
+    pub(crate) fn defer(
+        &mut self,
+        redis: &Redis,
+        event: RedisInsert,
+        sender: Option<AnswerSender>,
+    ) {
+        if redis
+            .pending_writes
+            .as_ref()
+            .is_some_and(|config| self.0.len() < config.capacity)
+        {
+            self.0.push_back((event, sender));
+            metrics::record_pending_write(true);
+            return;
+        }
+
+        metrics::record_pending_write(false);
+        match sender {
+            Some(sender) => Redis::not_ready::<()>(sender),
+            None => {
+                warn!(
+                    key = event.key,
+                    "[REDIS] Insert dropped, the connection is not ready"
+                );
+                Redis::report_error(redis.own_distributor(), "NotReady", &RedisError::NotReady);
+            }
+        }
+    }
+
+    /// Hold every entry of a multi-key insert as its own insert
+    pub(crate) fn defer_many(&mut self, redis: &Redis, event: RedisMultiInsert) {
+        for (key, value) in event.entries {
+            let insert = RedisInsert {
+                key,
+                value,
+                ttl: None,
+                caller: event.caller.clone(),
+            };
+            self.defer(redis, insert, None);
+        }
+    }
+
+    /// Refuse every held insert, when the actor gives up connecting
+    pub(crate) fn refuse(&mut self) {
+        for (event, sender) in self.0.drain(..) {
+            match sender {
+                Some(sender) => Redis::not_ready::<()>(sender),
+                None => warn!(key = event.key, "[REDIS] Held insert dropped"),
+            }
+        }
+    }
+
+    /// Calls running every held insert in order, to be made once the actor is `Initialized`
+    pub(crate) fn replay(&mut self, redis: &Redis) -> Vec<Blocking> {
+        self.0
+            .drain(..)
+            .filter_map(|(event, sender)| redis.run_insert(event, sender))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use super::*;
+
+    fn insert(key: &str) -> RedisInsert {
+        RedisInsert {
+            key: key.to_owned(),
+            value: Bytes::from_static(b"value"),
+            ttl: None,
+            caller: None,
+        }
+    }
+
+    #[test]
+    fn inserts_are_held_up_to_the_capacity() {
+        let mut redis = Redis {
+            pending_writes: Some(PendingWritesConfig { capacity: 2 }),
+            ..Default::default()
+        };
+        let mut pending = PendingWrites::default();
+
+        pending.defer(&redis, insert("a"), None);
+        pending.defer(&redis, insert("b"), None);
+        pending.defer(&redis, insert("c"), None);
+        assert_eq!(2, pending.0.len());
+
+        redis.pending_writes = None;
+        pending.defer(&redis, insert("d"), None);
+        assert_eq!(2, pending.0.len());
+
+        let keys: Vec<_> = pending
+            .0
+            .iter()
+            .map(|(event, _)| event.key.as_str())
+            .collect();
+        assert_eq!(vec!["a", "b"], keys);
+    }
+}
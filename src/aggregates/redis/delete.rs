@@ -0,0 +1,216 @@
+use std::ops::ControlFlow;
+
+use bastion::prelude::{AnswerSender, Distributor};
+use r2d2::Pool;
+use redis::{Connection, RedisResult};
+use serde::{Deserialize, Serialize};
+use tokio::task;
+
+use super::{
+    audit::AuditLog,
+    backend::{MemoryBackend, RedisBackend},
+    chunked,
+    connection::{self, RedisConnection},
+    error::RedisError,
+    mirror::{Mirror, MirroredWrite},
+    multi::{fan_out, group_by_slot},
+    pool::{checkout, RedisManager},
+    scan, trace, Redis,
+};
+
+/// Keys requested per `SCAN` and unlinked per pipeline by default
+const DEFAULT_BATCH: usize = 500;
+
+/// Question deleting every key matching a glob-style `pattern` on every master, replied with
+/// `Result<u64, RedisError>` counting the keys deleted.
+///
+/// Keys are found with `SCAN`, never `KEYS`, and deleted with `UNLINK` in batches. Keys written
+/// while the scan runs may be missed.
+#[derive(Default, Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RedisDeleteByPattern {
+    pub pattern: String,
+    /// `SCAN COUNT` hint and keys per `UNLINK` pipeline, 500 when unset
+    #[serde(default)]
+    pub batch: Option<usize>,
+    /// Caller tag recorded by the audit log
+    #[serde(default)]
+    pub caller: Option<String>,
+}
+
+/// Question deleting `keys` at once, replied with `Result<u64, RedisError>` counting the keys
+/// that existed
+#[derive(Default, Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RedisDeleteMany {
+    pub keys: Vec<String>,
+    /// Caller tag recorded by the audit log
+    #[serde(default)]
+    pub caller: Option<String>,
+}
+
+/// Delete by pattern from a blocking task on its own pooled connection
+pub(crate) fn spawn(
+    pool: &Pool<RedisManager>,
+    redis: &Redis,
+    event: RedisDeleteByPattern,
+    sender: AnswerSender,
+) {
+    let pool = pool.clone();
+    let urls = redis.urls.clone();
+    let (hash_trace_keys, chunked) = (redis.hash_trace_keys, redis.chunking.is_some());
+    let (audit, actor) = (redis.audit.clone(), redis.own_distributor());
+    let mirror = redis.mirroring.clone();
+    task::spawn_blocking(move || {
+        let batch = event.batch.unwrap_or(DEFAULT_BATCH).max(1);
+        let result = checkout(&pool)
+            .map_err(|e| RedisError::Command(e.to_string()))
+            .and_then(|mut conn| {
+                trace::command("unlink", &event.pattern, hash_trace_keys, || {
+                    delete_by_pattern(&mut conn, &urls, &event.pattern, batch, chunked)
+                })
+                .map_err(|e| RedisError::Command(e.to_string()))
+            });
+
+        finish(&audit, mirror.as_ref(), actor, &event, result, sender);
+    });
+}
+
+/// Delete by pattern from the in-memory backend
+pub(crate) fn run_memory(
+    backend: &mut MemoryBackend,
+    redis: &Redis,
+    event: RedisDeleteByPattern,
+    sender: AnswerSender,
+) {
+    let keys = backend.keys(&event.pattern);
+    let result = delete_keys(backend, &keys, redis.chunking.is_some())
+        .map_err(|e| RedisError::Command(e.to_string()));
+    finish(
+        &redis.audit,
+        redis.mirroring.as_ref(),
+        redis.own_distributor(),
+        &event,
+        result,
+        sender,
+    );
+}
+
+fn finish(
+    audit: &AuditLog,
+    mirroring: Option<&Mirror>,
+    actor: Distributor,
+    event: &RedisDeleteByPattern,
+    result: Result<u64, RedisError>,
+    sender: AnswerSender,
+) {
+    audit.record(
+        "unlink",
+        &event.pattern,
+        0,
+        event.caller.as_deref(),
+        result.is_ok(),
+    );
+    match (&result, mirroring) {
+        (Ok(_), Some(mirror)) => mirror.send(MirroredWrite::DeleteByPattern(event.clone())),
+        (Err(e), _) => Redis::report_error(actor, "DeleteByPattern", e),
+        (Ok(_), None) => {}
+    }
+    // The caller may be gone already
+    let _ = sender.reply(result);
+}
+
+fn delete_by_pattern(
+    conn: &mut RedisConnection,
+    urls: &[String],
+    pattern: &str,
+    batch: usize,
+    chunked: bool,
+) -> RedisResult<u64> {
+    let mut deleted = 0;
+    scan::scan_masters(conn, urls, pattern, batch, |conn, node_conn, keys| {
+        if chunked {
+            // Chunks live in other slots, removed through the cluster connection
+            for key in keys {
+                chunked::remove_all_chunks(conn, key)?;
+            }
+        }
+        deleted += unlink(node_conn, keys)?;
+        Ok(ControlFlow::Continue(()))
+    })?;
+    Ok(deleted)
+}
+
+// One `UNLINK` per slot in a single pipeline, a node's keys span many slots
+fn unlink(conn: &mut Connection, keys: &[String]) -> RedisResult<u64> {
+    if keys.is_empty() {
+        return Ok(0);
+    }
+    let mut pipe = redis::pipe();
+    for indexes in group_by_slot(keys.iter().map(String::as_str)).into_values() {
+        pipe.cmd("UNLINK")
+            .arg(indexes.iter().map(|i| &keys[*i]).collect::<Vec<_>>());
+    }
+    let counts: Vec<u64> = pipe.query(conn)?;
+    Ok(counts.iter().sum())
+}
+
+/// UNLINK of `keys`, one pipeline per node with one command per slot
+pub(crate) fn unlink_many(
+    pool: &Pool<RedisManager>,
+    urls: &[String],
+    keys: &[String],
+    hash_trace_keys: bool,
+    chunked: bool,
+) -> RedisResult<u64> {
+    let groups = group_by_slot(keys.iter().map(String::as_str));
+
+    let label = format!("{} keys", keys.len());
+    let replies = trace::command("unlink", &label, hash_trace_keys, || {
+        fan_out(pool, urls, groups, |conn, groups| {
+            let mut pipe = connection::pipe();
+            for indexes in groups {
+                if chunked {
+                    for i in indexes {
+                        chunked::remove_all_chunks(conn, &keys[*i])?;
+                    }
+                }
+                pipe.cmd("UNLINK")
+                    .arg(indexes.iter().map(|i| &keys[*i]).collect::<Vec<_>>());
+            }
+            pipe.query::<Vec<u64>>(conn)
+        })
+    })?;
+    Ok(replies.iter().flat_map(|(_, counts)| counts).sum())
+}
+
+/// Audit every key of a `RedisDeleteMany` and report its failure to `actor`
+pub(crate) fn finish_many(
+    audit: &AuditLog,
+    mirroring: Option<&Mirror>,
+    actor: Distributor,
+    event: &RedisDeleteMany,
+    result: RedisResult<u64>,
+) -> Result<u64, RedisError> {
+    for key in &event.keys {
+        audit.record("unlink", key, 0, event.caller.as_deref(), result.is_ok());
+    }
+    if let (Ok(_), Some(mirror)) = (&result, mirroring) {
+        mirror.send(MirroredWrite::Delete(event.clone()));
+    }
+    result.map_err(|e| Redis::command_error(actor, e))
+}
+
+/// `DEL` key by key, removing the chunks of chunked values first
+pub(crate) fn delete_keys(
+    conn: &mut dyn RedisBackend,
+    keys: &[String],
+    chunked: bool,
+) -> RedisResult<u64> {
+    let mut deleted = 0;
+    for key in keys {
+        if chunked {
+            chunked::remove_all_chunks(conn, key)?;
+        }
+        deleted += u64::from(conn.del(key)?);
+    }
+    Ok(deleted)
+}
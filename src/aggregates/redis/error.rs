@@ -1,5 +1,123 @@
+use std::{collections::BTreeMap, time::Duration};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
 /// Errors for redis actor
-#[derive(Debug, Error)]
-pub enum RedisError {}
+#[derive(Debug, Clone, Error, PartialEq, Eq)]
+pub enum RedisError {
+    #[error("no cluster node urls were given")]
+    EmptyUrls,
+    #[error("malformed redis url `{url}`: {reason}")]
+    MalformedUrl { url: String, reason: String },
+    #[error("cluster node urls use different credentials")]
+    ConflictingAuth,
+    #[error("redis command failed: {0}")]
+    Command(String),
+    #[error("the redis connection is not initialized yet")]
+    NotReady,
+    #[error("no route rule matches the key `{0}`")]
+    NoRoute(String),
+    #[error("a route rule names the unknown cluster `{0}`")]
+    UnknownCluster(String),
+    #[error("unknown tenant `{0}`")]
+    UnknownTenant(String),
+    #[error("tenant `{0}` has an empty key prefix")]
+    EmptyTenantPrefix(String),
+    #[error("tenants `{0}` and `{1}` have overlapping key prefixes")]
+    OverlappingTenants(String, String),
+    #[error("the key `{0}` belongs to a tenant and is only reached on its behalf")]
+    TenantKey(String),
+    #[error("unknown keyspace `{0}`")]
+    UnknownKeyspace(String),
+    #[error("cannot encode or decode the value: {0}")]
+    Codec(String),
+    #[error("no cluster node matches {0}")]
+    UnknownNode(String),
+    #[error("failover needs a health check interval")]
+    FailoverWithoutHealthCheck,
+    #[error("no mirror is configured")]
+    MirrorDisabled,
+    #[error("the redis actor is draining, no new calls are accepted")]
+    Draining,
+    #[error("the value of `{key}` is {size} bytes, above the limit of {max}")]
+    ValueTooLarge {
+        key: String,
+        size: usize,
+        max: usize,
+    },
+}
+
+/// Errors starting the redis actor
+#[derive(Debug, Clone, Error, PartialEq, Eq)]
+pub enum RedisInitError {
+    #[error("invalid redis configuration: {0}")]
+    Config(#[from] RedisError),
+    #[error("cannot start the redis actor: {0}")]
+    Spawn(String),
+    #[error("the redis actor is already running with another configuration")]
+    AlreadyRunning,
+    #[error("cannot stop the redis actor: {0}")]
+    Stop(String),
+    #[error("the redis actor is not initialized after {waited:?}, last error: {last_error:?}")]
+    Timeout {
+        waited: Duration,
+        last_error: Option<String>,
+    },
+}
+
+/// Whether a failed command may succeed when sent again: a dropped connection, a failover or
+/// a node still loading its dataset
+pub(crate) fn is_transient(error: &redis::RedisError) -> bool {
+    error.is_io_error() || is_refused(error)
+}
+
+/// Whether the node refused the command for now without running it, during a failover or
+/// while loading its dataset
+pub(crate) fn is_refused(error: &redis::RedisError) -> bool {
+    use redis::ErrorKind;
+
+    matches!(
+        error.kind(),
+        ErrorKind::TryAgain
+            | ErrorKind::ClusterDown
+            | ErrorKind::MasterDown
+            | ErrorKind::BusyLoadingError
+    )
+}
+
+/// Most recent error seen by the actor
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct LastError {
+    pub kind: String,
+    pub error: String,
+    pub at: Option<DateTime<Utc>>,
+}
+
+/// Error counts per kind, kept in the aggregate state
+#[derive(Default, Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ErrorStats {
+    pub counts: BTreeMap<String, u64>,
+    pub last: Option<LastError>,
+}
+
+impl ErrorStats {
+    pub(crate) fn record(&mut self, kind: &str, error: &str, at: Option<DateTime<Utc>>) {
+        *self.counts.entry(kind.to_owned()).or_default() += 1;
+        self.last = Some(LastError {
+            kind: kind.to_owned(),
+            error: error.to_owned(),
+            at,
+        });
+    }
+
+    /// Total number of errors of every kind
+    pub fn total(&self) -> u64 {
+        self.counts.values().sum()
+    }
+}
+
+/// Question asking the actor for its `ErrorStats`
+#[derive(Default, Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RedisErrorStatsQuery;
@@ -1,27 +1,177 @@
-use cqrs_es::DomainEvent;
+use chrono::{DateTime, Utc};
+#[cfg(feature = "cqrs")]
+use cqrs_es::{
+    persist::{EventUpcaster, SemanticVersionEventUpcaster, SerializedEvent},
+    DomainEvent,
+};
 use serde::{Deserialize, Serialize};
+#[cfg(feature = "cqrs")]
+use serde_json::Value;
+
+/// Current schema version of `RedisEvent`, bump it whenever a variant changes shape
+pub const EVENT_VERSION: &str = "1.1";
 
 /// Events for redis actor
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum RedisEvent {
-    RedisServerReconnected { urls: Vec<String> },
-    RedisServerConnected { urls: Vec<String> },
+    RedisServerReconnected {
+        urls: Vec<String>,
+    },
+    RedisServerConnected {
+        urls: Vec<String>,
+    },
+    RedisServerDisconnected {
+        error: String,
+    },
+    RedisServerAbandoned {
+        error: String,
+        attempts: u32,
+    },
+    RedisServerFailedOver {
+        from: Vec<String>,
+        to: Vec<String>,
+    },
+    RedisErrorOccurred {
+        /// Error kind, e.g. `IoError` or `TryAgain`
+        kind: String,
+        error: String,
+        /// Missing on events recorded before 1.1
+        #[serde(default)]
+        at: Option<DateTime<Utc>>,
+    },
 }
 
+#[cfg(feature = "cqrs")]
 impl DomainEvent for RedisEvent {
     fn event_type(&self) -> String {
         match self {
-            RedisEvent::RedisServerReconnected { urls } => {
-                format!("Redis reconnect to cluster server: {:?}", urls)
-            }
-
-            RedisEvent::RedisServerConnected { urls } => {
-                format!("Redis connect to cluster server: {:?}", urls)
-            }
+            RedisEvent::RedisServerReconnected { .. } => "RedisServerReconnected".to_owned(),
+            RedisEvent::RedisServerConnected { .. } => "RedisServerConnected".to_owned(),
+            RedisEvent::RedisServerDisconnected { .. } => "RedisServerDisconnected".to_owned(),
+            RedisEvent::RedisServerAbandoned { .. } => "RedisServerAbandoned".to_owned(),
+            RedisEvent::RedisServerFailedOver { .. } => "RedisServerFailedOver".to_owned(),
+            RedisEvent::RedisErrorOccurred { .. } => "RedisErrorOccurred".to_owned(),
         }
     }
 
     fn event_version(&self) -> String {
-        "1.0".to_owned()
+        EVENT_VERSION.to_owned()
+    }
+}
+
+#[cfg(feature = "cqrs")]
+impl RedisEvent {
+    /// Upcasters migrating older persisted events to the current schema.
+    ///
+    /// Register a `SemanticVersionEventUpcaster` here for every schema change, keyed by the
+    /// event type and the version it migrates to, so events written by older versions keep
+    /// deserializing.
+    pub fn upcasters() -> Vec<Box<dyn EventUpcaster>> {
+        vec![
+            // 1.1 added the error kind
+            Box::new(SemanticVersionEventUpcaster::new(
+                "RedisErrorOccurred",
+                "1.1",
+                Box::new(|mut payload| {
+                    if let Some(Value::Object(fields)) = payload.get_mut("RedisErrorOccurred") {
+                        fields
+                            .entry("kind")
+                            .or_insert_with(|| Value::from("Unknown"));
+                    }
+                    payload
+                }),
+            )),
+        ]
+    }
+
+    /// Deserialize a persisted event, running every matching upcaster first
+    pub fn from_serialized(
+        event: SerializedEvent,
+        upcasters: &[Box<dyn EventUpcaster>],
+    ) -> Result<Self, serde_json::Error> {
+        let event = upcasters.iter().fold(event, |event, upcaster| {
+            if upcaster.can_upcast(&event.event_type, &event.event_version) {
+                upcaster.upcast(event)
+            } else {
+                event
+            }
+        });
+        serde_json::from_value(event.payload)
+    }
+}
+
+#[cfg(all(test, feature = "cqrs"))]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn serialized(event_version: &str, payload: Value) -> SerializedEvent {
+        SerializedEvent::new(
+            "redis".to_owned(),
+            1,
+            "redis".to_owned(),
+            "RedisServerConnected".to_owned(),
+            event_version.to_owned(),
+            payload,
+            Value::Null,
+        )
+    }
+
+    #[test]
+    fn current_events_round_trip() {
+        let event = RedisEvent::RedisServerConnected {
+            urls: vec!["redis://127.0.0.1:30001".to_owned()],
+        };
+        let payload = serde_json::to_value(&event).unwrap();
+
+        let restored = RedisEvent::from_serialized(
+            serialized(EVENT_VERSION, payload),
+            &RedisEvent::upcasters(),
+        )
+        .unwrap();
+        assert_eq!(event, restored);
+    }
+
+    #[test]
+    fn errors_before_1_1_get_a_kind() {
+        let mut event = serialized(
+            "1.0",
+            json!({ "RedisErrorOccurred": { "error": "connection refused" } }),
+        );
+        event.event_type = "RedisErrorOccurred".to_owned();
+
+        let restored = RedisEvent::from_serialized(event, &RedisEvent::upcasters()).unwrap();
+        assert_eq!(
+            RedisEvent::RedisErrorOccurred {
+                kind: "Unknown".to_owned(),
+                error: "connection refused".to_owned(),
+                at: None,
+            },
+            restored
+        );
+    }
+
+    #[test]
+    fn older_events_are_upcasted() {
+        // Pretend version 0.9 stored a single `url` instead of a list
+        let upcaster: Box<dyn EventUpcaster> = Box::new(SemanticVersionEventUpcaster::new(
+            "RedisServerConnected",
+            EVENT_VERSION,
+            Box::new(|payload| {
+                let url = payload["RedisServerConnected"]["url"].clone();
+                json!({ "RedisServerConnected": { "urls": [url] } })
+            }),
+        ));
+        let payload = json!({ "RedisServerConnected": { "url": "redis://127.0.0.1:30001" } });
+
+        let restored =
+            RedisEvent::from_serialized(serialized("0.9", payload), &[upcaster]).unwrap();
+        assert_eq!(
+            RedisEvent::RedisServerConnected {
+                urls: vec!["redis://127.0.0.1:30001".to_owned()]
+            },
+            restored
+        );
     }
 }
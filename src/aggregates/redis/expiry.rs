@@ -0,0 +1,277 @@
+//! Periodic audit of keys left without an expiration.
+//!
+//! Cache keys are expected to expire, a code path forgetting the TTL leaks them forever. Every
+//! run samples keys under the configured prefixes, checks their `PTTL` and reports the keys
+//! without one, or with one above the policy maximum.
+
+use std::{ops::ControlFlow, time::Duration};
+
+use bastion::prelude::Distributor;
+use chrono::{DateTime, Utc};
+use r2d2::Pool;
+use redis::{Connection, RedisResult};
+use serde::{Deserialize, Serialize};
+use tokio::task;
+use tracing::{error, warn};
+
+use super::{
+    connection::RedisConnection,
+    metrics,
+    pool::{checkout, RedisManager},
+    scan, Redis, Ttl,
+};
+
+/// Expiry audit settings
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ExpiryAuditConfig {
+    /// How often the audit runs
+    pub interval: Duration,
+    /// Key prefixes audited, every key when empty
+    #[serde(default)]
+    pub prefixes: Vec<String>,
+    /// Keys checked per prefix on each run
+    pub sample: usize,
+    /// Longest TTL allowed, any TTL passes when unset
+    #[serde(default)]
+    pub max_ttl: Option<Duration>,
+    /// Name of the distributor receiving every `ExpiryAuditReport`
+    #[serde(default)]
+    pub report_to: Option<String>,
+}
+
+impl Default for ExpiryAuditConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(600),
+            prefixes: vec![],
+            sample: 1000,
+            max_ttl: None,
+            report_to: None,
+        }
+    }
+}
+
+/// What is wrong with a key's expiration
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum ExpiryIssue {
+    /// The key never expires
+    NoTtl,
+    /// The key expires later than the policy maximum
+    AboveMax(Duration),
+}
+
+impl ExpiryIssue {
+    fn label(&self) -> &'static str {
+        match self {
+            Self::NoTtl => "no_ttl",
+            Self::AboveMax(_) => "above_max",
+        }
+    }
+}
+
+/// One key breaking the expiry policy
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ExpiryFinding {
+    pub key: String,
+    /// Configured prefix the key was sampled under
+    pub prefix: String,
+    pub issue: ExpiryIssue,
+}
+
+/// Result of one audit run
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ExpiryAuditReport {
+    pub at: DateTime<Utc>,
+    /// Keys whose TTL was checked
+    pub checked: u64,
+    pub findings: Vec<ExpiryFinding>,
+}
+
+/// Question replied with the `Option<ExpiryAuditReport>` of the last completed run
+#[derive(Default, Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RedisExpiryAuditQuery;
+
+/// Tick driving the periodic audit
+#[derive(Debug)]
+pub(crate) struct ExpiryAuditTick;
+
+/// Told back to the actor when a run ends, without a report when it failed
+#[derive(Debug)]
+pub(crate) struct ExpiryAuditDone(Option<ExpiryAuditReport>);
+
+/// Runs one audit at a time and keeps the last report
+#[derive(Debug, Default)]
+pub(crate) struct ExpiryAudit {
+    running: bool,
+    last: Option<ExpiryAuditReport>,
+}
+
+impl ExpiryAudit {
+    /// Start a run from a blocking task on its own pooled connection, unless one is running
+    pub(crate) fn start(&mut self, pool: &Pool<RedisManager>, redis: &Redis) {
+        let Some(config) = redis.expiry_audit.clone() else {
+            return;
+        };
+        if self.running {
+            warn!("[REDIS] Expiry audit still running, tick skipped");
+            return;
+        }
+        self.running = true;
+
+        let pool = pool.clone();
+        let (urls, actor) = (redis.urls.clone(), redis.own_distributor());
+        task::spawn_blocking(move || {
+            let report = checkout(&pool)
+                .map_err(|e| e.to_string())
+                .and_then(|mut conn| audit(&mut conn, &urls, &config).map_err(|e| e.to_string()));
+            let done = match report {
+                Ok(report) => ExpiryAuditDone(Some(report)),
+                Err(e) => {
+                    error!(error = %e, "expiry audit failed");
+                    Redis::report_error(actor, "ExpiryAudit", &e);
+                    ExpiryAuditDone(None)
+                }
+            };
+            if let Err(e) = actor.tell_one(done) {
+                warn!("[REDIS] Cannot report expiry audit: {e:?}");
+            }
+        });
+    }
+
+    /// Record the end of a run and deliver its report
+    pub(crate) fn finish(&mut self, done: ExpiryAuditDone, config: Option<&ExpiryAuditConfig>) {
+        self.running = false;
+        let Some(report) = done.0 else {
+            return;
+        };
+
+        for finding in &report.findings {
+            warn!(
+                key = finding.key,
+                prefix = finding.prefix,
+                issue = ?finding.issue,
+                "redis key breaks the expiry policy"
+            );
+            metrics::record_expiry_finding(finding.issue.label());
+        }
+        metrics::record_expiry_audit(report.checked, report.findings.len());
+
+        if let Some(name) = config.and_then(|config| config.report_to.as_deref()) {
+            if let Err(e) = Distributor::named(name).tell_one(report.clone()) {
+                warn!("[REDIS] Cannot deliver expiry audit report: {e:?}");
+            }
+        }
+        self.last = Some(report);
+    }
+
+    /// Report of the last completed run
+    pub(crate) fn last(&self) -> Option<ExpiryAuditReport> {
+        self.last.clone()
+    }
+}
+
+fn audit(
+    conn: &mut RedisConnection,
+    urls: &[String],
+    config: &ExpiryAuditConfig,
+) -> RedisResult<ExpiryAuditReport> {
+    let prefixes = if config.prefixes.is_empty() {
+        vec![String::new()]
+    } else {
+        config.prefixes.clone()
+    };
+
+    let mut checked = 0;
+    let mut findings = vec![];
+    for prefix in prefixes {
+        let pattern = format!("{}*", escape_glob(&prefix));
+        let mut sampled = 0;
+        scan::scan_masters(conn, urls, &pattern, 100, |_, node_conn, keys| {
+            let keys = &keys[..keys.len().min(config.sample - sampled)];
+            for (key, ttl) in keys.iter().zip(ttls(node_conn, keys)?) {
+                if let Some(issue) = issue(ttl, config.max_ttl) {
+                    findings.push(ExpiryFinding {
+                        key: key.clone(),
+                        prefix: prefix.clone(),
+                        issue,
+                    });
+                }
+            }
+            sampled += keys.len();
+            Ok(if sampled < config.sample {
+                ControlFlow::Continue(())
+            } else {
+                ControlFlow::Break(())
+            })
+        })?;
+        checked += sampled as u64;
+    }
+
+    Ok(ExpiryAuditReport {
+        at: Utc::now(),
+        checked,
+        findings,
+    })
+}
+
+// `PTTL` of keys held by one node
+fn ttls(node_conn: &mut Connection, keys: &[String]) -> RedisResult<Vec<Ttl>> {
+    let mut pipe = redis::pipe();
+    for key in keys {
+        pipe.cmd("PTTL").arg(key);
+    }
+    let ttls: Vec<i64> = pipe.query(node_conn)?;
+    Ok(ttls
+        .into_iter()
+        .map(|ttl| match ttl {
+            -2 => Ttl::NoKey,
+            ttl if ttl < 0 => Ttl::NoExpiry,
+            ttl => Ttl::Expires(Duration::from_millis(ttl as u64)),
+        })
+        .collect())
+}
+
+fn issue(ttl: Ttl, max_ttl: Option<Duration>) -> Option<ExpiryIssue> {
+    match ttl {
+        // Deleted since the scan saw it
+        Ttl::NoKey => None,
+        Ttl::NoExpiry => Some(ExpiryIssue::NoTtl),
+        Ttl::Expires(ttl) if max_ttl.is_some_and(|max| ttl > max) => {
+            Some(ExpiryIssue::AboveMax(ttl))
+        }
+        Ttl::Expires(_) => None,
+    }
+}
+
+// Match a prefix literally in a `SCAN MATCH` pattern
+fn escape_glob(prefix: &str) -> String {
+    let mut escaped = String::with_capacity(prefix.len());
+    for c in prefix.chars() {
+        if matches!(c, '*' | '?' | '[' | ']' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_without_ttl_or_above_the_maximum_are_findings() {
+        let max = Some(Duration::from_secs(3600));
+        let long = Duration::from_secs(7200);
+
+        assert_eq!(Some(ExpiryIssue::NoTtl), issue(Ttl::NoExpiry, max));
+        assert_eq!(
+            Some(ExpiryIssue::AboveMax(long)),
+            issue(Ttl::Expires(long), max)
+        );
+        assert_eq!(None, issue(Ttl::Expires(long), None));
+        assert_eq!(None, issue(Ttl::Expires(Duration::from_secs(60)), max));
+        assert_eq!(None, issue(Ttl::NoKey, max));
+        assert_eq!("cache\\[v1\\]:", escape_glob("cache[v1]:"));
+    }
+}
@@ -0,0 +1,252 @@
+//! Logical backups of selected keys.
+//!
+//! An export is written as JSON Lines, one `ExportRecord` per line:
+//!
+//! ```text
+//! {"key":"user:1","ttl_ms":59000,"kind":"string","value":"aGk="}
+//! {"key":"queue:jobs","ttl_ms":null,"kind":"dump","value":"DgEB..."}
+//! ```
+//!
+//! - `key`: the key
+//! - `ttl_ms`: time to live left when exported in milliseconds, `null` without expiry
+//! - `kind`: `string` when `value` is the plain value, `dump` when it is the `DUMP` payload of
+//!   any other type, which only a server with a compatible RDB version can `RESTORE`
+//! - `value`: base64, standard alphabet with padding
+//!
+//! Keys are scanned on every master, an export is not a consistent snapshot.
+
+use std::{io, ops::ControlFlow};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use bytes::Bytes;
+use r2d2::Pool;
+use redis::RedisResult;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use thiserror::Error;
+use tokio::{
+    io::{AsyncWrite, AsyncWriteExt},
+    sync::mpsc,
+    task,
+};
+
+use super::{
+    backend::{MemoryBackend, RedisBackend},
+    connection::RedisConnection,
+    error::RedisError,
+    pool::{checkout, RedisManager},
+    scan::{self, Throttle},
+    Redis, Ttl,
+};
+
+/// Records buffered in the channel before the export waits for the caller
+pub const EXPORT_BUFFER: usize = 256;
+
+/// Keys requested per `SCAN` and read per pipeline by default
+const DEFAULT_BATCH: usize = 100;
+
+/// How `ExportRecord::value` is encoded
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RecordKind {
+    String,
+    Dump,
+}
+
+/// One exported key, a line of the export format
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ExportRecord {
+    pub key: String,
+    pub ttl_ms: Option<u64>,
+    pub kind: RecordKind,
+    #[serde(serialize_with = "to_base64", deserialize_with = "from_base64")]
+    pub value: Bytes,
+}
+
+fn to_base64<S: Serializer>(value: &Bytes, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&STANDARD.encode(value))
+}
+
+fn from_base64<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Bytes, D::Error> {
+    let encoded = String::deserialize(deserializer)?;
+    STANDARD
+        .decode(encoded)
+        .map(Bytes::from)
+        .map_err(serde::de::Error::custom)
+}
+
+/// Receiving end of the sink handed to `RedisExport`
+pub type ExportStream = mpsc::Receiver<Result<ExportRecord, RedisError>>;
+
+/// Where exported records are sent, the stream ends when the sender is dropped
+#[derive(Debug, Clone)]
+pub struct ExportSink(mpsc::Sender<Result<ExportRecord, RedisError>>);
+
+impl ExportSink {
+    /// Sink with its stream, buffering `EXPORT_BUFFER` records
+    pub fn channel() -> (Self, ExportStream) {
+        let (sender, receiver) = mpsc::channel(EXPORT_BUFFER);
+        (Self(sender), receiver)
+    }
+
+    /// End the stream with an error, dropped when the stream is full or gone
+    pub(crate) fn fail(&self, error: RedisError) {
+        let _ = self.0.try_send(Err(error));
+    }
+}
+
+/// Tell exporting every key matching `pattern` to `sink`, an error ends the stream
+#[derive(Debug, Clone)]
+pub struct RedisExport {
+    pub pattern: String,
+    /// `SCAN COUNT` hint and keys read per pipeline, 100 when unset
+    pub batch: Option<usize>,
+    /// Keys exported per second at most
+    pub rate_limit: Option<u32>,
+    pub sink: ExportSink,
+}
+
+/// Failure while writing an export
+#[derive(Debug, Error)]
+pub enum ExportError {
+    #[error(transparent)]
+    Redis(#[from] RedisError),
+    #[error("cannot write the export: {0}")]
+    Io(#[from] io::Error),
+}
+
+/// Write the records of `stream` to `writer` in the export format, returning how many were
+/// written
+pub async fn write_records(
+    mut stream: ExportStream,
+    mut writer: impl AsyncWrite + Unpin,
+) -> Result<u64, ExportError> {
+    let mut written = 0;
+    while let Some(record) = stream.recv().await {
+        let mut line = serde_json::to_vec(&record?).expect("records serialize to JSON");
+        line.push(b'\n');
+        writer.write_all(&line).await?;
+        written += 1;
+    }
+    writer.flush().await?;
+    Ok(written)
+}
+
+/// Export from a blocking task on its own pooled connection
+pub(crate) fn spawn(pool: &Pool<RedisManager>, redis: &Redis, event: RedisExport) {
+    let pool = pool.clone();
+    let (urls, actor) = (redis.urls.clone(), redis.own_distributor());
+    task::spawn_blocking(move || {
+        let result = checkout(&pool)
+            .map_err(|e| RedisError::Command(e.to_string()))
+            .and_then(|mut conn| {
+                export(&mut conn, &urls, &event).map_err(|e| RedisError::Command(e.to_string()))
+            });
+        if let Err(e) = result {
+            Redis::report_error(actor, "Export", &e);
+            // The caller may be gone already
+            let _ = event.sink.0.blocking_send(Err(e));
+        }
+    });
+}
+
+fn export(conn: &mut RedisConnection, urls: &[String], event: &RedisExport) -> RedisResult<()> {
+    let batch = event.batch.unwrap_or(DEFAULT_BATCH).max(1);
+    let mut throttle = Throttle::new(event.rate_limit);
+
+    scan::scan_masters(conn, urls, &event.pattern, batch, |_, node_conn, keys| {
+        // The scanned node owns the keys
+        let mut pipe = redis::pipe();
+        for key in keys {
+            pipe.cmd("TYPE")
+                .arg(key)
+                .cmd("PTTL")
+                .arg(key)
+                .cmd("DUMP")
+                .arg(key);
+        }
+        let found: Vec<(String, i64, Option<Vec<u8>>)> = pipe.query(node_conn)?;
+
+        let mut strings = redis::pipe();
+        for (key, (kind, _, _)) in keys.iter().zip(&found) {
+            if kind == "string" {
+                strings.cmd("GET").arg(key);
+            }
+        }
+        let mut values = strings
+            .query::<Vec<Option<Vec<u8>>>>(node_conn)?
+            .into_iter();
+
+        for (key, (kind, ttl, dump)) in keys.iter().zip(found) {
+            let (kind, value) = if kind == "string" {
+                (RecordKind::String, values.next().flatten())
+            } else {
+                (RecordKind::Dump, dump)
+            };
+            // Gone since the scan
+            let Some(value) = value else {
+                continue;
+            };
+            let record = ExportRecord {
+                key: key.clone(),
+                ttl_ms: u64::try_from(ttl).ok(),
+                kind,
+                value: Bytes::from(value),
+            };
+            if event.sink.0.blocking_send(Ok(record)).is_err() {
+                // The caller stopped reading
+                return Ok(ControlFlow::Break(()));
+            }
+        }
+        throttle.wait(keys.len());
+        Ok(ControlFlow::Continue(()))
+    })
+}
+
+/// Export the string keys of the in-memory backend
+pub(crate) fn run_memory(backend: &mut MemoryBackend, event: RedisExport) {
+    let mut records = vec![];
+    for key in backend.keys(&event.pattern) {
+        let ttl = match backend.ttl(&key) {
+            Ok(Ttl::Expires(ttl)) => Some(ttl.as_millis() as u64),
+            _ => None,
+        };
+        if let Ok(Some(value)) = backend.get(&key) {
+            records.push(ExportRecord {
+                key,
+                ttl_ms: ttl,
+                kind: RecordKind::String,
+                value: Bytes::from(value),
+            });
+        }
+    }
+    // Sent from a task, the handler must not wait for the caller
+    task::spawn(async move {
+        for record in records {
+            if event.sink.0.send(Ok(record)).await.is_err() {
+                break;
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn records_are_json_lines_with_base64_values() {
+        let record = ExportRecord {
+            key: "user:1".to_owned(),
+            ttl_ms: None,
+            kind: RecordKind::String,
+            value: Bytes::from("hi"),
+        };
+        let line = serde_json::to_string(&record).unwrap();
+
+        assert_eq!(
+            r#"{"key":"user:1","ttl_ms":null,"kind":"string","value":"aGk="}"#,
+            line
+        );
+        assert_eq!(record, serde_json::from_str(&line).unwrap());
+    }
+}
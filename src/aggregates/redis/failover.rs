@@ -0,0 +1,87 @@
+//! Failover to a secondary cluster.
+//!
+//! The writer looks at its health checker after every `HealthTick`. Once the cluster it is on
+//! has been unhealthy for `FailoverConfig::after`, it fails over: `RedisServerFailedOver` moves
+//! it to the secondary urls the way a reconnect would. It stays there until `reconnect` moves it
+//! back, to the primary or anywhere else. Readers keep the urls they were built with.
+
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use super::{command::validate_urls, error::RedisError, Redis};
+
+/// Failover settings, the primary cluster being `Redis::urls`
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct FailoverConfig {
+    /// Urls of the cluster taking over
+    pub secondary: Vec<String>,
+    /// How long failed health checks last before failing over
+    pub after: Duration,
+}
+
+/// The secondary urls of `redis` must parse and share the same credentials, and health checks
+/// must run to notice the primary is down
+pub fn validate(redis: &Redis) -> Result<(), RedisError> {
+    let Some(config) = &redis.failover else {
+        return Ok(());
+    };
+    if redis.health_check_interval.is_none() {
+        return Err(RedisError::FailoverWithoutHealthCheck);
+    }
+    validate_urls(&config.secondary)
+}
+
+impl FailoverConfig {
+    /// Urls to move to from `urls`, unhealthy for `unhealthy`, `None` while the actor should
+    /// stay or is on the secondary already
+    pub(crate) fn target(&self, urls: &[String], unhealthy: Option<Duration>) -> Option<&[String]> {
+        let failing = unhealthy.is_some_and(|unhealthy| unhealthy >= self.after);
+        (failing && urls != self.secondary).then_some(self.secondary.as_slice())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn failover_needs_health_checks() {
+        let mut redis = Redis {
+            failover: Some(FailoverConfig {
+                secondary: vec!["redis://127.0.0.1:30002".to_owned()],
+                after: Duration::from_secs(10),
+            }),
+            ..Default::default()
+        };
+        assert_eq!(
+            Err(RedisError::FailoverWithoutHealthCheck),
+            validate(&redis)
+        );
+        redis.health_check_interval = Some(Duration::from_secs(1));
+        assert_eq!(Ok(()), validate(&redis));
+        redis.failover.as_mut().unwrap().secondary.clear();
+        assert_eq!(Err(RedisError::EmptyUrls), validate(&redis));
+    }
+
+    #[test]
+    fn fails_over_once_unhealthy_long_enough() {
+        let config = FailoverConfig {
+            secondary: vec!["redis://127.0.0.1:30002".to_owned()],
+            after: Duration::from_secs(10),
+        };
+        let primary = vec!["redis://127.0.0.1:30001".to_owned()];
+
+        assert_eq!(None, config.target(&primary, None));
+        assert_eq!(None, config.target(&primary, Some(Duration::from_secs(9))));
+        assert_eq!(
+            Some(config.secondary.as_slice()),
+            config.target(&primary, Some(Duration::from_secs(10)))
+        );
+        // Already on the secondary
+        assert_eq!(
+            None,
+            config.target(&config.secondary, Some(Duration::from_secs(60)))
+        );
+    }
+}
@@ -0,0 +1,104 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use super::{backend::RedisBackend, pool::PoolStats, RedisState};
+
+/// Health report used by liveness and readiness probes
+#[derive(Default, Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RedisHealth {
+    pub initialized: bool,
+    pub pool: PoolStats,
+    pub last_ping_ok: bool,
+    pub last_ping_at: Option<DateTime<Utc>>,
+    /// First failed ping of the current failure streak
+    pub unhealthy_since: Option<DateTime<Utc>>,
+}
+
+impl RedisHealth {
+    /// Initialized, with pooled connections and a successful last PING
+    pub fn is_healthy(&self) -> bool {
+        self.initialized && self.pool.connections > 0 && self.last_ping_ok
+    }
+}
+
+/// Question asking the actor for a fresh `RedisHealth`
+#[derive(Default, Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RedisHealthQuery;
+
+/// Tick driving the periodic health check
+#[derive(Debug)]
+pub(crate) struct HealthTick;
+
+/// Outcome of a PING made off the actor, told back to be recorded
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct HealthPinged {
+    ok: bool,
+    at: DateTime<Utc>,
+}
+
+impl HealthPinged {
+    /// PING through `conn`, from a blocking task
+    pub(crate) fn ping(conn: &mut dyn RedisBackend) -> Self {
+        let at = Utc::now();
+        let result = conn.ping();
+        if let Err(e) = &result {
+            warn!(error = %e, "health check ping failed");
+        }
+        Self {
+            ok: result.is_ok(),
+            at,
+        }
+    }
+}
+
+/// Tracks the outcome of PINGs sent through the actor's backend
+#[derive(Debug, Default, Clone)]
+pub(crate) struct HealthChecker {
+    last_ping_ok: bool,
+    last_ping_at: Option<DateTime<Utc>>,
+    unhealthy_since: Option<DateTime<Utc>>,
+}
+
+impl HealthChecker {
+    /// PING and record the outcome right away, for backends that do not block
+    pub(crate) fn ping(&mut self, conn: &mut dyn RedisBackend) {
+        self.record(HealthPinged::ping(conn));
+    }
+
+    pub(crate) fn record(&mut self, pinged: HealthPinged) {
+        self.last_ping_ok = pinged.ok;
+        self.last_ping_at = Some(pinged.at);
+        if pinged.ok {
+            self.unhealthy_since = None;
+        } else {
+            self.unhealthy_since.get_or_insert(pinged.at);
+        }
+    }
+
+    /// Count a check failed without a connection to ping
+    pub(crate) fn unreachable(&mut self) {
+        let now = Utc::now();
+        self.last_ping_ok = false;
+        self.last_ping_at = Some(now);
+        self.unhealthy_since.get_or_insert(now);
+    }
+
+    /// How long checks have been failing, `None` while healthy
+    pub(crate) fn unhealthy_for(&self) -> Option<Duration> {
+        let since = self.unhealthy_since?;
+        Some((Utc::now() - since).to_std().unwrap_or_default())
+    }
+
+    pub(crate) fn report(&self, state: &RedisState, pool: PoolStats) -> RedisHealth {
+        RedisHealth {
+            initialized: *state == RedisState::Initialized,
+            pool,
+            last_ping_ok: self.last_ping_ok,
+            last_ping_at: self.last_ping_at,
+            unhealthy_since: self.unhealthy_since,
+        }
+    }
+}
@@ -0,0 +1,336 @@
+//! Hot-key detection.
+//!
+//! Queries and inserts passing through the actor are sampled into a count-min sketch, a fixed
+//! grid of counters giving an upper bound of each key's accesses in constant memory, and the
+//! keys with the highest estimates are kept aside. At the end of every window the hottest keys
+//! are logged, sent to `report_to` and kept for `RedisHotKeysQuery`, then counting starts over.
+//! The writer and the readers of an actor share the counts.
+
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    fmt::{self, Debug},
+    hash::{Hash, Hasher},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use bastion::prelude::Distributor;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+/// Hot-key sampling settings
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct HotKeysConfig {
+    /// How long accesses are counted before the hottest keys are reported
+    pub window: Duration,
+    /// Keys reported per window
+    pub top: usize,
+    /// Count one access out of this many, every access when one
+    pub sample_every: u32,
+    /// Counters per row of the sketch, more of them make estimates tighter
+    pub width: usize,
+    /// Rows of the sketch, each hashing keys differently
+    pub depth: usize,
+    /// Name of the distributor receiving every `HotKeyReport`
+    #[serde(default)]
+    pub report_to: Option<String>,
+}
+
+impl Default for HotKeysConfig {
+    fn default() -> Self {
+        Self {
+            window: Duration::from_secs(60),
+            top: 10,
+            sample_every: 1,
+            width: 2048,
+            depth: 4,
+            report_to: None,
+        }
+    }
+}
+
+/// Question returning the report of the last complete window, `None` before the first one ends
+/// or when hot keys are not tracked
+#[derive(Default, Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RedisHotKeysQuery;
+
+/// Tick ending a window
+#[derive(Debug)]
+pub(crate) struct HotKeysTick;
+
+/// Hottest keys of a window, hottest first
+#[derive(Default, Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct HotKeyReport {
+    pub keys: Vec<HotKey>,
+    /// Queries and inserts seen during the window
+    pub accesses: u64,
+    pub window: Duration,
+}
+
+/// Estimated accesses of a key, scaled back from the sampled ones. Collisions in the sketch can
+/// only inflate it.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct HotKey {
+    pub key: String,
+    pub accesses: u64,
+}
+
+/// Counters of a count-min sketch, `depth` rows of `width`
+struct Sketch {
+    width: usize,
+    counters: Vec<Vec<u32>>,
+}
+
+impl Sketch {
+    fn new(width: usize, depth: usize) -> Self {
+        let width = width.max(1);
+        Self {
+            width,
+            counters: vec![vec![0; width]; depth.max(1)],
+        }
+    }
+
+    /// Count an access of `key`, returning its estimate
+    fn add(&mut self, key: &str) -> u32 {
+        let width = self.width;
+        self.counters
+            .iter_mut()
+            .enumerate()
+            .map(|(row, counters)| {
+                let counter = &mut counters[column(row, key, width)];
+                *counter = counter.saturating_add(1);
+                *counter
+            })
+            .min()
+            .unwrap_or_default()
+    }
+
+    fn clear(&mut self) {
+        for row in &mut self.counters {
+            row.fill(0);
+        }
+    }
+}
+
+/// Counter of `key` in `row`
+fn column(row: usize, key: &str, width: usize) -> usize {
+    let mut hasher = DefaultHasher::new();
+    row.hash(&mut hasher);
+    key.hash(&mut hasher);
+    (hasher.finish() % width as u64) as usize
+}
+
+/// Counts of the current window and the report of the last one
+struct Window {
+    sketch: Sketch,
+    /// Highest estimates, at most `top` of them
+    hottest: HashMap<String, u32>,
+    top: usize,
+    started: Instant,
+    last: Option<HotKeyReport>,
+}
+
+impl Window {
+    fn add(&mut self, key: &str) {
+        let estimate = self.sketch.add(key);
+        if let Some(count) = self.hottest.get_mut(key) {
+            *count = estimate;
+            return;
+        }
+        if self.hottest.len() < self.top {
+            self.hottest.insert(key.to_owned(), estimate);
+            return;
+        }
+        let coldest = self
+            .hottest
+            .iter()
+            .min_by_key(|(_, count)| **count)
+            .map(|(key, count)| (key.clone(), *count));
+        if let Some((coldest, count)) = coldest {
+            if estimate > count {
+                self.hottest.remove(&coldest);
+                self.hottest.insert(key.to_owned(), estimate);
+            }
+        }
+    }
+}
+
+struct Sampler {
+    every: u64,
+    /// Accesses seen during the window, sampled or not
+    seen: AtomicU64,
+    window: Mutex<Window>,
+}
+
+/// Hot-key counts shared by the children of an actor, off by default
+#[derive(Clone, Default)]
+pub struct HotKeySampler(Option<Arc<Sampler>>);
+
+impl HotKeySampler {
+    pub fn new(config: &HotKeysConfig) -> Self {
+        Self(Some(Arc::new(Sampler {
+            every: u64::from(config.sample_every.max(1)),
+            seen: AtomicU64::new(0),
+            window: Mutex::new(Window {
+                sketch: Sketch::new(config.width, config.depth),
+                hottest: HashMap::new(),
+                top: config.top,
+                started: Instant::now(),
+                last: None,
+            }),
+        })))
+    }
+
+    pub(crate) fn is_on(&self) -> bool {
+        self.0.is_some()
+    }
+
+    /// Count an access of `key` when it is sampled
+    pub(crate) fn record(&self, key: &str) {
+        if let Some(sampler) = &self.0 {
+            let seen = sampler.seen.fetch_add(1, Ordering::Relaxed);
+            if seen % sampler.every == 0 {
+                sampler.window.lock().unwrap().add(key);
+            }
+        }
+    }
+
+    /// End the window and start the next one, returning its report
+    pub(crate) fn rotate(&self) -> Option<HotKeyReport> {
+        let sampler = self.0.as_ref()?;
+        let mut window = sampler.window.lock().unwrap();
+        let mut keys: Vec<HotKey> = window
+            .hottest
+            .drain()
+            .map(|(key, count)| HotKey {
+                key,
+                accesses: u64::from(count) * sampler.every,
+            })
+            .collect();
+        keys.sort_by(|a, b| b.accesses.cmp(&a.accesses).then_with(|| a.key.cmp(&b.key)));
+        let report = HotKeyReport {
+            keys,
+            accesses: sampler.seen.swap(0, Ordering::Relaxed),
+            window: window.started.elapsed(),
+        };
+        window.sketch.clear();
+        window.started = Instant::now();
+        window.last = Some(report.clone());
+        Some(report)
+    }
+
+    /// Report of the last complete window
+    pub(crate) fn last(&self) -> Option<HotKeyReport> {
+        let sampler = self.0.as_ref()?;
+        let window = sampler.window.lock().unwrap();
+        window.last.clone()
+    }
+
+    /// End the window, log its hottest keys and deliver its report
+    pub(crate) fn report(&self, config: Option<&HotKeysConfig>) {
+        let Some(report) = self.rotate() else {
+            return;
+        };
+        if let Some(hottest) = report.keys.first() {
+            info!(
+                key = hottest.key,
+                accesses = hottest.accesses,
+                keys = ?report.keys,
+                "redis hot keys"
+            );
+        }
+        if let Some(name) = config.and_then(|config| config.report_to.as_deref()) {
+            if let Err(e) = Distributor::named(name).tell_one(report) {
+                warn!("[REDIS] Cannot deliver hot key report: {e:?}");
+            }
+        }
+    }
+}
+
+impl Debug for HotKeySampler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("HotKeySampler").field(&self.is_on()).finish()
+    }
+}
+
+impl PartialEq for HotKeySampler {
+    fn eq(&self, other: &Self) -> bool {
+        match (&self.0, &other.0) {
+            (Some(a), Some(b)) => Arc::ptr_eq(a, b),
+            (None, None) => true,
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hottest_keys_are_reported_per_window() {
+        let sampler = HotKeySampler::new(&HotKeysConfig {
+            top: 2,
+            ..Default::default()
+        });
+        for (key, accesses) in [("cold", 1), ("warm", 5), ("hot", 20), ("lukewarm", 3)] {
+            for _ in 0..accesses {
+                sampler.record(key);
+            }
+        }
+
+        let report = sampler.rotate().unwrap();
+        assert_eq!(
+            vec![
+                HotKey {
+                    key: "hot".to_owned(),
+                    accesses: 20
+                },
+                HotKey {
+                    key: "warm".to_owned(),
+                    accesses: 5
+                }
+            ],
+            report.keys
+        );
+        assert_eq!(29, report.accesses);
+        assert_eq!(Some(report), sampler.last());
+
+        // Counting starts over
+        sampler.record("cold");
+        let report = sampler.rotate().unwrap();
+        assert_eq!(vec!["cold"], keys(&report));
+        assert_eq!(1, report.accesses);
+    }
+
+    #[test]
+    fn sampled_counts_are_scaled_back() {
+        let sampler = HotKeySampler::new(&HotKeysConfig {
+            sample_every: 4,
+            ..Default::default()
+        });
+        for _ in 0..40 {
+            sampler.record("hot");
+        }
+        let report = sampler.rotate().unwrap();
+        assert_eq!(40, report.keys[0].accesses);
+        assert!(HotKeySampler::default().rotate().is_none());
+    }
+
+    #[test]
+    fn estimates_never_undercount() {
+        // Far more keys than counters, so rows collide
+        let mut sketch = Sketch::new(16, 4);
+        for i in 0..200 {
+            sketch.add(&format!("key:{i}"));
+        }
+        assert!(sketch.add("key:7") >= 2);
+    }
+
+    fn keys(report: &HotKeyReport) -> Vec<&str> {
+        report.keys.iter().map(|key| key.key.as_str()).collect()
+    }
+}
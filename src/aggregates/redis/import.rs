@@ -0,0 +1,261 @@
+//! Restores of exports written in the format of the `export` module.
+//!
+//! Records are sent to the actor in batches, `string` records are written with `SET` and `dump`
+//! records with `RESTORE`, pipelined per hash slot. TTLs are restored as they were when exported.
+
+use std::io;
+
+use bastion::prelude::AnswerSender;
+use r2d2::Pool;
+use redis::RedisResult;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tokio::{
+    io::{AsyncBufRead, AsyncBufReadExt},
+    task,
+};
+
+use super::{
+    backend::{MemoryBackend, RedisBackend},
+    connection::RedisConnection,
+    error::RedisError,
+    export::{ExportRecord, RecordKind},
+    mirror::{Mirror, MirroredWrite},
+    multi::{fan_out, group_by_slot},
+    pool::RedisManager,
+    Redis,
+};
+
+/// Records sent to the actor per `RedisImport`
+pub const IMPORT_BATCH: usize = 100;
+
+/// What happens to a record whose key already exists
+#[derive(Default, Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum ConflictPolicy {
+    /// Keep the existing key
+    #[default]
+    Skip,
+    Replace,
+}
+
+/// Keys handled by an import so far
+#[derive(Default, Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ImportProgress {
+    pub imported: u64,
+    /// Records kept out by `ConflictPolicy::Skip` or expired on export
+    pub skipped: u64,
+}
+
+impl ImportProgress {
+    pub(crate) fn add(&mut self, other: ImportProgress) {
+        self.imported += other.imported;
+        self.skipped += other.skipped;
+    }
+}
+
+/// Question writing a batch of export records, replied with
+/// `Result<ImportProgress, RedisError>` for the batch
+#[derive(Default, Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RedisImport {
+    pub records: Vec<ExportRecord>,
+    pub policy: ConflictPolicy,
+}
+
+/// Failure while reading an export back
+#[derive(Debug, Error)]
+pub enum ImportError {
+    #[error(transparent)]
+    Redis(#[from] RedisError),
+    #[error("cannot read the export: {0}")]
+    Io(#[from] io::Error),
+    #[error("invalid record on line {line}: {error}")]
+    Record {
+        line: usize,
+        error: serde_json::Error,
+    },
+    #[error("cannot reach the redis actor: {0}")]
+    Send(String),
+}
+
+/// Read the export at `reader` back through the actor, calling `progress` with the running
+/// totals after every batch
+pub async fn read_records(
+    reader: impl AsyncBufRead + Unpin,
+    policy: ConflictPolicy,
+    mut progress: impl FnMut(ImportProgress),
+) -> Result<ImportProgress, ImportError> {
+    let mut lines = reader.lines();
+    let mut total = ImportProgress::default();
+    let mut records = vec![];
+    let mut line = 0;
+    loop {
+        let next = lines.next_line().await?;
+        if let Some(text) = &next {
+            line += 1;
+            if !text.trim().is_empty() {
+                let record = serde_json::from_str(text)
+                    .map_err(|error| ImportError::Record { line, error })?;
+                records.push(record);
+            }
+        }
+        if records.len() == IMPORT_BATCH || (next.is_none() && !records.is_empty()) {
+            let event = RedisImport {
+                records: std::mem::take(&mut records),
+                policy,
+            };
+            let reply: Result<ImportProgress, RedisError> = Redis::distributor()
+                .request(event)
+                .await
+                .map_err(|e| ImportError::Send(format!("{e:?}")))?
+                .map_err(|e| ImportError::Send(e.to_string()))?;
+            total.add(reply?);
+            progress(total);
+        }
+        if next.is_none() {
+            return Ok(total);
+        }
+    }
+}
+
+/// Import a batch from a blocking task, nodes are written concurrently on pooled connections
+pub(crate) fn spawn(
+    pool: &Pool<RedisManager>,
+    redis: &Redis,
+    event: RedisImport,
+    sender: AnswerSender,
+) {
+    let (pool, urls, actor) = (pool.clone(), redis.urls.clone(), redis.own_distributor());
+    let mirror = redis.mirroring.clone();
+    task::spawn_blocking(move || {
+        let groups = group_by_slot(event.records.iter().map(|record| record.key.as_str()));
+        let result = fan_out(&pool, &urls, groups, |conn, groups| {
+            let mut total = ImportProgress::default();
+            for indexes in groups {
+                let records: Vec<&ExportRecord> =
+                    indexes.iter().map(|i| &event.records[*i]).collect();
+                total.add(import(conn, &records, event.policy)?);
+            }
+            Ok(total)
+        })
+        .map(|progress| {
+            progress
+                .into_iter()
+                .fold(ImportProgress::default(), |mut total, (_, p)| {
+                    total.add(p);
+                    total
+                })
+        })
+        .map_err(|e| RedisError::Command(e.to_string()));
+        match (&result, &mirror) {
+            (Ok(_), Some(mirror)) => mirror.send(MirroredWrite::Import(event)),
+            (Err(e), _) => Redis::report_error(actor, "Import", e),
+            (Ok(_), None) => {}
+        }
+        // The caller may be gone already
+        let _ = sender.reply(result);
+    });
+}
+
+// Records of one slot, a pipeline of SET and RESTORE
+fn import(
+    conn: &mut RedisConnection,
+    records: &[&ExportRecord],
+    policy: ConflictPolicy,
+) -> RedisResult<ImportProgress> {
+    let mut progress = ImportProgress::default();
+    let records: Vec<&ExportRecord> = records
+        .iter()
+        .copied()
+        .filter(|record| {
+            // Expired on export
+            let expired = record.ttl_ms == Some(0);
+            progress.skipped += u64::from(expired);
+            !expired
+        })
+        .collect();
+
+    // RESTORE fails on existing keys instead of skipping them, they are left out up front
+    let mut existing = vec![false; records.len()];
+    if policy == ConflictPolicy::Skip {
+        let mut pipe = redis::pipe();
+        for record in &records {
+            pipe.cmd("EXISTS").arg(&record.key);
+        }
+        existing = pipe.query(conn)?;
+    }
+
+    let mut pipe = redis::pipe();
+    let mut writes = 0;
+    for (record, existing) in records.iter().zip(existing) {
+        if existing {
+            progress.skipped += 1;
+            continue;
+        }
+        match record.kind {
+            RecordKind::String => {
+                pipe.cmd("SET").arg(&record.key).arg(&record.value[..]);
+                if let Some(ttl) = record.ttl_ms {
+                    pipe.arg("PX").arg(ttl);
+                }
+                if policy == ConflictPolicy::Skip {
+                    pipe.arg("NX");
+                }
+            }
+            RecordKind::Dump => {
+                pipe.cmd("RESTORE")
+                    .arg(&record.key)
+                    .arg(record.ttl_ms.unwrap_or(0))
+                    .arg(&record.value[..]);
+                if policy == ConflictPolicy::Replace {
+                    pipe.arg("REPLACE");
+                }
+            }
+        }
+        writes += 1;
+    }
+    if writes == 0 {
+        return Ok(progress);
+    }
+
+    // `SET NX` replies nil for a key written since the check
+    let replies: Vec<Option<String>> = pipe.query(conn)?;
+    for reply in replies {
+        match reply {
+            Some(_) => progress.imported += 1,
+            None => progress.skipped += 1,
+        }
+    }
+    Ok(progress)
+}
+
+/// Import a batch into the in-memory backend, which only holds strings
+pub(crate) fn run_memory(
+    backend: &mut MemoryBackend,
+    mirroring: Option<&Mirror>,
+    event: RedisImport,
+) -> Result<ImportProgress, RedisError> {
+    let mut progress = ImportProgress::default();
+    for record in &event.records {
+        if record.kind == RecordKind::Dump {
+            return Err(RedisError::Command(
+                "dump records need a cluster backend".to_owned(),
+            ));
+        }
+        let exists = backend
+            .exists(&record.key)
+            .map_err(|e| RedisError::Command(e.to_string()))?;
+        if record.ttl_ms == Some(0) || (exists && event.policy == ConflictPolicy::Skip) {
+            progress.skipped += 1;
+            continue;
+        }
+        let ttl = record.ttl_ms.map(std::time::Duration::from_millis);
+        backend
+            .set(&record.key, &record.value, ttl)
+            .map_err(|e| RedisError::Command(e.to_string()))?;
+        progress.imported += 1;
+    }
+    if let Some(mirror) = mirroring {
+        mirror.send(MirroredWrite::Import(event));
+    }
+    Ok(progress)
+}
@@ -0,0 +1,134 @@
+//! Named logical keyspaces.
+//!
+//! A keyspace groups keys under a prefix with a default TTL, a value codec and the actor
+//! instance serving them, so callers address values by keyspace and key instead of building
+//! raw key strings, e.g. `insert_in("sessions", "42", &session)`.
+
+use std::time::Duration;
+
+use bastion::prelude::Distributor;
+use bytes::Bytes;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use super::{error::RedisError, Redis, RedisInsert, RedisQuery, RedisSlidingQuery};
+
+/// Encoding of the values of a keyspace
+#[derive(Default, Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum Codec {
+    #[default]
+    Json,
+    /// Structs are encoded as maps keyed by field name
+    #[cfg(feature = "messagepack")]
+    MessagePack,
+}
+
+impl Codec {
+    pub fn encode<T: Serialize>(self, value: &T) -> Result<Bytes, RedisError> {
+        let encoded = match self {
+            Self::Json => serde_json::to_vec(value).map_err(|e| e.to_string()),
+            #[cfg(feature = "messagepack")]
+            Self::MessagePack => rmp_serde::to_vec_named(value).map_err(|e| e.to_string()),
+        };
+        encoded.map(Bytes::from).map_err(RedisError::Codec)
+    }
+
+    pub fn decode<T: DeserializeOwned>(self, value: &[u8]) -> Result<T, RedisError> {
+        let decoded = match self {
+            Self::Json => serde_json::from_slice(value).map_err(|e| e.to_string()),
+            #[cfg(feature = "messagepack")]
+            Self::MessagePack => rmp_serde::from_slice(value).map_err(|e| e.to_string()),
+        };
+        decoded.map_err(RedisError::Codec)
+    }
+}
+
+/// Prefix, expiry, codec and placement shared by the keys of a keyspace
+#[derive(Default, Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Keyspace {
+    /// Added in front of every key, e.g. `session:`
+    pub prefix: String,
+    /// Expiry of every value stored, they are kept until deleted otherwise
+    #[serde(default)]
+    pub ttl: Option<Duration>,
+    #[serde(default)]
+    pub codec: Codec,
+    /// Name of the actor instance serving the keyspace, the unnamed actor otherwise
+    #[serde(default)]
+    pub instance: Option<String>,
+    /// Reads re-apply `ttl`, so values expire after `ttl` without being read instead of after
+    /// being written
+    #[serde(default)]
+    pub sliding: bool,
+}
+
+impl Keyspace {
+    /// Full key of `key` in the keyspace
+    pub fn key(&self, key: &str) -> String {
+        format!("{}{key}", self.prefix)
+    }
+
+    /// Distributor of the actor serving the keyspace
+    pub fn distributor(&self) -> Distributor {
+        Redis::distributor_named(self.instance.as_deref())
+    }
+
+    /// Insert of `value` encoded with the keyspace codec and expiring after its TTL
+    pub fn insert<T: Serialize>(&self, key: &str, value: &T) -> Result<RedisInsert, RedisError> {
+        Ok(RedisInsert {
+            key: self.key(key),
+            value: self.codec.encode(value)?,
+            ttl: self.ttl,
+            caller: None,
+        })
+    }
+
+    /// Query of `key` in the keyspace
+    pub fn query(&self, key: &str) -> RedisQuery {
+        RedisQuery { key: self.key(key) }
+    }
+
+    /// Query of `key` refreshing its TTL, `None` unless the keyspace is sliding and has a TTL
+    pub fn sliding_query(&self, key: &str) -> Option<RedisSlidingQuery> {
+        let ttl = self.ttl.filter(|_| self.sliding)?;
+        Some(RedisSlidingQuery {
+            key: self.key(key),
+            ttl,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_get_the_prefix_and_values_the_ttl_and_codec() {
+        let sessions = Keyspace {
+            prefix: "session:".to_owned(),
+            ttl: Some(Duration::from_secs(60)),
+            ..Default::default()
+        };
+        let insert = sessions.insert("42", &vec![1, 2]).unwrap();
+        assert_eq!("session:42", insert.key);
+        assert_eq!(Bytes::from("[1,2]"), insert.value);
+        assert_eq!(Some(Duration::from_secs(60)), insert.ttl);
+        assert_eq!("session:42", sessions.query("42").key);
+        assert_eq!(None, sessions.sliding_query("42"));
+        let sliding = Keyspace {
+            sliding: true,
+            ..sessions.clone()
+        };
+        assert_eq!(
+            Some(Duration::from_secs(60)),
+            sliding.sliding_query("42").map(|query| query.ttl)
+        );
+        assert_eq!(
+            Ok(vec![1, 2]),
+            sessions.codec.decode::<Vec<u8>>(&insert.value)
+        );
+        assert!(matches!(
+            sessions.codec.decode::<Vec<u8>>(b"not json"),
+            Err(RedisError::Codec(_))
+        ));
+    }
+}
@@ -0,0 +1,178 @@
+//! Value size limits.
+//!
+//! Inserts above `ValueLimit::max_bytes` are handled by its policy before anything is sent, so a
+//! buggy caller writing multi-megabyte values cannot block a node. Compressed values carry a
+//! prefix and are inflated again by queries, builds without the `compression` feature return
+//! them as stored.
+
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use super::{error::RedisError, metrics};
+
+/// Prefix marking a value compressed by `OversizePolicy::Compress`
+#[cfg(feature = "compression")]
+const COMPRESSED_MAGIC: &[u8] = b"\x00redis-actor:lz4\x00";
+
+/// Largest value accepted by inserts and what happens to larger ones
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ValueLimit {
+    pub max_bytes: usize,
+    #[serde(default)]
+    pub policy: OversizePolicy,
+}
+
+impl Default for ValueLimit {
+    fn default() -> Self {
+        Self {
+            max_bytes: 1 << 20,
+            policy: OversizePolicy::default(),
+        }
+    }
+}
+
+/// Handling of a value above the limit
+#[derive(Default, Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum OversizePolicy {
+    /// Refuse the insert with `RedisError::ValueTooLarge`
+    #[default]
+    Reject,
+    /// Store the first `max_bytes` bytes and log a warning
+    Truncate,
+    /// Store the value compressed with LZ4, refused when it is still too large
+    #[cfg(feature = "compression")]
+    Compress,
+}
+
+impl OversizePolicy {
+    fn label(self) -> &'static str {
+        match self {
+            Self::Reject => "reject",
+            Self::Truncate => "truncate",
+            #[cfg(feature = "compression")]
+            Self::Compress => "compress",
+        }
+    }
+}
+
+impl ValueLimit {
+    /// Value to store under `key`, `value` itself when it fits
+    pub(crate) fn apply(&self, key: &str, value: Bytes) -> Result<Bytes, RedisError> {
+        let size = value.len();
+        if size <= self.max_bytes {
+            return Ok(value);
+        }
+        metrics::record_oversized_value(self.policy.label());
+        let too_large = || RedisError::ValueTooLarge {
+            key: key.to_owned(),
+            size,
+            max: self.max_bytes,
+        };
+        match self.policy {
+            OversizePolicy::Reject => {
+                warn!(key, size, max = self.max_bytes, "[REDIS] Value too large");
+                Err(too_large())
+            }
+            OversizePolicy::Truncate => {
+                warn!(key, size, max = self.max_bytes, "[REDIS] Value truncated");
+                Ok(value.slice(..self.max_bytes))
+            }
+            #[cfg(feature = "compression")]
+            OversizePolicy::Compress => {
+                let compressed = compress(&value);
+                if compressed.len() > self.max_bytes {
+                    warn!(
+                        key,
+                        size,
+                        compressed = compressed.len(),
+                        max = self.max_bytes,
+                        "[REDIS] Value too large even compressed"
+                    );
+                    return Err(too_large());
+                }
+                Ok(compressed)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "compression")]
+fn compress(value: &[u8]) -> Bytes {
+    let mut compressed = COMPRESSED_MAGIC.to_vec();
+    compressed.extend(lz4_flex::compress_prepend_size(value));
+    Bytes::from(compressed)
+}
+
+/// Value as it was inserted, decompressing it when it was stored compressed
+#[cfg(feature = "compression")]
+pub(crate) fn inflate(value: Vec<u8>) -> Result<Vec<u8>, RedisError> {
+    match value.strip_prefix(COMPRESSED_MAGIC) {
+        Some(compressed) => lz4_flex::decompress_size_prepended(compressed)
+            .map_err(|e| RedisError::Codec(e.to_string())),
+        None => Ok(value),
+    }
+}
+
+/// Value as stored, compressed values are only recognized with the `compression` feature
+#[cfg(not(feature = "compression"))]
+pub(crate) fn inflate(value: Vec<u8>) -> Result<Vec<u8>, RedisError> {
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limit(policy: OversizePolicy) -> ValueLimit {
+        ValueLimit {
+            max_bytes: 8,
+            policy,
+        }
+    }
+
+    #[test]
+    fn oversized_values_are_rejected_or_truncated() {
+        let small = Bytes::from("12345678");
+        let large = Bytes::from("123456789");
+        assert_eq!(
+            Ok(small.clone()),
+            limit(OversizePolicy::Reject).apply("k", small)
+        );
+        assert_eq!(
+            Err(RedisError::ValueTooLarge {
+                key: "k".to_owned(),
+                size: 9,
+                max: 8
+            }),
+            limit(OversizePolicy::Reject).apply("k", large.clone())
+        );
+        assert_eq!(
+            Ok(Bytes::from("12345678")),
+            limit(OversizePolicy::Truncate).apply("k", large)
+        );
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn compressed_values_are_inflated_on_read() {
+        let limit = ValueLimit {
+            max_bytes: 256,
+            policy: OversizePolicy::Compress,
+        };
+        let value = Bytes::from("a".repeat(4096));
+        let stored = limit.apply("k", value.clone()).unwrap();
+        assert!(stored.len() <= 256);
+        assert_eq!(Ok(value.to_vec()), inflate(stored.to_vec()));
+        assert_eq!(Ok(b"plain".to_vec()), inflate(b"plain".to_vec()));
+
+        // Random bytes do not compress below the limit
+        let noise: Vec<u8> = (0..4096u32)
+            .map(|i| (i.wrapping_mul(2_654_435_761) >> 13) as u8)
+            .collect();
+        assert!(matches!(
+            limit.apply("k", Bytes::from(noise)),
+            Err(RedisError::ValueTooLarge { .. })
+        ));
+    }
+}
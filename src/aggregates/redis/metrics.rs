@@ -0,0 +1,123 @@
+//! Metrics recorded through the `metrics` facade, no-ops unless the `metrics` feature is on
+
+use std::time::Duration;
+
+use super::pool::PoolStats;
+
+/// Record one executed operation with its latency and outcome
+pub(crate) fn record_command(op: &'static str, elapsed: Duration, ok: bool) {
+    #[cfg(feature = "metrics")]
+    {
+        metrics::counter!("redis_commands_total", "op" => op).increment(1);
+        if !ok {
+            metrics::counter!("redis_command_errors_total", "op" => op).increment(1);
+        }
+        metrics::histogram!("redis_command_duration_seconds", "op" => op)
+            .record(elapsed.as_secs_f64());
+    }
+    #[cfg(not(feature = "metrics"))]
+    let _ = (op, elapsed, ok);
+}
+
+/// Record the retries an insert took and whether it finally failed
+pub(crate) fn record_insert(retries: u32, ok: bool) {
+    #[cfg(feature = "metrics")]
+    {
+        metrics::counter!("redis_insert_retries_total").increment(u64::from(retries));
+        if !ok {
+            metrics::counter!("redis_insert_failures_total").increment(1);
+        }
+    }
+    #[cfg(not(feature = "metrics"))]
+    let _ = (retries, ok);
+}
+
+/// Record a command sent again after a transient error
+pub(crate) fn record_retry(op: &'static str) {
+    #[cfg(feature = "metrics")]
+    metrics::counter!("redis_command_retries_total", "op" => op).increment(1);
+    #[cfg(not(feature = "metrics"))]
+    let _ = op;
+}
+
+/// Record an insert arriving before the connection is ready, held or refused
+pub(crate) fn record_pending_write(held: bool) {
+    #[cfg(feature = "metrics")]
+    {
+        let outcome = if held { "held" } else { "refused" };
+        metrics::counter!("redis_pending_writes_total", "outcome" => outcome).increment(1);
+    }
+    #[cfg(not(feature = "metrics"))]
+    let _ = held;
+}
+
+/// Record an insert above the value limit with the policy applied to it
+pub(crate) fn record_oversized_value(policy: &'static str) {
+    #[cfg(feature = "metrics")]
+    metrics::counter!("redis_oversized_values_total", "policy" => policy).increment(1);
+    #[cfg(not(feature = "metrics"))]
+    let _ = policy;
+}
+
+/// Record a query served by a read of the same key already in flight
+pub(crate) fn record_coalesced_query() {
+    #[cfg(feature = "metrics")]
+    metrics::counter!("redis_coalesced_queries_total").increment(1);
+}
+
+/// Record a write replayed on the mirror, with how long after the primary write it was applied
+pub(crate) fn record_mirror_write(lag: Duration, ok: bool) {
+    #[cfg(feature = "metrics")]
+    {
+        let outcome = if ok { "replayed" } else { "failed" };
+        metrics::counter!("redis_mirror_writes_total", "outcome" => outcome).increment(1);
+        metrics::histogram!("redis_mirror_lag_seconds").record(lag.as_secs_f64());
+    }
+    #[cfg(not(feature = "metrics"))]
+    let _ = (lag, ok);
+}
+
+/// Record a write not replayed on the mirror because too many were waiting
+pub(crate) fn record_mirror_dropped() {
+    #[cfg(feature = "metrics")]
+    metrics::counter!("redis_mirror_writes_total", "outcome" => "dropped").increment(1);
+}
+
+/// Record the writes waiting to be replayed on the mirror
+pub(crate) fn record_mirror_pending(pending: usize) {
+    #[cfg(feature = "metrics")]
+    metrics::gauge!("redis_mirror_pending_writes").set(pending as f64);
+    #[cfg(not(feature = "metrics"))]
+    let _ = pending;
+}
+
+/// Record pool gauges and how long a connection checkout waited
+pub(crate) fn record_pool(stats: PoolStats, wait: Duration) {
+    #[cfg(feature = "metrics")]
+    {
+        metrics::gauge!("redis_pool_connections").set(stats.connections);
+        metrics::gauge!("redis_pool_idle_connections").set(stats.idle_connections);
+        metrics::histogram!("redis_pool_wait_seconds").record(wait.as_secs_f64());
+    }
+    #[cfg(not(feature = "metrics"))]
+    let _ = (stats, wait);
+}
+
+/// Record one key found breaking the expiry policy
+pub(crate) fn record_expiry_finding(issue: &'static str) {
+    #[cfg(feature = "metrics")]
+    metrics::counter!("redis_expiry_audit_findings_total", "issue" => issue).increment(1);
+    #[cfg(not(feature = "metrics"))]
+    let _ = issue;
+}
+
+/// Record the outcome of the last expiry audit run
+pub(crate) fn record_expiry_audit(checked: u64, findings: usize) {
+    #[cfg(feature = "metrics")]
+    {
+        metrics::gauge!("redis_expiry_audit_checked_keys").set(checked as f64);
+        metrics::gauge!("redis_expiry_audit_findings").set(findings as f64);
+    }
+    #[cfg(not(feature = "metrics"))]
+    let _ = (checked, findings);
+}
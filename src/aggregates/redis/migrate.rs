@@ -0,0 +1,128 @@
+//! Key migration between clusters.
+//!
+//! Keys matching a pattern are scanned on every master of the actor's cluster, read with
+//! `DUMP` and `PTTL` and written to the target cluster with `RESTORE`, keeping their TTL. Values
+//! split by chunking only move along when their `key:chunk:N` keys match the pattern too.
+
+use std::ops::ControlFlow;
+
+use bastion::prelude::{AnswerSender, Distributor};
+use r2d2::Pool;
+use redis::RedisResult;
+use serde::{Deserialize, Serialize};
+use tokio::task;
+use tracing::{info, warn};
+
+use super::{
+    connection::{self, RedisConnection},
+    error::RedisError,
+    pool::{checkout, RedisManager},
+    scan::{self, Throttle},
+    Redis,
+};
+
+/// Keys requested per `SCAN` and dumped per pipeline by default
+const DEFAULT_BATCH: usize = 100;
+
+/// Question copying the keys matching `pattern` to the cluster at `target_urls`, replied with
+/// `Result<MigrationProgress, RedisError>` once every master was scanned
+#[derive(Default, Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RedisMigrate {
+    pub pattern: String,
+    /// Node urls of the target cluster
+    pub target_urls: Vec<String>,
+    /// `SCAN COUNT` hint and keys dumped per pipeline, 100 when unset
+    #[serde(default)]
+    pub batch: Option<usize>,
+    /// Keys migrated per second at most
+    #[serde(default)]
+    pub rate_limit: Option<u32>,
+    /// Overwrite keys already on the target, they are skipped otherwise
+    #[serde(default)]
+    pub replace: bool,
+    /// Name of a distributor told a `MigrationProgress` after every batch
+    #[serde(default)]
+    pub progress_to: Option<String>,
+}
+
+/// Keys handled by a migration so far
+#[derive(Default, Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub struct MigrationProgress {
+    pub scanned: u64,
+    pub migrated: u64,
+    /// Keys already on the target, or gone before they were dumped
+    pub skipped: u64,
+}
+
+/// Migrate from a blocking task on its own pooled connection
+pub(crate) fn spawn(
+    pool: &Pool<RedisManager>,
+    redis: &Redis,
+    event: RedisMigrate,
+    sender: AnswerSender,
+) {
+    let pool = pool.clone();
+    let (urls, actor) = (redis.urls.clone(), redis.own_distributor());
+    task::spawn_blocking(move || {
+        let result = checkout(&pool)
+            .map_err(|e| RedisError::Command(e.to_string()))
+            .and_then(|mut conn| {
+                migrate(&mut conn, &urls, &event).map_err(|e| RedisError::Command(e.to_string()))
+            });
+        match &result {
+            Ok(progress) => info!(pattern = event.pattern, ?progress, "migration done"),
+            Err(e) => Redis::report_error(actor, "Migration", e),
+        }
+        // The caller may be gone already
+        let _ = sender.reply(result);
+    });
+}
+
+fn migrate(
+    conn: &mut RedisConnection,
+    urls: &[String],
+    event: &RedisMigrate,
+) -> RedisResult<MigrationProgress> {
+    let mut target = connection::connect(&event.target_urls)?;
+    let progress_to = event.progress_to.clone().map(Distributor::named);
+    let batch = event.batch.unwrap_or(DEFAULT_BATCH).max(1);
+    let mut throttle = Throttle::new(event.rate_limit);
+    let mut progress = MigrationProgress::default();
+
+    scan::scan_masters(conn, urls, &event.pattern, batch, |_, node_conn, keys| {
+        // The scanned node owns the keys, dumped in one round trip
+        let mut pipe = redis::pipe();
+        for key in keys {
+            pipe.cmd("DUMP").arg(key).cmd("PTTL").arg(key);
+        }
+        let dumps: Vec<(Option<Vec<u8>>, i64)> = pipe.query(node_conn)?;
+
+        for (key, (dump, ttl)) in keys.iter().zip(dumps) {
+            progress.scanned += 1;
+            let Some(dump) = dump else {
+                progress.skipped += 1;
+                continue;
+            };
+            let mut restore = redis::cmd("RESTORE");
+            // -1 has no expiry, RESTORE takes 0 for that
+            restore.arg(key).arg(ttl.max(0)).arg(dump);
+            if event.replace {
+                restore.arg("REPLACE");
+            }
+            match restore.query::<()>(&mut target) {
+                Ok(()) => progress.migrated += 1,
+                Err(e) if e.code() == Some("BUSYKEY") => progress.skipped += 1,
+                Err(e) => return Err(e),
+            }
+        }
+
+        if let Some(progress_to) = &progress_to {
+            if let Err(e) = progress_to.tell_one(progress) {
+                warn!("[REDIS] Cannot report migration progress: {e:?}");
+            }
+        }
+        throttle.wait(keys.len());
+        Ok(ControlFlow::Continue(()))
+    })?;
+    Ok(progress)
+}
@@ -0,0 +1,219 @@
+//! Best-effort replay of writes to a secondary actor.
+//!
+//! With `Redis::mirror` set, every successful write is replayed in order to the named actor
+//! instance of the secondary cluster, off the writer, e.g. a warm standby or the target of a live
+//! migration: inserts, tagged inserts, deletes by key, pattern or tag, expiries, imports, rate
+//! limit checks, typed commands other than reads and the TTL refreshed by sliding queries.
+//! `RedisNodeCommand` runs as is on a single node and is never replayed. Writes are dropped and
+//! counted when the secondary falls too far behind, `RedisMirrorBackfill` copies existing keys
+//! over.
+
+use std::{
+    fmt::{self, Debug},
+    future::Future,
+    pin::Pin,
+    time::Instant,
+};
+
+use bastion::prelude::{AnswerSender, Distributor};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+use tracing::warn;
+
+use super::{
+    delete::{RedisDeleteByPattern, RedisDeleteMany},
+    error::RedisError,
+    export::{ExportSink, RedisExport},
+    import::{ConflictPolicy, ImportProgress, RedisImport, IMPORT_BATCH},
+    metrics,
+    ratelimit::{RateLimitDecision, RedisRateLimit},
+    router::ask,
+    tags::{RedisInvalidateTag, RedisTaggedInsert},
+    typed::TypedCommand,
+    Redis, RedisExpire, RedisInsert,
+};
+
+/// Mirror settings
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct MirrorConfig {
+    /// Name of the actor instance running on the secondary cluster
+    pub instance: String,
+    /// Writes waiting to be replayed at most, later ones are dropped
+    pub capacity: usize,
+}
+
+impl Default for MirrorConfig {
+    fn default() -> Self {
+        Self {
+            instance: "mirror".to_owned(),
+            capacity: 10_000,
+        }
+    }
+}
+
+/// Write replayed on the secondary
+#[derive(Debug)]
+pub(crate) enum MirroredWrite {
+    Insert(RedisInsert),
+    TaggedInsert(RedisTaggedInsert),
+    Delete(RedisDeleteMany),
+    DeleteByPattern(RedisDeleteByPattern),
+    InvalidateTag(RedisInvalidateTag),
+    Expire(RedisExpire),
+    Import(RedisImport),
+    RateLimit(RedisRateLimit),
+    Command(MirroredCommand),
+}
+
+/// Reply of a replayed write
+type Replayed = Pin<Box<dyn Future<Output = Result<(), RedisError>> + Send>>;
+
+/// Typed command other than a read, replayed as sent
+pub(crate) struct MirroredCommand {
+    op: &'static str,
+    replay: Box<dyn FnOnce(Distributor) -> Replayed + Send>,
+}
+
+impl MirroredCommand {
+    pub(crate) fn new<C: TypedCommand>(command: C) -> Self {
+        Self {
+            op: C::OP,
+            replay: Box::new(move |target| {
+                Box::pin(async move { ask::<C::Reply>(target, command).await.map(drop) })
+            }),
+        }
+    }
+}
+
+impl Debug for MirroredCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MirroredCommand")
+            .field("op", &self.op)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Queue of writes replayed in order by a background task, which stops once every handle is
+/// dropped
+#[derive(Debug, Clone)]
+pub struct Mirror {
+    writes: mpsc::Sender<(MirroredWrite, Instant)>,
+    target: Distributor,
+}
+
+impl PartialEq for Mirror {
+    fn eq(&self, other: &Self) -> bool {
+        self.writes.same_channel(&other.writes)
+    }
+}
+
+impl Mirror {
+    /// Start replaying to the instance of `config`
+    pub(crate) fn start(config: &MirrorConfig) -> Self {
+        let (writes, pending) = mpsc::channel(config.capacity.max(1));
+        let target = Redis::distributor_named(Some(&config.instance));
+        tokio::spawn(replay(target, pending));
+        Self { writes, target }
+    }
+
+    /// Queue a successful write, dropped when the secondary is too far behind
+    pub(crate) fn send(&self, write: MirroredWrite) {
+        if let Err(e) = self.writes.try_send((write, Instant::now())) {
+            warn!("[REDIS] Mirror write dropped: {e}");
+            metrics::record_mirror_dropped();
+        }
+        metrics::record_mirror_pending(self.writes.max_capacity() - self.writes.capacity());
+    }
+
+    /// Distributor of the secondary
+    pub(crate) fn target(&self) -> Distributor {
+        self.target
+    }
+}
+
+// Replay the queued writes one at a time so the secondary applies them in order
+async fn replay(target: Distributor, mut pending: mpsc::Receiver<(MirroredWrite, Instant)>) {
+    while let Some((write, at)) = pending.recv().await {
+        let result = match write {
+            MirroredWrite::Insert(event) => ask::<()>(target, event).await,
+            MirroredWrite::TaggedInsert(event) => ask::<()>(target, event).await,
+            MirroredWrite::Delete(event) => ask::<u64>(target, event).await.map(drop),
+            MirroredWrite::DeleteByPattern(event) => ask::<u64>(target, event).await.map(drop),
+            MirroredWrite::InvalidateTag(event) => ask::<u64>(target, event).await.map(drop),
+            MirroredWrite::Expire(event) => ask::<bool>(target, event).await.map(drop),
+            MirroredWrite::Import(event) => ask::<ImportProgress>(target, event).await.map(drop),
+            MirroredWrite::RateLimit(event) => {
+                ask::<RateLimitDecision>(target, event).await.map(drop)
+            }
+            MirroredWrite::Command(command) => (command.replay)(target).await,
+        };
+        if let Err(e) = &result {
+            warn!(error = %e, "[REDIS] Mirror write failed");
+        }
+        metrics::record_mirror_write(at.elapsed(), result.is_ok());
+        metrics::record_mirror_pending(pending.len());
+    }
+}
+
+/// Question copying every key matching `pattern` to the mirror, replied with
+/// `Result<ImportProgress, RedisError>`
+#[derive(Default, Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RedisMirrorBackfill {
+    pub pattern: String,
+    /// Keys already on the secondary are kept by default
+    pub policy: ConflictPolicy,
+}
+
+/// Export the keys through `actor` and import them on the mirror in batches
+pub(crate) fn backfill(
+    actor: Distributor,
+    mirror: Option<&Mirror>,
+    event: RedisMirrorBackfill,
+    sender: AnswerSender,
+) {
+    let Some(target) = mirror.map(Mirror::target) else {
+        let _ = sender.reply(Err::<ImportProgress, _>(RedisError::MirrorDisabled));
+        return;
+    };
+    let (sink, mut records) = ExportSink::channel();
+    let export = RedisExport {
+        pattern: event.pattern,
+        batch: None,
+        rate_limit: None,
+        sink,
+    };
+    if let Err(e) = actor.tell_one(export) {
+        let error = RedisError::Command(format!("cannot start the export: {e:?}"));
+        let _ = sender.reply(Err::<ImportProgress, _>(error));
+        return;
+    }
+
+    tokio::spawn(async move {
+        let mut total = ImportProgress::default();
+        let mut batch = vec![];
+        let result = loop {
+            let next = match records.recv().await {
+                Some(Ok(record)) => Some(record),
+                Some(Err(e)) => break Err(e),
+                None => None,
+            };
+            let done = next.is_none();
+            batch.extend(next);
+            if batch.len() == IMPORT_BATCH || (done && !batch.is_empty()) {
+                let import = RedisImport {
+                    records: std::mem::take(&mut batch),
+                    policy: event.policy,
+                };
+                match ask::<ImportProgress>(target, import).await {
+                    Ok(progress) => total.add(progress),
+                    Err(e) => break Err(e),
+                }
+            }
+            if done {
+                break Ok(total);
+            }
+        };
+        // The caller may be gone already
+        let _ = sender.reply(result);
+    });
+}
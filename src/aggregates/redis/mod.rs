@@ -1,39 +1,199 @@
+use std::{
+    fmt::Display,
+    time::{Duration, Instant},
+};
+
 use anyhow::Result;
 use async_trait::async_trait;
 use bastion::{
-    prelude::{AnswerSender, BastionContext, Distributor, Message, MessageHandler},
-    run,
+    prelude::{AnswerSender, BastionContext, Distributor, Message},
     supervisor::{ActorRestartStrategy, RestartPolicy, RestartStrategy},
 };
-use r2d2::ManageConnection;
-use core::fmt::Debug;
-use cqrs_es::Aggregate;
-use log::warn;
-use redis::{
-    cluster::{ClusterClientBuilder, ClusterConnection},
-    Commands, FromRedisValue, RedisError, ToRedisArgs,
-};
+use bytes::Bytes;
+use chrono::Utc;
+use r2d2::Pool;
 use serde::{Deserialize, Serialize};
-use std::{marker::PhantomData, error::Error};
+use tracing::warn;
 
-use crate::actors::base::TActor;
+use crate::actors::{
+    base::{liveness, TActor, TypedDistributor},
+    machine::StateMachine,
+};
 
-use self::{command::RedisCommand, event::RedisEvent};
+use self::{
+    audit::AuditLog,
+    backend::{Backend, RedisBackend},
+    chunked::ChunkingConfig,
+    command::RedisCommand,
+    deferred::PendingWritesConfig,
+    delete::RedisDeleteMany,
+    error::{ErrorStats, RedisError},
+    event::RedisEvent,
+    expiry::ExpiryAuditConfig,
+    failover::FailoverConfig,
+    hotkeys::{HotKeySampler, HotKeysConfig},
+    limit::ValueLimit,
+    mirror::{Mirror, MirrorConfig, MirroredCommand, MirroredWrite},
+    multi::{RedisExists, RedisMultiInsert, RedisMultiQuery},
+    pipeline::PipelineConfig,
+    pool::{ReconnectConfig, RedisManager},
+    ratelimit::{RateLimitDecision, RedisRateLimit},
+    resp3::Resp3Config,
+    retry::{CommandClass, RetryConfig},
+    session::{MemorySession, RedisSession},
+    slowlog::SlowlogConfig,
+    tags::{RedisInvalidateTag, RedisTaggedInsert},
+    typed::TypedCommand,
+};
 
-mod command;
-mod error;
-mod event;
+pub mod access;
+#[cfg(feature = "admin-http")]
+pub mod admin;
+pub mod audit;
+pub mod backend;
+pub mod bigkeys;
+pub mod chunked;
+pub mod coalesce;
+pub mod collection;
+pub mod command;
+pub mod connection;
+pub mod control;
+pub mod deferred;
+pub mod delete;
+pub mod error;
+pub mod event;
+pub mod expiry;
+pub mod export;
+pub mod failover;
+pub mod health;
+pub mod hotkeys;
+pub mod import;
+pub mod keyspace;
+pub mod limit;
+mod metrics;
+pub mod migrate;
+pub mod mirror;
+#[cfg(feature = "monitor")]
+pub mod monitor;
+pub mod multi;
+pub mod node;
+pub mod numeric;
+pub mod object;
+#[cfg(feature = "otel")]
+pub mod otel;
+pub mod pipeline;
+pub mod pool;
+pub mod pubsub;
+pub mod ratelimit;
+pub mod resp3;
+pub mod retry;
+pub mod router;
+pub mod sample;
+mod scan;
+mod session;
+pub mod slowlog;
+pub mod sorted_set;
+pub mod stream;
+pub mod tags;
+pub mod tenancy;
+mod trace;
+pub mod typed;
+pub mod view;
+pub mod warmup;
 
 #[derive(Default, Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct Redis {
     pub state: RedisState,
+    /// Seed nodes of the cluster with the `cluster` feature, the single node at the first url
+    /// otherwise
     pub urls: Vec<String>,
+    /// Hash keys before recording them in tracing spans
+    #[serde(default)]
+    pub hash_trace_keys: bool,
+    /// Periodically collect `SLOWLOG GET` from every node when set
+    #[serde(default)]
+    pub slowlog: Option<SlowlogConfig>,
+    /// Periodically report sampled keys without a TTL, or above a maximum TTL, when set
+    #[serde(default)]
+    pub expiry_audit: Option<ExpiryAuditConfig>,
+    /// Interval of the background health check PING, probes always ping on demand
+    #[serde(default)]
+    pub health_check_interval: Option<Duration>,
+    /// Error counts per kind and the most recent error
+    #[serde(default)]
+    pub errors: ErrorStats,
+    /// Batch data commands arriving close together into one pipeline when set
+    #[serde(default)]
+    pub pipeline: Option<PipelineConfig>,
+    /// Run questions on their own pooled connection in a blocking task instead of queueing them
+    /// behind the actor's connection, ignored while pipelining
+    #[serde(default)]
+    pub parallel_reads: bool,
+    /// Split values above a threshold across several keys when set. Single-key commands then
+    /// skip the pipeline, multi-key commands store values as they are.
+    #[serde(default)]
+    pub chunking: Option<ChunkingConfig>,
+    /// Largest value inserts may write and the policy for larger ones, unlimited when unset
+    #[serde(default)]
+    pub value_limit: Option<ValueLimit>,
+    /// Receive RESP3 server pushes (client tracking invalidations) when set
+    #[serde(default)]
+    pub resp3: Option<Resp3Config>,
+    /// Connection retries while the cluster cannot be reached
+    #[serde(default)]
+    pub reconnect: ReconnectConfig,
+    /// Move to a secondary cluster once this one fails its health checks for long enough, when
+    /// set. Needs `health_check_interval`.
+    #[serde(default)]
+    pub failover: Option<FailoverConfig>,
+    /// Commands retried on the actor's connection after a transient error, per class
+    #[serde(default)]
+    pub retry: RetryConfig,
+    /// Hold inserts arriving before the connection is ready and run them once it is, when set.
+    /// They are refused with `RedisError::NotReady` otherwise.
+    #[serde(default)]
+    pub pending_writes: Option<PendingWritesConfig>,
+    /// Number of children answering reads in their own group next to the single writer,
+    /// reads go to the writer when zero
+    #[serde(default)]
+    pub readers: usize,
+    /// Roll the liveness counts of every child on this tick and answer `LivenessQuery`, when set
+    #[serde(default)]
+    pub heartbeat_tick: Option<Duration>,
+    /// Share one `GET` between the callers of `try_query` asking for the same key at the same time
+    #[serde(default)]
+    pub coalesce_queries: bool,
+    /// Sample queries and inserts to report the hottest keys of every window, when set
+    #[serde(default)]
+    pub hot_keys: Option<HotKeysConfig>,
+    /// Counts started from `hot_keys`, shared by the writer and the readers
+    #[serde(skip)]
+    pub hot_key_sampler: HotKeySampler,
+    /// Records every mutating command when enabled
+    #[serde(skip)]
+    pub audit: AuditLog,
+    /// Where data commands run, the cluster unless an in-memory backend is selected
+    #[serde(skip)]
+    pub backend: Backend,
+    /// Name the actor was built with, it picks the distributors. Set by the actor.
+    #[serde(skip)]
+    pub instance: Option<String>,
+    /// Replay successful writes to a secondary actor when set
+    #[serde(default)]
+    pub mirror: Option<MirrorConfig>,
+    /// Mirror started by the writer from `mirror`
+    #[serde(skip)]
+    pub mirroring: Option<Mirror>,
 }
 
+/// Connection lifecycle of the actor, data commands only run once `Initialized`
 #[derive(Default, Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum RedisState {
+    /// Not started, or gave up connecting
     #[default]
     Uninitialized,
+    /// Waiting for the cluster, retried with backoff
+    Connecting,
     Initialized,
 }
 
@@ -47,8 +207,538 @@ impl Redis {
     fn get_urls(&self) -> Vec<String> {
         self.urls.clone()
     }
+
+    // Reports an error to the actor behind `actor` so it is counted in its aggregate
+    fn report_error(actor: Distributor, kind: &str, error: &dyn Display) {
+        let event = RedisEvent::RedisErrorOccurred {
+            kind: kind.to_owned(),
+            error: error.to_string(),
+            at: Some(Utc::now()),
+        };
+        if let Err(e) = actor.tell_one(event) {
+            warn!("[REDIS] Cannot report error: {e:?}");
+        }
+    }
+
+    // Reports a write the value limit refused when nobody waits for its reply
+    fn report_dropped(actor: Distributor, error: &RedisError) {
+        Self::report_error(actor, "ValueLimit", error);
+    }
+
+    // Reports a failed Redis call under the kind of its error
+    fn report_redis_error(actor: Distributor, error: &redis::RedisError) {
+        Self::report_error(actor, &format!("{:?}", error.kind()), error);
+    }
+
+    // Reports a failed Redis call and turns it into the error replied to the caller
+    fn command_error(actor: Distributor, error: redis::RedisError) -> RedisError {
+        Self::report_redis_error(actor, &error);
+        RedisError::Command(error.to_string())
+    }
+
+    // Refuses a question replied with `Result<T, RedisError>` before the connection is ready
+    pub(crate) fn not_ready<T: Message>(sender: AnswerSender) {
+        let reply: Result<T, RedisError> = Err(RedisError::NotReady);
+        // The caller may be gone already
+        let _ = sender.reply(reply);
+    }
+
+    // Runs a query, replied with `Result<Option<Vec<u8>>, RedisError>` even when not ready
+    fn run_query(&self, event: RedisQuery, sender: AnswerSender) -> Option<Blocking> {
+        self.hot_key_sampler.record(&event.key);
+        if let RedisState::Initialized = self.get_state() {
+            let (hash_trace_keys, chunking) = (self.hash_trace_keys, self.chunking);
+            let (retry, actor) = (self.retry, self.own_distributor());
+            return Some(Box::new(move |conn| {
+                let result = Self::get(conn, &event.key, hash_trace_keys, chunking, retry, actor);
+                // The caller may be gone already
+                let _ = sender.reply(result);
+            }));
+        }
+        Self::not_ready::<Option<Vec<u8>>>(sender);
+        None
+    }
+
+    // Runs a query re-applying the TTL of the key, refused until the connection is initialized
+    fn run_sliding_query(
+        &self,
+        event: RedisSlidingQuery,
+        sender: AnswerSender,
+    ) -> Option<Blocking> {
+        self.hot_key_sampler.record(&event.key);
+        if let RedisState::Initialized = self.get_state() {
+            let (hash_trace_keys, chunking) = (self.hash_trace_keys, self.chunking);
+            let (retry, actor) = (self.retry, self.own_distributor());
+            let mirror = self.mirroring.clone();
+            return Some(Box::new(move |conn| {
+                let (result, _) = retry.run(CommandClass::Write, "getex", || {
+                    trace::command("getex", &event.key, hash_trace_keys, || match chunking {
+                        Some(_) => chunked::getex(conn, &event.key, event.ttl),
+                        None => conn.getex(&event.key, event.ttl),
+                    })
+                });
+                if let (Ok(Some(_)), Some(mirror)) = (&result, &mirror) {
+                    mirror.send(MirroredWrite::Expire(RedisExpire {
+                        key: event.key.clone(),
+                        ttl: event.ttl,
+                        caller: None,
+                    }));
+                }
+                let result = result
+                    .map_err(|e| Self::command_error(actor, e))
+                    .and_then(|value| value.map(limit::inflate).transpose());
+                // The caller may be gone already
+                let _ = sender.reply(result);
+            }));
+        }
+        Self::not_ready::<Option<Vec<u8>>>(sender);
+        None
+    }
+
+    // Insert as stored under the value limit, refused when it is too large
+    pub(crate) fn fit(&self, mut event: RedisInsert) -> Result<RedisInsert, RedisError> {
+        if let Some(limit) = &self.value_limit {
+            event.value = limit.apply(&event.key, event.value)?;
+        }
+        Ok(event)
+    }
+
+    // GET without access to the aggregate, so it can also run off the actor
+    fn get(
+        conn: &mut dyn RedisBackend,
+        key: &str,
+        hash_trace_keys: bool,
+        chunking: Option<ChunkingConfig>,
+        retry: RetryConfig,
+        actor: Distributor,
+    ) -> Result<Option<Vec<u8>>, RedisError> {
+        let (result, _) = retry.run(CommandClass::Read, "get", || {
+            trace::command("get", key, hash_trace_keys, || match conn.get(key)? {
+                Some(value) if chunking.is_some() => chunked::resolve(conn, key, value).map(Some),
+                value => Ok(value),
+            })
+        });
+        result
+            .map_err(|e| Self::command_error(actor, e))?
+            .map(limit::inflate)
+            .transpose()
+    }
+
+    // Runs a multi-key query on pooled connections, replied with
+    // `Result<Vec<Option<Bytes>>, RedisError>` even when not ready
+    fn run_multi_query(
+        &self,
+        pool: &Pool<RedisManager>,
+        event: RedisMultiQuery,
+        sender: AnswerSender,
+    ) -> Option<Blocking> {
+        if let RedisState::Initialized = self.get_state() {
+            let (pool, urls) = (pool.clone(), self.urls.clone());
+            let (hash_trace_keys, chunking) = (self.hash_trace_keys, self.chunking);
+            let actor = self.own_distributor();
+            return Some(Box::new(move |_| {
+                let result = multi::mget(&pool, &urls, &event.keys, hash_trace_keys, chunking)
+                    .map_err(|e| Self::command_error(actor, e))
+                    .and_then(|values| {
+                        values
+                            .into_iter()
+                            .map(|value| {
+                                value
+                                    .map(|value| limit::inflate(value.to_vec()).map(Bytes::from))
+                                    .transpose()
+                            })
+                            .collect::<Result<Vec<_>, _>>()
+                    });
+                // The caller may be gone already
+                let _ = sender.reply(result);
+            }));
+        }
+        Self::not_ready::<Vec<Option<Bytes>>>(sender);
+        None
+    }
+
+    // Runs EXISTS on several keys on pooled connections, replied with
+    // `Result<Vec<bool>, RedisError>` even when not ready
+    fn run_exists(
+        &self,
+        pool: &Pool<RedisManager>,
+        event: RedisExists,
+        sender: AnswerSender,
+    ) -> Option<Blocking> {
+        if let RedisState::Initialized = self.get_state() {
+            let (pool, urls) = (pool.clone(), self.urls.clone());
+            let (hash_trace_keys, actor) = (self.hash_trace_keys, self.own_distributor());
+            return Some(Box::new(move |_| {
+                let result = multi::exists(&pool, &urls, &event.keys, hash_trace_keys)
+                    .map_err(|e| Self::command_error(actor, e));
+                // The caller may be gone already
+                let _ = sender.reply(result);
+            }));
+        }
+        Self::not_ready::<Vec<bool>>(sender);
+        None
+    }
+
+    // Runs a multi-key insert on pooled connections, dropped until the connection is
+    // initialized
+    fn run_multi_insert(
+        &self,
+        pool: &Pool<RedisManager>,
+        mut event: RedisMultiInsert,
+    ) -> Option<Blocking> {
+        if self.get_state() != RedisState::Initialized {
+            return None;
+        }
+        // Values refused by the limit are reported and left out, the others are still written
+        if let Some(limit) = &self.value_limit {
+            let actor = self.own_distributor();
+            event.entries = std::mem::take(&mut event.entries)
+                .into_iter()
+                .filter_map(|(key, value)| match limit.apply(&key, value) {
+                    Ok(value) => Some((key, value)),
+                    Err(e) => {
+                        Self::report_dropped(actor, &e);
+                        None
+                    }
+                })
+                .collect();
+        }
+        let (pool, urls) = (pool.clone(), self.urls.clone());
+        let (hash_trace_keys, chunking) = (self.hash_trace_keys, self.chunking);
+        let actor = self.own_distributor();
+        let (audit, mirroring) = (self.audit.clone(), self.mirroring.clone());
+        Some(Box::new(move |_| {
+            let result = multi::mset(&pool, &urls, &event.entries, hash_trace_keys, chunking);
+            for (key, value) in &event.entries {
+                audit.record(
+                    "set",
+                    key,
+                    value.len(),
+                    event.caller.as_deref(),
+                    result.is_ok(),
+                );
+            }
+            match result {
+                Ok(()) => {
+                    if let Some(mirror) = &mirroring {
+                        for (key, value) in event.entries {
+                            mirror.send(MirroredWrite::Insert(RedisInsert {
+                                key,
+                                value,
+                                ttl: None,
+                                caller: event.caller.clone(),
+                            }));
+                        }
+                    }
+                }
+                Err(e) => Self::report_redis_error(actor, &e),
+            }
+        }))
+    }
+
+    // Runs a multi-key delete on pooled connections, replied with `Result<u64, RedisError>`
+    // even when not ready
+    fn run_delete_many(
+        &self,
+        pool: &Pool<RedisManager>,
+        event: RedisDeleteMany,
+        sender: AnswerSender,
+    ) -> Option<Blocking> {
+        if let RedisState::Initialized = self.get_state() {
+            let (pool, urls) = (pool.clone(), self.urls.clone());
+            let (hash_trace_keys, chunked) = (self.hash_trace_keys, self.chunking.is_some());
+            let (audit, mirroring, actor) = (
+                self.audit.clone(),
+                self.mirroring.clone(),
+                self.own_distributor(),
+            );
+            return Some(Box::new(move |_| {
+                let result =
+                    delete::unlink_many(&pool, &urls, &event.keys, hash_trace_keys, chunked);
+                let result = delete::finish_many(&audit, mirroring.as_ref(), actor, &event, result);
+                // The caller may be gone already
+                let _ = sender.reply(result);
+            }));
+        }
+        Self::not_ready::<u64>(sender);
+        None
+    }
+
+    // Runs an insert, dropped until the connection is initialized. An asked insert is replied
+    // with `Result<(), RedisError>`.
+    fn run_insert(&self, event: RedisInsert, sender: Option<AnswerSender>) -> Option<Blocking> {
+        self.hot_key_sampler.record(&event.key);
+        if let RedisState::Initialized = self.get_state() {
+            let event = match self.fit(event) {
+                Ok(event) => event,
+                Err(e) => {
+                    match sender {
+                        Some(sender) => {
+                            let _ = sender.reply(Err::<(), _>(e));
+                        }
+                        None => Self::report_dropped(self.own_distributor(), &e),
+                    }
+                    return None;
+                }
+            };
+            let (hash_trace_keys, chunking) = (self.hash_trace_keys, self.chunking);
+            let audit = self.audit.clone();
+            let (retry, actor) = (self.retry, self.own_distributor());
+            let mirror = self.mirroring.clone();
+            return Some(Box::new(move |conn| {
+                let copy = mirror.as_ref().map(|_| event.clone());
+                let result =
+                    Self::set(conn, event, hash_trace_keys, chunking, &audit, retry, actor);
+                if let (Ok(()), Some(mirror), Some(copy)) = (&result, &mirror, copy) {
+                    mirror.send(MirroredWrite::Insert(copy));
+                }
+                if let Some(sender) = sender {
+                    // The caller may be gone already
+                    let _ = sender.reply(result);
+                }
+            }));
+        }
+        if let Some(sender) = sender {
+            Self::not_ready::<()>(sender);
+        }
+        None
+    }
+
+    // Runs a tagged insert, tagging the key first, refused until the connection is initialized
+    fn run_tagged_insert(
+        &self,
+        event: RedisTaggedInsert,
+        sender: AnswerSender,
+    ) -> Option<Blocking> {
+        if let RedisState::Initialized = self.get_state() {
+            let RedisTaggedInsert { insert, tags } = event;
+            let insert = match self.fit(insert) {
+                Ok(insert) => insert,
+                Err(e) => {
+                    let _ = sender.reply(Err::<(), _>(e));
+                    return None;
+                }
+            };
+            let (hash_trace_keys, chunking) = (self.hash_trace_keys, self.chunking);
+            let audit = self.audit.clone();
+            let (retry, actor) = (self.retry, self.own_distributor());
+            let mirror = self.mirroring.clone();
+            return Some(Box::new(move |conn| {
+                let (tagged, _) = retry.run(CommandClass::Write, "sadd", || {
+                    trace::command("sadd", &insert.key, hash_trace_keys, || {
+                        tags::tag(conn, &insert.key, &tags)
+                    })
+                });
+                let tagged = tagged.map_err(|e| Self::command_error(actor, e));
+                let copy = mirror.as_ref().map(|_| insert.clone());
+                let result = tagged.and_then(|()| {
+                    Self::set(
+                        conn,
+                        insert,
+                        hash_trace_keys,
+                        chunking,
+                        &audit,
+                        retry,
+                        actor,
+                    )
+                });
+                if let (Ok(()), Some(mirror), Some(insert)) = (&result, &mirror, copy) {
+                    mirror.send(MirroredWrite::TaggedInsert(RedisTaggedInsert {
+                        insert,
+                        tags,
+                    }));
+                }
+                // The caller may be gone already
+                let _ = sender.reply(result);
+            }));
+        }
+        Self::not_ready::<()>(sender);
+        None
+    }
+
+    // Deletes the keys of a tag, refused until the connection is initialized
+    fn run_invalidate_tag(
+        &self,
+        event: RedisInvalidateTag,
+        sender: AnswerSender,
+    ) -> Option<Blocking> {
+        if let RedisState::Initialized = self.get_state() {
+            let (hash_trace_keys, chunked) = (self.hash_trace_keys, self.chunking.is_some());
+            let audit = self.audit.clone();
+            let (retry, actor) = (self.retry, self.own_distributor());
+            let mirror = self.mirroring.clone();
+            return Some(Box::new(move |conn| {
+                let (result, _) = retry.run(CommandClass::Write, "unlink", || {
+                    trace::command("unlink", &event.tag, hash_trace_keys, || {
+                        tags::invalidate(conn, &event.tag, chunked)
+                    })
+                });
+                audit.record(
+                    "invalidate",
+                    &tags::tag_key(&event.tag),
+                    0,
+                    event.caller.as_deref(),
+                    result.is_ok(),
+                );
+                if let (Ok(_), Some(mirror)) = (&result, &mirror) {
+                    mirror.send(MirroredWrite::InvalidateTag(event.clone()));
+                }
+                let result = result.map_err(|e| Self::command_error(actor, e));
+                // The caller may be gone already
+                let _ = sender.reply(result);
+            }));
+        }
+        Self::not_ready::<u64>(sender);
+        None
+    }
+
+    // Runs a typed command, refused until the connection is initialized
+    fn run_command<C: TypedCommand>(&self, command: C, sender: AnswerSender) -> Option<Blocking> {
+        if let RedisState::Initialized = self.get_state() {
+            let hash_trace_keys = self.hash_trace_keys;
+            let audit = self.audit.clone();
+            let (retry, actor) = (self.retry, self.own_distributor());
+            let mirror = self.mirroring.clone().filter(|_| !C::READ_ONLY);
+            let class = match (C::READ_ONLY, C::IDEMPOTENT) {
+                (true, _) => CommandClass::Read,
+                (false, true) => CommandClass::Write,
+                (false, false) => CommandClass::NonIdempotent,
+            };
+            return Some(Box::new(move |conn| {
+                let (result, _) = retry.run(class, C::OP, || {
+                    trace::command(C::OP, command.key(), hash_trace_keys, || {
+                        command.parse(&conn.command(&command.cmd())?)
+                    })
+                });
+                if !C::READ_ONLY {
+                    audit.record(C::OP, command.key(), 0, None, result.is_ok());
+                }
+                if let (Ok(_), Some(mirror)) = (&result, &mirror) {
+                    mirror.send(MirroredWrite::Command(MirroredCommand::new(command)));
+                }
+                let result = result.map_err(|e| Self::command_error(actor, e));
+                // The caller may be gone already
+                let _ = sender.reply(result);
+            }));
+        }
+        Self::not_ready::<C::Reply>(sender);
+        None
+    }
+
+    // Runs a PTTL, refused until the connection is initialized
+    fn run_ttl(&self, event: RedisTtlQuery, sender: AnswerSender) -> Option<Blocking> {
+        if let RedisState::Initialized = self.get_state() {
+            let hash_trace_keys = self.hash_trace_keys;
+            let (retry, actor) = (self.retry, self.own_distributor());
+            return Some(Box::new(move |conn| {
+                let (result, _) = retry.run(CommandClass::Read, "pttl", || {
+                    trace::command("pttl", &event.key, hash_trace_keys, || conn.ttl(&event.key))
+                });
+                let result = result.map_err(|e| Self::command_error(actor, e));
+                // The caller may be gone already
+                let _ = sender.reply(result);
+            }));
+        }
+        Self::not_ready::<Ttl>(sender);
+        None
+    }
+
+    // Checks a rate limit, refused until the connection is initialized
+    fn run_rate_limit(&self, event: RedisRateLimit, sender: AnswerSender) -> Option<Blocking> {
+        if let RedisState::Initialized = self.get_state() {
+            let hash_trace_keys = self.hash_trace_keys;
+            let (retry, actor) = (self.retry, self.own_distributor());
+            let local = self.backend.as_memory().is_some();
+            let mirror = self.mirroring.clone();
+            return Some(Box::new(move |conn| {
+                let (key, gcra, cost) = (&event.key, &event.gcra, event.cost);
+                let (result, _) = retry.run(CommandClass::NonIdempotent, "gcra", || {
+                    trace::command("gcra", key, hash_trace_keys, || match local {
+                        true => ratelimit::check_local(conn, key, gcra, cost),
+                        false => ratelimit::check(conn, key, gcra, cost),
+                    })
+                });
+                if let (Ok(_), Some(mirror)) = (&result, &mirror) {
+                    mirror.send(MirroredWrite::RateLimit(event.clone()));
+                }
+                let result = result.map_err(|e| Self::command_error(actor, e));
+                // The caller may be gone already
+                let _ = sender.reply(result);
+            }));
+        }
+        Self::not_ready::<RateLimitDecision>(sender);
+        None
+    }
+
+    // Runs a PEXPIRE, refused until the connection is initialized
+    fn run_expire(&self, event: RedisExpire, sender: AnswerSender) -> Option<Blocking> {
+        if let RedisState::Initialized = self.get_state() {
+            let (hash_trace_keys, chunking) = (self.hash_trace_keys, self.chunking);
+            let audit = self.audit.clone();
+            let (retry, actor) = (self.retry, self.own_distributor());
+            let mirror = self.mirroring.clone();
+            return Some(Box::new(move |conn| {
+                let (result, _) = retry.run(CommandClass::Write, "pexpire", || {
+                    trace::command("pexpire", &event.key, hash_trace_keys, || match chunking {
+                        Some(_) => chunked::expire(conn, &event.key, event.ttl),
+                        None => conn.expire(&event.key, event.ttl),
+                    })
+                });
+                audit.record(
+                    "pexpire",
+                    &event.key,
+                    0,
+                    event.caller.as_deref(),
+                    result.is_ok(),
+                );
+                if let (Ok(_), Some(mirror)) = (&result, &mirror) {
+                    mirror.send(MirroredWrite::Expire(event.clone()));
+                }
+                let result = result.map_err(|e| Self::command_error(actor, e));
+                // The caller may be gone already
+                let _ = sender.reply(result);
+            }));
+        }
+        Self::not_ready::<bool>(sender);
+        None
+    }
+
+    // SET without access to the aggregate, so it can also run off the actor. Transient failures
+    // are retried a few times before the insert counts as failed.
+    fn set(
+        conn: &mut dyn RedisBackend,
+        event: RedisInsert,
+        hash_trace_keys: bool,
+        chunking: Option<ChunkingConfig>,
+        audit: &AuditLog,
+        retry: RetryConfig,
+        actor: Distributor,
+    ) -> Result<(), RedisError> {
+        let size = event.value.len();
+        let (result, retries) = retry.run(CommandClass::Write, "set", || {
+            trace::command("set", &event.key, hash_trace_keys, || match chunking {
+                Some(config) => chunked::set(conn, &event.key, &event.value, event.ttl, config),
+                None => conn.set(&event.key, &event.value, event.ttl),
+            })
+        });
+        metrics::record_insert(retries, result.is_ok());
+        audit.record(
+            "set",
+            &event.key,
+            size,
+            event.caller.as_deref(),
+            result.is_ok(),
+        );
+        result.map_err(|e| Self::command_error(actor, e))
+    }
 }
 
+/// Synchronous call on the actor's connection, run on a blocking thread so Redis round trips
+/// don't hold up the async workers shared with every other actor
+pub(crate) type Blocking = Box<dyn FnOnce(&mut dyn RedisBackend) + Send>;
+
+/// Question for the value of a key, replied with `Result<Option<Vec<u8>>, RedisError>`: `None`
+/// for a missing key, `RedisError::NotReady` before the connection is initialized
 #[derive(Default, Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct RedisQuery {
     pub key: String,
@@ -60,10 +750,27 @@ impl RedisQuery {
     }
 }
 
+/// Question for the value of a key that also expires it `ttl` from now, so values read often
+/// stay alive (sliding expiration). Replied like `RedisQuery`. It changes the key, so it is served
+/// by the writer and never by a read group.
+#[derive(Default, Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RedisSlidingQuery {
+    pub key: String,
+    pub ttl: Duration,
+}
+
+/// Stores a value. Told, failures are only logged and counted; asked, it is replied with
+/// `Result<(), RedisError>` once written.
 #[derive(Default, Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct RedisInsert {
     pub key: String,
-    pub value: Vec<u8>,
+    pub value: Bytes,
+    /// Expire the key after this long, set by the same `SET ... PX` as the value
+    #[serde(default)]
+    pub ttl: Option<Duration>,
+    /// Caller tag recorded by the audit log
+    #[serde(default)]
+    pub caller: Option<String>,
 }
 
 impl RedisInsert {
@@ -73,28 +780,61 @@ impl RedisInsert {
             ..Default::default()
         }
     }
+
+    pub fn with_caller(mut self, caller: impl Into<String>) -> Self {
+        self.caller = Some(caller.into());
+        self
+    }
+
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
 }
 
-/// Implement Aggregate trait for Redis Aggregate
-#[async_trait]
-impl Aggregate for Redis {
+/// Question setting the TTL of an existing key without rewriting its value, replied with
+/// `Result<bool, RedisError>`, false when the key does not exist
+#[derive(Default, Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RedisExpire {
+    pub key: String,
+    pub ttl: Duration,
+    /// Caller tag recorded by the audit log
+    #[serde(default)]
+    pub caller: Option<String>,
+}
+
+/// Question for the remaining lifetime of a key, replied with `Result<Ttl, RedisError>`
+#[derive(Default, Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RedisTtlQuery {
+    pub key: String,
+}
+
+/// Remaining lifetime of a key
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum Ttl {
+    NoKey,
+    NoExpiry,
+    Expires(Duration),
+}
+
+/// Question asking the actor for its current `RedisStatus`
+#[derive(Default, Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RedisStatusQuery;
+
+impl StateMachine for Redis {
     type Command = RedisCommand;
 
     type Event = RedisEvent;
 
     type Error = RedisError;
 
-    type Services = ();
-
-    fn aggregate_type() -> String {
+    fn machine_type() -> String {
         "redis".to_owned()
     }
 
-    async fn handle(
-        &self,
-        command: Self::Command,
-        _: &Self::Services,
-    ) -> Result<Vec<Self::Event>, Self::Error> {
+    fn handle(&self, command: Self::Command) -> Result<Vec<Self::Event>, Self::Error> {
+        command.validate()?;
+
         let mut events = vec![];
         match command {
             RedisCommand::ReconnectRedisServer { urls } => {
@@ -103,6 +843,18 @@ impl Aggregate for Redis {
             RedisCommand::ConnectRedisServer { urls } => {
                 events.push(RedisEvent::RedisServerConnected { urls });
             }
+            RedisCommand::DisconnectRedisServer { error } => {
+                events.push(RedisEvent::RedisServerDisconnected { error });
+            }
+            RedisCommand::AbandonRedisServer { error, attempts } => {
+                events.push(RedisEvent::RedisServerAbandoned { error, attempts });
+            }
+            RedisCommand::FailOverRedisServer { urls } => {
+                events.push(RedisEvent::RedisServerFailedOver {
+                    from: self.get_urls(),
+                    to: urls,
+                });
+            }
         }
         Ok(events)
     }
@@ -113,51 +865,119 @@ impl Aggregate for Redis {
                 self.state = RedisState::Initialized;
                 self.urls = urls;
             }
-            RedisEvent::RedisServerReconnected { urls } => {
+            RedisEvent::RedisServerReconnected { urls }
+            | RedisEvent::RedisServerFailedOver { to: urls, .. } => {
                 self.urls = urls;
             }
+            RedisEvent::RedisServerDisconnected { .. } => {
+                self.state = RedisState::Connecting;
+            }
+            RedisEvent::RedisServerAbandoned { .. } => {
+                self.state = RedisState::Uninitialized;
+            }
+            RedisEvent::RedisErrorOccurred { kind, error, at } => {
+                self.errors.record(&kind, &error, at);
+            }
         }
     }
 }
 
-pub struct RedisManager {
-    urls: Vec<String>,
+/// The same state machine as a cqrs-es aggregate
+#[cfg(feature = "cqrs")]
+#[async_trait]
+impl cqrs_es::Aggregate for Redis {
+    type Command = RedisCommand;
+
+    type Event = RedisEvent;
+
+    type Error = RedisError;
+
+    type Services = ();
+
+    fn aggregate_type() -> String {
+        Self::machine_type()
+    }
+
+    async fn handle(
+        &self,
+        command: Self::Command,
+        _: &Self::Services,
+    ) -> Result<Vec<Self::Event>, Self::Error> {
+        StateMachine::handle(self, command)
+    }
+
+    fn apply(&mut self, event: Self::Event) {
+        StateMachine::apply(self, event)
+    }
 }
 
-impl RedisManager {
-    fn get_urls(&self) -> Vec<String> {
-        self.urls.clone()
+#[cfg(feature = "cqrs")]
+impl crate::actors::cqrs::CqrsAggregate for Redis {
+    fn distributor() -> Distributor {
+        Self::distributor()
     }
 }
 
-impl ManageConnection for RedisManager {
-    type Connection = ClusterConnection;
+impl Redis {
+    /// Distributor of the unnamed instance, its commands and events go through it
+    pub fn distributor() -> Distributor {
+        Self::distributor_named(None)
+    }
+
+    /// Distributor of the read children group
+    pub fn reader_distributor() -> Distributor {
+        Self::reader_distributor_named(None)
+    }
+
+    /// Distributor of the instance built with `name`, `"redis_actor"` for an unnamed one
+    pub fn distributor_named(name: Option<&str>) -> Distributor {
+        match name {
+            Some(name) => Distributor::named(format!("redis_actor:{name}")),
+            None => Distributor::named("redis_actor"),
+        }
+    }
 
-    type Error = redis::RedisError;
+    /// Distributor of the read children group of the instance built with `name`,
+    /// `"redis_reader"` for an unnamed one
+    pub fn reader_distributor_named(name: Option<&str>) -> Distributor {
+        match name {
+            Some(name) => Distributor::named(format!("redis_reader:{name}")),
+            None => Distributor::named("redis_reader"),
+        }
+    }
 
-    fn connect(&self) -> Result<Self::Connection, Self::Error> {
-        let conn = ClusterClientBuilder::new(self.get_urls())
-            .build()
-            .unwrap()
-            .get_connection();
-        conn
-            
-        
+    /// Writer of the instance built with `name`, only sending `M` and expecting `R` back
+    pub fn typed<M: Message, R: Message>(name: Option<&str>) -> TypedDistributor<M, R> {
+        TypedDistributor::new(Self::distributor_named(name))
     }
 
-    fn is_valid(&self, conn: &mut Self::Connection) -> Result<(), redis::RedisError> {
-        return Ok(())
+    /// Read group of the instance built with `name`, only sending `M` and expecting `R` back
+    pub fn typed_reader<M: Message, R: Message>(name: Option<&str>) -> TypedDistributor<M, R> {
+        TypedDistributor::new(Self::reader_distributor_named(name))
     }
 
-    fn has_broken(&self, conn: &mut Self::Connection) -> bool {
-        return false
+    /// Distributor of this instance, its internal commands and events go through it
+    pub fn own_distributor(&self) -> Distributor {
+        Self::distributor_named(self.instance.as_deref())
     }
 }
 
 #[async_trait]
 impl TActor for Redis {
     fn with_distributor() -> Option<Distributor> {
-        Some(Distributor::named("redis_actor"))
+        Some(Self::distributor())
+    }
+
+    fn with_named_distributor(name: &str) -> Option<Distributor> {
+        Some(Self::distributor_named(Some(name)))
+    }
+
+    fn with_named_read_distributor(name: &str) -> Option<Distributor> {
+        Some(Self::reader_distributor_named(Some(name)))
+    }
+
+    fn with_instance_name(&mut self, name: &str) {
+        self.instance = Some(name.to_owned());
     }
 
     /// Actor behaviours if it failed
@@ -168,69 +988,91 @@ impl TActor for Redis {
         ))
     }
 
-    async fn handler(&mut self, ctx: BastionContext) -> Result<(), ()> {
-        // let mut conn = ClusterClientBuilder::new(self.get_urls())
-        //     .build()
-        //     .unwrap()
-        //     .get_connection()
-        //     .unwrap();
-
-        let manager = RedisManager {
-            urls: self.get_urls()
-        };
-
-        let pool = r2d2::Pool::builder()
-            .max_size(15)
-            .build(manager)
-            .unwrap();
+    fn with_read_distributor() -> Option<Distributor> {
+        Some(Self::reader_distributor())
+    }
 
-        let mut conn = pool.get().unwrap();
+    async fn read_handler(&mut self, ctx: BastionContext) -> Result<(), ()> {
+        if let Some(backend) = self.backend.as_memory() {
+            let mut session = MemorySession::reader(self, backend.clone());
+            loop {
+                session.handle(self, ctx.recv().await?);
+                liveness::processed();
+            }
+        }
 
-        Distributor::named("redis_actor")
-            .tell_one(RedisCommand::ConnectRedisServer {
-                urls: self.get_urls(),
-            })
-            .unwrap();
+        let mut session = RedisSession::reader(self);
 
         loop {
-            MessageHandler::new(ctx.recv().await?)
-                .on_tell(|command: RedisCommand, _| {
-                    run!(async {
-                        let events = self.handle(command, &()).await.unwrap();
-                        for e in events {
-                            Distributor::named("redis_actor").tell_one(e).unwrap();
-                        }
-                    });
-                })
-                .on_tell(|event: RedisEvent, _| {
-                    self.apply(event.clone());
-                    run!(async {
-                        match event {
-                            RedisEvent::RedisServerReconnected { urls } => {
-                                // conn = ClusterClientBuilder::new(urls)
-                                //     .build()
-                                //     .unwrap()
-                                //     .get_connection()
-                                //     .unwrap();
-                                
-                                conn = pool.get().unwrap();
-                            }
-                            RedisEvent::RedisServerConnected { urls: _ } => {}
+            session.handle_read(self, ctx.recv().await?);
+            session.run_blocking().await;
+            liveness::processed();
+        }
+    }
+
+    async fn handler(&mut self, ctx: BastionContext) -> Result<(), ()> {
+        self.mirroring = self.mirror.as_ref().map(Mirror::start);
+        if let Some(config) = self
+            .hot_keys
+            .as_ref()
+            .filter(|_| !self.hot_key_sampler.is_on())
+        {
+            self.hot_key_sampler = HotKeySampler::new(config);
+        }
+        let mut lane = control::open(self.own_distributor());
+        if let Some(backend) = self.backend.as_memory() {
+            let mut session = MemorySession::start(self, backend.clone());
+            loop {
+                // Control messages go first however long the mailbox is
+                tokio::select! {
+                    biased;
+                    Some(request) = lane.recv() => {
+                        if session.control(self, request).is_break() {
+                            return Ok(());
                         }
-                    });
-                })
-                .on_question(|event: RedisQuery, sender| {
-                    if let RedisState::Initialized = self.get_state() {
-                        let result: Vec<u8> = conn.get(event.key).unwrap();
-                        sender.reply(result).expect("cannot reply");
                     }
-                })
-                .on_tell(|event: RedisInsert, _| {
-                    if let RedisState::Initialized = self.get_state() {
-                        let _: () = conn.set(event.key, event.value).unwrap();
+                    msg = ctx.recv() => session.handle(self, msg?),
+                }
+                liveness::processed();
+            }
+        }
+
+        let mut session = RedisSession::start(self);
+
+        loop {
+            // Control messages go first however long the mailbox is
+            tokio::select! {
+                biased;
+                Some(request) = lane.recv() => {
+                    if session.control(self, request).is_break() {
+                        return Ok(());
                     }
-                })
-                .on_fallback(|unknown, _| warn!("[REDIS] Unknown message: {unknown:?}"));
+                    liveness::processed();
+                    continue;
+                }
+                msg = ctx.recv() => session.handle(self, msg?),
+            }
+            session.run_blocking().await;
+            liveness::processed();
+
+            // Keep collecting data commands for the rest of the window, then send them at once
+            if let Some(config) = self.pipeline.clone() {
+                let deadline = Instant::now() + config.window;
+                while session.pending() > 0 && session.pending() < config.max_commands {
+                    let Some(left) = deadline.checked_duration_since(Instant::now()) else {
+                        break;
+                    };
+                    match ctx.try_recv_timeout(left).await {
+                        Ok(msg) => {
+                            session.handle(self, msg);
+                            session.run_blocking().await;
+                            liveness::processed();
+                        }
+                        Err(_) => break,
+                    }
+                }
+                session.flush(self);
+            }
         }
     }
 }
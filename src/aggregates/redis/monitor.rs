@@ -0,0 +1,277 @@
+//! `MONITOR` capture for debugging.
+//!
+//! An opt-in actor attaching `MONITOR` to one node for a bounded time, so an incident can be
+//! debugged from exactly what reaches Redis. Commands with an argument matching a key pattern
+//! are streamed to a `MonitorSink` as the node sees them, the stream ends with the capture.
+//!
+//! `MONITOR` costs the node throughput while it runs and shows the commands of every client,
+//! values included, so captures are capped by `Monitor::max_duration`.
+
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use bastion::prelude::{BastionContext, Distributor, MessageHandler};
+use redis::Value;
+use serde::{Deserialize, Serialize};
+use tokio::{sync::mpsc, task};
+use tracing::{info, warn};
+
+use crate::actors::base::{liveness, TActor};
+
+use super::{
+    backend::glob_match,
+    connection,
+    error::RedisError,
+    node::{self, NodeTarget},
+    view::RedisStatus,
+    Redis, RedisState, RedisStatusQuery,
+};
+
+/// Commands buffered in the channel before the capture waits for the caller
+pub const MONITOR_BUFFER: usize = 1024;
+/// Longest wait for a command before the capture checks whether it is over
+const POLL: Duration = Duration::from_millis(200);
+
+/// Receiving end of the sink handed to `RedisMonitor`
+pub type MonitorStream = mpsc::Receiver<Result<MonitoredCommand, RedisError>>;
+
+/// Where captured commands are sent, the stream ends when the sender is dropped
+#[derive(Debug, Clone)]
+pub struct MonitorSink(mpsc::Sender<Result<MonitoredCommand, RedisError>>);
+
+impl MonitorSink {
+    /// Sink with its stream, buffering `MONITOR_BUFFER` commands
+    pub fn channel() -> (Self, MonitorStream) {
+        let (sender, receiver) = mpsc::channel(MONITOR_BUFFER);
+        (Self(sender), receiver)
+    }
+
+    /// End the stream with an error, dropped when the stream is full or gone
+    pub(crate) fn fail(&self, error: RedisError) {
+        let _ = self.0.try_send(Err(error));
+    }
+}
+
+/// Tell capturing the commands one node receives into `sink`, an error ends the stream
+#[derive(Debug, Clone)]
+pub struct RedisMonitor {
+    pub target: NodeTarget,
+    /// Glob-style pattern, commands are kept when one of their arguments matches it
+    pub pattern: String,
+    /// How long to capture, capped by `Monitor::max_duration`
+    pub duration: Duration,
+    /// Commands captured at most, unbounded when unset
+    pub limit: Option<usize>,
+    pub sink: MonitorSink,
+}
+
+/// One command received by the node
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct MonitoredCommand {
+    /// Unix time the node received it at, in seconds
+    pub timestamp: f64,
+    pub db: u32,
+    /// `host:port` of the client, `lua` for scripts
+    pub client: String,
+    /// Command name and arguments, invalid UTF-8 replaced
+    pub args: Vec<String>,
+}
+
+impl MonitoredCommand {
+    /// Whether an argument after the command name matches `pattern`
+    fn matches(&self, pattern: &str) -> bool {
+        self.args
+            .iter()
+            .skip(1)
+            .any(|arg| glob_match(pattern.as_bytes(), arg.as_bytes()))
+    }
+}
+
+/// Capture actor settings, the actor state
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Monitor {
+    /// Name of the Redis actor instance whose cluster is captured, the unnamed actor otherwise
+    pub instance: Option<String>,
+    /// Longest capture, whatever the duration asked for
+    pub max_duration: Duration,
+}
+
+impl Default for Monitor {
+    fn default() -> Self {
+        Self {
+            instance: None,
+            max_duration: Duration::from_secs(60),
+        }
+    }
+}
+
+impl Monitor {
+    /// Distributor of the capture actor
+    pub fn distributor() -> Distributor {
+        Distributor::named("redis_monitor")
+    }
+
+    // Capture on the urls the Redis actor is connected to, ending the stream with any error
+    async fn run(self, event: RedisMonitor) {
+        let writer = Redis::distributor_named(self.instance.as_deref());
+        let urls = match writer.request::<RedisStatus>(RedisStatusQuery).await {
+            Ok(Ok(status)) if status.state == RedisState::Initialized => status.urls,
+            _ => return event.sink.fail(RedisError::NotReady),
+        };
+        let duration = event.duration.min(self.max_duration);
+        info!(node = ?event.target, pattern = event.pattern, ?duration, "[MONITOR] Capturing");
+        let sink = event.sink.clone();
+        match task::spawn_blocking(move || capture(&urls, &event, duration)).await {
+            Ok(Ok(captured)) => info!(captured, "[MONITOR] Done"),
+            Ok(Err(e)) => {
+                warn!(error = %e, "[MONITOR] Failed");
+                sink.fail(e);
+            }
+            Err(e) => sink.fail(RedisError::Command(e.to_string())),
+        }
+    }
+}
+
+// Stream the matching commands the target node receives for `duration`, returning how many
+fn capture(urls: &[String], event: &RedisMonitor, duration: Duration) -> Result<usize, RedisError> {
+    let command_error = |e: redis::RedisError| RedisError::Command(e.to_string());
+    let mut conn = connection::connect(urls).map_err(command_error)?;
+    let nodes = node::nodes(&mut conn, urls).map_err(command_error)?;
+    let node = event
+        .target
+        .pick(&nodes)
+        .ok_or_else(|| RedisError::UnknownNode(format!("{:?}", event.target)))?;
+    let mut node_conn = node
+        .client(urls)
+        .and_then(|client| client.get_connection())
+        .map_err(command_error)?;
+    node_conn
+        .set_read_timeout(Some(POLL))
+        .map_err(command_error)?;
+    redis::cmd("MONITOR")
+        .query::<()>(&mut node_conn)
+        .map_err(command_error)?;
+
+    let deadline = Instant::now() + duration;
+    let mut captured = 0;
+    while Instant::now() < deadline && event.limit.is_none_or(|limit| captured < limit) {
+        let line = match node_conn.recv_response() {
+            Ok(Value::Status(line)) => line,
+            Ok(_) => continue,
+            Err(e) if e.is_timeout() => continue,
+            Err(e) => return Err(command_error(e)),
+        };
+        let Some(command) = parse(&line) else {
+            warn!(line, "[MONITOR] Cannot parse");
+            continue;
+        };
+        if command.matches(&event.pattern) {
+            if event.sink.0.blocking_send(Ok(command)).is_err() {
+                // The caller is gone
+                break;
+            }
+            captured += 1;
+        }
+    }
+    // Dropping the connection ends `MONITOR`
+    Ok(captured)
+}
+
+// A `MONITOR` line, e.g. `1339518083.107412 [0 127.0.0.1:60866] "set" "k" "v"`
+fn parse(line: &str) -> Option<MonitoredCommand> {
+    let (timestamp, rest) = line.split_once(' ')?;
+    let (source, args) = rest.strip_prefix('[')?.split_once("] ")?;
+    let (db, client) = source.split_once(' ')?;
+    Some(MonitoredCommand {
+        timestamp: timestamp.parse().ok()?,
+        db: db.parse().ok()?,
+        client: client.to_owned(),
+        args: unquote(args)?,
+    })
+}
+
+// Arguments quoted by the node, with `\"`, `\\`, `\n`, `\r`, `\t`, `\a`, `\b` and `\xHH`
+// escapes
+fn unquote(args: &str) -> Option<Vec<String>> {
+    let mut bytes = args.bytes();
+    let mut unquoted = vec![];
+    loop {
+        match bytes.next() {
+            None => return Some(unquoted),
+            Some(b' ') => continue,
+            Some(b'"') => {}
+            Some(_) => return None,
+        }
+        let mut arg = vec![];
+        loop {
+            match bytes.next()? {
+                b'"' => break,
+                b'\\' => arg.push(match bytes.next()? {
+                    b'n' => b'\n',
+                    b'r' => b'\r',
+                    b't' => b'\t',
+                    b'a' => 0x07,
+                    b'b' => 0x08,
+                    b'x' => {
+                        let hex = [bytes.next()?, bytes.next()?];
+                        u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?
+                    }
+                    escaped => escaped,
+                }),
+                byte => arg.push(byte),
+            }
+        }
+        unquoted.push(String::from_utf8_lossy(&arg).into_owned());
+    }
+}
+
+#[async_trait]
+impl TActor for Monitor {
+    fn with_distributor() -> Option<Distributor> {
+        Some(Self::distributor())
+    }
+
+    async fn handler(&mut self, ctx: BastionContext) -> Result<(), ()> {
+        loop {
+            MessageHandler::new(ctx.recv().await?)
+                .on_tell(|event: RedisMonitor, _| {
+                    tokio::spawn(self.clone().run(event));
+                })
+                .on_fallback(|unknown, _| warn!("[MONITOR] Unknown message: {unknown:?}"));
+            liveness::processed();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn monitor_lines_are_parsed() {
+        let command =
+            parse(r#"1339518083.107412 [0 127.0.0.1:60866] "set" "user:1" "a \"b\"\x00\\""#)
+                .unwrap();
+        assert_eq!(
+            MonitoredCommand {
+                timestamp: 1339518083.107412,
+                db: 0,
+                client: "127.0.0.1:60866".to_owned(),
+                args: vec![
+                    "set".to_owned(),
+                    "user:1".to_owned(),
+                    "a \"b\"\0\\".to_owned()
+                ],
+            },
+            command
+        );
+        assert!(command.matches("user:*"));
+        // The command name is not a key
+        assert!(!command.matches("se?"));
+
+        let script = parse(r#"1339518083.1 [3 lua] "get" "k""#).unwrap();
+        assert_eq!((3, "lua"), (script.db, script.client.as_str()));
+        assert_eq!(None, parse("OK"));
+        assert_eq!(None, parse(r#"1.0 [0 127.0.0.1:1] "unterminated"#));
+    }
+}
@@ -0,0 +1,299 @@
+use std::{collections::BTreeMap, thread, time::Duration};
+
+use bytes::Bytes;
+use r2d2::{Pool, PooledConnection};
+use redis::{ErrorKind, RedisError, RedisResult};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    chunked::{self, ChunkingConfig},
+    connection::{self, RedisConnection},
+    node::{self, NodeInfo},
+    pool::{checkout_timeout, RedisManager},
+    trace,
+};
+
+/// Number of hash slots in a Redis cluster
+pub const SLOTS: u16 = 16384;
+
+/// Upper bound of pooled connections a single multi-key command fans out to
+const MAX_WORKERS: usize = 8;
+/// Longest wait for each pooled connection of a multi-key command
+const CHECKOUT_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Question fetching several keys at once, replied with `Result<Vec<Option<Bytes>>, RedisError>`
+/// holding one value per key in order
+#[derive(Default, Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RedisMultiQuery {
+    pub keys: Vec<String>,
+}
+
+/// Question testing several keys at once, replied with `Result<Vec<bool>, RedisError>` telling
+/// whether each key exists in order
+#[derive(Default, Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RedisExists {
+    pub keys: Vec<String>,
+}
+
+/// Insert several keys at once
+#[derive(Default, Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RedisMultiInsert {
+    pub entries: Vec<(String, Bytes)>,
+    /// Caller tag recorded by the audit log
+    #[serde(default)]
+    pub caller: Option<String>,
+}
+
+/// Cluster hash slot of a key, honouring `{hash tags}`
+pub fn key_slot(key: &str) -> u16 {
+    let key = key.as_bytes();
+    let hashed = match key.iter().position(|b| *b == b'{') {
+        Some(open) => match key[open + 1..].iter().position(|b| *b == b'}') {
+            Some(len) if len > 0 => &key[open + 1..open + 1 + len],
+            _ => key,
+        },
+        None => key,
+    };
+    crc16::State::<crc16::XMODEM>::calculate(hashed) % SLOTS
+}
+
+/// Indexes of `keys` grouped by hash slot, so each group is a valid multi-key command
+pub(crate) fn group_by_slot<'a>(keys: impl Iterator<Item = &'a str>) -> BTreeMap<u16, Vec<usize>> {
+    let mut groups: BTreeMap<u16, Vec<usize>> = BTreeMap::new();
+    for (i, key) in keys.enumerate() {
+        groups.entry(key_slot(key)).or_default().push(i);
+    }
+    groups
+}
+
+/// Slot groups gathered by the master serving them, so each node gets one pipeline. Slots no
+/// master claims, e.g. while resharding, are left together to the cluster redirections.
+pub(crate) fn group_by_node(
+    nodes: &[NodeInfo],
+    groups: BTreeMap<u16, Vec<usize>>,
+) -> Vec<Vec<Vec<usize>>> {
+    let mut by_node: Vec<Vec<Vec<usize>>> = vec![vec![]; nodes.len() + 1];
+    for (slot, group) in groups {
+        let node = nodes.iter().position(|node| node.serves(slot));
+        by_node[node.unwrap_or(nodes.len())].push(group);
+    }
+    by_node.retain(|groups| !groups.is_empty());
+    by_node
+}
+
+/// Run `f` once per node with the slot groups it serves, spread over up to `MAX_WORKERS`
+/// pooled connections at once. Replies come back with the groups they answer.
+pub(crate) fn fan_out<T, F>(
+    pool: &Pool<RedisManager>,
+    urls: &[String],
+    groups: BTreeMap<u16, Vec<usize>>,
+    f: F,
+) -> RedisResult<Vec<(Vec<Vec<usize>>, T)>>
+where
+    T: Send,
+    F: Fn(&mut RedisConnection, &[Vec<usize>]) -> RedisResult<T> + Sync,
+{
+    if groups.is_empty() {
+        return Ok(vec![]);
+    }
+    let nodes = node::nodes(&mut *pooled(pool)?, urls)?;
+    let by_node = group_by_node(&nodes, groups);
+
+    // Half the pool stays with the sessions and other multi-key commands
+    let workers = MAX_WORKERS
+        .min(pool.max_size() as usize / 2)
+        .min(by_node.len())
+        .max(1);
+    let mut chunks: Vec<Vec<Vec<Vec<usize>>>> = vec![vec![]; workers];
+    for (i, groups) in by_node.into_iter().enumerate() {
+        chunks[i % workers].push(groups);
+    }
+
+    let replies = thread::scope(|scope| {
+        let handles: Vec<_> = chunks
+            .into_iter()
+            .map(|chunk| {
+                let f = &f;
+                scope.spawn(move || -> RedisResult<Vec<(Vec<Vec<usize>>, T)>> {
+                    let mut conn = pooled(pool)?;
+                    chunk
+                        .into_iter()
+                        .map(|groups| {
+                            let reply = f(&mut conn, &groups)?;
+                            Ok((groups, reply))
+                        })
+                        .collect()
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().expect("fan-out worker panicked"))
+            .collect::<RedisResult<Vec<_>>>()
+    })?;
+    Ok(replies.into_iter().flatten().collect())
+}
+
+// Pooled connection, failing after `CHECKOUT_TIMEOUT` rather than waiting on a busy pool
+fn pooled(pool: &Pool<RedisManager>) -> RedisResult<PooledConnection<RedisManager>> {
+    checkout_timeout(pool, CHECKOUT_TIMEOUT)
+        .map_err(|e| RedisError::from((ErrorKind::IoError, "pool checkout failed", e.to_string())))
+}
+
+/// Values of `keys` in order, one pipeline of `GET` per node since cluster pipelines refuse
+/// `MGET`. With `chunking`, chunked values are reassembled from their chunks.
+pub(crate) fn mget(
+    pool: &Pool<RedisManager>,
+    urls: &[String],
+    keys: &[String],
+    hash_trace_keys: bool,
+    chunking: Option<ChunkingConfig>,
+) -> RedisResult<Vec<Option<Bytes>>> {
+    let groups = group_by_slot(keys.iter().map(String::as_str));
+
+    let label = format!("{} keys", keys.len());
+    let replies = trace::command("mget", &label, hash_trace_keys, || {
+        fan_out(pool, urls, groups, |conn, groups| {
+            let mut pipe = connection::pipe();
+            for i in groups.iter().flatten() {
+                pipe.cmd("GET").arg(&keys[*i]);
+            }
+            let values = pipe.query::<Vec<Option<Vec<u8>>>>(conn)?;
+            if chunking.is_none() {
+                return Ok(values);
+            }
+            // Chunks live in other slots, each one is read on its own
+            groups
+                .iter()
+                .flatten()
+                .zip(values)
+                .map(|(i, value)| {
+                    value
+                        .map(|value| chunked::resolve(conn, &keys[*i], value))
+                        .transpose()
+                })
+                .collect()
+        })
+    })?;
+
+    let mut values = vec![None; keys.len()];
+    for (groups, reply) in replies {
+        for (i, value) in groups.iter().flatten().zip(reply) {
+            values[*i] = value.map(Bytes::from);
+        }
+    }
+    Ok(values)
+}
+
+/// EXISTS of every key in order, one pipeline per node
+pub(crate) fn exists(
+    pool: &Pool<RedisManager>,
+    urls: &[String],
+    keys: &[String],
+    hash_trace_keys: bool,
+) -> RedisResult<Vec<bool>> {
+    let groups = group_by_slot(keys.iter().map(String::as_str));
+
+    let label = format!("{} keys", keys.len());
+    let replies = trace::command("exists", &label, hash_trace_keys, || {
+        fan_out(pool, urls, groups, |conn, groups| {
+            // `EXISTS a b` only counts, one command per key tells them apart
+            let mut pipe = connection::pipe();
+            for i in groups.iter().flatten() {
+                pipe.cmd("EXISTS").arg(&keys[*i]);
+            }
+            pipe.query::<Vec<bool>>(conn)
+        })
+    })?;
+
+    let mut exists = vec![false; keys.len()];
+    for (groups, reply) in replies {
+        for (i, found) in groups.iter().flatten().zip(reply) {
+            exists[*i] = found;
+        }
+    }
+    Ok(exists)
+}
+
+/// SET of every entry, one pipeline per node since cluster pipelines refuse `MSET`. With
+/// `chunking`, each entry is written on its own so large values are split and the chunks of
+/// overwritten values removed.
+pub(crate) fn mset(
+    pool: &Pool<RedisManager>,
+    urls: &[String],
+    entries: &[(String, Bytes)],
+    hash_trace_keys: bool,
+    chunking: Option<ChunkingConfig>,
+) -> RedisResult<()> {
+    let groups = group_by_slot(entries.iter().map(|(key, _)| key.as_str()));
+
+    let label = format!("{} keys", entries.len());
+    trace::command("mset", &label, hash_trace_keys, || {
+        fan_out(pool, urls, groups, |conn, groups| {
+            if let Some(config) = chunking {
+                for i in groups.iter().flatten() {
+                    let (key, value) = &entries[*i];
+                    chunked::set(conn, key, value, None, config)?;
+                }
+                return Ok(());
+            }
+            let mut pipe = connection::pipe();
+            for i in groups.iter().flatten() {
+                let (key, value) = &entries[*i];
+                pipe.cmd("SET").arg(key).arg(&value[..]).ignore();
+            }
+            pipe.query::<()>(conn)
+        })
+    })?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slots_match_redis() {
+        assert_eq!(12182, key_slot("foo"));
+        assert_eq!(12739, key_slot("123456789"));
+        assert_eq!(key_slot("user1000"), key_slot("{user1000}.following"));
+        assert_eq!(
+            key_slot("{user1000}.following"),
+            key_slot("{user1000}.followers")
+        );
+        // Empty tags hash the whole key
+        assert_eq!(
+            crc16::State::<crc16::XMODEM>::calculate(b"{}key") % SLOTS,
+            key_slot("{}key")
+        );
+    }
+
+    #[test]
+    fn keys_are_grouped_by_slot() {
+        let groups = group_by_slot(["{a}1", "b", "{a}2"].into_iter());
+        assert_eq!(Some(&vec![0, 2]), groups.get(&key_slot("a")));
+        assert_eq!(Some(&vec![1]), groups.get(&key_slot("b")));
+    }
+
+    #[test]
+    fn slots_are_grouped_by_node() {
+        let nodes = node::parse_nodes(
+            "07c3 127.0.0.1:30004@31004 slave e7d1 0 1426238317239 4 connected
+             67ed 127.0.0.1:30002@31002 master - 0 1426238316232 2 connected 5461-16383
+             e7d1 127.0.0.1:30001@31001 myself,master - 0 0 1 connected 0-5000
+",
+        );
+        // `baz` hashes to 4813 on 30001, `foo` to 12182 on 30002 and `bar` to 5061, unclaimed
+        let groups = BTreeMap::from([
+            (key_slot("foo"), vec![0]),
+            (key_slot("bar"), vec![1]),
+            (key_slot("baz"), vec![2]),
+            (5500, vec![3]),
+        ]);
+
+        assert_eq!(
+            vec![vec![vec![3], vec![0]], vec![vec![2]], vec![vec![1]]],
+            group_by_node(&nodes, groups)
+        );
+    }
+}
@@ -0,0 +1,319 @@
+//! Cluster nodes.
+//!
+//! Nodes are discovered with `CLUSTER NODES` on every call, so answers follow failovers and
+//! resharding. `RedisNodeCommand` sends a command to one of them, e.g. `SLOWLOG GET`,
+//! `CONFIG GET` or a `SCAN` of a single master, which the cluster connection would otherwise
+//! route by key or send to a random node.
+
+use bastion::prelude::AnswerSender;
+use r2d2::Pool;
+use redis::{Client, ConnectionAddr, ConnectionInfo, IntoConnectionInfo, RedisResult, Value};
+use serde::{Deserialize, Serialize};
+use tokio::task;
+
+use super::{
+    connection::RedisConnection,
+    error::RedisError,
+    multi::{key_slot, SLOTS},
+    pool::{checkout, RedisManager},
+    trace, Redis,
+};
+
+/// Question returning the cluster nodes seen by the actor, replied with
+/// `Result<Vec<ClusterNode>, RedisError>`
+#[derive(Default, Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RedisTopologyQuery;
+
+/// Reachable node of the cluster
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ClusterNode {
+    /// Id of the node in the cluster, empty without the `cluster` feature
+    #[serde(default)]
+    pub id: String,
+    /// `host:port` of the node
+    pub addr: String,
+    /// Slot ranges served by the node, inclusive, empty for replicas
+    pub slots: Vec<(u16, u16)>,
+}
+
+/// One line of `CLUSTER NODES`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct NodeInfo {
+    pub(crate) id: String,
+    pub(crate) host: String,
+    pub(crate) port: u16,
+    /// Slot ranges served by the node, inclusive, empty for replicas
+    pub(crate) slots: Vec<(u16, u16)>,
+}
+
+impl NodeInfo {
+    pub(crate) fn addr(&self) -> String {
+        format!("{}:{}", self.host, self.port)
+    }
+
+    pub(crate) fn serves(&self, slot: u16) -> bool {
+        self.slots
+            .iter()
+            .any(|(start, end)| (*start..=*end).contains(&slot))
+    }
+
+    /// Direct client to the node, with the credentials of the configured urls
+    pub(crate) fn client(&self, urls: &[String]) -> RedisResult<Client> {
+        let base = urls[0].as_str().into_connection_info()?;
+        Client::open(ConnectionInfo {
+            addr: ConnectionAddr::Tcp(self.host.clone(), self.port),
+            redis: base.redis,
+        })
+    }
+}
+
+impl From<NodeInfo> for ClusterNode {
+    fn from(node: NodeInfo) -> Self {
+        Self {
+            addr: node.addr(),
+            id: node.id,
+            slots: node.slots,
+        }
+    }
+}
+
+/// Node a `RedisNodeCommand` is sent to
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub enum NodeTarget {
+    /// Node with this id, as listed by `CLUSTER NODES` or `RedisTopologyQuery`
+    Id(String),
+    /// Node at this `host:port`
+    Addr(String),
+    /// Master serving the hash slot of this key
+    Key(String),
+    /// Master serving this hash slot
+    Slot(u16),
+}
+
+impl NodeTarget {
+    /// First of `nodes` matching the target
+    pub(crate) fn pick<'a>(&self, nodes: &'a [NodeInfo]) -> Option<&'a NodeInfo> {
+        let slot = match self {
+            Self::Id(id) => return nodes.iter().find(|node| !id.is_empty() && node.id == *id),
+            Self::Addr(addr) => return nodes.iter().find(|node| node.addr() == *addr),
+            Self::Key(key) => key_slot(key),
+            Self::Slot(slot) if *slot < SLOTS => *slot,
+            Self::Slot(_) => return None,
+        };
+        nodes.iter().find(|node| node.serves(slot))
+    }
+}
+
+/// Question sending a command to a single node, replied with
+/// `Result<NodeReply, RedisError>`.
+///
+/// The command runs on a direct connection to the node, without slot redirections, and may
+/// target a replica.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RedisNodeCommand {
+    pub target: NodeTarget,
+    /// Command name and its arguments, e.g. `["CONFIG", "GET", "maxmemory"]`
+    pub args: Vec<String>,
+}
+
+/// Reply of a node, as sent by Redis
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub enum NodeReply {
+    Nil,
+    Int(i64),
+    Data(Vec<u8>),
+    Bulk(Vec<NodeReply>),
+    Status(String),
+    Okay,
+}
+
+impl From<Value> for NodeReply {
+    fn from(value: Value) -> Self {
+        match value {
+            Value::Nil => Self::Nil,
+            Value::Int(int) => Self::Int(int),
+            Value::Data(data) => Self::Data(data),
+            Value::Bulk(values) => Self::Bulk(values.into_iter().map(Self::from).collect()),
+            Value::Status(status) => Self::Status(status),
+            Value::Okay => Self::Okay,
+        }
+    }
+}
+
+/// Run the command from a blocking task, discovering nodes on a pooled connection
+pub(crate) fn spawn(
+    pool: &Pool<RedisManager>,
+    redis: &Redis,
+    event: RedisNodeCommand,
+    sender: AnswerSender,
+) {
+    let pool = pool.clone();
+    let urls = redis.urls.clone();
+    let (hash_trace_keys, actor) = (redis.hash_trace_keys, redis.own_distributor());
+    task::spawn_blocking(move || {
+        let result = checkout(&pool)
+            .map_err(|e| RedisError::Command(e.to_string()))
+            .and_then(|mut conn| run(&mut conn, &urls, &event, hash_trace_keys));
+        if let Err(e) = &result {
+            Redis::report_error(actor, "NodeCommand", e);
+        }
+        // The caller may be gone already
+        let _ = sender.reply(result);
+    });
+}
+
+fn run(
+    conn: &mut RedisConnection,
+    urls: &[String],
+    event: &RedisNodeCommand,
+    hash_trace_keys: bool,
+) -> Result<NodeReply, RedisError> {
+    let Some((name, args)) = event.args.split_first() else {
+        return Err(RedisError::Command("no command to send".to_owned()));
+    };
+    let nodes = nodes(conn, urls).map_err(|e| RedisError::Command(e.to_string()))?;
+    let node = event
+        .target
+        .pick(&nodes)
+        .ok_or_else(|| RedisError::UnknownNode(format!("{:?}", event.target)))?;
+    trace::command("node", &node.addr(), hash_trace_keys, || {
+        let mut node_conn = node.client(urls)?.get_connection()?;
+        redis::cmd(name).arg(args).query::<Value>(&mut node_conn)
+    })
+    .map(NodeReply::from)
+    .map_err(|e| RedisError::Command(e.to_string()))
+}
+
+/// Parse `CLUSTER NODES`, skipping failed nodes and nodes without an address
+#[cfg(any(feature = "cluster", test))]
+pub(crate) fn parse_nodes(nodes: &str) -> Vec<NodeInfo> {
+    nodes
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let flags = fields.get(2)?;
+            if flags.contains("fail") || flags.contains("noaddr") {
+                return None;
+            }
+            // `ip:port@cport[,hostname]`
+            let addr = fields.get(1)?.split('@').next()?;
+            let (host, port) = addr.rsplit_once(':')?;
+            // Slots start at the ninth field, `[..]` entries are slots being migrated
+            let slots = fields
+                .iter()
+                .skip(8)
+                .filter(|range| !range.starts_with('['))
+                .filter_map(|range| match range.split_once('-') {
+                    Some((start, end)) => Some((start.parse().ok()?, end.parse().ok()?)),
+                    None => range.parse().ok().map(|slot| (slot, slot)),
+                })
+                .collect();
+            Some(NodeInfo {
+                id: fields.first()?.to_string(),
+                host: host.to_owned(),
+                port: port.parse().ok()?,
+                slots,
+            })
+        })
+        .collect()
+}
+
+/// Every reachable node of the cluster, discovered with `CLUSTER NODES`
+#[cfg(feature = "cluster")]
+pub(crate) fn nodes(conn: &mut RedisConnection, _urls: &[String]) -> RedisResult<Vec<NodeInfo>> {
+    let nodes: String = redis::cmd("CLUSTER").arg("NODES").query(conn)?;
+    Ok(parse_nodes(&nodes))
+}
+
+/// The node at the first of `urls`, serving every slot
+#[cfg(not(feature = "cluster"))]
+pub(crate) fn nodes(_conn: &mut RedisConnection, urls: &[String]) -> RedisResult<Vec<NodeInfo>> {
+    let url = urls.first().ok_or((
+        redis::ErrorKind::InvalidClientConfig,
+        "no url to connect to",
+    ))?;
+    match url.as_str().into_connection_info()?.addr {
+        ConnectionAddr::Tcp(host, port) | ConnectionAddr::TcpTls { host, port, .. } => {
+            Ok(vec![NodeInfo {
+                // Only known to cluster nodes
+                id: String::new(),
+                host,
+                port,
+                slots: vec![(0, SLOTS - 1)],
+            }])
+        }
+        ConnectionAddr::Unix(_) => Err((
+            redis::ErrorKind::InvalidClientConfig,
+            "node commands need a TCP address",
+        )
+            .into()),
+    }
+}
+
+/// Direct clients to every reachable node of the cluster, keyed by `host:port`.
+///
+/// Nodes reuse the credentials of the configured urls.
+pub(crate) fn cluster_nodes(
+    conn: &mut RedisConnection,
+    urls: &[String],
+) -> RedisResult<Vec<(String, Client)>> {
+    nodes(conn, urls)?
+        .into_iter()
+        .map(|node| Ok((node.addr(), node.client(urls)?)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cluster_nodes_are_parsed() {
+        let nodes = parse_nodes(
+            "07c3 127.0.0.1:30004@31004 slave e7d1 0 1426238317239 4 connected\n\
+             67ed 127.0.0.1:30002@31002 master - 0 1426238316232 2 connected 5461-10922 [5462->-e7d1]\n\
+             e7d1 127.0.0.1:30001@31001 myself,master - 0 0 1 connected 0-5460 16383\n\
+             6ec2 127.0.0.1:30005@31005 master,fail - 1426238316232 0 5 disconnected\n",
+        );
+
+        assert_eq!(3, nodes.len());
+        assert!(nodes[0].slots.is_empty());
+        assert_eq!(vec![(5461, 10922)], nodes[1].slots);
+        assert!(nodes[2].serves(16383));
+        assert!(!nodes[2].serves(5461));
+        assert_eq!("127.0.0.1:30001", nodes[2].addr());
+        assert_eq!("e7d1", nodes[2].id);
+    }
+
+    #[test]
+    fn targets_pick_a_node() {
+        let nodes = parse_nodes(
+            "07c3 127.0.0.1:30004@31004 slave e7d1 0 1426238317239 4 connected
+             67ed 127.0.0.1:30002@31002 master - 0 1426238316232 2 connected 5461-16383
+             e7d1 127.0.0.1:30001@31001 myself,master - 0 0 1 connected 0-5460
+",
+        );
+        let addr = |target: NodeTarget| target.pick(&nodes).map(NodeInfo::addr);
+
+        assert_eq!(
+            Some("127.0.0.1:30004".to_owned()),
+            addr(NodeTarget::Id("07c3".to_owned()))
+        );
+        assert_eq!(
+            Some("127.0.0.1:30002".to_owned()),
+            addr(NodeTarget::Addr("127.0.0.1:30002".to_owned()))
+        );
+        // `foo` hashes to slot 12182
+        assert_eq!(
+            Some("127.0.0.1:30002".to_owned()),
+            addr(NodeTarget::Key("foo".to_owned()))
+        );
+        assert_eq!(
+            Some("127.0.0.1:30001".to_owned()),
+            addr(NodeTarget::Slot(0))
+        );
+        assert_eq!(None, addr(NodeTarget::Slot(SLOTS)));
+        assert_eq!(None, addr(NodeTarget::Id("dead".to_owned())));
+        assert_eq!(None, addr(NodeTarget::Id(String::new())));
+    }
+}
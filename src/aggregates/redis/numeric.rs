@@ -0,0 +1,119 @@
+use redis::{from_redis_value, Cmd, RedisResult, Value};
+use serde::{Deserialize, Serialize};
+
+use super::typed::TypedCommand;
+
+/// `INCRBYFLOAT`, replied with the new value
+#[derive(Default, Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RedisIncrByFloat {
+    pub key: String,
+    pub increment: f64,
+}
+
+impl TypedCommand for RedisIncrByFloat {
+    type Reply = f64;
+    const OP: &'static str = "incrbyfloat";
+    const READ_ONLY: bool = false;
+    const IDEMPOTENT: bool = false;
+
+    fn key(&self) -> &str {
+        &self.key
+    }
+
+    fn cmd(&self) -> Cmd {
+        let mut cmd = redis::cmd("INCRBYFLOAT");
+        cmd.arg(&self.key).arg(self.increment);
+        cmd
+    }
+
+    fn parse(&self, value: &Value) -> RedisResult<f64> {
+        from_redis_value(value)
+    }
+}
+
+/// `HINCRBYFLOAT` of a hash field, replied with the new value
+#[derive(Default, Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RedisHIncrByFloat {
+    pub key: String,
+    pub field: String,
+    pub increment: f64,
+}
+
+impl TypedCommand for RedisHIncrByFloat {
+    type Reply = f64;
+    const OP: &'static str = "hincrbyfloat";
+    const READ_ONLY: bool = false;
+    const IDEMPOTENT: bool = false;
+
+    fn key(&self) -> &str {
+        &self.key
+    }
+
+    fn cmd(&self) -> Cmd {
+        let mut cmd = redis::cmd("HINCRBYFLOAT");
+        cmd.arg(&self.key).arg(&self.field).arg(self.increment);
+        cmd
+    }
+
+    fn parse(&self, value: &Value) -> RedisResult<f64> {
+        from_redis_value(value)
+    }
+}
+
+/// `HINCRBY` of a hash field counter, replied with the new value
+#[derive(Default, Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RedisHashIncrement {
+    pub key: String,
+    pub field: String,
+    pub by: i64,
+}
+
+impl TypedCommand for RedisHashIncrement {
+    type Reply = i64;
+    const OP: &'static str = "hincrby";
+    const READ_ONLY: bool = false;
+    const IDEMPOTENT: bool = false;
+
+    fn key(&self) -> &str {
+        &self.key
+    }
+
+    fn cmd(&self) -> Cmd {
+        let mut cmd = redis::cmd("HINCRBY");
+        cmd.arg(&self.key).arg(&self.field).arg(self.by);
+        cmd
+    }
+
+    fn parse(&self, value: &Value) -> RedisResult<i64> {
+        from_redis_value(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aggregates::redis::typed::args;
+
+    #[test]
+    fn float_increments_are_built() {
+        let incr = RedisHIncrByFloat {
+            key: "totals".to_owned(),
+            field: "eur".to_owned(),
+            increment: -1.5,
+        };
+        assert_eq!(
+            vec!["HINCRBYFLOAT", "totals", "eur", "-1.5"],
+            args(&incr.cmd())
+        );
+
+        let views = RedisHashIncrement {
+            key: "article:1".to_owned(),
+            field: "views".to_owned(),
+            by: 1,
+        };
+        assert_eq!(
+            vec!["HINCRBY", "article:1", "views", "1"],
+            args(&views.cmd())
+        );
+    }
+}
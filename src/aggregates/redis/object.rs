@@ -0,0 +1,212 @@
+//! Structs stored field by field in a Redis hash.
+//!
+//! Each field of a `RedisObject` is one hash field holding its value encoded as JSON, so single
+//! fields can be read or updated without the rest of the object, and numeric fields stay
+//! usable with `HINCRBY`.
+
+use bytes::Bytes;
+use redis::{from_redis_value, Cmd, RedisResult, Value};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Map;
+
+use super::{
+    error::RedisError,
+    typed::{pairs, TypedCommand},
+};
+
+/// Struct stored as a hash, e.g. `impl RedisObject for Session {}`
+pub trait RedisObject: Serialize + DeserializeOwned {
+    /// Hash fields of the object
+    fn to_fields(&self) -> Result<Vec<(String, Bytes)>, RedisError> {
+        fields(self)
+    }
+
+    /// Object from the fields of its hash, fields it does not know are ignored
+    fn from_fields(fields: Vec<(String, Vec<u8>)>) -> Result<Self, RedisError> {
+        let object = fields
+            .into_iter()
+            .map(|(field, value)| Ok((field, decode(&value)?)))
+            .collect::<Result<Map<_, _>, RedisError>>()?;
+        serde_json::from_value(object.into()).map_err(|e| RedisError::Codec(e.to_string()))
+    }
+}
+
+/// Hash fields of a struct or map, e.g. the subset of an object's fields to update
+pub fn fields<T: Serialize + ?Sized>(value: &T) -> Result<Vec<(String, Bytes)>, RedisError> {
+    match serde_json::to_value(value) {
+        Ok(serde_json::Value::Object(object)) => object
+            .into_iter()
+            .map(|(field, value)| Ok((field, encode(&value)?)))
+            .collect(),
+        Ok(_) => Err(RedisError::Codec(
+            "only structs and maps are stored as hashes".to_owned(),
+        )),
+        Err(e) => Err(RedisError::Codec(e.to_string())),
+    }
+}
+
+/// Value of one field
+pub fn encode<T: Serialize + ?Sized>(value: &T) -> Result<Bytes, RedisError> {
+    serde_json::to_vec(value)
+        .map(Bytes::from)
+        .map_err(|e| RedisError::Codec(e.to_string()))
+}
+
+/// Value of one field
+pub fn decode<T: DeserializeOwned>(value: &[u8]) -> Result<T, RedisError> {
+    serde_json::from_slice(value).map_err(|e| RedisError::Codec(e.to_string()))
+}
+
+/// `HSET` of several fields, replied with the number of fields created
+#[derive(Default, Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RedisHashSet {
+    pub key: String,
+    pub fields: Vec<(String, Bytes)>,
+}
+
+impl TypedCommand for RedisHashSet {
+    type Reply = u64;
+    const OP: &'static str = "hset";
+    const READ_ONLY: bool = false;
+
+    fn key(&self) -> &str {
+        &self.key
+    }
+
+    fn cmd(&self) -> Cmd {
+        let mut cmd = redis::cmd("HSET");
+        cmd.arg(&self.key);
+        for (field, value) in &self.fields {
+            cmd.arg(field).arg(&value[..]);
+        }
+        cmd
+    }
+
+    fn parse(&self, value: &Value) -> RedisResult<u64> {
+        from_redis_value(value)
+    }
+}
+
+/// `HMGET`, replied with the value of each field, `None` for a missing one
+#[derive(Default, Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RedisHashGet {
+    pub key: String,
+    pub fields: Vec<String>,
+}
+
+impl TypedCommand for RedisHashGet {
+    type Reply = Vec<Option<Vec<u8>>>;
+    const OP: &'static str = "hmget";
+    const READ_ONLY: bool = true;
+
+    fn key(&self) -> &str {
+        &self.key
+    }
+
+    fn cmd(&self) -> Cmd {
+        let mut cmd = redis::cmd("HMGET");
+        cmd.arg(&self.key).arg(&self.fields);
+        cmd
+    }
+
+    fn parse(&self, value: &Value) -> RedisResult<Vec<Option<Vec<u8>>>> {
+        from_redis_value(value)
+    }
+}
+
+/// `HGETALL`, replied with every field and its value, none for a missing key
+#[derive(Default, Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RedisHashGetAll {
+    pub key: String,
+}
+
+impl TypedCommand for RedisHashGetAll {
+    type Reply = Vec<(String, Vec<u8>)>;
+    const OP: &'static str = "hgetall";
+    const READ_ONLY: bool = true;
+
+    fn key(&self) -> &str {
+        &self.key
+    }
+
+    fn cmd(&self) -> Cmd {
+        let mut cmd = redis::cmd("HGETALL");
+        cmd.arg(&self.key);
+        cmd
+    }
+
+    fn parse(&self, value: &Value) -> RedisResult<Vec<(String, Vec<u8>)>> {
+        pairs(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aggregates::redis::typed::args;
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Session {
+        user: String,
+        visits: u32,
+        #[serde(default)]
+        admin: Option<bool>,
+    }
+
+    impl RedisObject for Session {}
+
+    #[test]
+    fn objects_round_trip_through_their_fields() {
+        let session = Session {
+            user: "ada".to_owned(),
+            visits: 3,
+            admin: None,
+        };
+        let encoded = session.to_fields().unwrap();
+        let set = RedisHashSet {
+            key: "session:1".to_owned(),
+            fields: encoded.clone(),
+        };
+        assert_eq!(
+            vec![
+                "HSET",
+                "session:1",
+                "admin",
+                "null",
+                "user",
+                "\"ada\"",
+                "visits",
+                "3"
+            ],
+            args(&set.cmd())
+        );
+
+        let mut stored: Vec<_> = encoded
+            .into_iter()
+            .map(|(field, value)| (field, value.to_vec()))
+            .filter(|(field, _)| field != "admin")
+            .collect();
+        stored.push(("unknown".to_owned(), b"1".to_vec()));
+        assert_eq!(Ok(session), Session::from_fields(stored));
+        assert!(matches!(
+            Session::from_fields(vec![("visits".to_owned(), b"3".to_vec())]),
+            Err(RedisError::Codec(_))
+        ));
+        assert!(matches!(fields(&3), Err(RedisError::Codec(_))));
+    }
+
+    #[test]
+    fn hgetall_replies_are_paired() {
+        let get = RedisHashGetAll {
+            key: "session:1".to_owned(),
+        };
+        let reply = Value::Bulk(vec![
+            Value::Data(b"visits".to_vec()),
+            Value::Data(b"3".to_vec()),
+        ]);
+        assert_eq!(
+            vec![("visits".to_owned(), b"3".to_vec())],
+            get.parse(&reply).unwrap()
+        );
+    }
+}
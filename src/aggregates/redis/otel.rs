@@ -0,0 +1,34 @@
+//! OpenTelemetry context carried across the actor boundary
+
+use std::collections::HashMap;
+
+use opentelemetry::global;
+use tracing::{info_span, Span};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+/// Message wrapped with the trace context of the caller
+#[derive(Debug, Clone)]
+pub struct Traced<M> {
+    pub message: M,
+    carrier: HashMap<String, String>,
+}
+
+impl<M> Traced<M> {
+    /// Wrap `message` with the context of the current span
+    pub fn new(message: M) -> Self {
+        let mut carrier = HashMap::new();
+        global::get_text_map_propagator(|propagator| {
+            propagator.inject_context(&Span::current().context(), &mut carrier)
+        });
+        Self { message, carrier }
+    }
+
+    /// Unwrap the message along with a span parented to the caller's context
+    pub fn into_parts(self) -> (M, Span) {
+        let cx = global::get_text_map_propagator(|propagator| propagator.extract(&self.carrier));
+        let span = info_span!("redis.message", message = std::any::type_name::<M>());
+        // Fails only when no OpenTelemetry layer is installed, the span is still usable
+        let _ = span.set_parent(cx);
+        (self.message, span)
+    }
+}
@@ -0,0 +1,145 @@
+use std::time::Duration;
+
+use bastion::prelude::AnswerSender;
+use redis::{RedisResult, Value};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use super::{
+    backend::set_cmd,
+    connection::{self, RedisConnection},
+    error::RedisError,
+    limit,
+    mirror::MirroredWrite,
+    trace, Redis, RedisInsert,
+};
+
+/// Auto-pipelining settings, commands arriving within `window` share one pipeline
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct PipelineConfig {
+    /// How long the actor keeps collecting commands after the first one
+    pub window: Duration,
+    /// Pipeline is sent as soon as it holds this many commands
+    pub max_commands: usize,
+}
+
+impl Default for PipelineConfig {
+    fn default() -> Self {
+        Self {
+            window: Duration::from_millis(1),
+            max_commands: 64,
+        }
+    }
+}
+
+/// Data command waiting for the pipeline to be flushed
+#[derive(Debug)]
+pub(crate) enum Pending {
+    Get {
+        key: String,
+        sender: AnswerSender,
+    },
+    /// Asked inserts carry the sender of their acknowledgement
+    Set(RedisInsert, Option<AnswerSender>),
+}
+
+/// Commands collected during the current window, kept in arrival order
+#[derive(Debug, Default)]
+pub(crate) struct Batch(Vec<Pending>);
+
+impl Batch {
+    pub(crate) fn push(&mut self, pending: Pending) {
+        self.0.push(pending);
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Refuse every collected command, the connection is gone
+    pub(crate) fn refuse(&mut self) {
+        for pending in std::mem::take(&mut self.0) {
+            match pending {
+                Pending::Get { sender, .. } => {
+                    let _ = sender.reply(Err::<Option<Vec<u8>>, _>(RedisError::NotReady));
+                }
+                Pending::Set(_, Some(sender)) => {
+                    let _ = sender.reply(Err::<(), _>(RedisError::NotReady));
+                }
+                Pending::Set(insert, None) => {
+                    warn!(
+                        caller = insert.caller,
+                        "[REDIS] Insert dropped while disconnected"
+                    );
+                }
+            }
+        }
+    }
+
+    /// Send every collected command as one pipeline and answer the queries
+    pub(crate) fn flush(&mut self, redis: &Redis, conn: &mut RedisConnection) {
+        let batch = std::mem::take(&mut self.0);
+        if batch.is_empty() {
+            return;
+        }
+
+        let mut pipe = connection::pipe();
+        for pending in &batch {
+            match pending {
+                Pending::Get { key, .. } => pipe.get(key),
+                Pending::Set(insert, _) => pipe
+                    .add_command(set_cmd(&insert.key, &insert.value, insert.ttl))
+                    .ignore(),
+            };
+        }
+
+        let result: RedisResult<Vec<Value>> = trace::command(
+            "pipeline",
+            &format!("{} commands", batch.len()),
+            false,
+            || pipe.query(conn),
+        );
+        if let Err(e) = &result {
+            Redis::report_redis_error(redis.own_distributor(), e);
+        }
+
+        // Sets are ignored in the reply so the values line up with the gets
+        let mut values = result.as_ref().ok().map(|values| values.iter());
+        for pending in batch {
+            match pending {
+                Pending::Get { sender, .. } => {
+                    let value = match (&result, values.as_mut().and_then(Iterator::next)) {
+                        (Err(e), _) => Err(RedisError::Command(e.to_string())),
+                        (Ok(_), Some(value)) => redis::from_redis_value::<Option<Vec<u8>>>(value)
+                            .map_err(|e| RedisError::Command(e.to_string()))
+                            .and_then(|value| value.map(limit::inflate).transpose()),
+                        (Ok(_), None) => Err(RedisError::Command(
+                            "pipeline reply is missing values".to_owned(),
+                        )),
+                    };
+                    // The caller may be gone already
+                    let _ = sender.reply(value);
+                }
+                Pending::Set(insert, sender) => {
+                    redis.audit.record(
+                        "set",
+                        &insert.key,
+                        insert.value.len(),
+                        insert.caller.as_deref(),
+                        result.is_ok(),
+                    );
+                    if let (Ok(_), Some(mirror)) = (&result, &redis.mirroring) {
+                        mirror.send(MirroredWrite::Insert(insert));
+                    }
+                    if let Some(sender) = sender {
+                        let ack = match &result {
+                            Ok(_) => Ok(()),
+                            Err(e) => Err(RedisError::Command(e.to_string())),
+                        };
+                        let _ = sender.reply(ack);
+                    }
+                }
+            }
+        }
+    }
+}
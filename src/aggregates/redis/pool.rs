@@ -0,0 +1,287 @@
+use std::{
+    collections::HashMap,
+    ops::{Deref, DerefMut},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, RwLock, Weak,
+    },
+    time::{Duration, Instant},
+};
+
+use r2d2::{ManageConnection, Pool, PooledConnection};
+use redis::ErrorKind;
+use serde::{Deserialize, Serialize};
+use tracing::info_span;
+
+use super::{
+    connection::{self, RedisConnection},
+    metrics,
+};
+
+/// Snapshot of the connection pool
+#[derive(Default, Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PoolStats {
+    /// Number of connections currently managed by the pool
+    pub connections: u32,
+    /// Number of idle connections in the pool
+    pub idle_connections: u32,
+}
+
+impl From<r2d2::State> for PoolStats {
+    fn from(state: r2d2::State) -> Self {
+        Self {
+            connections: state.connections,
+            idle_connections: state.idle_connections,
+        }
+    }
+}
+
+/// Connection retries of the writer while the cluster cannot be reached
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ReconnectConfig {
+    /// Wait before the first retry, doubled after every failed one
+    pub initial_delay: Duration,
+    /// Longest wait between two retries
+    pub max_delay: Duration,
+    /// Retries before the actor gives up and stays `Uninitialized`, unbounded when unset
+    #[serde(default)]
+    pub max_attempts: Option<u32>,
+}
+
+impl Default for ReconnectConfig {
+    fn default() -> Self {
+        Self {
+            initial_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(10),
+            max_attempts: None,
+        }
+    }
+}
+
+impl ReconnectConfig {
+    /// Wait before retry number `attempt`, counted from zero
+    pub(crate) fn delay(&self, attempt: u32) -> Duration {
+        self.initial_delay
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max_delay)
+    }
+}
+
+/// Question asking the actor for a `PoolStatsReport`
+#[derive(Default, Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RedisPoolStats;
+
+/// Pool saturation and the age of every open connection
+#[derive(Default, Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct PoolStatsReport {
+    pub stats: PoolStats,
+    pub max_size: u32,
+    /// Oldest first
+    pub connection_ages: Vec<Duration>,
+}
+
+/// Creation time of every open connection, shared between the manager and the actor
+#[derive(Debug, Default, Clone)]
+pub(crate) struct ConnectionRegistry(Arc<Mutex<HashMap<u64, Instant>>>);
+
+impl ConnectionRegistry {
+    pub(crate) fn ages(&self) -> Vec<Duration> {
+        let mut ages: Vec<Duration> = self
+            .0
+            .lock()
+            .unwrap()
+            .values()
+            .map(Instant::elapsed)
+            .collect();
+        ages.sort_by(|a, b| b.cmp(a));
+        ages
+    }
+
+    pub(crate) fn report(&self, pool: &Pool<RedisManager>) -> PoolStatsReport {
+        PoolStatsReport {
+            stats: pool.state().into(),
+            max_size: pool.max_size(),
+            connection_ages: self.ages(),
+        }
+    }
+}
+
+/// Cluster connection registered for its lifetime in the `ConnectionRegistry`
+pub struct ManagedConnection {
+    id: u64,
+    /// `SharedConfig` generation the connection was made with
+    generation: u64,
+    conn: RedisConnection,
+    registry: Weak<Mutex<HashMap<u64, Instant>>>,
+}
+
+impl Deref for ManagedConnection {
+    type Target = RedisConnection;
+
+    fn deref(&self) -> &Self::Target {
+        &self.conn
+    }
+}
+
+impl DerefMut for ManagedConnection {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.conn
+    }
+}
+
+impl Drop for ManagedConnection {
+    fn drop(&mut self) {
+        if let Some(registry) = self.registry.upgrade() {
+            registry.lock().unwrap().remove(&self.id);
+        }
+    }
+}
+
+/// Connection settings the manager connects with
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConnectionConfig {
+    pub urls: Vec<String>,
+}
+
+/// Current `ConnectionConfig` shared by the manager and the actor.
+///
+/// Replacing it bumps the generation, connections of older generations are discarded by the
+/// pool instead of rebuilding the whole pool.
+#[derive(Debug)]
+pub(crate) struct SharedConfig {
+    current: RwLock<Arc<ConnectionConfig>>,
+    generation: AtomicU64,
+}
+
+impl SharedConfig {
+    pub(crate) fn new(config: ConnectionConfig) -> Arc<Self> {
+        Arc::new(Self {
+            current: RwLock::new(Arc::new(config)),
+            generation: AtomicU64::new(0),
+        })
+    }
+
+    pub(crate) fn get(&self) -> Arc<ConnectionConfig> {
+        self.current.read().unwrap().clone()
+    }
+
+    fn generation(&self) -> u64 {
+        self.generation.load(Ordering::Acquire)
+    }
+
+    /// Swap the config, a no-op when nothing changed
+    pub(crate) fn replace(&self, config: ConnectionConfig) {
+        let mut current = self.current.write().unwrap();
+        if **current != config {
+            *current = Arc::new(config);
+            self.generation.fetch_add(1, Ordering::AcqRel);
+        }
+    }
+}
+
+pub struct RedisManager {
+    config: Arc<SharedConfig>,
+    registry: ConnectionRegistry,
+    next_id: AtomicU64,
+}
+
+impl RedisManager {
+    pub(crate) fn new(config: Arc<SharedConfig>, registry: ConnectionRegistry) -> Self {
+        Self {
+            config,
+            registry,
+            next_id: AtomicU64::new(0),
+        }
+    }
+}
+
+impl ManageConnection for RedisManager {
+    type Connection = ManagedConnection;
+
+    type Error = redis::RedisError;
+
+    fn connect(&self) -> Result<Self::Connection, Self::Error> {
+        // Read the generation first, a concurrent swap then only makes this connection stale
+        let generation = self.config.generation();
+        let config = self.config.get();
+
+        let _span = info_span!("redis.connect", urls = ?config.urls).entered();
+        let conn = connection::connect(&config.urls)?;
+
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.registry.0.lock().unwrap().insert(id, Instant::now());
+        Ok(ManagedConnection {
+            id,
+            generation,
+            conn,
+            registry: Arc::downgrade(&self.registry.0),
+        })
+    }
+
+    fn is_valid(&self, conn: &mut Self::Connection) -> Result<(), redis::RedisError> {
+        if conn.generation != self.config.generation() {
+            return Err((ErrorKind::ClientError, "connection config was replaced").into());
+        }
+        Ok(())
+    }
+
+    fn has_broken(&self, conn: &mut Self::Connection) -> bool {
+        conn.generation != self.config.generation()
+    }
+}
+
+// Checks out a pooled connection, recording pool metrics
+pub(crate) fn checkout(
+    pool: &Pool<RedisManager>,
+) -> Result<PooledConnection<RedisManager>, r2d2::Error> {
+    checkout_timeout(pool, pool.connection_timeout())
+}
+
+// Checks out a pooled connection, giving up after `timeout`
+pub(crate) fn checkout_timeout(
+    pool: &Pool<RedisManager>,
+    timeout: Duration,
+) -> Result<PooledConnection<RedisManager>, r2d2::Error> {
+    let _span = info_span!("redis.checkout").entered();
+    let start = Instant::now();
+    let conn = pool.get_timeout(timeout);
+    metrics::record_pool(pool.state().into(), start.elapsed());
+    conn
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(url: &str) -> ConnectionConfig {
+        ConnectionConfig {
+            urls: vec![url.to_owned()],
+        }
+    }
+
+    #[test]
+    fn reconnect_delays_double_up_to_the_maximum() {
+        let config = ReconnectConfig {
+            initial_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(1),
+            max_attempts: None,
+        };
+        let delays: Vec<u64> = (0..6)
+            .map(|attempt| config.delay(attempt).as_millis() as u64)
+            .collect();
+        assert_eq!(vec![100, 200, 400, 800, 1000, 1000], delays);
+        assert_eq!(Duration::from_secs(1), config.delay(u32::MAX));
+    }
+
+    #[test]
+    fn replacing_the_config_bumps_the_generation() {
+        let shared = SharedConfig::new(config("redis://127.0.0.1:30001"));
+
+        shared.replace(config("redis://127.0.0.1:30001"));
+        assert_eq!(0, shared.generation());
+
+        shared.replace(config("redis://127.0.0.1:30002"));
+        assert_eq!(1, shared.generation());
+        assert_eq!(config("redis://127.0.0.1:30002"), *shared.get());
+    }
+}
@@ -0,0 +1,198 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+use bastion::prelude::Distributor;
+use bytes::Bytes;
+use redis::{Client, RedisResult, Value};
+use serde::{Deserialize, Serialize};
+use tracing::{error, warn};
+
+use super::{connection::RedisConnection, error::RedisError, multi::key_slot, node, trace, Redis};
+
+/// How often subscriber threads check whether their subscription was dropped
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Tell publishing a message on a channel.
+///
+/// Sharded messages use `SPUBLISH` (Redis 7+) and only travel to the shard owning the
+/// channel's slot instead of being broadcast over the cluster bus.
+#[derive(Default, Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RedisPublish {
+    pub channel: String,
+    pub message: Bytes,
+    #[serde(default)]
+    pub sharded: bool,
+}
+
+/// Question subscribing to channels, answered with `Result<Subscription, RedisError>`.
+///
+/// Every message received is told as a `PubSubMessage` to the `deliver_to` distributor.
+#[derive(Default, Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RedisSubscribe {
+    pub channels: Vec<String>,
+    /// Use `SSUBSCRIBE` on the shards owning the channels (Redis 7+)
+    #[serde(default)]
+    pub sharded: bool,
+    /// Name of the distributor receiving the messages
+    pub deliver_to: String,
+}
+
+/// Message received on a subscribed channel
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct PubSubMessage {
+    pub channel: String,
+    pub payload: Bytes,
+    pub sharded: bool,
+}
+
+/// Handle keeping a subscription alive, the subscriber threads stop once every clone is dropped
+#[derive(Debug, Clone)]
+pub struct Subscription(#[allow(dead_code)] Arc<StopOnDrop>);
+
+#[derive(Debug)]
+struct StopOnDrop(Arc<AtomicBool>);
+
+impl Drop for StopOnDrop {
+    fn drop(&mut self) {
+        self.0.store(true, Ordering::Release);
+    }
+}
+
+/// Publish a message, returns the number of clients that received it
+pub(crate) fn publish(
+    conn: &mut RedisConnection,
+    event: &RedisPublish,
+    hash_trace_keys: bool,
+) -> RedisResult<u64> {
+    let op = if event.sharded { "spublish" } else { "publish" };
+    trace::command(op, &event.channel, hash_trace_keys, || {
+        // Both are routed by their channel argument
+        redis::cmd(&op.to_uppercase())
+            .arg(&event.channel)
+            .arg(&event.message[..])
+            .query(conn)
+    })
+}
+
+/// Subscribe on dedicated connections, one per shard owning a channel when sharded
+pub(crate) fn subscribe(
+    conn: &mut RedisConnection,
+    urls: &[String],
+    actor: Distributor,
+    event: RedisSubscribe,
+) -> Result<Subscription, RedisError> {
+    let command = |e: redis::RedisError| RedisError::Command(e.to_string());
+
+    // Channels grouped by the node serving them
+    let mut groups: Vec<(String, Client, Vec<String>)> = vec![];
+    if event.sharded {
+        let nodes = node::nodes(conn, urls).map_err(command)?;
+        for channel in event.channels {
+            let slot = key_slot(&channel);
+            let owner = nodes
+                .iter()
+                .find(|node| node.serves(slot))
+                .ok_or_else(|| RedisError::Command(format!("no node serves slot {slot}")))?;
+            let addr = owner.addr();
+            match groups.iter_mut().find(|(node, ..)| *node == addr) {
+                Some((.., channels)) => channels.push(channel),
+                None => groups.push((addr, owner.client(urls).map_err(command)?, vec![channel])),
+            }
+        }
+    } else {
+        // Classic messages are broadcast, any node delivers every channel
+        let url = urls.first().ok_or(RedisError::EmptyUrls)?;
+        let client = Client::open(url.as_str()).map_err(command)?;
+        groups.push((url.clone(), client, event.channels));
+    }
+
+    let stop = Arc::new(AtomicBool::new(false));
+    for (_, client, channels) in groups {
+        let mut sub = client.get_connection().map_err(command)?;
+        sub.set_read_timeout(Some(POLL_INTERVAL)).map_err(command)?;
+        let verb = if event.sharded {
+            "SSUBSCRIBE"
+        } else {
+            "SUBSCRIBE"
+        };
+        sub.send_packed_command(&redis::cmd(verb).arg(&channels).get_packed_command())
+            .map_err(command)?;
+
+        let stop = stop.clone();
+        let deliver_to = Distributor::named(event.deliver_to.clone());
+        let sharded = event.sharded;
+        thread::spawn(move || {
+            while !stop.load(Ordering::Acquire) {
+                match sub.recv_response() {
+                    Ok(value) => {
+                        if let Some(message) = parse_push(value, sharded) {
+                            if let Err(e) = deliver_to.tell_one(message) {
+                                warn!("[REDIS] Cannot deliver pub/sub message: {e:?}");
+                            }
+                        }
+                    }
+                    Err(e) if e.is_timeout() => {}
+                    Err(e) => {
+                        error!(error = %e, ?channels, "subscription closed");
+                        Redis::report_redis_error(actor, &e);
+                        break;
+                    }
+                }
+            }
+        });
+    }
+    Ok(Subscription(Arc::new(StopOnDrop(stop))))
+}
+
+// `["message" | "smessage", channel, payload]`, subscription confirmations are skipped
+fn parse_push(value: Value, sharded: bool) -> Option<PubSubMessage> {
+    let Value::Bulk(items) = value else {
+        return None;
+    };
+    match &items[..] {
+        [Value::Data(kind), Value::Data(channel), Value::Data(payload)]
+            if kind == b"message" || kind == b"smessage" =>
+        {
+            Some(PubSubMessage {
+                channel: String::from_utf8_lossy(channel).into_owned(),
+                payload: Bytes::copy_from_slice(payload),
+                sharded,
+            })
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn data(s: &str) -> Value {
+        Value::Data(s.as_bytes().to_vec())
+    }
+
+    #[test]
+    fn pushes_are_parsed() {
+        let message = parse_push(
+            Value::Bulk(vec![data("smessage"), data("orders"), data("1")]),
+            true,
+        );
+        assert_eq!(
+            Some(PubSubMessage {
+                channel: "orders".to_owned(),
+                payload: Bytes::from_static(b"1"),
+                sharded: true,
+            }),
+            message
+        );
+
+        let confirmation = Value::Bulk(vec![data("ssubscribe"), data("orders"), Value::Int(1)]);
+        assert_eq!(None, parse_push(confirmation, true));
+    }
+}
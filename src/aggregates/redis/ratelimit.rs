@@ -0,0 +1,219 @@
+//! Rate limiting with GCRA, the generic cell rate algorithm.
+//!
+//! A limited key holds its theoretical arrival time: when the next request would be on schedule
+//! if requests came exactly at the limited rate. A request is allowed while it does not arrive
+//! earlier than that time minus the burst tolerance, and pushes the time forward by its cost.
+//! Limits are smooth, with a single key and one Lua call per check on the cluster.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use redis::{ErrorKind, RedisResult, Script};
+use serde::{Deserialize, Serialize};
+
+use super::backend::RedisBackend;
+
+/// Checks the limit and records the request in one call. Times are in microseconds from the
+/// server clock, replied as `{allowed, remaining, retry_after, reset_after}`.
+const GCRA: &str = r"
+local emission = tonumber(ARGV[1])
+local tolerance = tonumber(ARGV[2])
+local increment = emission * tonumber(ARGV[3])
+local time = redis.call('TIME')
+local now = tonumber(time[1]) * 1000000 + tonumber(time[2])
+local tat = math.max(tonumber(redis.call('GET', KEYS[1])) or now, now)
+local new_tat = tat + increment
+local diff = now - (new_tat - tolerance)
+if diff < 0 then
+    local remaining = math.max(math.floor((now - (tat - tolerance)) / emission), 0)
+    -- Costs above the burst never fit
+    local retry_after = increment > tolerance and -1 or -diff
+    return {0, remaining, retry_after, tat - now}
+end
+redis.call('SET', KEYS[1], string.format('%d', new_tat), 'PX', math.ceil((new_tat - now) / 1000))
+return {1, math.floor(diff / emission), 0, new_tat - now}
+";
+
+/// `limit` requests per `period`, up to `burst` of them at once
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Gcra {
+    pub limit: u32,
+    pub period: Duration,
+    /// Requests allowed back to back after an idle period, at least one
+    pub burst: u32,
+}
+
+impl Gcra {
+    /// Time between two requests at the limited rate, in microseconds
+    fn emission(&self) -> u64 {
+        (self.period.as_micros() as u64 / u64::from(self.limit.max(1))).max(1)
+    }
+
+    /// How early a request may arrive, in microseconds
+    fn tolerance(&self) -> u64 {
+        self.emission() * u64::from(self.burst.max(1))
+    }
+
+    /// Decide on a request costing `cost` at `now`, given the stored arrival time `tat`. Returns
+    /// the arrival time to store when the request is allowed. Mirrors the Lua script.
+    pub(crate) fn check(
+        &self,
+        tat: Option<u64>,
+        now: u64,
+        cost: u32,
+    ) -> (RateLimitDecision, Option<u64>) {
+        let (emission, tolerance) = (self.emission(), self.tolerance());
+        let tat = tat.unwrap_or(now).max(now);
+        let new_tat = tat + emission * u64::from(cost);
+        let allow_at = new_tat.saturating_sub(tolerance);
+        if now < allow_at {
+            let remaining = (now + tolerance).saturating_sub(tat) / emission;
+            let decision = RateLimitDecision {
+                allowed: false,
+                remaining: remaining as u32,
+                retry_after: (cost <= self.burst.max(1))
+                    .then(|| Duration::from_micros(allow_at - now)),
+                reset_after: Duration::from_micros(tat - now),
+            };
+            return (decision, None);
+        }
+        let decision = RateLimitDecision {
+            allowed: true,
+            remaining: ((now - allow_at) / emission) as u32,
+            retry_after: None,
+            reset_after: Duration::from_micros(new_tat - now),
+        };
+        (decision, Some(new_tat))
+    }
+}
+
+/// Question checking a GCRA limit on `key` and counting the request when allowed, replied with
+/// `Result<RateLimitDecision, RedisError>`
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RedisRateLimit {
+    pub key: String,
+    pub gcra: Gcra,
+    /// Requests this one counts for, usually one
+    pub cost: u32,
+}
+
+/// Outcome of a rate limit check
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RateLimitDecision {
+    pub allowed: bool,
+    /// Requests still allowed right away
+    pub remaining: u32,
+    /// When a refused request would be allowed, `None` when it costs more than the burst
+    pub retry_after: Option<Duration>,
+    /// When the limit is back to a full burst
+    pub reset_after: Duration,
+}
+
+/// Run the GCRA script on `conn`, loading it on the first call
+pub(crate) fn check(
+    conn: &mut dyn RedisBackend,
+    key: &str,
+    gcra: &Gcra,
+    cost: u32,
+) -> RedisResult<RateLimitDecision> {
+    let script = Script::new(GCRA);
+    let mut cmd = redis::cmd("EVALSHA");
+    cmd.arg(script.get_hash())
+        .arg(1)
+        .arg(key)
+        .arg(gcra.emission())
+        .arg(gcra.tolerance())
+        .arg(cost);
+    let reply = match conn.command(&cmd) {
+        Err(e) if e.kind() == ErrorKind::NoScriptError => {
+            let mut eval = redis::cmd("EVAL");
+            eval.arg(GCRA)
+                .arg(1)
+                .arg(key)
+                .arg(gcra.emission())
+                .arg(gcra.tolerance())
+                .arg(cost);
+            conn.command(&eval)?
+        }
+        reply => reply?,
+    };
+    let (allowed, remaining, retry_after, reset_after): (u8, u32, i64, u64) =
+        redis::from_redis_value(&reply)?;
+    Ok(RateLimitDecision {
+        allowed: allowed == 1,
+        remaining,
+        retry_after: u64::try_from(retry_after)
+            .ok()
+            .filter(|_| allowed == 0)
+            .map(Duration::from_micros),
+        reset_after: Duration::from_micros(reset_after),
+    })
+}
+
+/// The same check on a backend keeping the arrival time as a plain value, with the local clock
+pub(crate) fn check_local(
+    conn: &mut dyn RedisBackend,
+    key: &str,
+    gcra: &Gcra,
+    cost: u32,
+) -> RedisResult<RateLimitDecision> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_micros() as u64;
+    let tat = conn
+        .get(key)?
+        .and_then(|tat| String::from_utf8(tat).ok()?.parse().ok());
+    let (decision, new_tat) = gcra.check(tat, now, cost);
+    if let Some(new_tat) = new_tat {
+        let ttl = Duration::from_micros(new_tat - now).max(Duration::from_millis(1));
+        conn.set(key, new_tat.to_string().as_bytes(), Some(ttl))?;
+    }
+    Ok(decision)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECOND: u64 = 1_000_000;
+
+    fn gcra() -> Gcra {
+        // One request every 100ms, 3 at once
+        Gcra {
+            limit: 10,
+            period: Duration::from_secs(1),
+            burst: 3,
+        }
+    }
+
+    #[test]
+    fn bursts_are_allowed_then_spaced_out() {
+        let gcra = gcra();
+        let mut tat = None;
+        let mut check = |now: u64| {
+            let (decision, new_tat) = gcra.check(tat, now, 1);
+            tat = new_tat.or(tat);
+            decision
+        };
+
+        let remaining: Vec<_> = (0..3).map(|_| check(SECOND).remaining).collect();
+        assert_eq!(vec![2, 1, 0], remaining);
+
+        let refused = check(SECOND);
+        assert!(!refused.allowed);
+        assert_eq!(Some(Duration::from_millis(100)), refused.retry_after);
+        assert_eq!(Duration::from_millis(300), refused.reset_after);
+
+        // One emission interval later there is room for exactly one more
+        assert!(check(SECOND + SECOND / 10).allowed);
+        assert!(!check(SECOND + SECOND / 10).allowed);
+    }
+
+    #[test]
+    fn requests_above_the_burst_are_never_allowed() {
+        let (decision, tat) = gcra().check(None, SECOND, 4);
+        assert!(!decision.allowed);
+        assert_eq!(None, decision.retry_after);
+        assert_eq!(None, tat);
+    }
+}
@@ -0,0 +1,283 @@
+//! RESP3 push connections.
+//!
+//! The client library only speaks RESP2, so data commands keep using it. Server pushes
+//! (client tracking invalidations, pub/sub) are received on dedicated connections per master
+//! node that negotiate `HELLO 3` and are parsed here.
+
+use std::{
+    io::{self, BufRead, BufReader, ErrorKind, Write},
+    net::{Shutdown, TcpStream},
+    thread,
+};
+
+use bastion::prelude::Distributor;
+use bytes::Bytes;
+use redis::{IntoConnectionInfo, RedisResult};
+use serde::{Deserialize, Serialize};
+use tracing::{error, warn};
+
+use super::{connection::RedisConnection, node};
+
+/// RESP3 push settings
+#[derive(Default, Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Resp3Config {
+    /// Key prefixes invalidations are broadcast for, every key when empty
+    #[serde(default)]
+    pub tracking_prefixes: Vec<String>,
+    /// Name of the distributor receiving every `RedisPush`
+    pub deliver_to: String,
+}
+
+/// RESP3 value
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum Resp3 {
+    Null,
+    Simple(String),
+    Error(String),
+    Blob(Bytes),
+    Int(i64),
+    Double(f64),
+    Bool(bool),
+    BigNumber(String),
+    Array(Vec<Resp3>),
+    Set(Vec<Resp3>),
+    Map(Vec<(Resp3, Resp3)>),
+    Push(Vec<Resp3>),
+}
+
+impl Resp3 {
+    /// Text of a simple, blob or verbatim string
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Resp3::Simple(s) => Some(s),
+            Resp3::Blob(b) => std::str::from_utf8(b).ok(),
+            _ => None,
+        }
+    }
+}
+
+/// Push message received from a node, e.g. `invalidate` or `message`
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RedisPush {
+    /// `host:port` of the node that pushed it
+    pub node: String,
+    pub kind: String,
+    pub data: Vec<Resp3>,
+}
+
+impl RedisPush {
+    /// Keys of an `invalidate` push, `None` flushes every key
+    pub fn invalidated_keys(&self) -> Option<Vec<String>> {
+        match self.data.first() {
+            Some(Resp3::Array(keys)) => Some(
+                keys.iter()
+                    .filter_map(|key| key.as_str().map(ToOwned::to_owned))
+                    .collect(),
+            ),
+            _ => None,
+        }
+    }
+}
+
+/// Push connections to every master node, closed when dropped
+#[derive(Debug)]
+pub(crate) struct PushListeners(Vec<TcpStream>);
+
+impl PushListeners {
+    /// Connect to every master node and tell pushes to the actor behind `actor`
+    pub(crate) fn start(
+        conn: &mut RedisConnection,
+        urls: &[String],
+        config: &Resp3Config,
+        actor: Distributor,
+    ) -> RedisResult<Self> {
+        let auth = urls[0].as_str().into_connection_info()?.redis;
+        let mut streams = vec![];
+        for node in node::nodes(conn, urls)?
+            .into_iter()
+            .filter(|node| !node.slots.is_empty())
+        {
+            let addr = node.addr();
+            let stream = TcpStream::connect(&addr)?;
+            let mut reader = BufReader::new(stream.try_clone()?);
+            let mut writer = stream.try_clone()?;
+
+            let mut hello = vec!["HELLO".to_owned(), "3".to_owned()];
+            if let Some(password) = &auth.password {
+                let username = auth
+                    .username
+                    .clone()
+                    .unwrap_or_else(|| "default".to_owned());
+                hello.extend(["AUTH".to_owned(), username, password.clone()]);
+            }
+            request(&mut writer, &mut reader, &hello)?;
+
+            let mut tracking = vec!["CLIENT", "TRACKING", "ON", "BCAST"]
+                .into_iter()
+                .map(ToOwned::to_owned)
+                .collect::<Vec<_>>();
+            for prefix in &config.tracking_prefixes {
+                tracking.extend(["PREFIX".to_owned(), prefix.clone()]);
+            }
+            request(&mut writer, &mut reader, &tracking)?;
+
+            thread::spawn(move || listen(addr, reader, actor));
+            streams.push(stream);
+        }
+        Ok(Self(streams))
+    }
+}
+
+impl Drop for PushListeners {
+    fn drop(&mut self) {
+        // Wakes the listener threads up with an error, they stop there
+        for stream in &self.0 {
+            let _ = stream.shutdown(Shutdown::Both);
+        }
+    }
+}
+
+fn listen(addr: String, mut reader: BufReader<TcpStream>, actor: Distributor) {
+    loop {
+        match read(&mut reader) {
+            Ok(Resp3::Push(mut items)) if !items.is_empty() => {
+                let kind = items.remove(0).as_str().unwrap_or_default().to_owned();
+                let push = RedisPush {
+                    node: addr.clone(),
+                    kind,
+                    data: items,
+                };
+                if let Err(e) = actor.tell_one(push) {
+                    warn!("[REDIS] Cannot forward push: {e:?}");
+                }
+            }
+            Ok(other) => warn!(
+                node = addr,
+                "unexpected reply on push connection: {other:?}"
+            ),
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => break,
+            Err(e) => {
+                // Shut down by `PushListeners`, or lost
+                if e.kind() != ErrorKind::NotConnected {
+                    error!(node = addr, error = %e, "push connection closed");
+                }
+                break;
+            }
+        }
+    }
+}
+
+// Sends a command and fails on an error reply
+fn request(
+    writer: &mut impl Write,
+    reader: &mut impl BufRead,
+    args: &[String],
+) -> RedisResult<Resp3> {
+    let mut packed = format!("*{}\r\n", args.len()).into_bytes();
+    for arg in args {
+        packed.extend(format!("${}\r\n", arg.len()).as_bytes());
+        packed.extend(arg.as_bytes());
+        packed.extend(b"\r\n");
+    }
+    writer.write_all(&packed)?;
+    match read(reader)? {
+        Resp3::Error(e) => Err((redis::ErrorKind::ResponseError, "RESP3 request failed", e).into()),
+        reply => Ok(reply),
+    }
+}
+
+/// Read one RESP3 value, attributes are skipped
+pub(crate) fn read(reader: &mut impl BufRead) -> io::Result<Resp3> {
+    let line = read_line(reader)?;
+    let (kind, rest) = line.split_at(line.chars().next().map_or(0, char::len_utf8));
+    let invalid = || {
+        io::Error::new(
+            ErrorKind::InvalidData,
+            format!("invalid RESP3 line `{line}`"),
+        )
+    };
+    let count = || rest.parse::<i64>().map_err(|_| invalid());
+
+    Ok(match kind {
+        "+" => Resp3::Simple(rest.to_owned()),
+        "-" => Resp3::Error(rest.to_owned()),
+        ":" => Resp3::Int(count()?),
+        "_" => Resp3::Null,
+        "#" => Resp3::Bool(rest == "t"),
+        "," => Resp3::Double(rest.parse().map_err(|_| invalid())?),
+        "(" => Resp3::BigNumber(rest.to_owned()),
+        "$" | "=" | "!" => {
+            let len = count()?;
+            if len < 0 {
+                return Ok(Resp3::Null);
+            }
+            let mut blob = vec![0; len as usize + 2];
+            reader.read_exact(&mut blob)?;
+            blob.truncate(len as usize);
+            match kind {
+                "!" => Resp3::Error(String::from_utf8_lossy(&blob).into_owned()),
+                // Verbatim strings start with their format, e.g. `txt:`
+                "=" => Resp3::Blob(Bytes::from(blob.split_off(4.min(blob.len())))),
+                _ => Resp3::Blob(Bytes::from(blob)),
+            }
+        }
+        "*" | "~" | ">" => {
+            let len = count()?;
+            if len < 0 {
+                return Ok(Resp3::Null);
+            }
+            let items = (0..len).map(|_| read(reader)).collect::<io::Result<_>>()?;
+            match kind {
+                "~" => Resp3::Set(items),
+                ">" => Resp3::Push(items),
+                _ => Resp3::Array(items),
+            }
+        }
+        "%" | "|" => {
+            let pairs = (0..count()?)
+                .map(|_| Ok((read(reader)?, read(reader)?)))
+                .collect::<io::Result<_>>()?;
+            if kind == "|" {
+                return read(reader);
+            }
+            Resp3::Map(pairs)
+        }
+        _ => return Err(invalid()),
+    })
+}
+
+fn read_line(reader: &mut impl BufRead) -> io::Result<String> {
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Err(ErrorKind::UnexpectedEof.into());
+    }
+    line.truncate(line.trim_end_matches("\r\n").len());
+    if line.is_empty() {
+        return Err(io::Error::new(ErrorKind::InvalidData, "empty RESP3 line"));
+    }
+    Ok(line)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pushes_and_maps_are_parsed() {
+        let mut input: &[u8] =
+            b">2\r\n$10\r\ninvalidate\r\n*1\r\n$5\r\nhello\r\n%1\r\n+proto\r\n:3\r\n";
+
+        let push = read(&mut input).unwrap();
+        assert_eq!(
+            Resp3::Push(vec![
+                Resp3::Blob(Bytes::from_static(b"invalidate")),
+                Resp3::Array(vec![Resp3::Blob(Bytes::from_static(b"hello"))]),
+            ]),
+            push
+        );
+        assert_eq!(
+            Resp3::Map(vec![(Resp3::Simple("proto".to_owned()), Resp3::Int(3))]),
+            read(&mut input).unwrap()
+        );
+    }
+}
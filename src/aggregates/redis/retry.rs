@@ -0,0 +1,192 @@
+//! Retries of commands failing with a transient error.
+//!
+//! A command failing because the connection dropped, the cluster is failing over or a node is
+//! still loading its dataset is sent again on the same connection after a backoff, so brief
+//! blips never reach the caller. This sits below the pool, which only reconnects once a
+//! connection is gone for good.
+//!
+//! Commands that are not idempotent, e.g. `INCRBY`, are only retried when the node refused them
+//! (`TRYAGAIN`, `CLUSTERDOWN`, `MASTERDOWN`, `LOADING`): after a dropped connection they may
+//! have run already.
+
+use std::{thread, time::Duration};
+
+use redis::RedisResult;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use super::{error, metrics};
+
+/// Attempts and backoff of one class of commands
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Attempts added to the first one, none when zero
+    pub retries: u32,
+    /// Wait before the first retry, doubled for each one after it
+    pub backoff: Duration,
+    /// Longest wait between two attempts
+    pub max_backoff: Duration,
+}
+
+impl RetryPolicy {
+    /// Never retry
+    pub const NONE: Self = Self {
+        retries: 0,
+        backoff: Duration::ZERO,
+        max_backoff: Duration::ZERO,
+    };
+
+    /// Wait before retry number `retry`, counted from one
+    fn delay(&self, retry: u32) -> Duration {
+        let factor = 1u32
+            .checked_shl(retry.saturating_sub(1))
+            .unwrap_or(u32::MAX);
+        self.backoff.saturating_mul(factor).min(self.max_backoff)
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            retries: 2,
+            backoff: Duration::from_millis(50),
+            max_backoff: Duration::from_secs(1),
+        }
+    }
+}
+
+/// Retry policy per class of commands
+#[derive(Default, Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RetryConfig {
+    #[serde(default)]
+    pub reads: RetryPolicy,
+    /// Writes giving the same result when applied twice, e.g. `SET` or `PEXPIRE`
+    #[serde(default)]
+    pub writes: RetryPolicy,
+    /// Other writes, e.g. increments or rate limit checks
+    #[serde(default)]
+    pub non_idempotent: RetryPolicy,
+}
+
+/// What a command does, picking its retry policy
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum CommandClass {
+    Read,
+    Write,
+    NonIdempotent,
+}
+
+impl RetryConfig {
+    /// Run `f` until it succeeds, fails with an error that is not worth retrying or runs out of
+    /// attempts. Returns its last result and the retries it took.
+    pub(crate) fn run<T>(
+        &self,
+        class: CommandClass,
+        op: &'static str,
+        mut f: impl FnMut() -> RedisResult<T>,
+    ) -> (RedisResult<T>, u32) {
+        let (policy, retryable): (_, fn(&redis::RedisError) -> bool) = match class {
+            CommandClass::Read => (self.reads, error::is_transient),
+            CommandClass::Write => (self.writes, error::is_transient),
+            CommandClass::NonIdempotent => (self.non_idempotent, error::is_refused),
+        };
+        let mut retries = 0;
+        loop {
+            match f() {
+                Err(e) if retries < policy.retries && retryable(&e) => {
+                    retries += 1;
+                    warn!(op, retries, error = %e, "[REDIS] Retrying command");
+                    metrics::record_retry(op);
+                    thread::sleep(policy.delay(retries));
+                }
+                result => return (result, retries),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use redis::ErrorKind;
+
+    use super::*;
+
+    fn policy() -> RetryConfig {
+        let policy = RetryPolicy {
+            retries: 3,
+            backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(2),
+        };
+        RetryConfig {
+            reads: policy,
+            writes: policy,
+            non_idempotent: policy,
+        }
+    }
+
+    fn failing(kinds: &[ErrorKind]) -> impl FnMut() -> RedisResult<u8> + '_ {
+        let mut kinds = kinds.iter();
+        move || match kinds.next() {
+            Some(kind) => Err((*kind, "failed").into()),
+            None => Ok(1),
+        }
+    }
+
+    #[test]
+    fn transient_errors_are_retried_until_the_budget_runs_out() {
+        let (result, retries) = policy().run(
+            CommandClass::Read,
+            "get",
+            failing(&[ErrorKind::TryAgain, ErrorKind::BusyLoadingError]),
+        );
+        assert_eq!((Some(1), 2), (result.ok(), retries));
+
+        let (result, retries) = policy().run(
+            CommandClass::Write,
+            "set",
+            failing(&[ErrorKind::TryAgain; 4]),
+        );
+        assert_eq!(
+            (Some(ErrorKind::TryAgain), 3),
+            (result.err().map(|e| e.kind()), retries)
+        );
+
+        let (result, retries) =
+            policy().run(CommandClass::Read, "get", failing(&[ErrorKind::TypeError]));
+        assert!(result.is_err());
+        assert_eq!(0, retries);
+    }
+
+    #[test]
+    fn non_idempotent_commands_are_retried_only_when_refused() {
+        let io = || -> RedisResult<u8> {
+            Err(std::io::Error::from(std::io::ErrorKind::ConnectionReset).into())
+        };
+        let (result, retries) = policy().run(CommandClass::NonIdempotent, "incrby", io);
+        assert!(result.is_err());
+        assert_eq!(0, retries);
+        let (_, retries) = policy().run(CommandClass::Write, "set", io);
+        assert_eq!(3, retries);
+
+        let (result, retries) = policy().run(
+            CommandClass::NonIdempotent,
+            "incrby",
+            failing(&[ErrorKind::ClusterDown]),
+        );
+        assert_eq!((Some(1), 1), (result.ok(), retries));
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_its_maximum() {
+        let policy = RetryPolicy {
+            retries: 10,
+            backoff: Duration::from_millis(50),
+            max_backoff: Duration::from_millis(300),
+        };
+        let delays: Vec<_> = (1..=5)
+            .map(|retry| policy.delay(retry).as_millis())
+            .collect();
+        assert_eq!(vec![50, 100, 200, 300, 300], delays);
+        assert_eq!(Duration::ZERO, RetryPolicy::NONE.delay(1));
+    }
+}
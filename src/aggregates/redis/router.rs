@@ -0,0 +1,319 @@
+//! Routing across several Redis actor instances.
+//!
+//! The router owns one named Redis actor per cluster and answers the same insert, query and
+//! delete messages as a single actor, forwarding each key to the cluster picked by the first
+//! matching `RouteRule`. Callers switch clusters by sending to `RedisRouter::distributor()`
+//! instead of `Redis::distributor()`. Messages wrapped in `ForTenant` are scoped to a tenant,
+//! see `tenancy`.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Debug,
+};
+
+use async_trait::async_trait;
+use bastion::prelude::{AnswerSender, BastionContext, Distributor, Message, MessageHandler};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::actors::base::{liveness, TActor};
+
+use super::{
+    command::validate_urls,
+    delete::RedisDeleteMany,
+    error::RedisError,
+    failover,
+    multi::key_slot,
+    tenancy::{self, ForTenant, Tenant, TenantKeys},
+    Redis, RedisInsert, RedisQuery,
+};
+
+/// How keys are assigned to a cluster
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum RouteRule {
+    /// Keys starting with `prefix` go to `cluster`
+    Prefix { prefix: String, cluster: String },
+    /// Every key goes to one of `clusters` by its hash slot, so keys sharing a `{hash tag}` stay
+    /// together
+    Hash { clusters: Vec<String> },
+}
+
+impl RouteRule {
+    /// Cluster of `key`, `None` when the rule does not apply to it
+    fn route(&self, key: &str) -> Option<&str> {
+        match self {
+            Self::Prefix { prefix, cluster } => key.starts_with(prefix).then_some(cluster),
+            Self::Hash { clusters } if !clusters.is_empty() => {
+                Some(&clusters[usize::from(key_slot(key)) % clusters.len()])
+            }
+            Self::Hash { .. } => None,
+        }
+    }
+
+    fn clusters(&self) -> Vec<&str> {
+        match self {
+            Self::Prefix { cluster, .. } => vec![cluster],
+            Self::Hash { clusters } => clusters.iter().map(String::as_str).collect(),
+        }
+    }
+}
+
+/// Router settings, the actor state
+#[derive(Default, Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RedisRouter {
+    /// Configuration of every cluster by name, each runs as the Redis actor built with that name
+    pub clusters: BTreeMap<String, Redis>,
+    /// Checked in order, the first rule matching a key picks its cluster
+    pub rules: Vec<RouteRule>,
+    /// Tenants by id, their keys are only reached through `ForTenant` messages
+    #[serde(default)]
+    pub tenants: BTreeMap<String, Tenant>,
+}
+
+impl RedisRouter {
+    /// Distributor the router answers on
+    pub fn distributor() -> Distributor {
+        Distributor::named("redis_router")
+    }
+
+    /// Check every cluster's urls, that rules and tenants only name known clusters and that
+    /// tenant prefixes don't overlap
+    pub fn validate(&self) -> Result<(), RedisError> {
+        for redis in self.clusters.values() {
+            validate_urls(&redis.urls)?;
+            failover::validate(redis)?;
+        }
+        for cluster in self.rules.iter().flat_map(RouteRule::clusters) {
+            if !self.clusters.contains_key(cluster) {
+                return Err(RedisError::UnknownCluster(cluster.to_owned()));
+            }
+        }
+        tenancy::validate(&self.tenants, &self.clusters)
+    }
+
+    /// Name of the cluster serving `key`
+    pub fn route(&self, key: &str) -> Result<&str, RedisError> {
+        self.rules
+            .iter()
+            .find_map(|rule| rule.route(key))
+            .ok_or_else(|| RedisError::NoRoute(key.to_owned()))
+    }
+
+    // Distributor of the actor serving `key`, sent on behalf of `tenant` when given. Keys under
+    // a tenant's prefix are refused otherwise.
+    fn target(&self, tenant: Option<&Tenant>, key: &str) -> Result<Distributor, RedisError> {
+        let cluster = match tenant {
+            Some(Tenant {
+                cluster: Some(cluster),
+                ..
+            }) => cluster,
+            Some(_) => self.route(key)?,
+            None if tenancy::owner(&self.tenants, key).is_some() => {
+                return Err(RedisError::TenantKey(key.to_owned()))
+            }
+            None => self.route(key)?,
+        };
+        Ok(Redis::distributor_named(Some(cluster)))
+    }
+
+    // Tenant of a scoped message and the message with its keys under the tenant's prefix
+    fn scope<M: TenantKeys>(&self, scoped: ForTenant<M>) -> Result<(&Tenant, M), RedisError> {
+        let tenant = self
+            .tenants
+            .get(&scoped.tenant)
+            .ok_or(RedisError::UnknownTenant(scoped.tenant))?;
+        let mut message = scoped.message;
+        message.prefix_keys(&tenant.prefix);
+        Ok((tenant, message))
+    }
+
+    // Tell or forward an insert, an asked one is replied with the cluster's answer
+    fn insert(&self, tenant: Option<&Tenant>, event: RedisInsert, sender: Option<AnswerSender>) {
+        let target = self.target(tenant, &event.key);
+        match sender {
+            Some(sender) => forward::<()>(target, event, sender),
+            None => match target {
+                Ok(target) => {
+                    if let Err(e) = target.tell_one(event) {
+                        warn!("[ROUTER] Cannot forward insert: {e:?}");
+                    }
+                }
+                Err(e) => warn!("[ROUTER] Insert dropped: {e}"),
+            },
+        }
+    }
+
+    // Split the keys by cluster and reply with the total deleted
+    fn delete_many(&self, tenant: Option<&Tenant>, event: RedisDeleteMany, sender: AnswerSender) {
+        let mut groups: HashMap<Distributor, Vec<String>> = HashMap::new();
+        for key in event.keys {
+            match self.target(tenant, &key) {
+                Ok(target) => groups.entry(target).or_default().push(key),
+                Err(e) => return refuse::<u64>(sender, e),
+            }
+        }
+        let caller = event.caller;
+        tokio::spawn(async move {
+            let mut total = 0;
+            for (target, keys) in groups {
+                let delete = RedisDeleteMany {
+                    keys,
+                    caller: caller.clone(),
+                };
+                match ask::<u64>(target, delete).await {
+                    Ok(deleted) => total += deleted,
+                    Err(e) => return refuse::<u64>(sender, e),
+                }
+            }
+            // The caller may be gone already
+            let _ = sender.reply(Ok::<_, RedisError>(total));
+        });
+    }
+}
+
+// Forward a question to `target` and reply with its answer
+fn forward<T>(target: Result<Distributor, RedisError>, question: impl Message, sender: AnswerSender)
+where
+    T: Send + Sync + Debug + 'static,
+{
+    tokio::spawn(async move {
+        let reply = match target {
+            Ok(target) => ask::<T>(target, question).await,
+            Err(e) => Err(e),
+        };
+        // The caller may be gone already
+        let _ = sender.reply(reply);
+    });
+}
+
+// Reply to a question expecting `Result<T, RedisError>` with `error`
+fn refuse<T>(sender: AnswerSender, error: RedisError)
+where
+    T: Send + Sync + Debug + 'static,
+{
+    // The caller may be gone already
+    let _ = sender.reply(Err::<T, _>(error));
+}
+
+// Ask an instance a question replied with `Result<T, RedisError>`, `RedisError::NotReady` when
+// it cannot be reached
+pub(crate) async fn ask<T>(target: Distributor, question: impl Message) -> Result<T, RedisError>
+where
+    T: Send + Sync + Debug + 'static,
+{
+    match target.request::<Result<T, RedisError>>(question).await {
+        Ok(Ok(reply)) => reply,
+        Ok(Err(e)) => {
+            warn!("[ROUTER] Cannot reach the cluster: {e:?}");
+            Err(RedisError::NotReady)
+        }
+        Err(_) => Err(RedisError::NotReady),
+    }
+}
+
+#[async_trait]
+impl TActor for RedisRouter {
+    fn with_distributor() -> Option<Distributor> {
+        Some(Self::distributor())
+    }
+
+    async fn handler(&mut self, ctx: BastionContext) -> Result<(), ()> {
+        loop {
+            MessageHandler::new(ctx.recv().await?)
+                .on_tell(|event: RedisInsert, _| self.insert(None, event, None))
+                .on_question(|event: RedisInsert, sender| self.insert(None, event, Some(sender)))
+                .on_question(|event: RedisQuery, sender| {
+                    let target = self.target(None, &event.key);
+                    forward::<Option<Vec<u8>>>(target, event, sender)
+                })
+                .on_question(|event: RedisDeleteMany, sender| self.delete_many(None, event, sender))
+                .on_tell(
+                    |scoped: ForTenant<RedisInsert>, _| match self.scope(scoped) {
+                        Ok((tenant, event)) => self.insert(Some(tenant), event, None),
+                        Err(e) => warn!("[ROUTER] Insert dropped: {e}"),
+                    },
+                )
+                .on_question(
+                    |scoped: ForTenant<RedisInsert>, sender| match self.scope(scoped) {
+                        Ok((tenant, event)) => self.insert(Some(tenant), event, Some(sender)),
+                        Err(e) => refuse::<()>(sender, e),
+                    },
+                )
+                .on_question(
+                    |scoped: ForTenant<RedisQuery>, sender| match self.scope(scoped) {
+                        Ok((tenant, event)) => {
+                            let target = self.target(Some(tenant), &event.key);
+                            forward::<Option<Vec<u8>>>(target, event, sender)
+                        }
+                        Err(e) => refuse::<Option<Vec<u8>>>(sender, e),
+                    },
+                )
+                .on_question(|scoped: ForTenant<RedisDeleteMany>, sender| {
+                    match self.scope(scoped) {
+                        Ok((tenant, event)) => self.delete_many(Some(tenant), event, sender),
+                        Err(e) => refuse::<u64>(sender, e),
+                    }
+                })
+                .on_fallback(|unknown, _| warn!("[ROUTER] Unknown message: {unknown:?}"));
+            liveness::processed();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn router(rules: Vec<RouteRule>) -> RedisRouter {
+        let cluster = Redis {
+            urls: vec!["redis://127.0.0.1:30006".to_owned()],
+            ..Default::default()
+        };
+        RedisRouter {
+            clusters: BTreeMap::from([
+                ("eu".to_owned(), cluster.clone()),
+                ("us".to_owned(), cluster),
+            ]),
+            rules,
+            tenants: BTreeMap::new(),
+        }
+    }
+
+    #[test]
+    fn the_first_matching_rule_picks_the_cluster() {
+        let router = router(vec![
+            RouteRule::Prefix {
+                prefix: "tenant:eu:".to_owned(),
+                cluster: "eu".to_owned(),
+            },
+            RouteRule::Hash {
+                clusters: vec!["eu".to_owned(), "us".to_owned()],
+            },
+        ]);
+        assert_eq!(Ok(()), router.validate());
+        assert_eq!(Ok("eu"), router.route("tenant:eu:1"));
+        // Hash tags keep keys together
+        assert_eq!(router.route("{user:7}:a"), router.route("{user:7}:b"));
+
+        let prefixes_only = RedisRouter {
+            rules: router.rules[..1].to_vec(),
+            ..router
+        };
+        assert_eq!(
+            Err(RedisError::NoRoute("tenant:us:1".to_owned())),
+            prefixes_only.route("tenant:us:1")
+        );
+    }
+
+    #[test]
+    fn rules_may_only_name_known_clusters() {
+        let router = router(vec![RouteRule::Prefix {
+            prefix: "a:".to_owned(),
+            cluster: "apac".to_owned(),
+        }]);
+        assert_eq!(
+            Err(RedisError::UnknownCluster("apac".to_owned())),
+            router.validate()
+        );
+    }
+}
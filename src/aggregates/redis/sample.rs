@@ -0,0 +1,175 @@
+use redis::{Cmd, RedisResult, Value};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    sorted_set::ScoredMember,
+    typed::{items, pairs, TypedCommand},
+};
+
+/// `SRANDMEMBER`, replied with random members of a set.
+///
+/// Without `count` one member is sampled, a negative `count` may sample a member several times.
+#[derive(Default, Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RedisSRandMember {
+    pub key: String,
+    #[serde(default)]
+    pub count: Option<i64>,
+}
+
+impl TypedCommand for RedisSRandMember {
+    type Reply = Vec<String>;
+    const OP: &'static str = "srandmember";
+    const READ_ONLY: bool = true;
+
+    fn key(&self) -> &str {
+        &self.key
+    }
+
+    fn cmd(&self) -> Cmd {
+        let mut cmd = redis::cmd("SRANDMEMBER");
+        cmd.arg(&self.key).arg(self.count);
+        cmd
+    }
+
+    fn parse(&self, value: &Value) -> RedisResult<Vec<String>> {
+        items(self.count, value)
+    }
+}
+
+/// Field of a hash, with its value when requested
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct HashField {
+    pub field: String,
+    pub value: Option<Vec<u8>>,
+}
+
+/// `HRANDFIELD`, replied with random fields of a hash, `with_values` needs `count`
+#[derive(Default, Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RedisHRandField {
+    pub key: String,
+    #[serde(default)]
+    pub count: Option<i64>,
+    #[serde(default)]
+    pub with_values: bool,
+}
+
+impl TypedCommand for RedisHRandField {
+    type Reply = Vec<HashField>;
+    const OP: &'static str = "hrandfield";
+    const READ_ONLY: bool = true;
+
+    fn key(&self) -> &str {
+        &self.key
+    }
+
+    fn cmd(&self) -> Cmd {
+        let mut cmd = redis::cmd("HRANDFIELD");
+        cmd.arg(&self.key).arg(self.count);
+        if self.with_values && self.count.is_some() {
+            cmd.arg("WITHVALUES");
+        }
+        cmd
+    }
+
+    fn parse(&self, value: &Value) -> RedisResult<Vec<HashField>> {
+        if self.with_values && self.count.is_some() {
+            return Ok(pairs(value)?
+                .into_iter()
+                .map(|(field, value)| HashField {
+                    field,
+                    value: Some(value),
+                })
+                .collect());
+        }
+        Ok(items(self.count, value)?
+            .into_iter()
+            .map(|field| HashField { field, value: None })
+            .collect())
+    }
+}
+
+/// `ZRANDMEMBER`, replied with random members of a sorted set, `with_scores` needs `count`
+#[derive(Default, Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RedisZRandMember {
+    pub key: String,
+    #[serde(default)]
+    pub count: Option<i64>,
+    #[serde(default)]
+    pub with_scores: bool,
+}
+
+impl TypedCommand for RedisZRandMember {
+    type Reply = Vec<ScoredMember>;
+    const OP: &'static str = "zrandmember";
+    const READ_ONLY: bool = true;
+
+    fn key(&self) -> &str {
+        &self.key
+    }
+
+    fn cmd(&self) -> Cmd {
+        let mut cmd = redis::cmd("ZRANDMEMBER");
+        cmd.arg(&self.key).arg(self.count);
+        if self.with_scores && self.count.is_some() {
+            cmd.arg("WITHSCORES");
+        }
+        cmd
+    }
+
+    fn parse(&self, value: &Value) -> RedisResult<Vec<ScoredMember>> {
+        if self.with_scores && self.count.is_some() {
+            return Ok(pairs(value)?
+                .into_iter()
+                .map(|(member, score)| ScoredMember {
+                    member,
+                    score: Some(score),
+                })
+                .collect());
+        }
+        Ok(items(self.count, value)?
+            .into_iter()
+            .map(|member| ScoredMember {
+                member,
+                score: None,
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aggregates::redis::typed::args;
+
+    #[test]
+    fn samples_are_built_and_parsed() {
+        let one = RedisSRandMember {
+            key: "jobs".to_owned(),
+            count: None,
+        };
+        assert_eq!(vec!["SRANDMEMBER", "jobs"], args(&one.cmd()));
+        assert_eq!(
+            vec!["a".to_owned()],
+            one.parse(&Value::Data(b"a".to_vec())).unwrap()
+        );
+        assert!(one.parse(&Value::Nil).unwrap().is_empty());
+
+        let fields = RedisHRandField {
+            key: "buckets".to_owned(),
+            count: Some(-2),
+            with_values: true,
+        };
+        assert_eq!(
+            vec!["HRANDFIELD", "buckets", "-2", "WITHVALUES"],
+            args(&fields.cmd())
+        );
+        let reply = Value::Bulk(vec![Value::Data(b"b".to_vec()), Value::Data(b"1".to_vec())]);
+        assert_eq!(
+            vec![HashField {
+                field: "b".to_owned(),
+                value: Some(b"1".to_vec()),
+            }],
+            fields.parse(&reply).unwrap()
+        );
+    }
+}
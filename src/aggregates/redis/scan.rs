@@ -0,0 +1,93 @@
+use std::{
+    ops::ControlFlow,
+    thread,
+    time::{Duration, Instant},
+};
+
+use redis::{Connection, RedisResult};
+
+use super::{connection::RedisConnection, node};
+
+/// `SCAN MATCH pattern` every master, calling `f` with the node connection and each batch of
+/// keys found until it breaks. Keys written while the scan runs may be missed.
+pub(crate) fn scan_masters(
+    conn: &mut RedisConnection,
+    urls: &[String],
+    pattern: &str,
+    batch: usize,
+    mut f: impl FnMut(&mut RedisConnection, &mut Connection, &[String]) -> RedisResult<ControlFlow<()>>,
+) -> RedisResult<()> {
+    // Every master holds its own keyspace, replicas mirror them
+    for node in node::nodes(conn, urls)?
+        .into_iter()
+        .filter(|node| !node.slots.is_empty())
+    {
+        let mut node_conn = node.client(urls)?.get_connection()?;
+        let mut cursor = 0u64;
+        loop {
+            let (next, keys): (u64, Vec<String>) = redis::cmd("SCAN")
+                .arg(cursor)
+                .arg("MATCH")
+                .arg(pattern)
+                .arg("COUNT")
+                .arg(batch)
+                .query(&mut node_conn)?;
+            for keys in keys.chunks(batch) {
+                if f(conn, &mut node_conn, keys)?.is_break() {
+                    return Ok(());
+                }
+            }
+            if next == 0 {
+                break;
+            }
+            cursor = next;
+        }
+    }
+    Ok(())
+}
+
+/// Keeps a blocking loop under `per_second` keys per second
+#[derive(Debug)]
+pub(crate) struct Throttle {
+    per_second: Option<u32>,
+    started: Instant,
+    done: u64,
+}
+
+impl Throttle {
+    pub(crate) fn new(per_second: Option<u32>) -> Self {
+        Self {
+            per_second,
+            started: Instant::now(),
+            done: 0,
+        }
+    }
+
+    /// Count `keys` more keys, sleeping while ahead of the rate
+    pub(crate) fn wait(&mut self, keys: usize) {
+        self.done += keys as u64;
+        if let Some(delay) = self.delay() {
+            thread::sleep(delay);
+        }
+    }
+
+    fn delay(&self) -> Option<Duration> {
+        let per_second = self.per_second.filter(|rate| *rate > 0)?;
+        let due = Duration::from_secs_f64(self.done as f64 / f64::from(per_second));
+        due.checked_sub(self.started.elapsed())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn throttles_wait_when_ahead_of_the_rate() {
+        let mut throttle = Throttle::new(Some(100));
+        throttle.done = 50;
+        assert!(throttle.delay().unwrap() > Duration::from_millis(400));
+
+        assert_eq!(None, Throttle::new(None).delay());
+    }
+}
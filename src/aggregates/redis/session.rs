@@ -0,0 +1,1451 @@
+use std::{
+    ops::{ControlFlow, Deref, DerefMut},
+    sync::Arc,
+    thread,
+    time::Duration,
+};
+
+use bastion::prelude::{AnswerSender, Distributor, MessageHandler, SignedMessage};
+use bytes::Bytes;
+use r2d2::{Pool, PooledConnection};
+use tokio::task;
+use tracing::{error, info_span, warn};
+
+use crate::actors::{base::ticker::Ticker, machine::MachineContext};
+
+use super::{
+    access::{self, KeyAccess, RedisAccessQuery},
+    backend::{MemoryBackend, RedisBackend},
+    bigkeys::{self, BigKeyReport, RedisBigKeyScan},
+    collection::{RedisLInsert, RedisLPos, RedisLRem, RedisLSet, RedisLen},
+    command::RedisCommand,
+    connection::RedisConnection,
+    control::{ControlRequest, RedisControl},
+    deferred::PendingWrites,
+    delete::{self, RedisDeleteByPattern, RedisDeleteMany},
+    error::{RedisError, RedisErrorStatsQuery},
+    event::RedisEvent,
+    expiry::{
+        ExpiryAudit, ExpiryAuditDone, ExpiryAuditReport, ExpiryAuditTick, RedisExpiryAuditQuery,
+    },
+    export::{self, RedisExport},
+    health::{HealthChecker, HealthPinged, HealthTick, RedisHealthQuery},
+    hotkeys::{HotKeysTick, RedisHotKeysQuery},
+    import::{self, ImportProgress, RedisImport},
+    migrate::{self, MigrationProgress, RedisMigrate},
+    mirror::{self, RedisMirrorBackfill},
+    multi::{RedisExists, RedisMultiInsert, RedisMultiQuery},
+    node::{self, ClusterNode, NodeReply, RedisNodeCommand, RedisTopologyQuery},
+    numeric::{RedisHIncrByFloat, RedisHashIncrement, RedisIncrByFloat},
+    object::{RedisHashGet, RedisHashGetAll, RedisHashSet},
+    pipeline::{Batch, Pending},
+    pool::{
+        checkout, checkout_timeout, ConnectionConfig, ConnectionRegistry, PoolStats,
+        PoolStatsReport, ReconnectConfig, RedisManager, RedisPoolStats, SharedConfig,
+    },
+    pubsub::{self, RedisPublish, RedisSubscribe, Subscription},
+    ratelimit::{RateLimitDecision, RedisRateLimit},
+    resp3::{PushListeners, RedisPush, Resp3Config},
+    sample::{RedisHRandField, RedisSRandMember, RedisZRandMember},
+    slowlog::{
+        self, RedisSlowlogQuery, SlowlogCollector, SlowlogEntry, SlowlogFetched, SlowlogTick,
+    },
+    sorted_set::{RedisZIncrBy, RedisZRangeByScore},
+    stream::{self, RedisStreamQuery},
+    tags::{RedisInvalidateTag, RedisTaggedInsert},
+    typed::TypedCommand,
+    view::RedisStatus,
+    Blocking, Redis, RedisExpire, RedisInsert, RedisQuery, RedisSlidingQuery, RedisState,
+    RedisStatusQuery, RedisTtlQuery, Ttl,
+};
+
+/// The actor's own connection, only away while a blocking call runs on it
+struct ActorConnection(Option<PooledConnection<RedisManager>>);
+
+impl Deref for ActorConnection {
+    type Target = RedisConnection;
+
+    fn deref(&self) -> &Self::Target {
+        self.0
+            .as_ref()
+            .expect("connection is back after every blocking call")
+    }
+}
+
+impl DerefMut for ActorConnection {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.0
+            .as_mut()
+            .expect("connection is back after every blocking call")
+    }
+}
+
+/// How long a connection attempt waits for the cluster
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+/// How long a disconnected session waits for an open connection before answering a message
+const RECHECK_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Told once a connection to the cluster can be opened again
+#[derive(Debug)]
+pub(crate) struct ReconnectTick;
+
+/// Waits for the cluster on its own thread, so the disconnected writer keeps answering, and
+/// tells it `ReconnectTick` once connections open again. Stops when dropped or when its
+/// retries run out.
+struct Reconnector {
+    _alive: Arc<()>,
+}
+
+impl Reconnector {
+    fn spawn(pool: Pool<RedisManager>, config: ReconnectConfig, actor: Distributor) -> Self {
+        let alive = Arc::new(());
+        let weak = Arc::downgrade(&alive);
+
+        thread::spawn(move || {
+            let mut attempt = 0;
+            loop {
+                thread::sleep(config.delay(attempt));
+                if weak.strong_count() == 0 {
+                    break;
+                }
+                match checkout_timeout(&pool, CONNECT_TIMEOUT) {
+                    // Back in the pool when dropped, ready for the writer
+                    Ok(_) => {
+                        if let Err(e) = actor.tell_one(ReconnectTick) {
+                            warn!("[REDIS] Cannot report the cluster is back: {e:?}");
+                        }
+                        break;
+                    }
+                    Err(e) => {
+                        attempt += 1;
+                        if config.max_attempts.is_some_and(|max| attempt >= max) {
+                            error!(attempts = attempt, error = %e, "[REDIS] Giving up connecting");
+                            let command = RedisCommand::AbandonRedisServer {
+                                error: e.to_string(),
+                                attempts: attempt,
+                            };
+                            if let Err(e) = actor.tell_one(command) {
+                                warn!("[REDIS] Cannot mark the actor abandoned: {e:?}");
+                            }
+                            break;
+                        }
+                        warn!(attempt, error = %e, "[REDIS] Still disconnected");
+                    }
+                }
+            }
+        });
+
+        Self { _alive: alive }
+    }
+}
+
+/// Everything a running handler owns besides the aggregate, dropped when the child restarts
+pub(crate) struct RedisSession {
+    config: Arc<SharedConfig>,
+    registry: ConnectionRegistry,
+    pool: Pool<RedisManager>,
+    conn: ActorConnection,
+    /// Call waiting to run on the connection off the async workers
+    blocking: Option<Blocking>,
+    /// Projection of the applied events
+    status: RedisStatus,
+    machine: MachineContext<Redis>,
+    /// Distributor of the actor instance, internal commands and events go through it
+    actor: Distributor,
+    health: HealthChecker,
+    slowlog: SlowlogCollector,
+    expiry_audit: ExpiryAudit,
+    batch: Batch,
+    /// RESP3 push connections, when enabled
+    push: Option<PushListeners>,
+    /// Inserts waiting for the connection to be ready
+    pending_writes: PendingWrites,
+    /// Whether the session runs the writer, which owns the aggregate state
+    writer: bool,
+    /// Connection attempts, only while the writer is disconnected
+    reconnect: Option<Reconnector>,
+    reconnect_config: ReconnectConfig,
+    _tickers: Vec<Ticker>,
+}
+
+impl RedisSession {
+    /// Build the pool and check out the session's connection, left without one when the
+    /// cluster cannot be reached
+    fn connect(redis: &Redis, writer: bool) -> Self {
+        let config = SharedConfig::new(ConnectionConfig {
+            urls: redis.get_urls(),
+        });
+        let registry = ConnectionRegistry::default();
+        let manager = RedisManager::new(config.clone(), registry.clone());
+
+        // Connections are opened on checkout, so building the pool cannot fail
+        let pool = r2d2::Pool::builder().max_size(15).build_unchecked(manager);
+
+        let conn = match checkout_timeout(&pool, CONNECT_TIMEOUT) {
+            Ok(conn) => Some(conn),
+            Err(e) => {
+                error!(error = %e, "cannot connect to the cluster");
+                Redis::report_error(redis.own_distributor(), "Pool", &e);
+                None
+            }
+        };
+
+        Self {
+            config,
+            registry,
+            pool,
+            conn: ActorConnection(conn),
+            blocking: None,
+            status: RedisStatus::default(),
+            machine: MachineContext::new(redis.own_distributor()),
+            actor: redis.own_distributor(),
+            health: HealthChecker::default(),
+            slowlog: SlowlogCollector::default(),
+            expiry_audit: ExpiryAudit::default(),
+            batch: Batch::default(),
+            push: None,
+            pending_writes: PendingWrites::default(),
+            writer,
+            reconnect: None,
+            reconnect_config: redis.reconnect.clone(),
+            _tickers: vec![],
+        }
+    }
+
+    /// Build the pool, check out a connection and ask the actor to connect, retrying in the
+    /// background when the cluster cannot be reached
+    pub(crate) fn start(redis: &Redis) -> Self {
+        let mut session = Self::connect(redis, true);
+
+        if let Some(interval) = redis.health_check_interval {
+            session
+                ._tickers
+                .push(Ticker::spawn(interval, session.actor, || HealthTick));
+        }
+        if let Some(config) = &redis.slowlog {
+            session
+                ._tickers
+                .push(Ticker::spawn(config.interval, session.actor, || {
+                    SlowlogTick
+                }));
+        }
+        if let Some(config) = &redis.expiry_audit {
+            session
+                ._tickers
+                .push(Ticker::spawn(config.interval, session.actor, || {
+                    ExpiryAuditTick
+                }));
+        }
+        if let Some(config) = &redis.hot_keys {
+            session
+                ._tickers
+                .push(Ticker::spawn(config.window, session.actor, || HotKeysTick));
+        }
+
+        match session.conn.0 {
+            Some(_) => session.connected(redis),
+            None => session.disconnect("cannot connect to the cluster"),
+        }
+        session
+    }
+
+    /// Session of a read child, serving from its own pool as soon as it is connected.
+    ///
+    /// Readers keep the urls they were built with, reconnects only move the writer.
+    pub(crate) fn reader(redis: &mut Redis) -> Self {
+        let session = Self::connect(redis, false);
+        redis.state = match session.conn.0 {
+            Some(_) => RedisState::Initialized,
+            None => RedisState::Connecting,
+        };
+        session
+    }
+
+    // Open the push connections and ask the actor to mark itself connected
+    fn connected(&mut self, redis: &Redis) {
+        if let Some(config) = &redis.resp3 {
+            listen_for_pushes(
+                &mut self.push,
+                &mut self.conn,
+                &redis.urls,
+                config,
+                self.actor,
+            );
+        }
+        let command = RedisCommand::ConnectRedisServer {
+            urls: redis.get_urls(),
+        };
+        if let Err(e) = self.actor.tell_one(command) {
+            error!("[REDIS] Cannot mark the actor connected: {e:?}");
+        }
+    }
+
+    // Drop the connection and ask the actor to refuse data commands, retrying in the background
+    fn disconnect(&mut self, error: &str) {
+        self.conn.0 = None;
+        self.push = None;
+        if self.reconnect.is_none() {
+            self.reconnect = Some(Reconnector::spawn(
+                self.pool.clone(),
+                self.reconnect_config.clone(),
+                self.actor,
+            ));
+        }
+        let command = RedisCommand::DisconnectRedisServer {
+            error: error.to_owned(),
+        };
+        if let Err(e) = self.actor.tell_one(command) {
+            error!("[REDIS] Cannot mark the actor disconnected: {e:?}");
+        }
+    }
+
+    // Take the connection the reconnector opened, waiting again when it is already gone
+    fn reconnect(&mut self, redis: &Redis) {
+        match checkout_timeout(&self.pool, RECHECK_TIMEOUT) {
+            Ok(conn) => {
+                self.conn.0 = Some(conn);
+                self.reconnect = None;
+                self.connected(redis);
+            }
+            Err(e) => {
+                warn!(error = %e, "[REDIS] Cluster lost again while reconnecting");
+                self.reconnect = Some(Reconnector::spawn(
+                    self.pool.clone(),
+                    self.reconnect_config.clone(),
+                    self.actor,
+                ));
+            }
+        }
+    }
+
+    // Whether a reader holds a connection, trying to get one back first
+    fn reader_connected(&mut self, redis: &mut Redis) -> bool {
+        if self.conn.0.is_none() {
+            if let Ok(conn) = checkout_timeout(&self.pool, RECHECK_TIMEOUT) {
+                self.conn.0 = Some(conn);
+                redis.state = RedisState::Initialized;
+            }
+        }
+        self.conn.0.is_some()
+    }
+
+    /// Number of data commands waiting for the pipeline
+    pub(crate) fn pending(&self) -> usize {
+        self.batch.len()
+    }
+
+    /// Send the pending pipeline
+    pub(crate) fn flush(&mut self, redis: &Redis) {
+        match self.conn.0.as_deref_mut() {
+            Some(conn) => self.batch.flush(redis, conn),
+            None => self.batch.refuse(),
+        }
+    }
+
+    /// Run the call left by the last message on a blocking thread, waiting for it so commands
+    /// keep their order. A lost connection is replaced, or the session disconnects.
+    pub(crate) async fn run_blocking(&mut self) {
+        let Some(call) = self.blocking.take() else {
+            return;
+        };
+        let Some(mut conn) = self.conn.0.take() else {
+            // Disconnected sessions leave no calls behind
+            return;
+        };
+        match task::spawn_blocking(move || {
+            call(&mut **conn);
+            conn
+        })
+        .await
+        {
+            Ok(conn) => self.conn.0 = Some(conn),
+            Err(e) => {
+                error!(error = %e, "blocking redis call failed");
+                match checkout(&self.pool) {
+                    Ok(conn) => self.conn.0 = Some(conn),
+                    Err(e) => {
+                        Redis::report_error(self.actor, "Pool", &e);
+                        if self.writer {
+                            self.disconnect(&e.to_string());
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Run a typed command on the actor's connection, after the writes already batched when
+    /// `pipelining`
+    fn command<C: TypedCommand>(
+        &mut self,
+        redis: &Redis,
+        pipelining: bool,
+        handler: MessageHandler<()>,
+    ) -> MessageHandler<()> {
+        handler.on_question(|event: C, sender| {
+            if pipelining {
+                self.batch.flush(redis, &mut self.conn);
+            }
+            self.blocking = redis.run_command(event, sender);
+        })
+    }
+
+    /// Answer a query from a blocking task on another pooled connection
+    fn spawn_query(&self, redis: &Redis, event: RedisQuery, sender: AnswerSender) {
+        redis.hot_key_sampler.record(&event.key);
+        let pool = self.pool.clone();
+        let (hash_trace_keys, chunking, retry) =
+            (redis.hash_trace_keys, redis.chunking, redis.retry);
+        let actor = self.actor;
+        task::spawn_blocking(move || {
+            let result = match checkout(&pool) {
+                Ok(mut conn) => Redis::get(
+                    &mut **conn,
+                    &event.key,
+                    hash_trace_keys,
+                    chunking,
+                    retry,
+                    actor,
+                ),
+                Err(e) => {
+                    error!(error = %e, "no pooled connection for query");
+                    Redis::report_error(actor, "Pool", &e);
+                    Err(RedisError::Command(e.to_string()))
+                }
+            };
+            // The caller may be gone already
+            let _ = sender.reply(result);
+        });
+    }
+
+    /// Handle one message from the mailbox
+    pub(crate) fn handle(&mut self, redis: &mut Redis, msg: SignedMessage) {
+        if self.conn.0.is_none() || redis.state != RedisState::Initialized {
+            return self.handle_not_ready(redis, msg);
+        }
+
+        let pipelining = redis.pipeline.is_some() && redis.chunking.is_none();
+        let mut reconnected = None;
+        let mut checked = false;
+
+        let handler = self
+            .machine
+            .dispatch(redis, MessageHandler::new(msg), |applied| {
+                self.status.update(applied);
+                if let RedisEvent::RedisServerReconnected { urls } = &applied.event {
+                    reconnected = Some(urls.clone());
+                }
+            })
+            .on_question(|_: RedisStatusQuery, sender| {
+                let status = RedisStatus {
+                    pool_stats: Some(self.pool.state().into()),
+                    ..self.status.clone()
+                };
+                // The caller may be gone already
+                let _ = sender.reply(status);
+            })
+            .on_question(|event: RedisQuery, sender| {
+                if pipelining {
+                    redis.hot_key_sampler.record(&event.key);
+                    self.batch.push(Pending::Get {
+                        key: event.key,
+                        sender,
+                    });
+                } else if redis.parallel_reads {
+                    self.spawn_query(redis, event, sender);
+                } else {
+                    self.blocking = redis.run_query(event, sender);
+                }
+            })
+            .on_tell(|event: RedisInsert, _| {
+                if pipelining {
+                    redis.hot_key_sampler.record(&event.key);
+                    match redis.fit(event) {
+                        Ok(event) => self.batch.push(Pending::Set(event, None)),
+                        Err(e) => Redis::report_dropped(self.actor, &e),
+                    }
+                } else {
+                    self.blocking = redis.run_insert(event, None);
+                }
+            })
+            .on_question(|event: RedisInsert, sender| {
+                if pipelining {
+                    redis.hot_key_sampler.record(&event.key);
+                    match redis.fit(event) {
+                        Ok(event) => self.batch.push(Pending::Set(event, Some(sender))),
+                        Err(e) => {
+                            let _ = sender.reply(Err::<(), _>(e));
+                        }
+                    }
+                } else {
+                    self.blocking = redis.run_insert(event, Some(sender));
+                }
+            })
+            .on_question(|event: RedisExpire, sender| {
+                // Runs after the writes already batched
+                if pipelining {
+                    self.batch.flush(redis, &mut self.conn);
+                }
+                self.blocking = redis.run_expire(event, sender);
+            })
+            .on_question(|event: RedisTtlQuery, sender| {
+                // Sees the writes already batched
+                if pipelining {
+                    self.batch.flush(redis, &mut self.conn);
+                }
+                self.blocking = redis.run_ttl(event, sender);
+            })
+            .on_question(|event: RedisRateLimit, sender| {
+                if pipelining {
+                    self.batch.flush(redis, &mut self.conn);
+                }
+                self.blocking = redis.run_rate_limit(event, sender);
+            })
+            .on_question(|event: RedisSlidingQuery, sender| {
+                // Sees the writes already batched
+                if pipelining {
+                    self.batch.flush(redis, &mut self.conn);
+                }
+                self.blocking = redis.run_sliding_query(event, sender);
+            })
+            .on_question(|event: RedisTaggedInsert, sender| {
+                if pipelining {
+                    self.batch.flush(redis, &mut self.conn);
+                }
+                self.blocking = redis.run_tagged_insert(event, sender);
+            })
+            .on_question(|event: RedisInvalidateTag, sender| {
+                // Runs after the writes already batched
+                if pipelining {
+                    self.batch.flush(redis, &mut self.conn);
+                }
+                self.blocking = redis.run_invalidate_tag(event, sender);
+            })
+            .on_question(|event: RedisMultiQuery, sender| {
+                // Sees the writes already batched
+                if pipelining {
+                    self.batch.flush(redis, &mut self.conn);
+                }
+                self.blocking = redis.run_multi_query(&self.pool, event, sender);
+            })
+            .on_question(|event: RedisExists, sender| {
+                // Sees the writes already batched
+                if pipelining {
+                    self.batch.flush(redis, &mut self.conn);
+                }
+                self.blocking = redis.run_exists(&self.pool, event, sender);
+            })
+            .on_tell(|event: RedisMultiInsert, _| {
+                // Runs after the writes already batched
+                if pipelining {
+                    self.batch.flush(redis, &mut self.conn);
+                }
+                self.blocking = redis.run_multi_insert(&self.pool, event);
+            })
+            .on_question(|event: RedisDeleteMany, sender| {
+                // Runs after the writes already batched
+                if pipelining {
+                    self.batch.flush(redis, &mut self.conn);
+                }
+                self.blocking = redis.run_delete_many(&self.pool, event, sender);
+            })
+            // Connected again before the tick arrived
+            .on_tell(|_: ReconnectTick, _| {})
+            .on_tell(|_: HealthTick, _| self.blocking = Some(ping(self.actor)))
+            .on_tell(|pinged: HealthPinged, _| {
+                self.health.record(pinged);
+                checked = true;
+            })
+            .on_question(|_: RedisHealthQuery, sender| {
+                let mut health = self.health.clone();
+                let (state, pool) = (redis.state.clone(), self.pool.state().into());
+                let actor = self.actor;
+                self.blocking = Some(Box::new(move |conn| {
+                    let pinged = HealthPinged::ping(conn);
+                    health.record(pinged);
+                    // The caller may be gone already
+                    let _ = sender.reply(health.report(&state, pool));
+                    tell_back(actor, pinged);
+                }));
+            })
+            .on_question(|_: RedisErrorStatsQuery, sender| {
+                // The caller may be gone already
+                let _ = sender.reply(redis.errors.clone());
+            })
+            .on_question(|_: RedisPoolStats, sender| {
+                // The caller may be gone already
+                let _ = sender.reply(self.registry.report(&self.pool));
+            })
+            .on_tell(|_: SlowlogTick, _| {
+                if let Some(config) = &redis.slowlog {
+                    let (urls, count, actor) = (redis.urls.clone(), config.count, self.actor);
+                    self.blocking = Some(on_connection(move |conn| {
+                        tell_back(actor, slowlog::fetch_all(conn, &urls, count));
+                    }));
+                }
+            })
+            .on_tell(|fetched: SlowlogFetched, _| {
+                if let Some(config) = &redis.slowlog {
+                    self.slowlog.record(fetched, config);
+                }
+            })
+            .on_question(|_: RedisSlowlogQuery, sender| {
+                // The caller may be gone already
+                let _ = sender.reply(self.slowlog.recent());
+            })
+            .on_tell(|_: ExpiryAuditTick, _| self.expiry_audit.start(&self.pool, redis))
+            .on_tell(|done: ExpiryAuditDone, _| {
+                self.expiry_audit.finish(done, redis.expiry_audit.as_ref())
+            })
+            .on_question(|_: RedisExpiryAuditQuery, sender| {
+                // The caller may be gone already
+                let _ = sender.reply(self.expiry_audit.last());
+            })
+            .on_tell(|_: HotKeysTick, _| redis.hot_key_sampler.report(redis.hot_keys.as_ref()))
+            .on_question(|_: RedisHotKeysQuery, sender| {
+                // The caller may be gone already
+                let _ = sender.reply(redis.hot_key_sampler.last());
+            })
+            .on_question(|event: RedisDeleteByPattern, sender| {
+                delete::spawn(&self.pool, redis, event, sender)
+            })
+            .on_question(|event: RedisAccessQuery, sender| {
+                access::spawn(&self.pool, redis, event, sender)
+            })
+            .on_question(|event: RedisBigKeyScan, sender| {
+                bigkeys::spawn(&self.pool, redis, event, sender)
+            })
+            .on_question(|event: RedisMigrate, sender| {
+                migrate::spawn(&self.pool, redis, event, sender)
+            })
+            .on_tell(|event: RedisExport, _| export::spawn(&self.pool, redis, event))
+            .on_question(|event: RedisImport, sender| {
+                import::spawn(&self.pool, redis, event, sender)
+            })
+            .on_question(|event: RedisMirrorBackfill, sender| {
+                mirror::backfill(self.actor, redis.mirroring.as_ref(), event, sender)
+            })
+            .on_question(|_: RedisTopologyQuery, sender| {
+                let urls = redis.urls.clone();
+                self.blocking = Some(on_connection(move |conn| {
+                    let topology = node::nodes(conn, &urls)
+                        .map(|nodes| nodes.into_iter().map(ClusterNode::from).collect::<Vec<_>>())
+                        .map_err(|e| RedisError::Command(e.to_string()));
+                    // The caller may be gone already
+                    let _ = sender.reply(topology);
+                }));
+            })
+            .on_question(|event: RedisNodeCommand, sender| {
+                node::spawn(&self.pool, redis, event, sender)
+            })
+            .on_tell(|event: RedisStreamQuery, _| stream::spawn(&self.pool, redis, event))
+            .on_tell(|push: RedisPush, _| {
+                if let Some(config) = &redis.resp3 {
+                    if let Err(e) = Distributor::named(&config.deliver_to).tell_one(push) {
+                        warn!("[REDIS] Cannot deliver push: {e:?}");
+                    }
+                }
+            })
+            .on_tell(|event: RedisPublish, _| {
+                let (hash_trace_keys, actor) = (redis.hash_trace_keys, self.actor);
+                self.blocking = Some(on_connection(move |conn| {
+                    if let Err(e) = pubsub::publish(conn, &event, hash_trace_keys) {
+                        Redis::report_redis_error(actor, &e);
+                    }
+                }));
+            })
+            .on_question(|event: RedisSubscribe, sender| {
+                let (urls, actor) = (redis.urls.clone(), self.actor);
+                self.blocking = Some(on_connection(move |conn| {
+                    let result = pubsub::subscribe(conn, &urls, actor, event);
+                    // The caller may be gone already
+                    let _ = sender.reply(result);
+                }));
+            });
+        let handler = self.command::<RedisIncrByFloat>(redis, pipelining, handler);
+        let handler = self.command::<RedisHIncrByFloat>(redis, pipelining, handler);
+        let handler = self.command::<RedisLen>(redis, pipelining, handler);
+        let handler = self.command::<RedisZRangeByScore>(redis, pipelining, handler);
+        let handler = self.command::<RedisZIncrBy>(redis, pipelining, handler);
+        let handler = self.command::<RedisHashIncrement>(redis, pipelining, handler);
+        let handler = self.command::<RedisSRandMember>(redis, pipelining, handler);
+        let handler = self.command::<RedisHRandField>(redis, pipelining, handler);
+        let handler = self.command::<RedisZRandMember>(redis, pipelining, handler);
+        let handler = self.command::<RedisLPos>(redis, pipelining, handler);
+        let handler = self.command::<RedisLSet>(redis, pipelining, handler);
+        let handler = self.command::<RedisLRem>(redis, pipelining, handler);
+        let handler = self.command::<RedisLInsert>(redis, pipelining, handler);
+        let handler = self.command::<RedisHashSet>(redis, pipelining, handler);
+        let handler = self.command::<RedisHashGet>(redis, pipelining, handler);
+        let handler = self.command::<RedisHashGetAll>(redis, pipelining, handler);
+
+        #[cfg(feature = "otel")]
+        let handler = handler
+            .on_question(|traced: super::otel::Traced<RedisQuery>, sender| {
+                let (event, span) = traced.into_parts();
+                self.blocking = redis
+                    .run_query(event, sender)
+                    .map(|call| in_span(call, span));
+            })
+            .on_tell(|traced: super::otel::Traced<RedisInsert>, _| {
+                let (event, span) = traced.into_parts();
+                self.blocking = redis
+                    .run_insert(event, None)
+                    .map(|call| in_span(call, span));
+            })
+            .on_question(|traced: super::otel::Traced<RedisInsert>, sender| {
+                let (event, span) = traced.into_parts();
+                self.blocking = redis
+                    .run_insert(event, Some(sender))
+                    .map(|call| in_span(call, span));
+            });
+
+        handler.on_fallback(|unknown, _| warn!("[REDIS] Unknown message: {unknown:?}"));
+
+        if let Some(urls) = reconnected {
+            self.switch_urls(redis, urls);
+        }
+        if checked {
+            self.fail_over(redis);
+        }
+    }
+
+    // Move to the secondary cluster once the current one has been unhealthy for long enough
+    fn fail_over(&mut self, redis: &mut Redis) {
+        let Some(config) = &redis.failover else {
+            return;
+        };
+        let Some(urls) = config.target(&redis.urls, self.health.unhealthy_for()) else {
+            return;
+        };
+        let command = RedisCommand::FailOverRedisServer {
+            urls: urls.to_vec(),
+        };
+        let mut failed_over = None;
+        let result = self.machine.execute_now(redis, command, |applied| {
+            self.status.update(applied);
+            if let RedisEvent::RedisServerFailedOver { from, to } = &applied.event {
+                warn!(?from, ?to, "[REDIS] Failing over to the secondary cluster");
+                failed_over = Some(to.clone());
+            }
+        });
+        if let Err(e) = result {
+            error!(error = %e, "[REDIS] Cannot fail over");
+        }
+        if let Some(urls) = failed_over {
+            // Checks start over on the secondary
+            self.health = HealthChecker::default();
+            self.switch_urls(redis, urls);
+        }
+    }
+
+    // Point the pool at `urls`. A connected writer moves right away, the pool is kept and
+    // connections to the old urls are discarded as they come back. Otherwise they are used from
+    // the next connection attempt, retried again if abandoned.
+    fn switch_urls(&mut self, redis: &Redis, urls: Vec<String>) {
+        let _span = info_span!("redis.reconnect", ?urls).entered();
+        self.config.replace(ConnectionConfig { urls: urls.clone() });
+        if self.conn.0.is_none() {
+            if self.reconnect.is_none() {
+                self.disconnect("reconnect requested");
+            }
+            return;
+        }
+        if redis.state != RedisState::Initialized {
+            return;
+        }
+        match checkout(&self.pool) {
+            Ok(new_conn) => self.conn.0 = Some(new_conn),
+            Err(e) => {
+                error!(error = %e, "reconnect failed");
+                Redis::report_error(self.actor, "Pool", &e);
+            }
+        }
+        if let Some(config) = &redis.resp3 {
+            listen_for_pushes(&mut self.push, &mut self.conn, &urls, config, self.actor);
+        }
+    }
+
+    /// Handle a control message ahead of the mailbox, breaks when the writer must stop
+    pub(crate) fn control(
+        &mut self,
+        redis: &mut Redis,
+        request: ControlRequest,
+    ) -> ControlFlow<()> {
+        match &request.control {
+            RedisControl::Reconnect { urls } => {
+                let command = RedisCommand::ReconnectRedisServer { urls: urls.clone() };
+                let mut reconnected = None;
+                let result = self.machine.execute_now(redis, command, |applied| {
+                    self.status.update(applied);
+                    if let RedisEvent::RedisServerReconnected { urls } = &applied.event {
+                        reconnected = Some(urls.clone());
+                    }
+                });
+                if let Some(urls) = reconnected {
+                    self.switch_urls(redis, urls);
+                }
+                request.reply(result);
+                ControlFlow::Continue(())
+            }
+            RedisControl::Flush => {
+                self.flush(redis);
+                request.reply(Ok(()));
+                ControlFlow::Continue(())
+            }
+            RedisControl::Shutdown => {
+                self.flush(redis);
+                self.pending_writes.refuse();
+                request.reply(Ok(()));
+                ControlFlow::Break(())
+            }
+        }
+    }
+
+    /// Handle one message while the writer is not connected and `Initialized`: state changes
+    /// and reports are served, inserts are held until the connection is ready and other data
+    /// questions are refused with `RedisError::NotReady`
+    fn handle_not_ready(&mut self, redis: &mut Redis, msg: SignedMessage) {
+        let disconnected = self.conn.0.is_none();
+        let mut reconnect = false;
+        let mut reconnected = None;
+        let mut checked = false;
+        let handler = self
+            .machine
+            .dispatch(redis, MessageHandler::new(msg), |applied| {
+                self.status.update(applied);
+                match &applied.event {
+                    RedisEvent::RedisServerReconnected { urls } => {
+                        reconnected = Some(urls.clone());
+                    }
+                    RedisEvent::RedisServerAbandoned { .. } => {
+                        self.reconnect = None;
+                        self.pending_writes.refuse();
+                    }
+                    _ => {}
+                }
+            })
+            .on_tell(|event: RedisInsert, _| self.pending_writes.defer(redis, event, None))
+            .on_question(|event: RedisInsert, sender| {
+                self.pending_writes.defer(redis, event, Some(sender))
+            })
+            .on_tell(|event: RedisMultiInsert, _| self.pending_writes.defer_many(redis, event))
+            .on_tell(|_: ReconnectTick, _| reconnect = disconnected)
+            .on_tell(|_: HealthTick, _| match self.conn.0 {
+                Some(_) => self.blocking = Some(ping(self.actor)),
+                None => {
+                    self.health.unreachable();
+                    checked = true;
+                }
+            })
+            .on_tell(|pinged: HealthPinged, _| {
+                self.health.record(pinged);
+                checked = true;
+            })
+            .on_question(|_: RedisStatusQuery, sender| {
+                let status = RedisStatus {
+                    pool_stats: Some(self.pool.state().into()),
+                    ..self.status.clone()
+                };
+                // The caller may be gone already
+                let _ = sender.reply(status);
+            })
+            .on_question(|_: RedisHealthQuery, sender| {
+                let report = self
+                    .health
+                    .report(&RedisState::Uninitialized, self.pool.state().into());
+                // The caller may be gone already
+                let _ = sender.reply(report);
+            })
+            .on_question(|_: RedisErrorStatsQuery, sender| {
+                // The caller may be gone already
+                let _ = sender.reply(redis.errors.clone());
+            })
+            .on_question(|_: RedisPoolStats, sender| {
+                // The caller may be gone already
+                let _ = sender.reply(self.registry.report(&self.pool));
+            })
+            .on_question(|_: RedisSlowlogQuery, sender| {
+                // The caller may be gone already
+                let _ = sender.reply(self.slowlog.recent());
+            })
+            // A poll started before the connection was lost still ends
+            .on_tell(|fetched: SlowlogFetched, _| {
+                if let Some(config) = &redis.slowlog {
+                    self.slowlog.record(fetched, config);
+                }
+            })
+            // A run started before the connection was lost still ends
+            .on_tell(|done: ExpiryAuditDone, _| {
+                self.expiry_audit.finish(done, redis.expiry_audit.as_ref())
+            })
+            .on_question(|_: RedisExpiryAuditQuery, sender| {
+                // The caller may be gone already
+                let _ = sender.reply(self.expiry_audit.last());
+            })
+            .on_tell(|_: HotKeysTick, _| redis.hot_key_sampler.report(redis.hot_keys.as_ref()))
+            .on_question(|_: RedisHotKeysQuery, sender| {
+                // The caller may be gone already
+                let _ = sender.reply(redis.hot_key_sampler.last());
+            });
+
+        #[cfg(feature = "otel")]
+        let handler = handler
+            .on_tell(|traced: super::otel::Traced<RedisInsert>, _| {
+                let (event, _) = traced.into_parts();
+                self.pending_writes.defer(redis, event, None)
+            })
+            .on_question(|traced: super::otel::Traced<RedisInsert>, sender| {
+                let (event, _) = traced.into_parts();
+                self.pending_writes.defer(redis, event, Some(sender))
+            });
+        refuse(handler);
+
+        if reconnect {
+            self.reconnect(redis);
+        } else if let Some(urls) = reconnected {
+            self.switch_urls(redis, urls);
+        }
+        if checked {
+            self.fail_over(redis);
+        }
+        if self.conn.0.is_some() && redis.state == RedisState::Initialized {
+            self.replay(redis);
+        }
+    }
+
+    // Leave the held inserts to run in order on the connection
+    fn replay(&mut self, redis: &Redis) {
+        let calls = self.pending_writes.replay(redis);
+        if !calls.is_empty() {
+            self.blocking = Some(Box::new(move |conn| {
+                for call in calls {
+                    call(conn);
+                }
+            }));
+        }
+    }
+
+    /// Handle one message from the mailbox of a read child
+    pub(crate) fn handle_read(&mut self, redis: &mut Redis, msg: SignedMessage) {
+        if !self.reader_connected(redis) {
+            return refuse(MessageHandler::new(msg));
+        }
+
+        let handler = MessageHandler::new(msg)
+            .on_question(|event: RedisQuery, sender| {
+                self.blocking = redis.run_query(event, sender);
+            })
+            .on_question(|event: RedisTtlQuery, sender| {
+                self.blocking = redis.run_ttl(event, sender);
+            })
+            .on_question(|event: RedisMultiQuery, sender| {
+                self.blocking = redis.run_multi_query(&self.pool, event, sender);
+            })
+            .on_question(|event: RedisExists, sender| {
+                self.blocking = redis.run_exists(&self.pool, event, sender);
+            })
+            .on_question(|event: RedisAccessQuery, sender| {
+                access::spawn(&self.pool, redis, event, sender)
+            })
+            .on_question(|event: RedisBigKeyScan, sender| {
+                bigkeys::spawn(&self.pool, redis, event, sender)
+            })
+            .on_tell(|event: RedisStreamQuery, _| stream::spawn(&self.pool, redis, event));
+        let handler = self.command::<RedisLen>(redis, false, handler);
+        let handler = self.command::<RedisZRangeByScore>(redis, false, handler);
+        let handler = self.command::<RedisSRandMember>(redis, false, handler);
+        let handler = self.command::<RedisHRandField>(redis, false, handler);
+        let handler = self.command::<RedisZRandMember>(redis, false, handler);
+        let handler = self.command::<RedisLPos>(redis, false, handler);
+        let handler = self.command::<RedisHashGet>(redis, false, handler);
+        let handler = self.command::<RedisHashGetAll>(redis, false, handler);
+
+        #[cfg(feature = "otel")]
+        let handler = handler.on_question(|traced: super::otel::Traced<RedisQuery>, sender| {
+            let (event, span) = traced.into_parts();
+            self.blocking = redis
+                .run_query(event, sender)
+                .map(|call| in_span(call, span));
+        });
+
+        handler
+            .on_fallback(|unknown, _| warn!("[REDIS] Unknown message for a reader: {unknown:?}"));
+    }
+}
+
+// Refuse the data questions of a session that is not ready, other messages are dropped
+fn refuse(handler: MessageHandler<()>) {
+    let handler = handler
+        .on_question(|_: RedisQuery, sender| Redis::not_ready::<Option<Vec<u8>>>(sender))
+        .on_question(|_: RedisInsert, sender| Redis::not_ready::<()>(sender))
+        .on_question(|_: RedisExpire, sender| Redis::not_ready::<bool>(sender))
+        .on_question(|_: RedisTtlQuery, sender| Redis::not_ready::<Ttl>(sender))
+        .on_question(|_: RedisRateLimit, sender| Redis::not_ready::<RateLimitDecision>(sender))
+        .on_question(|_: RedisSlidingQuery, sender| Redis::not_ready::<Option<Vec<u8>>>(sender))
+        .on_question(|_: RedisTaggedInsert, sender| Redis::not_ready::<()>(sender))
+        .on_question(|_: RedisInvalidateTag, sender| Redis::not_ready::<u64>(sender))
+        .on_question(|_: RedisMultiQuery, sender| Redis::not_ready::<Vec<Option<Bytes>>>(sender))
+        .on_question(|_: RedisExists, sender| Redis::not_ready::<Vec<bool>>(sender))
+        .on_question(|_: RedisDeleteMany, sender| Redis::not_ready::<u64>(sender))
+        .on_question(|_: RedisDeleteByPattern, sender| Redis::not_ready::<u64>(sender))
+        .on_question(|_: RedisAccessQuery, sender| Redis::not_ready::<Vec<KeyAccess>>(sender))
+        .on_question(|_: RedisBigKeyScan, sender| Redis::not_ready::<BigKeyReport>(sender))
+        .on_question(|_: RedisMigrate, sender| Redis::not_ready::<MigrationProgress>(sender))
+        .on_question(|_: RedisImport, sender| Redis::not_ready::<ImportProgress>(sender))
+        .on_question(|_: RedisMirrorBackfill, sender| Redis::not_ready::<ImportProgress>(sender))
+        .on_question(|_: RedisTopologyQuery, sender| Redis::not_ready::<Vec<ClusterNode>>(sender))
+        .on_question(|_: RedisNodeCommand, sender| Redis::not_ready::<NodeReply>(sender))
+        .on_question(|_: RedisSubscribe, sender| Redis::not_ready::<Subscription>(sender))
+        .on_tell(|event: RedisStreamQuery, _| event.sink.fail(RedisError::NotReady))
+        .on_tell(|event: RedisExport, _| event.sink.fail(RedisError::NotReady));
+    let handler = refuse_command::<RedisIncrByFloat>(handler);
+    let handler = refuse_command::<RedisHIncrByFloat>(handler);
+    let handler = refuse_command::<RedisHashIncrement>(handler);
+    let handler = refuse_command::<RedisLen>(handler);
+    let handler = refuse_command::<RedisZRangeByScore>(handler);
+    let handler = refuse_command::<RedisZIncrBy>(handler);
+    let handler = refuse_command::<RedisSRandMember>(handler);
+    let handler = refuse_command::<RedisHRandField>(handler);
+    let handler = refuse_command::<RedisZRandMember>(handler);
+    let handler = refuse_command::<RedisLPos>(handler);
+    let handler = refuse_command::<RedisLSet>(handler);
+    let handler = refuse_command::<RedisLRem>(handler);
+    let handler = refuse_command::<RedisLInsert>(handler);
+    let handler = refuse_command::<RedisHashSet>(handler);
+    let handler = refuse_command::<RedisHashGet>(handler);
+    let handler = refuse_command::<RedisHashGetAll>(handler);
+
+    #[cfg(feature = "otel")]
+    let handler = handler
+        .on_question(|_: super::otel::Traced<RedisQuery>, sender| {
+            Redis::not_ready::<Option<Vec<u8>>>(sender)
+        })
+        .on_question(|_: super::otel::Traced<RedisInsert>, sender| Redis::not_ready::<()>(sender));
+
+    handler.on_fallback(|unknown, _| warn!("[REDIS] Dropped while not ready: {unknown:?}"));
+}
+
+fn refuse_command<C: TypedCommand>(handler: MessageHandler<()>) -> MessageHandler<()> {
+    handler.on_question(|_: C, sender| Redis::not_ready::<C::Reply>(sender))
+}
+
+// PING from a blocking thread, the outcome is told back as `HealthPinged`
+fn ping(actor: Distributor) -> Blocking {
+    Box::new(move |conn| tell_back(actor, HealthPinged::ping(conn)))
+}
+
+// A blocking call needing the cluster connection itself, which the actor's backend always is
+fn on_connection(call: impl FnOnce(&mut RedisConnection) + Send + 'static) -> Blocking {
+    Box::new(move |backend| match backend.connection() {
+        Some(conn) => call(conn),
+        None => error!("[REDIS] No cluster connection behind the backend"),
+    })
+}
+
+// Tell a result from a blocking thread back to the actor's own mailbox
+fn tell_back<T: std::fmt::Debug + Send + Sync + 'static>(actor: Distributor, result: T) {
+    if let Err(e) = actor.tell_one(result) {
+        warn!("[REDIS] Cannot report back to the actor: {e:?}");
+    }
+}
+
+// Keep the caller's span around a call moved to a blocking thread
+#[cfg(feature = "otel")]
+fn in_span(call: Blocking, span: tracing::Span) -> Blocking {
+    Box::new(move |conn| {
+        let _enter = span.enter();
+        call(conn)
+    })
+}
+
+// (Re)open the RESP3 push connections, closing the previous ones first
+fn listen_for_pushes(
+    push: &mut Option<PushListeners>,
+    conn: &mut RedisConnection,
+    urls: &[String],
+    config: &Resp3Config,
+    actor: Distributor,
+) {
+    *push = None;
+    match PushListeners::start(conn, urls, config, actor) {
+        Ok(listeners) => *push = Some(listeners),
+        Err(e) => {
+            error!(error = %e, "cannot open push connections");
+            Redis::report_redis_error(actor, &e);
+        }
+    }
+}
+
+/// Handler state when data commands are served by a `MemoryBackend` instead of the cluster
+pub(crate) struct MemorySession {
+    backend: MemoryBackend,
+    status: RedisStatus,
+    machine: MachineContext<Redis>,
+    health: HealthChecker,
+    /// Inserts arriving before the actor is `Initialized`
+    pending_writes: PendingWrites,
+    _tickers: Vec<Ticker>,
+}
+
+impl MemorySession {
+    fn new(redis: &Redis, backend: MemoryBackend) -> Self {
+        Self {
+            backend,
+            status: RedisStatus::default(),
+            machine: MachineContext::new(redis.own_distributor()),
+            health: HealthChecker::default(),
+            pending_writes: PendingWrites::default(),
+            _tickers: vec![],
+        }
+    }
+
+    // Run a typed command on the backend right away
+    fn command<C: TypedCommand>(
+        &mut self,
+        redis: &Redis,
+        handler: MessageHandler<()>,
+    ) -> MessageHandler<()> {
+        handler.on_question(|event: C, sender| {
+            if let Some(call) = redis.run_command(event, sender) {
+                call(&mut self.backend);
+            }
+        })
+    }
+
+    // Run an insert, or hold it until the actor is `Initialized`
+    fn insert(&mut self, redis: &Redis, event: RedisInsert, sender: Option<AnswerSender>) {
+        if redis.state != RedisState::Initialized {
+            return self.pending_writes.defer(redis, event, sender);
+        }
+        if let Some(call) = redis.run_insert(event, sender) {
+            call(&mut self.backend);
+        }
+    }
+
+    /// Ask the actor to connect, nothing is contacted
+    pub(crate) fn start(redis: &Redis, backend: MemoryBackend) -> Self {
+        let command = RedisCommand::ConnectRedisServer {
+            urls: redis.get_urls(),
+        };
+        if let Err(e) = redis.own_distributor().tell_one(command) {
+            error!("[REDIS] Cannot mark the actor connected: {e:?}");
+        }
+
+        let mut session = Self::new(redis, backend);
+        if let Some(config) = &redis.hot_keys {
+            let actor = redis.own_distributor();
+            session
+                ._tickers
+                .push(Ticker::spawn(config.window, actor, || HotKeysTick));
+        }
+        session
+    }
+
+    /// Session of a read child, sharing the writer's data
+    pub(crate) fn reader(redis: &mut Redis, backend: MemoryBackend) -> Self {
+        redis.state = RedisState::Initialized;
+        Self::new(redis, backend)
+    }
+
+    /// Handle a control message ahead of the mailbox, breaks when the writer must stop
+    pub(crate) fn control(
+        &mut self,
+        redis: &mut Redis,
+        request: ControlRequest,
+    ) -> ControlFlow<()> {
+        match &request.control {
+            RedisControl::Reconnect { urls } => {
+                let command = RedisCommand::ReconnectRedisServer { urls: urls.clone() };
+                let result = self
+                    .machine
+                    .execute_now(redis, command, |applied| self.status.update(applied));
+                request.reply(result);
+                ControlFlow::Continue(())
+            }
+            // Writes are not batched in memory
+            RedisControl::Flush => {
+                request.reply(Ok(()));
+                ControlFlow::Continue(())
+            }
+            RedisControl::Shutdown => {
+                self.pending_writes.refuse();
+                request.reply(Ok(()));
+                ControlFlow::Break(())
+            }
+        }
+    }
+
+    /// Handle one message from the mailbox, commands without an in-memory equivalent are
+    /// logged as unknown
+    pub(crate) fn handle(&mut self, redis: &mut Redis, msg: SignedMessage) {
+        // Stands in for the pool in status and health reports
+        let pool = PoolStats {
+            connections: 1,
+            idle_connections: 1,
+        };
+
+        let handler = self
+            .machine
+            .dispatch(redis, MessageHandler::new(msg), |applied| {
+                self.status.update(applied)
+            })
+            .on_question(|_: RedisStatusQuery, sender| {
+                let status = RedisStatus {
+                    pool_stats: Some(pool),
+                    ..self.status.clone()
+                };
+                // The caller may be gone already
+                let _ = sender.reply(status);
+            })
+            .on_question(|event: RedisQuery, sender| {
+                if let Some(call) = redis.run_query(event, sender) {
+                    call(&mut self.backend);
+                }
+            })
+            .on_tell(|event: RedisInsert, _| self.insert(redis, event, None))
+            .on_question(|event: RedisInsert, sender| self.insert(redis, event, Some(sender)))
+            .on_question(|event: RedisMultiQuery, sender| {
+                if redis.state != RedisState::Initialized {
+                    return Redis::not_ready::<Vec<Option<Bytes>>>(sender);
+                }
+                let (hash_trace_keys, chunking, retry) =
+                    (redis.hash_trace_keys, redis.chunking, redis.retry);
+                let values: Result<Vec<Option<Bytes>>, RedisError> = event
+                    .keys
+                    .iter()
+                    .map(|key| {
+                        let value = Redis::get(
+                            &mut self.backend,
+                            key,
+                            hash_trace_keys,
+                            chunking,
+                            retry,
+                            redis.own_distributor(),
+                        )?;
+                        Ok(value.map(Bytes::from))
+                    })
+                    .collect();
+                // The caller may be gone already
+                let _ = sender.reply(values);
+            })
+            .on_question(|event: RedisExists, sender| {
+                if redis.state != RedisState::Initialized {
+                    return Redis::not_ready::<Vec<bool>>(sender);
+                }
+                let exists: Result<Vec<bool>, RedisError> = event
+                    .keys
+                    .iter()
+                    .map(|key| self.backend.exists(key))
+                    .collect::<redis::RedisResult<_>>()
+                    .map_err(|e| Redis::command_error(redis.own_distributor(), e));
+                // The caller may be gone already
+                let _ = sender.reply(exists);
+            })
+            .on_tell(|event: RedisMultiInsert, _| {
+                if redis.state != RedisState::Initialized {
+                    return self.pending_writes.defer_many(redis, event);
+                }
+                for (key, value) in event.entries {
+                    let insert = RedisInsert {
+                        key,
+                        value,
+                        ttl: None,
+                        caller: event.caller.clone(),
+                    };
+                    self.insert(redis, insert, None);
+                }
+            })
+            .on_tell(|_: HealthTick, _| self.health.ping(&mut self.backend))
+            .on_question(|_: RedisHealthQuery, sender| {
+                self.health.ping(&mut self.backend);
+                let report = self.health.report(&redis.state, pool);
+                // The caller may be gone already
+                let _ = sender.reply(report);
+            })
+            .on_question(|_: RedisErrorStatsQuery, sender| {
+                // The caller may be gone already
+                let _ = sender.reply(redis.errors.clone());
+            })
+            .on_question(|event: RedisExpire, sender| {
+                if let Some(call) = redis.run_expire(event, sender) {
+                    call(&mut self.backend);
+                }
+            })
+            .on_question(|event: RedisTtlQuery, sender| {
+                if let Some(call) = redis.run_ttl(event, sender) {
+                    call(&mut self.backend);
+                }
+            })
+            .on_question(|event: RedisRateLimit, sender| {
+                if let Some(call) = redis.run_rate_limit(event, sender) {
+                    call(&mut self.backend);
+                }
+            })
+            .on_question(|event: RedisSlidingQuery, sender| {
+                if let Some(call) = redis.run_sliding_query(event, sender) {
+                    call(&mut self.backend);
+                }
+            })
+            .on_question(|event: RedisTaggedInsert, sender| {
+                if let Some(call) = redis.run_tagged_insert(event, sender) {
+                    call(&mut self.backend);
+                }
+            })
+            .on_question(|event: RedisInvalidateTag, sender| {
+                if let Some(call) = redis.run_invalidate_tag(event, sender) {
+                    call(&mut self.backend);
+                }
+            })
+            .on_question(|event: RedisDeleteMany, sender| {
+                if redis.state != RedisState::Initialized {
+                    return Redis::not_ready::<u64>(sender);
+                }
+                let chunked = redis.chunking.is_some();
+                let result = delete::delete_keys(&mut self.backend, &event.keys, chunked);
+                let result = delete::finish_many(
+                    &redis.audit,
+                    redis.mirroring.as_ref(),
+                    redis.own_distributor(),
+                    &event,
+                    result,
+                );
+                // The caller may be gone already
+                let _ = sender.reply(result);
+            })
+            .on_question(|_: RedisAccessQuery, sender| {
+                // Nothing tracks accesses in memory
+                let result: Result<Vec<access::KeyAccess>, RedisError> = Err(RedisError::Command(
+                    "access statistics are not supported by the in-memory backend".to_owned(),
+                ));
+                // The caller may be gone already
+                let _ = sender.reply(result);
+            })
+            .on_question(|event: RedisBigKeyScan, sender| {
+                bigkeys::run_memory(&mut self.backend, event, sender)
+            })
+            .on_question(|_: RedisExpiryAuditQuery, sender| {
+                // The audit only runs against a cluster
+                let report: Option<ExpiryAuditReport> = None;
+                // The caller may be gone already
+                let _ = sender.reply(report);
+            })
+            .on_tell(|_: HotKeysTick, _| redis.hot_key_sampler.report(redis.hot_keys.as_ref()))
+            .on_question(|_: RedisHotKeysQuery, sender| {
+                // The caller may be gone already
+                let _ = sender.reply(redis.hot_key_sampler.last());
+            })
+            .on_tell(|event: RedisExport, _| export::run_memory(&mut self.backend, event))
+            .on_question(|event: RedisImport, sender| {
+                let result = import::run_memory(&mut self.backend, redis.mirroring.as_ref(), event);
+                // The caller may be gone already
+                let _ = sender.reply(result);
+            })
+            .on_question(|event: RedisMirrorBackfill, sender| {
+                mirror::backfill(
+                    redis.own_distributor(),
+                    redis.mirroring.as_ref(),
+                    event,
+                    sender,
+                )
+            })
+            .on_question(|_: RedisMigrate, sender| {
+                let result: Result<migrate::MigrationProgress, RedisError> = Err(
+                    RedisError::Command("migrations need a cluster backend".to_owned()),
+                );
+                // The caller may be gone already
+                let _ = sender.reply(result);
+            })
+            .on_question(|event: RedisDeleteByPattern, sender| {
+                delete::run_memory(&mut self.backend, redis, event, sender)
+            })
+            .on_question(|_: RedisTopologyQuery, sender| {
+                // No cluster behind the in-memory backend
+                let topology: Result<Vec<ClusterNode>, RedisError> = Ok(vec![]);
+                // The caller may be gone already
+                let _ = sender.reply(topology);
+            })
+            .on_question(|_: RedisNodeCommand, sender| {
+                let result: Result<NodeReply, RedisError> = Err(RedisError::Command(
+                    "node commands need a cluster backend".to_owned(),
+                ));
+                // The caller may be gone already
+                let _ = sender.reply(result);
+            })
+            .on_question(|_: RedisPoolStats, sender| {
+                // No pool behind the in-memory backend
+                let report = PoolStatsReport::default();
+                // The caller may be gone already
+                let _ = sender.reply(report);
+            })
+            .on_question(|_: RedisSlowlogQuery, sender| {
+                // Nothing is slow enough to log in memory
+                let entries: Vec<SlowlogEntry> = vec![];
+                // The caller may be gone already
+                let _ = sender.reply(entries);
+            })
+            .on_tell(|event: RedisStreamQuery, _| {
+                event.sink.fail(RedisError::Command(
+                    "streams need a cluster backend".to_owned(),
+                ))
+            })
+            .on_tell(|_: RedisPublish, _| {
+                Redis::report_error(
+                    redis.own_distributor(),
+                    "Publish",
+                    &"pub/sub needs a cluster backend",
+                )
+            })
+            .on_question(|_: RedisSubscribe, sender| {
+                let result: Result<Subscription, RedisError> = Err(RedisError::Command(
+                    "pub/sub needs a cluster backend".to_owned(),
+                ));
+                // The caller may be gone already
+                let _ = sender.reply(result);
+            });
+        let handler = self.command::<RedisIncrByFloat>(redis, handler);
+        let handler = self.command::<RedisHIncrByFloat>(redis, handler);
+        let handler = self.command::<RedisLen>(redis, handler);
+        let handler = self.command::<RedisZRangeByScore>(redis, handler);
+        let handler = self.command::<RedisZIncrBy>(redis, handler);
+        let handler = self.command::<RedisHashIncrement>(redis, handler);
+        let handler = self.command::<RedisSRandMember>(redis, handler);
+        let handler = self.command::<RedisHRandField>(redis, handler);
+        let handler = self.command::<RedisZRandMember>(redis, handler);
+        let handler = self.command::<RedisLPos>(redis, handler);
+        let handler = self.command::<RedisLSet>(redis, handler);
+        let handler = self.command::<RedisLRem>(redis, handler);
+        let handler = self.command::<RedisLInsert>(redis, handler);
+        let handler = self.command::<RedisHashSet>(redis, handler);
+        let handler = self.command::<RedisHashGet>(redis, handler);
+        let handler = self.command::<RedisHashGetAll>(redis, handler);
+
+        #[cfg(feature = "otel")]
+        let handler = handler
+            .on_question(|traced: super::otel::Traced<RedisQuery>, sender| {
+                let (event, span) = traced.into_parts();
+                let _enter = span.enter();
+                if let Some(call) = redis.run_query(event, sender) {
+                    call(&mut self.backend);
+                }
+            })
+            .on_tell(|traced: super::otel::Traced<RedisInsert>, _| {
+                let (event, span) = traced.into_parts();
+                let _enter = span.enter();
+                self.insert(redis, event, None);
+            })
+            .on_question(|traced: super::otel::Traced<RedisInsert>, sender| {
+                let (event, span) = traced.into_parts();
+                let _enter = span.enter();
+                self.insert(redis, event, Some(sender));
+            });
+
+        handler.on_fallback(|unknown, _| warn!("[REDIS] Unknown message for memory: {unknown:?}"));
+
+        if redis.state == RedisState::Initialized {
+            for call in self.pending_writes.replay(redis) {
+                call(&mut self.backend);
+            }
+        }
+    }
+}
@@ -0,0 +1,187 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    time::Duration,
+};
+
+use redis::{from_redis_value, RedisResult, Value};
+use serde::{Deserialize, Serialize};
+use tracing::{error, warn};
+
+use super::{connection::RedisConnection, node::cluster_nodes};
+
+/// Periodic `SLOWLOG GET` collection settings
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SlowlogConfig {
+    /// How often every node is polled
+    pub interval: Duration,
+    /// Entries requested per node on each poll
+    pub count: usize,
+    /// Entries kept for `RedisSlowlogQuery`
+    pub capacity: usize,
+}
+
+impl Default for SlowlogConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(60),
+            count: 128,
+            capacity: 1024,
+        }
+    }
+}
+
+/// One slow command reported by a node
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SlowlogEntry {
+    /// `host:port` of the node
+    pub node: String,
+    pub id: i64,
+    /// Unix time the command was processed
+    pub timestamp: i64,
+    pub duration: Duration,
+    pub args: Vec<String>,
+}
+
+/// Question returning the most recent slow commands, oldest first
+#[derive(Default, Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RedisSlowlogQuery;
+
+/// Tick driving the periodic collection
+#[derive(Debug)]
+pub(crate) struct SlowlogTick;
+
+/// Deduplicates slowlog entries across polls and keeps the most recent ones
+#[derive(Debug, Default)]
+pub(crate) struct SlowlogCollector {
+    /// Highest entry id reported per node, ids only grow until the node restarts
+    last_ids: HashMap<String, i64>,
+    recent: VecDeque<SlowlogEntry>,
+}
+
+/// Entries fetched from every node off the actor, told back to be recorded
+#[derive(Debug)]
+pub(crate) struct SlowlogFetched(Vec<(String, Vec<SlowlogEntry>)>);
+
+/// Poll every node for its last `count` entries, from a blocking task
+pub(crate) fn fetch_all(
+    conn: &mut RedisConnection,
+    urls: &[String],
+    count: usize,
+) -> SlowlogFetched {
+    let nodes = match cluster_nodes(conn, urls) {
+        Ok(nodes) => nodes,
+        Err(e) => {
+            error!(error = %e, "cannot discover cluster nodes for slowlog");
+            return SlowlogFetched(vec![]);
+        }
+    };
+
+    let fetched = nodes
+        .into_iter()
+        .filter_map(|(node, client)| match fetch(&node, &client, count) {
+            Ok(entries) => Some((node, entries)),
+            Err(e) => {
+                error!(node, error = %e, "cannot fetch slowlog");
+                None
+            }
+        })
+        .collect();
+    SlowlogFetched(fetched)
+}
+
+impl SlowlogCollector {
+    /// Report the fetched entries that have not been seen yet
+    pub(crate) fn record(&mut self, fetched: SlowlogFetched, config: &SlowlogConfig) {
+        for (node, entries) in fetched.0 {
+            self.push(&node, entries, config.capacity);
+        }
+    }
+
+    /// Entries kept so far, oldest first
+    pub(crate) fn recent(&self) -> Vec<SlowlogEntry> {
+        self.recent.iter().cloned().collect()
+    }
+
+    fn push(&mut self, node: &str, entries: Vec<SlowlogEntry>, capacity: usize) {
+        // Nodes return newest first
+        let newest = entries.first().map_or(-1, |entry| entry.id);
+        let mut last_id = self.last_ids.get(node).copied().unwrap_or(-1);
+        if newest < last_id {
+            // Ids went backwards, the node was restarted or its slowlog reset
+            last_id = -1;
+        }
+
+        for entry in entries.into_iter().rev().filter(|entry| entry.id > last_id) {
+            warn!(
+                node = entry.node,
+                duration_us = entry.duration.as_micros() as u64,
+                command = entry.args.join(" "),
+                "slow redis command"
+            );
+            self.recent.push_back(entry);
+        }
+        self.last_ids.insert(node.to_owned(), newest.max(last_id));
+
+        while self.recent.len() > capacity {
+            self.recent.pop_front();
+        }
+    }
+}
+
+fn fetch(node: &str, client: &redis::Client, count: usize) -> RedisResult<Vec<SlowlogEntry>> {
+    let mut conn = client.get_connection()?;
+    let entries: Vec<Value> = redis::cmd("SLOWLOG")
+        .arg("GET")
+        .arg(count)
+        .query(&mut conn)?;
+
+    entries
+        .iter()
+        .map(|entry| {
+            // [id, timestamp, duration in µs, [args..], client addr, client name]
+            let fields: Vec<Value> = from_redis_value(entry)?;
+            let field = |i: usize| fields.get(i).unwrap_or(&Value::Nil);
+            Ok(SlowlogEntry {
+                node: node.to_owned(),
+                id: from_redis_value(field(0))?,
+                timestamp: from_redis_value(field(1))?,
+                duration: Duration::from_micros(from_redis_value(field(2))?),
+                args: from_redis_value(field(3))?,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(id: i64) -> SlowlogEntry {
+        SlowlogEntry {
+            node: "127.0.0.1:30001".to_owned(),
+            id,
+            timestamp: 0,
+            duration: Duration::from_millis(20),
+            args: vec!["GET".to_owned(), "key".to_owned()],
+        }
+    }
+
+    #[test]
+    fn entries_are_deduplicated_across_polls() {
+        let mut collector = SlowlogCollector::default();
+        collector.push("127.0.0.1:30001", vec![entry(1), entry(0)], 10);
+        collector.push("127.0.0.1:30001", vec![entry(2), entry(1), entry(0)], 10);
+
+        let ids: Vec<i64> = collector.recent().iter().map(|entry| entry.id).collect();
+        assert_eq!(vec![0, 1, 2], ids);
+    }
+
+    #[test]
+    fn capacity_keeps_the_newest_entries() {
+        let mut collector = SlowlogCollector::default();
+        collector.push("127.0.0.1:30001", vec![entry(2), entry(1), entry(0)], 2);
+
+        let ids: Vec<i64> = collector.recent().iter().map(|entry| entry.id).collect();
+        assert_eq!(vec![1, 2], ids);
+    }
+}
@@ -0,0 +1,180 @@
+use redis::{from_redis_value, Cmd, RedisResult, Value};
+use serde::{Deserialize, Serialize};
+
+use super::typed::{pairs, TypedCommand};
+
+/// Score bound of a range query
+#[derive(Default, Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum ScoreBound {
+    /// `-inf` as a minimum, `+inf` as a maximum
+    #[default]
+    Unbounded,
+    Inclusive(f64),
+    Exclusive(f64),
+}
+
+impl ScoreBound {
+    fn arg(&self, unbounded: &str) -> String {
+        match self {
+            ScoreBound::Unbounded => unbounded.to_owned(),
+            ScoreBound::Inclusive(score) => score.to_string(),
+            ScoreBound::Exclusive(score) => format!("({score}"),
+        }
+    }
+}
+
+/// `LIMIT` of a range query
+#[derive(Default, Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RangeLimit {
+    pub offset: usize,
+    pub count: usize,
+}
+
+/// Member of a sorted set, with its score when requested
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ScoredMember {
+    pub member: String,
+    pub score: Option<f64>,
+}
+
+/// `ZRANGEBYSCORE`, replied with the members between `min` and `max` by ascending score
+#[derive(Default, Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RedisZRangeByScore {
+    pub key: String,
+    pub min: ScoreBound,
+    pub max: ScoreBound,
+    #[serde(default)]
+    pub limit: Option<RangeLimit>,
+    /// Reply with the score of every member
+    #[serde(default)]
+    pub with_scores: bool,
+}
+
+impl TypedCommand for RedisZRangeByScore {
+    type Reply = Vec<ScoredMember>;
+    const OP: &'static str = "zrangebyscore";
+    const READ_ONLY: bool = true;
+
+    fn key(&self) -> &str {
+        &self.key
+    }
+
+    fn cmd(&self) -> Cmd {
+        let mut cmd = redis::cmd("ZRANGEBYSCORE");
+        cmd.arg(&self.key)
+            .arg(self.min.arg("-inf"))
+            .arg(self.max.arg("+inf"));
+        if self.with_scores {
+            cmd.arg("WITHSCORES");
+        }
+        if let Some(limit) = self.limit {
+            cmd.arg("LIMIT").arg(limit.offset).arg(limit.count);
+        }
+        cmd
+    }
+
+    fn parse(&self, value: &Value) -> RedisResult<Vec<ScoredMember>> {
+        if !self.with_scores {
+            let members: Vec<String> = from_redis_value(value)?;
+            return Ok(members
+                .into_iter()
+                .map(|member| ScoredMember {
+                    member,
+                    score: None,
+                })
+                .collect());
+        }
+
+        Ok(pairs(value)?
+            .into_iter()
+            .map(|(member, score)| ScoredMember {
+                member,
+                score: Some(score),
+            })
+            .collect())
+    }
+}
+
+/// `ZINCRBY` of a member, replied with its new score
+#[derive(Default, Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RedisZIncrBy {
+    pub key: String,
+    pub member: String,
+    pub delta: f64,
+}
+
+impl TypedCommand for RedisZIncrBy {
+    type Reply = f64;
+    const OP: &'static str = "zincrby";
+    const READ_ONLY: bool = false;
+    const IDEMPOTENT: bool = false;
+
+    fn key(&self) -> &str {
+        &self.key
+    }
+
+    fn cmd(&self) -> Cmd {
+        let mut cmd = redis::cmd("ZINCRBY");
+        cmd.arg(&self.key).arg(self.delta).arg(&self.member);
+        cmd
+    }
+
+    fn parse(&self, value: &Value) -> RedisResult<f64> {
+        from_redis_value(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aggregates::redis::typed::args;
+
+    #[test]
+    fn score_ranges_are_built_and_parsed() {
+        let range = RedisZRangeByScore {
+            key: "events".to_owned(),
+            min: ScoreBound::Exclusive(1.5),
+            max: ScoreBound::Unbounded,
+            limit: Some(RangeLimit {
+                offset: 10,
+                count: 5,
+            }),
+            with_scores: true,
+        };
+        assert_eq!(
+            vec![
+                "ZRANGEBYSCORE",
+                "events",
+                "(1.5",
+                "+inf",
+                "WITHSCORES",
+                "LIMIT",
+                "10",
+                "5"
+            ],
+            args(&range.cmd())
+        );
+
+        let reply = Value::Bulk(vec![Value::Data(b"a".to_vec()), Value::Data(b"2".to_vec())]);
+        assert_eq!(
+            vec![ScoredMember {
+                member: "a".to_owned(),
+                score: Some(2.0),
+            }],
+            range.parse(&reply).unwrap()
+        );
+    }
+
+    #[test]
+    fn increments_put_the_delta_before_the_member() {
+        let incr = RedisZIncrBy {
+            key: "ranking".to_owned(),
+            member: "alice".to_owned(),
+            delta: 2.5,
+        };
+        assert_eq!(
+            vec!["ZINCRBY", "ranking", "2.5", "alice"],
+            args(&incr.cmd())
+        );
+    }
+}
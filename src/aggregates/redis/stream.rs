@@ -0,0 +1,153 @@
+use bytes::Bytes;
+use r2d2::Pool;
+use redis::{ErrorKind, RedisResult};
+use tokio::{sync::mpsc, task};
+
+use super::{
+    chunked, connection::RedisConnection, error::RedisError, pool::checkout, pool::RedisManager,
+    trace, Redis,
+};
+
+/// Chunks buffered in the channel before the reader waits for the caller
+pub const STREAM_BUFFER: usize = 16;
+
+/// Receiving end of the sink handed to `RedisStreamQuery`
+pub type ChunkStream = mpsc::Receiver<Result<Bytes, RedisError>>;
+
+/// Where the chunks of a streamed value are sent, the stream ends when the sender is dropped
+#[derive(Debug, Clone)]
+pub struct ChunkSink(mpsc::Sender<Result<Bytes, RedisError>>);
+
+impl ChunkSink {
+    /// Sink with its stream, buffering `STREAM_BUFFER` chunks
+    pub fn channel() -> (Self, ChunkStream) {
+        let (sender, receiver) = mpsc::channel(STREAM_BUFFER);
+        (Self(sender), receiver)
+    }
+
+    /// End the stream with an error, dropped when the stream is full or gone
+    pub(crate) fn fail(&self, error: RedisError) {
+        let _ = self.0.try_send(Err(error));
+    }
+}
+
+/// Tell streaming a value to `sink` in `GETRANGE` chunks of `chunk_size` bytes.
+///
+/// A missing key ends the stream without chunks. Chunks are read one by one, a write landing
+/// in between shows up in the following chunks. With chunking, a chunked value is streamed
+/// from its chunks in order.
+#[derive(Debug, Clone)]
+pub struct RedisStreamQuery {
+    pub key: String,
+    pub chunk_size: usize,
+    pub sink: ChunkSink,
+}
+
+/// Stream the value from a blocking task on its own pooled connection
+pub(crate) fn spawn(pool: &Pool<RedisManager>, redis: &Redis, event: RedisStreamQuery) {
+    let pool = pool.clone();
+    let (hash_trace_keys, chunked) = (redis.hash_trace_keys, redis.chunking.is_some());
+    let actor = redis.own_distributor();
+    task::spawn_blocking(move || {
+        let RedisStreamQuery {
+            key,
+            chunk_size,
+            sink,
+        } = event;
+        let result = checkout(&pool)
+            .map_err(|e| RedisError::Command(e.to_string()))
+            .and_then(|mut conn| {
+                stream(
+                    &mut conn,
+                    &key,
+                    chunk_size,
+                    hash_trace_keys,
+                    chunked,
+                    &sink.0,
+                )
+                .map_err(|e| RedisError::Command(e.to_string()))
+            });
+        if let Err(e) = result {
+            Redis::report_error(actor, "Stream", &e);
+            // The caller may be gone already
+            let _ = sink.0.blocking_send(Err(e));
+        }
+    });
+}
+
+fn stream(
+    conn: &mut RedisConnection,
+    key: &str,
+    chunk_size: usize,
+    hash_trace_keys: bool,
+    chunked: bool,
+    sink: &mpsc::Sender<Result<Bytes, RedisError>>,
+) -> RedisResult<()> {
+    let chunk_keys = match chunked {
+        true => chunked::chunk_keys(conn, key)?,
+        false => None,
+    };
+    let Some(chunk_keys) = chunk_keys else {
+        stream_key(conn, key, chunk_size, hash_trace_keys, sink)?;
+        return Ok(());
+    };
+    for chunk_key in chunk_keys {
+        match stream_key(conn, &chunk_key, chunk_size, hash_trace_keys, sink)? {
+            Some(0) => {
+                return Err((ErrorKind::TypeError, "chunk of a chunked value is missing").into())
+            }
+            Some(_) => {}
+            None => break,
+        }
+    }
+    Ok(())
+}
+
+// Stream one key, its length or `None` once the caller dropped the stream
+fn stream_key(
+    conn: &mut RedisConnection,
+    key: &str,
+    chunk_size: usize,
+    hash_trace_keys: bool,
+    sink: &mpsc::Sender<Result<Bytes, RedisError>>,
+) -> RedisResult<Option<usize>> {
+    let len: usize = trace::command("strlen", key, hash_trace_keys, || {
+        redis::cmd("STRLEN").arg(key).query(conn)
+    })?;
+    for (start, end) in chunk_ranges(len, chunk_size) {
+        let chunk: Vec<u8> = trace::command("getrange", key, hash_trace_keys, || {
+            redis::cmd("GETRANGE")
+                .arg(key)
+                .arg(start)
+                .arg(end)
+                .query(conn)
+        })?;
+        if sink.blocking_send(Ok(Bytes::from(chunk))).is_err() {
+            // Stream dropped by the caller
+            return Ok(None);
+        }
+    }
+    Ok(Some(len))
+}
+
+// Inclusive `GETRANGE` bounds covering `len` bytes
+fn chunk_ranges(len: usize, chunk_size: usize) -> impl Iterator<Item = (usize, usize)> {
+    let chunk_size = chunk_size.max(1);
+    (0..len)
+        .step_by(chunk_size)
+        .map(move |start| (start, (start + chunk_size).min(len) - 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ranges_cover_the_value() {
+        assert_eq!(
+            vec![(0, 3), (4, 7), (8, 9)],
+            chunk_ranges(10, 4).collect::<Vec<_>>()
+        );
+        assert_eq!(0, chunk_ranges(0, 4).count());
+    }
+}
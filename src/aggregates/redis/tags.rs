@@ -0,0 +1,120 @@
+//! Cache tags and group invalidation.
+//!
+//! A tag is a Redis set under `tag:<name>` holding the keys inserted with it, so everything
+//! related to an entity can be dropped at once after it changes, e.g. `invalidate_tag("user:42")`.
+//! Keys are added to their tag sets before the value is written: a failed write leaves a
+//! member pointing at nothing, never a value its tag does not know about. Members of expired
+//! keys stay in the set until the tag is invalidated.
+
+use redis::{Cmd, RedisResult};
+use serde::{Deserialize, Serialize};
+
+use super::{backend::RedisBackend, chunked, RedisInsert};
+
+/// Prefix of the set tracking the keys of a tag
+pub const TAG_PREFIX: &str = "tag:";
+
+/// Question storing a value like `RedisInsert` and adding its key to every tag of `tags`,
+/// replied with `Result<(), RedisError>`
+#[derive(Default, Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RedisTaggedInsert {
+    pub insert: RedisInsert,
+    pub tags: Vec<String>,
+}
+
+/// Question deleting every key tagged with `tag`, replied with `Result<u64, RedisError>`
+/// counting the keys that existed.
+///
+/// Without the `cluster` feature the keys go in one `UNLINK`, so readers see all of them or
+/// none. On a cluster one `UNLINK` is sent per hash slot, keys sharing a slot disappear
+/// together.
+#[derive(Default, Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RedisInvalidateTag {
+    pub tag: String,
+    /// Caller tag recorded by the audit log
+    #[serde(default)]
+    pub caller: Option<String>,
+}
+
+/// Key of the set tracking `tag`
+pub fn tag_key(tag: &str) -> String {
+    format!("{TAG_PREFIX}{tag}")
+}
+
+/// `SADD` of `key` to the set of every tag
+pub(crate) fn tag(conn: &mut dyn RedisBackend, key: &str, tags: &[String]) -> RedisResult<()> {
+    for tag in tags {
+        let mut cmd = redis::cmd("SADD");
+        cmd.arg(tag_key(tag)).arg(key);
+        conn.command(&cmd)?;
+    }
+    Ok(())
+}
+
+/// Delete the keys of `tag`, then drop them from its set. Keys tagged meanwhile are kept.
+pub(crate) fn invalidate(
+    conn: &mut dyn RedisBackend,
+    tag: &str,
+    chunked: bool,
+) -> RedisResult<u64> {
+    let set = tag_key(tag);
+    let mut members = redis::cmd("SMEMBERS");
+    members.arg(&set);
+    let keys: Vec<String> = redis::from_redis_value(&conn.command(&members)?)?;
+    if keys.is_empty() {
+        return Ok(0);
+    }
+
+    if chunked {
+        // Chunks first, they never outlive the manifest pointing at them
+        for key in &keys {
+            chunked::remove_all_chunks(conn, key)?;
+        }
+    }
+    let mut deleted = 0;
+    for cmd in unlinks(&keys) {
+        deleted += redis::from_redis_value::<u64>(&conn.command(&cmd)?)?;
+    }
+
+    let mut forget = redis::cmd("SREM");
+    forget.arg(&set).arg(&keys);
+    conn.command(&forget)?;
+    Ok(deleted)
+}
+
+/// `UNLINK` of `keys`, one command per slot on a cluster
+fn unlinks(keys: &[String]) -> Vec<Cmd> {
+    #[cfg(feature = "cluster")]
+    let groups: Vec<Vec<&String>> = super::multi::group_by_slot(keys.iter().map(String::as_str))
+        .into_values()
+        .map(|group| group.into_iter().map(|i| &keys[i]).collect())
+        .collect();
+    #[cfg(not(feature = "cluster"))]
+    let groups = vec![keys.iter().collect::<Vec<_>>()];
+
+    groups
+        .into_iter()
+        .map(|group| {
+            let mut cmd = redis::cmd("UNLINK");
+            cmd.arg(group);
+            cmd
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aggregates::redis::typed::args;
+
+    #[test]
+    fn tagged_keys_are_unlinked_together() {
+        let keys = vec!["user:42".to_owned(), "{user:42}:posts".to_owned()];
+        // One slot thanks to the hash tag, so one command with or without a cluster
+        assert_eq!(
+            vec![vec!["UNLINK", "user:42", "{user:42}:posts"]],
+            unlinks(&keys).iter().map(args).collect::<Vec<_>>()
+        );
+        assert_eq!("tag:user:42", tag_key("user:42"));
+    }
+}
@@ -0,0 +1,146 @@
+//! Tenant isolation on top of the router.
+//!
+//! Every tenant owns the keys under its prefix. Messages wrapped in `ForTenant` get the prefix
+//! added to their keys before they are routed, to the tenant's own cluster when it has one.
+//! The router refuses unwrapped messages touching a tenant's keys, and prefixes may not overlap,
+//! so no tenant reaches another tenant's keys.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use super::{delete::RedisDeleteMany, error::RedisError, Redis, RedisInsert, RedisQuery};
+
+/// Key prefix and placement of a tenant
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Tenant {
+    /// Added in front of every key of the tenant, e.g. `tenant:acme:`
+    pub prefix: String,
+    /// Cluster serving every key of the tenant, the router rules pick it otherwise
+    #[serde(default)]
+    pub cluster: Option<String>,
+}
+
+/// Message run on behalf of `tenant`, its keys are relative to the tenant's prefix
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ForTenant<M> {
+    pub tenant: String,
+    pub message: M,
+}
+
+impl<M> ForTenant<M> {
+    pub fn new(tenant: impl Into<String>, message: M) -> Self {
+        Self {
+            tenant: tenant.into(),
+            message,
+        }
+    }
+}
+
+/// Message whose keys can be moved under a tenant's prefix
+pub trait TenantKeys {
+    fn prefix_keys(&mut self, prefix: &str);
+}
+
+impl TenantKeys for RedisInsert {
+    fn prefix_keys(&mut self, prefix: &str) {
+        self.key.insert_str(0, prefix);
+    }
+}
+
+impl TenantKeys for RedisQuery {
+    fn prefix_keys(&mut self, prefix: &str) {
+        self.key.insert_str(0, prefix);
+    }
+}
+
+impl TenantKeys for RedisDeleteMany {
+    fn prefix_keys(&mut self, prefix: &str) {
+        for key in &mut self.keys {
+            key.insert_str(0, prefix);
+        }
+    }
+}
+
+/// Check that every tenant has a prefix of its own and only names known clusters
+pub(crate) fn validate(
+    tenants: &BTreeMap<String, Tenant>,
+    clusters: &BTreeMap<String, Redis>,
+) -> Result<(), RedisError> {
+    for (id, tenant) in tenants {
+        if tenant.prefix.is_empty() {
+            return Err(RedisError::EmptyTenantPrefix(id.clone()));
+        }
+        if let Some(cluster) = &tenant.cluster {
+            if !clusters.contains_key(cluster) {
+                return Err(RedisError::UnknownCluster(cluster.clone()));
+            }
+        }
+        // One prefix starting another would let the shorter one reach into the longer one
+        if let Some((other, _)) = tenants
+            .iter()
+            .find(|(other, them)| other != &id && them.prefix.starts_with(&tenant.prefix))
+        {
+            return Err(RedisError::OverlappingTenants(id.clone(), other.clone()));
+        }
+    }
+    Ok(())
+}
+
+/// Tenant owning `key`, when it lies under a tenant's prefix
+pub(crate) fn owner<'a>(tenants: &'a BTreeMap<String, Tenant>, key: &str) -> Option<&'a str> {
+    tenants
+        .iter()
+        .find(|(_, tenant)| key.starts_with(&tenant.prefix))
+        .map(|(id, _)| id.as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tenant(prefix: &str) -> Tenant {
+        Tenant {
+            prefix: prefix.to_owned(),
+            cluster: None,
+        }
+    }
+
+    #[test]
+    fn prefixes_may_not_overlap() {
+        let clusters = BTreeMap::new();
+        let tenants = BTreeMap::from([
+            ("a".to_owned(), tenant("t:a:")),
+            ("b".to_owned(), tenant("t:b:")),
+        ]);
+        assert_eq!(Ok(()), validate(&tenants, &clusters));
+        assert_eq!(Some("b"), owner(&tenants, "t:b:1"));
+        assert_eq!(None, owner(&tenants, "shared"));
+
+        let nested = BTreeMap::from([
+            ("a".to_owned(), tenant("t:a")),
+            ("ab".to_owned(), tenant("t:ab:")),
+        ]);
+        assert_eq!(
+            Err(RedisError::OverlappingTenants(
+                "a".to_owned(),
+                "ab".to_owned()
+            )),
+            validate(&nested, &clusters)
+        );
+        assert_eq!(
+            Err(RedisError::EmptyTenantPrefix("a".to_owned())),
+            validate(&BTreeMap::from([("a".to_owned(), tenant(""))]), &clusters)
+        );
+    }
+
+    #[test]
+    fn keys_are_moved_under_the_prefix() {
+        let mut delete = RedisDeleteMany {
+            keys: vec!["1".to_owned(), "2".to_owned()],
+            caller: None,
+        };
+        delete.prefix_keys("t:a:");
+        assert_eq!(vec!["t:a:1", "t:a:2"], delete.keys);
+    }
+}
@@ -0,0 +1,50 @@
+//! Tracing spans around redis operations
+
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+    time::Instant,
+};
+
+use redis::RedisResult;
+use tracing::{error, field, info_span};
+
+use super::metrics;
+
+/// Key as it appears in spans, hashed when keys may carry sensitive data
+pub(crate) fn trace_key(key: &str, hash: bool) -> String {
+    if hash {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        format!("{:016x}", hasher.finish())
+    } else {
+        key.to_owned()
+    }
+}
+
+/// Run one command inside a `redis.command` span, recording its duration and metrics
+pub(crate) fn command<T>(
+    op: &'static str,
+    key: &str,
+    hash_key: bool,
+    f: impl FnOnce() -> RedisResult<T>,
+) -> RedisResult<T> {
+    let span = info_span!(
+        "redis.command",
+        op,
+        key = %trace_key(key, hash_key),
+        duration_us = field::Empty,
+    );
+    let _enter = span.enter();
+
+    let start = Instant::now();
+    let result = f();
+    let elapsed = start.elapsed();
+
+    span.record("duration_us", elapsed.as_micros() as u64);
+    metrics::record_command(op, elapsed, result.is_ok());
+    if let Err(e) = &result {
+        error!(error = %e, "command failed");
+    }
+    result
+}
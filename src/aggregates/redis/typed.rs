@@ -0,0 +1,60 @@
+use bastion::prelude::Message;
+use redis::{from_redis_value, Cmd, ErrorKind, FromRedisValue, RedisResult, Value};
+
+/// Question run as one command on the actor's connection, replied with
+/// `Result<Self::Reply, RedisError>`
+pub trait TypedCommand: Message {
+    type Reply: Message;
+
+    /// Command name recorded in traces and the audit log
+    const OP: &'static str;
+
+    /// Also answered by the read group, never audited
+    const READ_ONLY: bool;
+
+    /// Gives the same result when applied twice, so it is retried after a dropped connection
+    const IDEMPOTENT: bool = true;
+
+    fn key(&self) -> &str;
+
+    fn cmd(&self) -> Cmd;
+
+    /// Typed reply, usually `FromRedisValue::from_redis_value`
+    fn parse(&self, value: &Value) -> RedisResult<Self::Reply>;
+}
+
+/// Flat `[a1, b1, a2, b2, ..]` reply, e.g. `WITHSCORES`, as pairs
+pub(crate) fn pairs<A: FromRedisValue, B: FromRedisValue>(
+    value: &Value,
+) -> RedisResult<Vec<(A, B)>> {
+    let items: Vec<Value> = from_redis_value(value)?;
+    let pairs = items.chunks_exact(2);
+    if !pairs.remainder().is_empty() {
+        return Err((ErrorKind::TypeError, "reply is not made of pairs").into());
+    }
+    pairs
+        .map(|pair| Ok((from_redis_value(&pair[0])?, from_redis_value(&pair[1])?)))
+        .collect()
+}
+
+/// Reply of a command taking an optional `COUNT`, one item or nil without it and a list with it
+pub(crate) fn items<T: FromRedisValue>(
+    count: Option<impl Sized>,
+    value: &Value,
+) -> RedisResult<Vec<T>> {
+    match count {
+        Some(_) => from_redis_value(value),
+        None => Ok(from_redis_value::<Option<T>>(value)?.into_iter().collect()),
+    }
+}
+
+/// Arguments of a command as text
+#[cfg(test)]
+pub(crate) fn args(cmd: &Cmd) -> Vec<String> {
+    cmd.args_iter()
+        .map(|arg| match arg {
+            redis::Arg::Simple(arg) => String::from_utf8_lossy(arg).into_owned(),
+            redis::Arg::Cursor => "<cursor>".to_owned(),
+        })
+        .collect()
+}
@@ -0,0 +1,73 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::actors::machine::Applied;
+
+use super::{event::RedisEvent, pool::PoolStats, RedisState};
+
+/// Connection status projection maintained from applied events
+#[derive(Default, Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RedisStatus {
+    pub state: RedisState,
+    pub urls: Vec<String>,
+    /// Filled at query time from the live pool
+    pub pool_stats: Option<PoolStats>,
+    pub last_error: Option<String>,
+    pub last_reconnect_at: Option<DateTime<Utc>>,
+    /// Last move to the secondary cluster
+    #[serde(default)]
+    pub last_failover_at: Option<DateTime<Utc>>,
+}
+
+impl RedisStatus {
+    /// Project an event applied by the actor
+    pub fn update(&mut self, applied: &Applied<RedisEvent>) {
+        match &applied.event {
+            RedisEvent::RedisServerConnected { urls } => {
+                self.state = RedisState::Initialized;
+                self.urls = urls.clone();
+            }
+            RedisEvent::RedisServerReconnected { urls } => {
+                self.urls = urls.clone();
+                self.last_reconnect_at = Some(applied.at);
+            }
+            RedisEvent::RedisServerDisconnected { error } => {
+                self.state = RedisState::Connecting;
+                self.last_error = Some(error.clone());
+            }
+            RedisEvent::RedisServerFailedOver { to, .. } => {
+                self.urls = to.clone();
+                self.last_failover_at = Some(applied.at);
+            }
+            RedisEvent::RedisServerAbandoned { error, .. } => {
+                self.state = RedisState::Uninitialized;
+                self.last_error = Some(error.clone());
+            }
+            RedisEvent::RedisErrorOccurred { error, .. } => {
+                self.last_error = Some(error.clone());
+            }
+        }
+    }
+}
+
+/// The same projection as a cqrs-es view, stamped with the event's timestamp metadata
+#[cfg(feature = "cqrs")]
+impl cqrs_es::View<super::Redis> for RedisStatus {
+    fn update(&mut self, event: &cqrs_es::EventEnvelope<super::Redis>) {
+        use crate::actors::cqrs::TIMESTAMP_METADATA;
+
+        let at = event
+            .metadata
+            .get(TIMESTAMP_METADATA)
+            .and_then(|at| DateTime::parse_from_rfc3339(at).ok())
+            .map_or_else(Utc::now, |at| at.with_timezone(&Utc));
+        RedisStatus::update(
+            self,
+            &Applied {
+                sequence: event.sequence,
+                event: event.payload.clone(),
+                at,
+            },
+        );
+    }
+}
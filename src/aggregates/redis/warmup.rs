@@ -0,0 +1,293 @@
+//! Cache warm-up.
+//!
+//! The warm-up actor preloads a set of keys into Redis from a `WarmupLoader`, usually the
+//! database the cache sits in front of, then checks they are there. It runs once the Redis
+//! actor is initialized when `on_start` is set and again on every `RedisWarmup`, so a cold
+//! cache is filled by one pass instead of by every caller missing it at once.
+
+use std::{
+    fmt::{self, Debug},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use bastion::prelude::{BastionContext, Distributor, MessageHandler};
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+use tokio::{task, time};
+use tracing::{error, info, warn};
+
+use crate::actors::base::{liveness, TActor};
+
+use super::{
+    error::RedisError, multi::RedisExists, router::ask, view::RedisStatus, Redis, RedisInsert,
+    RedisQuery, RedisState, RedisStatusQuery,
+};
+
+/// How often the actor asks Redis for its state before the first warm-up
+const READY_POLL: Duration = Duration::from_millis(50);
+
+/// Source of the values to preload
+pub trait WarmupLoader: Send + Sync + 'static {
+    /// Keys to preload with `WarmupKeys::Loader`
+    fn keys(&self) -> anyhow::Result<Vec<String>> {
+        Ok(vec![])
+    }
+
+    /// Value of `key`, `None` when there is nothing to cache for it
+    fn load(&self, key: &str) -> anyhow::Result<Option<Bytes>>;
+}
+
+/// Loads nothing
+impl WarmupLoader for () {
+    fn load(&self, _: &str) -> anyhow::Result<Option<Bytes>> {
+        Ok(None)
+    }
+}
+
+/// Where the keys to preload come from
+#[derive(Default, Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum WarmupKeys {
+    /// These keys
+    List(Vec<String>),
+    /// The keys of the JSON array stored under this key, none when it is missing
+    Manifest(String),
+    /// The keys listed by `WarmupLoader::keys`
+    #[default]
+    Loader,
+}
+
+/// Warm-up configuration and state of the warm-up actor
+#[derive(Clone)]
+pub struct Warmup {
+    pub keys: WarmupKeys,
+    pub loader: Arc<dyn WarmupLoader>,
+    /// Expiry of the values written
+    pub ttl: Option<Duration>,
+    /// Reload keys already cached instead of leaving them as they are
+    pub overwrite: bool,
+    /// Warm up once the Redis actor is initialized, waiting up to `ready_timeout`
+    pub on_start: bool,
+    pub ready_timeout: Duration,
+    /// Name of the Redis actor instance to fill, the unnamed actor otherwise
+    pub instance: Option<String>,
+}
+
+impl Default for Warmup {
+    fn default() -> Self {
+        Self {
+            keys: WarmupKeys::default(),
+            loader: Arc::new(()),
+            ttl: None,
+            overwrite: false,
+            on_start: true,
+            ready_timeout: Duration::from_secs(30),
+            instance: None,
+        }
+    }
+}
+
+impl Debug for Warmup {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Warmup")
+            .field("keys", &self.keys)
+            .field("ttl", &self.ttl)
+            .field("overwrite", &self.overwrite)
+            .field("on_start", &self.on_start)
+            .field("ready_timeout", &self.ready_timeout)
+            .field("instance", &self.instance)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Question running a warm-up now, replied with `Result<WarmupReport, RedisError>`
+#[derive(Default, Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RedisWarmup;
+
+/// Outcome of a warm-up
+#[derive(Default, Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct WarmupReport {
+    /// Keys to preload
+    pub keys: usize,
+    /// Keys already cached and left as they were
+    pub present: usize,
+    /// Keys written from the loader
+    pub loaded: usize,
+    /// Keys the loader had no value for
+    pub empty: usize,
+    /// Keys the loader or the write failed for
+    pub failed: Vec<String>,
+    /// Keys written but not found afterwards, e.g. evicted right away
+    pub missing: Vec<String>,
+    pub took: Duration,
+}
+
+impl Warmup {
+    /// Distributor of the warm-up actor
+    pub fn distributor() -> Distributor {
+        Distributor::named("redis_warmup")
+    }
+
+    /// Preload the keys and check they are cached
+    pub async fn run(&self) -> Result<WarmupReport, RedisError> {
+        let started = Instant::now();
+        let writer = Redis::distributor_named(self.instance.as_deref());
+        let keys = self.keys(writer).await?;
+        let mut report = WarmupReport {
+            keys: keys.len(),
+            ..Default::default()
+        };
+
+        let cached = ask::<Vec<bool>>(writer, RedisExists { keys: keys.clone() }).await?;
+        let wanted: Vec<String> = keys
+            .into_iter()
+            .zip(cached)
+            .filter(|(_, cached)| self.overwrite || !cached)
+            .map(|(key, _)| key)
+            .collect();
+        report.present = report.keys - wanted.len();
+
+        let loader = self.loader.clone();
+        let loaded = task::spawn_blocking(move || {
+            wanted
+                .into_iter()
+                .map(|key| {
+                    let value = loader.load(&key);
+                    (key, value)
+                })
+                .collect::<Vec<_>>()
+        })
+        .await
+        .map_err(|e| RedisError::Command(e.to_string()))?;
+
+        let mut written = vec![];
+        for (key, value) in loaded {
+            let value = match value {
+                Ok(Some(value)) => value,
+                Ok(None) => {
+                    report.empty += 1;
+                    continue;
+                }
+                Err(e) => {
+                    warn!(key, error = %e, "[WARMUP] Cannot load");
+                    report.failed.push(key);
+                    continue;
+                }
+            };
+            let insert = RedisInsert {
+                key: key.clone(),
+                value,
+                ttl: self.ttl,
+                caller: Some("warmup".to_owned()),
+            };
+            match ask::<()>(writer, insert).await {
+                Ok(()) => written.push(key),
+                Err(e) => {
+                    warn!(key, error = %e, "[WARMUP] Cannot write");
+                    report.failed.push(key);
+                }
+            }
+        }
+        report.loaded = written.len();
+
+        let cached = ask::<Vec<bool>>(
+            writer,
+            RedisExists {
+                keys: written.clone(),
+            },
+        )
+        .await?;
+        report.missing = written
+            .into_iter()
+            .zip(cached)
+            .filter(|(_, cached)| !cached)
+            .map(|(key, _)| key)
+            .collect();
+        report.took = started.elapsed();
+        Ok(report)
+    }
+
+    // Keys to preload
+    async fn keys(&self, writer: Distributor) -> Result<Vec<String>, RedisError> {
+        match &self.keys {
+            WarmupKeys::List(keys) => Ok(keys.clone()),
+            WarmupKeys::Manifest(key) => {
+                let query = RedisQuery { key: key.clone() };
+                match ask::<Option<Vec<u8>>>(writer, query).await? {
+                    Some(manifest) => serde_json::from_slice(&manifest)
+                        .map_err(|e| RedisError::Codec(e.to_string())),
+                    None => Ok(vec![]),
+                }
+            }
+            WarmupKeys::Loader => {
+                let loader = self.loader.clone();
+                task::spawn_blocking(move || loader.keys())
+                    .await
+                    .map_err(|e| RedisError::Command(e.to_string()))?
+                    .map_err(|e| RedisError::Command(e.to_string()))
+            }
+        }
+    }
+
+    // Wait until the Redis actor is initialized, false after `ready_timeout`
+    async fn ready(&self) -> bool {
+        let writer = Redis::distributor_named(self.instance.as_deref());
+        let deadline = Instant::now() + self.ready_timeout;
+        while Instant::now() < deadline {
+            if let Ok(Ok(status)) = writer.request::<RedisStatus>(RedisStatusQuery).await {
+                if status.state == RedisState::Initialized {
+                    return true;
+                }
+            }
+            time::sleep(READY_POLL).await;
+        }
+        false
+    }
+}
+
+// Run a warm-up and log its outcome
+async fn warm_up(warmup: Warmup) -> Result<WarmupReport, RedisError> {
+    let report = warmup.run().await;
+    match &report {
+        Ok(report) if report.failed.is_empty() && report.missing.is_empty() => {
+            info!(?report, "[WARMUP] Done")
+        }
+        Ok(report) => warn!(?report, "[WARMUP] Done with failures"),
+        Err(e) => error!(error = %e, "[WARMUP] Failed"),
+    }
+    report
+}
+
+#[async_trait]
+impl TActor for Warmup {
+    fn with_distributor() -> Option<Distributor> {
+        Some(Self::distributor())
+    }
+
+    async fn handler(&mut self, ctx: BastionContext) -> Result<(), ()> {
+        if self.on_start {
+            let warmup = self.clone();
+            tokio::spawn(async move {
+                if warmup.ready().await {
+                    let _ = warm_up(warmup).await;
+                } else {
+                    error!("[WARMUP] Redis is not initialized, skipped");
+                }
+            });
+        }
+        loop {
+            MessageHandler::new(ctx.recv().await?)
+                .on_question(|_: RedisWarmup, sender| {
+                    let warmup = self.clone();
+                    tokio::spawn(async move {
+                        let report = warm_up(warmup).await;
+                        // The caller may be gone already
+                        let _ = sender.reply(report);
+                    });
+                })
+                .on_fallback(|unknown, _| warn!("[WARMUP] Unknown message: {unknown:?}"));
+            liveness::processed();
+        }
+    }
+}
@@ -0,0 +1,98 @@
+//! Graceful drain on termination.
+//!
+//! On SIGTERM or ctrl-c the public API refuses new calls with `RedisError::Draining`, the calls
+//! in progress and the messages already queued for the actor finish, batched writes are sent
+//! and the actor stops, then Bastion. A terminated pod does not lose the writes it buffered.
+
+use std::{
+    sync::atomic::Ordering,
+    thread,
+    time::{Duration, Instant},
+};
+
+use bastion::{run, Bastion};
+use tokio::task::{self, JoinHandle};
+use tracing::{info, warn};
+
+use crate::{
+    aggregates::redis::{
+        control::{self, RedisControl},
+        error::RedisInitError,
+        view::RedisStatus,
+        Redis, RedisStatusQuery,
+    },
+    shutdown_redis, wait_ready, DRAINING, IN_FLIGHT, READY_POLL, RUNNING,
+};
+
+/// Drain on the first SIGTERM or ctrl-c, within `timeout`, then stop Bastion. Must be called
+/// from within a tokio runtime.
+pub fn on_termination(timeout: Duration) -> JoinHandle<Result<(), RedisInitError>> {
+    tokio::spawn(async move {
+        terminated().await;
+        info!("[REDIS] Termination requested, draining");
+        let drained = task::spawn_blocking(move || drain(timeout))
+            .await
+            .unwrap_or_else(|e| Err(RedisInitError::Stop(e.to_string())));
+        if let Err(e) = &drained {
+            warn!("[REDIS] Drain incomplete: {e}");
+        }
+        Bastion::stop();
+        drained
+    })
+}
+
+// First SIGTERM or ctrl-c
+async fn terminated() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        match signal(SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                tokio::select! {
+                    _ = sigterm.recv() => {}
+                    _ = tokio::signal::ctrl_c() => {}
+                }
+                return;
+            }
+            Err(e) => warn!("[REDIS] Cannot listen for SIGTERM: {e}"),
+        }
+    }
+    if let Err(e) = tokio::signal::ctrl_c().await {
+        warn!("[REDIS] Cannot listen for ctrl-c, never draining: {e}");
+        std::future::pending::<()>().await;
+    }
+}
+
+/// Refuse new public calls, wait for the calls in progress and the messages already queued,
+/// send batched writes and stop the running actor, all within `timeout`.
+///
+/// Writes held while the actor is disconnected are written if the connection comes back in
+/// time and dropped otherwise. Public calls stay refused until the actor is started again.
+pub fn drain(timeout: Duration) -> Result<(), RedisInitError> {
+    let deadline = Instant::now() + timeout;
+    DRAINING.store(true, Ordering::SeqCst);
+    while IN_FLIGHT.load(Ordering::SeqCst) > 0 {
+        if Instant::now() >= deadline {
+            return Err(RedisInitError::Stop(format!(
+                "{} calls still in progress after {timeout:?}",
+                IN_FLIGHT.load(Ordering::SeqCst)
+            )));
+        }
+        thread::sleep(READY_POLL);
+    }
+    if RUNNING.lock().unwrap().is_none() {
+        return Ok(());
+    }
+
+    if let Err(e) = wait_ready(deadline.saturating_duration_since(Instant::now())) {
+        warn!("[REDIS] Draining while disconnected, held writes are dropped: {e}");
+    }
+    // Answered once every message queued before it is handled
+    let status = Redis::typed::<_, RedisStatus>(None);
+    run!(status.request(RedisStatusQuery))
+        .map_err(|e| RedisInitError::Stop(format!("cannot reach the actor: {e:?}")))?;
+    run!(control::send(None, RedisControl::Flush))
+        .map_err(|e| RedisInitError::Stop(e.to_string()))?;
+    shutdown_redis(deadline.saturating_duration_since(Instant::now()))
+}
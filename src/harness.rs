@@ -0,0 +1,153 @@
+use std::{
+    net::{IpAddr, ToSocketAddrs},
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, bail, Result};
+use testcontainers::{
+    core::{IntoContainerPort, WaitFor},
+    runners::AsyncRunner,
+    ContainerAsync, GenericImage, ImageExt,
+};
+
+use crate::{
+    actors::base::Actor,
+    aggregates::redis::{Redis, RedisState},
+    init_redis_with, status,
+};
+
+/// Port Redis listens on inside the container
+const REDIS_PORT: u16 = 6379;
+
+/// How long the container and the actor get to become ready
+const READY_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Single-node Redis cluster in a container with the actor connected to it.
+///
+/// The node serves every slot, so cluster redirects never leave the mapped port. Dropping it
+/// removes the container. Only one harness per process, the actor's distributor is global.
+pub struct RedisHarness {
+    url: String,
+    actor: Actor<Redis>,
+    // Kept last so the actor is dropped before the container
+    _container: ContainerAsync<GenericImage>,
+}
+
+impl RedisHarness {
+    /// Start the container and the actor with the default configuration
+    pub async fn start() -> Result<Self> {
+        Self::start_with(Redis::default()).await
+    }
+
+    /// Start the container and the actor with `redis`, its urls are replaced by the container's
+    pub async fn start_with(mut redis: Redis) -> Result<Self> {
+        let container = GenericImage::new("redis", "7.2")
+            .with_exposed_port(REDIS_PORT.tcp())
+            .with_wait_for(WaitFor::message_on_stdout("Ready to accept connections"))
+            .with_cmd(["redis-server", "--cluster-enabled", "yes"])
+            .start()
+            .await?;
+        let host = container.get_host().await?.to_string();
+        let port = container.get_host_port_ipv4(REDIS_PORT).await?;
+
+        let ip = announced_ip(&host, port)?;
+        let url = format!("redis://{ip}:{port}");
+        tokio::task::spawn_blocking({
+            let url = url.clone();
+            move || form_cluster(&url, ip, port)
+        })
+        .await??;
+
+        redis.urls = vec![url.clone()];
+        let actor = init_redis_with(redis)?;
+        wait_for(|| status().state == RedisState::Initialized).await?;
+
+        Ok(Self {
+            url,
+            actor,
+            _container: container,
+        })
+    }
+
+    /// Url of the node, as given to the actor
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    pub fn actor(&self) -> &Actor<Redis> {
+        &self.actor
+    }
+}
+
+// Cluster nodes announce an IP, never a host name
+fn announced_ip(host: &str, port: u16) -> Result<IpAddr> {
+    (host, port)
+        .to_socket_addrs()?
+        .map(|addr| addr.ip())
+        .find(IpAddr::is_ipv4)
+        .ok_or_else(|| anyhow!("no IPv4 address for container host {host}"))
+}
+
+// Announce the mapped address and give every slot to the single node
+fn form_cluster(url: &str, ip: IpAddr, port: u16) -> Result<()> {
+    let mut conn = redis::Client::open(url)?.get_connection()?;
+    redis::cmd("CONFIG")
+        .arg("SET")
+        .arg("cluster-announce-ip")
+        .arg(ip.to_string())
+        .arg("cluster-announce-port")
+        .arg(port)
+        .query::<()>(&mut conn)?;
+    redis::cmd("CLUSTER")
+        .arg("ADDSLOTSRANGE")
+        .arg(0)
+        .arg(16383)
+        .query::<()>(&mut conn)?;
+
+    let deadline = Instant::now() + READY_TIMEOUT;
+    loop {
+        let info: String = redis::cmd("CLUSTER").arg("INFO").query(&mut conn)?;
+        if info.contains("cluster_state:ok") {
+            return Ok(());
+        }
+        if Instant::now() > deadline {
+            bail!("cluster did not become ready");
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
+}
+
+async fn wait_for(ready: impl Fn() -> bool) -> Result<()> {
+    let deadline = Instant::now() + READY_TIMEOUT;
+    while !ready() {
+        if Instant::now() > deadline {
+            bail!("actor did not connect");
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use super::*;
+    use crate::{actors::base::testkit::runtime, insert, query};
+
+    #[test]
+    fn host_names_resolve_to_an_ip() {
+        let ip = announced_ip("localhost", REDIS_PORT).unwrap();
+        assert!(ip.is_loopback());
+    }
+
+    #[test]
+    #[ignore = "needs a docker daemon"]
+    fn actor_round_trips_through_the_container() {
+        let _harness = runtime().block_on(RedisHarness::start()).unwrap();
+        let _runtime = runtime().enter();
+        insert("hello".to_owned(), "hi");
+
+        assert_eq!(Bytes::from("hi"), query("hello".to_owned()));
+    }
+}
@@ -1,30 +1,356 @@
-use actors::base::Actor;
-use aggregates::redis::{Redis, RedisInsert, RedisQuery};
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use actors::base::{
+    liveness::{LivenessQuery, LivenessReport},
+    tree::{self, SupervisorReport},
+    Actor, TypedDistributor,
+};
+use aggregates::redis::{
+    bigkeys::{BigKeyReport, RedisBigKeyScan},
+    coalesce::Coalescer,
+    command::validate_urls,
+    control::{self, RedisControl},
+    delete::{RedisDeleteByPattern, RedisDeleteMany},
+    error::{RedisError, RedisInitError},
+    export::{write_records, ExportError, ExportSink, ExportStream, RedisExport},
+    failover,
+    health::{RedisHealth, RedisHealthQuery},
+    hotkeys::HotKeySampler,
+    import::{read_records, ConflictPolicy, ImportError, ImportProgress},
+    keyspace::Keyspace,
+    multi::{RedisExists, RedisMultiQuery},
+    node::{NodeReply, NodeTarget, RedisNodeCommand},
+    object::{self, RedisHashGet, RedisHashGetAll, RedisHashSet, RedisObject},
+    pool::{PoolStatsReport, RedisPoolStats},
+    ratelimit::{Gcra, RateLimitDecision, RedisRateLimit},
+    router::RedisRouter,
+    stream::{ChunkSink, ChunkStream, RedisStreamQuery},
+    tags::{RedisInvalidateTag, RedisTaggedInsert},
+    view::RedisStatus,
+    warmup::{RedisWarmup, Warmup, WarmupReport},
+    Redis, RedisExpire, RedisInsert, RedisQuery, RedisSlidingQuery, RedisState, RedisStatusQuery,
+    RedisTtlQuery, Ttl,
+};
 use bastion::{
     prelude::{Distributor, Message, SendError},
     run,
 };
-use log::{error, info};
+use bytes::Bytes;
+use serde::{de::DeserializeOwned, Serialize};
+use tracing::{error, info};
 
 pub mod actors;
 pub mod aggregates;
+/// Graceful drain on termination signals
+#[cfg(feature = "graceful-drain")]
+pub mod drain;
+/// Redis container harness for integration tests
+#[cfg(feature = "test-harness")]
+pub mod harness;
+/// Redis access as a `tower::Service`
+#[cfg(feature = "tower")]
+pub mod service;
+/// Redis actor as a plain tokio task, without Bastion
+#[cfg(feature = "tokio-actor")]
+pub mod task;
+
+/// Reads of `try_query` in flight, shared when `Redis::coalesce_queries` is set
+static QUERIES: Coalescer<Result<Option<Bytes>, RedisError>> = Coalescer::new();
+
+/// Keyspaces registered with `register_keyspace`, by name
+static KEYSPACES: Mutex<BTreeMap<String, Keyspace>> = Mutex::new(BTreeMap::new());
 
-pub fn init_redis(urls: Vec<String>) -> Actor<Redis> {
+/// How often `wait_ready` asks the actor for its state
+const READY_POLL: Duration = Duration::from_millis(50);
+
+/// The actor started by `init_redis_with` with its configuration, every message goes to the
+/// single `"redis_actor"` distributor
+static RUNNING: Mutex<Option<(Redis, Actor<Redis>)>> = Mutex::new(None);
+
+/// Set while the running actor drains, public calls are refused until it is started again
+static DRAINING: AtomicBool = AtomicBool::new(false);
+/// Public calls accepted and not finished yet
+static IN_FLIGHT: AtomicUsize = AtomicUsize::new(0);
+
+/// Public call in progress, counted by `IN_FLIGHT` until dropped
+pub(crate) struct Call(());
+
+impl Drop for Call {
+    fn drop(&mut self) {
+        IN_FLIGHT.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+// Counted before checking, so a drain waiting for `IN_FLIGHT` sees every call it let through
+pub(crate) fn accept() -> Result<Call, RedisError> {
+    IN_FLIGHT.fetch_add(1, Ordering::SeqCst);
+    let call = Call(());
+    if DRAINING.load(Ordering::SeqCst) {
+        return Err(RedisError::Draining);
+    }
+    Ok(call)
+}
+
+/// Start the actor on the cluster at `urls`, which are checked first
+pub fn init_redis(urls: Vec<String>) -> Result<Actor<Redis>, RedisInitError> {
     let __redis_aggr = Redis {
         urls,
         ..Default::default()
     };
 
-    let _redis_actor = Actor::<Redis>::builder()
-        .with_state_inner(__redis_aggr)
+    init_redis_with(__redis_aggr)
+}
+
+/// Start the actor with a full configuration, adding a read group when `readers` is set.
+///
+/// Returns once the actor is started, it keeps connecting in the background. Starting it again
+/// with the same configuration returns the running actor, `shutdown_redis` stops it first
+/// otherwise.
+pub fn init_redis_with(redis: Redis) -> Result<Actor<Redis>, RedisInitError> {
+    let mut running = RUNNING.lock().unwrap();
+    if let Some((config, actor)) = running.as_ref() {
+        if *config != redis {
+            return Err(RedisInitError::AlreadyRunning);
+        }
+        return Ok(actor.clone());
+    }
+    validate_urls(&redis.urls)?;
+    failover::validate(&redis)?;
+
+    let actor = start(redis.clone(), None)?;
+    *running = Some((redis, actor.clone()));
+    DRAINING.store(false, Ordering::SeqCst);
+    Ok(actor)
+}
+
+// Run an actor, named `name` when given, with a read group when `readers` is set
+fn start(mut redis: Redis, name: Option<&str>) -> Result<Actor<Redis>, RedisInitError> {
+    if let Some(config) = &redis.hot_keys {
+        // Started before the readers are cloned so they count into the writer's sampler
+        redis.hot_key_sampler = HotKeySampler::new(config);
+    }
+    let mut builder = Actor::<Redis>::builder();
+    if let Some(name) = name {
+        builder = builder.with_name(name);
+    }
+    if redis.readers > 0 {
+        let reader = redis.clone();
+        builder = builder.with_readers(redis.readers, move || reader.clone());
+    }
+    if let Some(tick) = redis.heartbeat_tick {
+        builder = builder.with_heartbeat_tick(tick);
+    }
+
+    builder
+        .with_state_inner(redis)
         .run()
-        .unwrap();
+        .map_err(|e| RedisInitError::Spawn(e.to_string()))
+}
+
+/// Start one actor per cluster of `router`, named after it, and the router in front of them.
+///
+/// Routed inserts, queries and deletes are sent to `RedisRouter::distributor()`.
+pub fn init_router(router: RedisRouter) -> Result<Actor<RedisRouter>, RedisInitError> {
+    router.validate()?;
+    for (name, redis) in &router.clusters {
+        start(redis.clone(), Some(name))?;
+    }
+    Actor::builder()
+        .with_state_inner(router)
+        .run()
+        .map_err(|e| RedisInitError::Spawn(e.to_string()))
+}
+
+/// Start the warm-up actor, it preloads its keys once the Redis actor is initialized when
+/// `on_start` is set
+pub fn init_warmup(warmup: Warmup) -> Result<Actor<Warmup>, RedisInitError> {
+    Actor::builder()
+        .with_state_inner(warmup)
+        .run()
+        .map_err(|e| RedisInitError::Spawn(e.to_string()))
+}
+
+/// Start the `MONITOR` capture actor, captures are then asked with `capture`
+#[cfg(feature = "monitor")]
+pub fn init_monitor(
+    monitor: aggregates::redis::monitor::Monitor,
+) -> Result<Actor<aggregates::redis::monitor::Monitor>, RedisInitError> {
+    Actor::builder()
+        .with_state_inner(monitor)
+        .run()
+        .map_err(|e| RedisInitError::Spawn(e.to_string()))
+}
+
+/// Stream the commands the node picked by `target` receives during `duration` when one of
+/// their arguments matches `pattern`, through the actor started by `init_monitor`
+#[cfg(feature = "monitor")]
+pub fn capture(
+    target: NodeTarget,
+    pattern: impl Into<String>,
+    duration: Duration,
+) -> aggregates::redis::monitor::MonitorStream {
+    use aggregates::redis::monitor::{Monitor, MonitorSink, RedisMonitor};
+
+    let (sink, stream) = MonitorSink::channel();
+    let message = RedisMonitor {
+        target,
+        pattern: pattern.into(),
+        duration,
+        limit: None,
+        sink,
+    };
+    if let Err(e) = Monitor::distributor().tell_one(message) {
+        error!("capture error: {:?}", e);
+    }
+    stream
+}
+
+/// Preload the keys of the warm-up actor now and check they are cached
+pub fn warm_up() -> Result<WarmupReport, RedisError> {
+    let _call = accept()?;
+    let warmup =
+        TypedDistributor::<_, Result<WarmupReport, RedisError>>::new(Warmup::distributor());
+    run!(warmup.request(RedisWarmup)).unwrap_or_else(|e| {
+        error!("warm-up error: {:?}", e);
+        Err(RedisError::NotReady)
+    })
+}
+
+/// Supervisor, children groups and child status, restarts and heartbeat recency of every actor
+/// still held, e.g. the one started by `init_redis`
+pub fn supervision_tree() -> Vec<SupervisorReport> {
+    tree::report_all()
+}
+
+/// Stop the running actor and wait up to `timeout` until its children are gone, so it can be
+/// started again. Nothing happens when it is not running.
+pub fn shutdown_redis(timeout: Duration) -> Result<(), RedisInitError> {
+    let Some((_, actor)) = RUNNING.lock().unwrap().take() else {
+        return Ok(());
+    };
+    actor
+        .stop()
+        .map_err(|e| RedisInitError::Stop(e.to_string()))?;
+
+    let deadline = Instant::now() + timeout;
+    while has_recipients(Distributor::named("redis_actor"))
+        || has_recipients(Redis::reader_distributor())
+    {
+        if Instant::now() >= deadline {
+            return Err(RedisInitError::Stop(format!(
+                "children still running after {timeout:?}"
+            )));
+        }
+        thread::sleep(READY_POLL);
+    }
+    Ok(())
+}
+
+// Whether a child still answers on `distributor`
+fn has_recipients(distributor: Distributor) -> bool {
+    let status = TypedDistributor::<_, RedisStatus>::new(distributor);
+    let reply = run!(status.request(RedisStatusQuery));
+    !matches!(
+        reply,
+        Err(SendError::EmptyRecipient | SendError::NoDistributor(_))
+    )
+}
+
+/// Start the actor like `init_redis_with` and wait up to `timeout` until it is `Initialized`
+pub fn init_redis_and_wait(
+    redis: Redis,
+    timeout: Duration,
+) -> Result<Actor<Redis>, RedisInitError> {
+    let actor = init_redis_with(redis)?;
+    wait_ready(timeout)?;
+    Ok(actor)
+}
+
+/// Wait up to `timeout` until the actor reports `Initialized`
+pub fn wait_ready(timeout: Duration) -> Result<(), RedisInitError> {
+    wait_status(timeout, |_| true)
+}
+
+/// Move the running actor to `urls`, e.g. the same nodes with rotated credentials or the
+/// primary cluster after a failover, and wait up to `timeout` until it is `Initialized` on
+/// them. The urls are checked first.
+pub fn reconnect(urls: Vec<String>, timeout: Duration) -> Result<(), RedisInitError> {
+    validate_urls(&urls)?;
+    send_control(RedisControl::Reconnect { urls: urls.clone() })?;
+    wait_status(timeout, |status| status.urls == urls)
+}
 
-    _redis_actor
+// Wait up to `timeout` until the actor reports `Initialized` with a status `done` accepts
+fn wait_status(
+    timeout: Duration,
+    done: impl Fn(&RedisStatus) -> bool,
+) -> Result<(), RedisInitError> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        let reply = run!(Redis::typed::<_, RedisStatus>(None).request(RedisStatusQuery));
+        // Not started yet when unreachable
+        let last_error = match reply {
+            Ok(status) if status.state == RedisState::Initialized && done(&status) => return Ok(()),
+            Ok(status) => status.last_error,
+            Err(_) => None,
+        };
+        if Instant::now() >= deadline {
+            return Err(RedisInitError::Timeout {
+                waited: timeout,
+                last_error,
+            });
+        }
+        thread::sleep(READY_POLL);
+    }
+}
+
+/// Serve the diagnostics endpoint of the Redis actor on `addr`, e.g. `127.0.0.1:9187`
+#[cfg(feature = "admin-http")]
+pub fn init_admin(
+    addr: impl Into<String>,
+) -> Result<Actor<aggregates::redis::admin::AdminHttp>, RedisInitError> {
+    Actor::builder()
+        .with_state_inner(aggregates::redis::admin::AdminHttp { addr: addr.into() })
+        .run()
+        .map_err(|e| RedisInitError::Spawn(e.to_string()))
 }
 
-pub fn insert(key: String, value: Vec<u8>) {
-    match Distributor::named("redis_actor").tell_one(RedisInsert { key, value }) {
+pub fn insert(key: String, value: impl Into<Bytes>) {
+    tell_insert(RedisInsert {
+        key,
+        value: value.into(),
+        ttl: None,
+        caller: None,
+    })
+}
+
+/// Store a value expiring after `ttl`, the value and its TTL are set by one command
+pub fn insert_with_ttl(key: String, value: impl Into<Bytes>, ttl: Duration) {
+    tell_insert(RedisInsert {
+        key,
+        value: value.into(),
+        ttl: Some(ttl),
+        caller: None,
+    })
+}
+
+fn tell_insert(message: RedisInsert) {
+    #[cfg(feature = "otel")]
+    let message = aggregates::redis::otel::Traced::new(message);
+
+    let _call = match accept() {
+        Ok(call) => call,
+        Err(e) => return error!("insert error: {e}"),
+    };
+    match Redis::typed::<_, Result<(), RedisError>>(None).tell_one(message) {
         Ok(_) => {
             info!("insert ok");
         }
@@ -34,32 +360,1729 @@ pub fn insert(key: String, value: Vec<u8>) {
     };
 }
 
-pub fn query(key: String) -> Vec<u8> {
-    let reply: Result<Vec<u8>, SendError> = run!(async {
-        Distributor::named("redis_actor")
-            .request(RedisQuery { key })
-            .await
-            .expect("couldn't receive reply")
-    });
-    reply.unwrap()
+/// Store a value and wait until it is written, transient failures are retried by the actor
+pub fn try_insert(key: String, value: impl Into<Bytes>) -> Result<(), RedisError> {
+    ask_insert(RedisInsert {
+        key,
+        value: value.into(),
+        ttl: None,
+        caller: None,
+    })
+}
+
+/// Store a value expiring after `ttl` and wait until it is written
+pub fn try_insert_with_ttl(
+    key: String,
+    value: impl Into<Bytes>,
+    ttl: Duration,
+) -> Result<(), RedisError> {
+    ask_insert(RedisInsert {
+        key,
+        value: value.into(),
+        ttl: Some(ttl),
+        caller: None,
+    })
+}
+
+fn ask_insert(message: RedisInsert) -> Result<(), RedisError> {
+    let _call = accept()?;
+    #[cfg(feature = "otel")]
+    let message = aggregates::redis::otel::Traced::new(message);
+
+    let writer = Redis::typed::<_, Result<(), RedisError>>(None);
+    run!(writer.request(message)).unwrap_or_else(|e| {
+        error!("insert error: {:?}", e);
+        Err(RedisError::NotReady)
+    })
+}
+
+/// Read a value, a missing key or a failure reads as an empty value
+pub fn query(key: String) -> Bytes {
+    match try_query(key) {
+        Ok(value) => value.unwrap_or_default(),
+        Err(e) => {
+            error!("query error: {e}");
+            Bytes::new()
+        }
+    }
+}
+
+/// Read a value, `None` when the key is missing and `RedisError::NotReady` while the actor is
+/// not connected or cannot be reached
+pub fn try_query(key: String) -> Result<Option<Bytes>, RedisError> {
+    let _call = accept()?;
+    let coalesce = RUNNING
+        .lock()
+        .unwrap()
+        .as_ref()
+        .is_some_and(|(redis, _)| redis.coalesce_queries);
+    if coalesce {
+        return QUERIES.run(&key, || read(&key));
+    }
+    read(&key)
+}
+
+// Send one query for `key`
+fn read(key: &str) -> Result<Option<Bytes>, RedisError> {
+    let message = RedisQuery {
+        key: key.to_owned(),
+    };
+    #[cfg(feature = "otel")]
+    let message = aggregates::redis::otel::Traced::new(message);
+
+    match run!(request_read::<_, Result<Option<Vec<u8>>, RedisError>>(
+        message
+    )) {
+        Ok(value) => value.map(|value| value.map(Bytes::from)),
+        Err(e) => {
+            error!("query error: {:?}", e);
+            Err(RedisError::NotReady)
+        }
+    }
+}
+
+/// Read a value and expire it `ttl` from now when it exists, so it stays alive while it keeps
+/// being read. Served by the writer, the TTL of a read group replica cannot be changed.
+pub fn try_query_sliding(key: String, ttl: Duration) -> Result<Option<Bytes>, RedisError> {
+    let _call = accept()?;
+    let writer = Redis::typed::<_, Result<Option<Vec<u8>>, RedisError>>(None);
+    match run!(writer.request(RedisSlidingQuery { key, ttl })) {
+        Ok(value) => value.map(|value| value.map(Bytes::from)),
+        Err(e) => {
+            error!("query error: {:?}", e);
+            Err(RedisError::NotReady)
+        }
+    }
+}
+
+/// Fetch several keys with one multi-get, missing keys map to `None`
+pub fn query_many(keys: Vec<String>) -> Result<HashMap<String, Option<Vec<u8>>>, RedisError> {
+    let _call = accept()?;
+    let message = RedisMultiQuery { keys: keys.clone() };
+
+    let reply = run!(request_read::<_, Result<Vec<Option<Bytes>>, RedisError>>(
+        message
+    ));
+    let values = reply.unwrap_or_else(|e| {
+        error!("query error: {:?}", e);
+        Err(RedisError::NotReady)
+    })?;
+    Ok(keys
+        .into_iter()
+        .zip(values)
+        .map(|(key, value)| (key, value.map(Vec::from)))
+        .collect())
+}
+
+/// Count a request of `cost` against the GCRA limit on `key`, refused requests are not counted
+pub fn rate_limit(key: String, gcra: Gcra, cost: u32) -> Result<RateLimitDecision, RedisError> {
+    let _call = accept()?;
+    let writer = Redis::typed::<_, Result<RateLimitDecision, RedisError>>(None);
+    run!(writer.request(RedisRateLimit { key, gcra, cost })).unwrap_or_else(|e| {
+        error!("rate limit error: {:?}", e);
+        Err(RedisError::NotReady)
+    })
+}
+
+/// Remaining lifetime of `key`
+pub fn ttl(key: String) -> Result<Ttl, RedisError> {
+    let _call = accept()?;
+    let reply = run!(request_read::<_, Result<Ttl, RedisError>>(RedisTtlQuery {
+        key
+    }));
+    reply.unwrap_or_else(|e| {
+        error!("ttl error: {:?}", e);
+        Err(RedisError::NotReady)
+    })
+}
+
+/// Whether `key` exists, without fetching its value
+pub fn exists(key: String) -> Result<bool, RedisError> {
+    Ok(exists_many(vec![key.clone()])?
+        .remove(&key)
+        .unwrap_or_default())
+}
+
+/// Whether each of `keys` exists, tested with one message
+pub fn exists_many(keys: Vec<String>) -> Result<HashMap<String, bool>, RedisError> {
+    let _call = accept()?;
+    let message = RedisExists { keys: keys.clone() };
+
+    let reply = run!(request_read::<_, Result<Vec<bool>, RedisError>>(message));
+    let exists = reply.unwrap_or_else(|e| {
+        error!("exists error: {:?}", e);
+        Err(RedisError::NotReady)
+    })?;
+    Ok(keys.into_iter().zip(exists).collect())
+}
+
+// Ask the read group when there is one, the writer otherwise
+pub(crate) async fn request_read<M: Message + Clone, R: Message>(
+    message: M,
+) -> Result<R, SendError> {
+    request_read_from(None, message).await
+}
+
+// Ask the read group of the instance built with `instance`, its writer when it has no readers
+async fn request_read_from<M: Message + Clone, R: Message>(
+    instance: Option<&str>,
+    message: M,
+) -> Result<R, SendError> {
+    let readers = Redis::typed_reader::<M, R>(instance);
+    match readers.request(message.clone()).await {
+        Err(SendError::EmptyRecipient | SendError::NoDistributor(_)) => {
+            Redis::typed::<M, R>(instance).request(message).await
+        }
+        reply => reply,
+    }
+}
+
+/// Set the TTL of an existing key without rewriting its value, false when the key does not
+/// exist
+pub fn expire(key: String, ttl: Duration) -> Result<bool, RedisError> {
+    let _call = accept()?;
+    let message = RedisExpire {
+        key,
+        ttl,
+        caller: None,
+    };
+    let writer = Redis::typed::<_, Result<bool, RedisError>>(None);
+    run!(writer.request(message)).unwrap_or_else(|e| {
+        error!("expire error: {:?}", e);
+        Err(RedisError::NotReady)
+    })
+}
+
+/// Delete every key matching a glob-style `pattern` with `SCAN` and batched `UNLINK`, returning
+/// how many keys were deleted
+pub fn delete_by_pattern(pattern: impl Into<String>) -> Result<u64, RedisError> {
+    let _call = accept()?;
+    let message = RedisDeleteByPattern {
+        pattern: pattern.into(),
+        ..Default::default()
+    };
+    let writer = Redis::typed::<_, Result<u64, RedisError>>(None);
+    run!(writer.request(message)).unwrap_or_else(|e| {
+        error!("delete by pattern error: {:?}", e);
+        Err(RedisError::NotReady)
+    })
+}
+
+/// Scan keys matching `scan.pattern` and return those above its thresholds, biggest first
+pub fn find_big_keys(scan: RedisBigKeyScan) -> Result<BigKeyReport, RedisError> {
+    let _call = accept()?;
+    let reply = run!(request_read::<_, Result<BigKeyReport, RedisError>>(scan));
+    reply.unwrap_or_else(|e| {
+        error!("big key scan error: {:?}", e);
+        Err(RedisError::NotReady)
+    })
+}
+
+/// Send `args`, a command name and its arguments, to the single node picked by `target`, e.g.
+/// `CONFIG GET` on one master
+pub fn node_command(target: NodeTarget, args: Vec<String>) -> Result<NodeReply, RedisError> {
+    let _call = accept()?;
+    let message = RedisNodeCommand { target, args };
+    let writer = Redis::typed::<_, Result<NodeReply, RedisError>>(None);
+    run!(writer.request(message)).unwrap_or_else(|e| {
+        error!("node command error: {:?}", e);
+        Err(RedisError::NotReady)
+    })
+}
+
+/// Delete `keys` with one message, returning how many of them existed
+pub fn delete_many(keys: Vec<String>) -> Result<u64, RedisError> {
+    let _call = accept()?;
+    let message = RedisDeleteMany { keys, caller: None };
+    let writer = Redis::typed::<_, Result<u64, RedisError>>(None);
+    run!(writer.request(message)).unwrap_or_else(|e| {
+        error!("delete many error: {:?}", e);
+        Err(RedisError::NotReady)
+    })
+}
+
+/// Stream the keys matching `pattern` as export records
+pub fn export(pattern: impl Into<String>) -> ExportStream {
+    let (sink, stream) = ExportSink::channel();
+    let message = RedisExport {
+        pattern: pattern.into(),
+        batch: None,
+        rate_limit: None,
+        sink,
+    };
+    let _call = match accept() {
+        Ok(call) => call,
+        Err(e) => {
+            message.sink.fail(e);
+            return stream;
+        }
+    };
+    if let Err(e) = Redis::typed::<_, ()>(None).tell_one(message) {
+        error!("export error: {:?}", e);
+    }
+    stream
+}
+
+/// Write the keys matching `pattern` to `writer` in the JSON Lines export format, e.g. a
+/// `tokio::fs::File`, returning how many records were written
+pub async fn export_to(
+    pattern: impl Into<String>,
+    writer: impl tokio::io::AsyncWrite + Unpin,
+) -> Result<u64, ExportError> {
+    write_records(export(pattern), writer).await
+}
+
+/// Read an export written by `export_to` back into Redis, `progress` gets the running totals
+/// after every batch
+pub async fn import_from(
+    reader: impl tokio::io::AsyncBufRead + Unpin,
+    policy: ConflictPolicy,
+    progress: impl FnMut(ImportProgress),
+) -> Result<ImportProgress, ImportError> {
+    read_records(reader, policy, progress).await
+}
+
+/// Store a value and add its key to every tag of `tags`, waiting until it is written
+pub fn insert_tagged(
+    key: String,
+    value: impl Into<Bytes>,
+    ttl: impl Into<Option<Duration>>,
+    tags: Vec<String>,
+) -> Result<(), RedisError> {
+    let _call = accept()?;
+    let message = RedisTaggedInsert {
+        insert: RedisInsert {
+            key,
+            value: value.into(),
+            ttl: ttl.into(),
+            caller: None,
+        },
+        tags,
+    };
+    let writer = Redis::typed::<_, Result<(), RedisError>>(None);
+    run!(writer.request(message)).unwrap_or_else(|e| {
+        error!("insert error: {:?}", e);
+        Err(RedisError::NotReady)
+    })
+}
+
+/// Delete every key inserted with `tag`, returning how many of them existed
+pub fn invalidate_tag(tag: &str) -> Result<u64, RedisError> {
+    let _call = accept()?;
+    let message = RedisInvalidateTag {
+        tag: tag.to_owned(),
+        caller: None,
+    };
+    let writer = Redis::typed::<_, Result<u64, RedisError>>(None);
+    run!(writer.request(message)).unwrap_or_else(|e| {
+        error!("invalidate error: {:?}", e);
+        Err(RedisError::NotReady)
+    })
+}
+
+/// Store `value` serialized as JSON
+pub fn insert_json<T: Serialize>(key: String, value: &T) -> Result<(), serde_json::Error> {
+    insert(key, serde_json::to_vec(value)?);
+    Ok(())
+}
+
+/// Read a value stored as JSON
+pub fn query_json<T: DeserializeOwned>(key: String) -> Result<T, serde_json::Error> {
+    serde_json::from_slice(&query(key))
+}
+
+/// Store `object` as a hash, one field per struct field, and wait until it is written. Fields
+/// already in the hash and not in the object are kept.
+pub fn insert_object<T: RedisObject>(key: String, object: &T) -> Result<(), RedisError> {
+    set_fields(key, object.to_fields()?)
+}
+
+/// Update only the fields present in `fields`, a struct or map holding part of an object's
+/// fields, e.g. `update_object("session:1".to_owned(), &json!({ "visits": 4 }))`
+pub fn update_object<F: Serialize + ?Sized>(key: String, fields: &F) -> Result<(), RedisError> {
+    set_fields(key, object::fields(fields)?)
+}
+
+// HSET of `fields` on the writer
+fn set_fields(key: String, fields: Vec<(String, Bytes)>) -> Result<(), RedisError> {
+    let _call = accept()?;
+    // HSET needs at least one field
+    if fields.is_empty() {
+        return Ok(());
+    }
+    let writer = Redis::typed::<_, Result<u64, RedisError>>(None);
+    match run!(writer.request(RedisHashSet { key, fields })) {
+        Ok(created) => created.map(|_| ()),
+        Err(e) => {
+            error!("insert error: {:?}", e);
+            Err(RedisError::NotReady)
+        }
+    }
+}
+
+/// Read an object stored with `insert_object`, `None` when the key is missing
+pub fn query_object<T: RedisObject>(key: String) -> Result<Option<T>, RedisError> {
+    let _call = accept()?;
+    match run!(
+        request_read::<_, Result<Vec<(String, Vec<u8>)>, RedisError>>(RedisHashGetAll { key })
+    ) {
+        Ok(Ok(fields)) if fields.is_empty() => Ok(None),
+        Ok(fields) => T::from_fields(fields?).map(Some),
+        Err(e) => {
+            error!("query error: {:?}", e);
+            Err(RedisError::NotReady)
+        }
+    }
+}
+
+/// Read one field of an object, `None` when the field or the key is missing
+pub fn query_object_field<V: DeserializeOwned>(
+    key: String,
+    field: &str,
+) -> Result<Option<V>, RedisError> {
+    let _call = accept()?;
+    let message = RedisHashGet {
+        key,
+        fields: vec![field.to_owned()],
+    };
+    match run!(request_read::<_, Result<Vec<Option<Vec<u8>>>, RedisError>>(
+        message
+    )) {
+        Ok(values) => values?
+            .pop()
+            .flatten()
+            .map(|value| object::decode(&value))
+            .transpose(),
+        Err(e) => {
+            error!("query error: {:?}", e);
+            Err(RedisError::NotReady)
+        }
+    }
+}
+
+/// Store `value` serialized as MessagePack, structs are encoded as maps keyed by field name
+#[cfg(feature = "messagepack")]
+pub fn insert_msgpack<T: Serialize>(
+    key: String,
+    value: &T,
+) -> Result<(), rmp_serde::encode::Error> {
+    insert(key, rmp_serde::to_vec_named(value)?);
+    Ok(())
+}
+
+/// Read a value stored as MessagePack
+#[cfg(feature = "messagepack")]
+pub fn query_msgpack<T: DeserializeOwned>(key: String) -> Result<T, rmp_serde::decode::Error> {
+    rmp_serde::from_slice(&query(key))
+}
+
+/// Store a protobuf message in its wire encoding
+#[cfg(feature = "prost")]
+pub fn insert_proto<T: prost::Message>(key: String, value: &T) {
+    insert(key, value.encode_to_vec());
+}
+
+/// Read a value stored as a protobuf message
+#[cfg(feature = "prost")]
+pub fn query_proto<T: prost::Message + Default>(key: String) -> Result<T, prost::DecodeError> {
+    T::decode(query(key))
+}
+
+/// Register `keyspace` under `name`, replacing any keyspace of that name
+pub fn register_keyspace(name: impl Into<String>, keyspace: Keyspace) {
+    KEYSPACES.lock().unwrap().insert(name.into(), keyspace);
+}
+
+// Keyspace registered as `name`
+fn keyspace(name: &str) -> Result<Keyspace, RedisError> {
+    KEYSPACES
+        .lock()
+        .unwrap()
+        .get(name)
+        .cloned()
+        .ok_or_else(|| RedisError::UnknownKeyspace(name.to_owned()))
+}
+
+/// Store `value` under `key` in the keyspace registered as `keyspace` and wait until it is
+/// written, encoded with its codec and expiring after its TTL
+pub fn insert_in<T: Serialize>(keyspace: &str, key: &str, value: &T) -> Result<(), RedisError> {
+    let _call = accept()?;
+    let keyspace = self::keyspace(keyspace)?;
+    let message = keyspace.insert(key, value)?;
+    let writer = Redis::typed::<_, Result<(), RedisError>>(keyspace.instance.as_deref());
+    run!(writer.request(message)).unwrap_or_else(|e| {
+        error!("insert error: {:?}", e);
+        Err(RedisError::NotReady)
+    })
+}
+
+/// Read `key` in the keyspace registered as `keyspace`, `None` when it is missing
+pub fn query_in<T: DeserializeOwned>(keyspace: &str, key: &str) -> Result<Option<T>, RedisError> {
+    let _call = accept()?;
+    let keyspace = self::keyspace(keyspace)?;
+    let instance = keyspace.instance.as_deref();
+    let reply = match keyspace.sliding_query(key) {
+        Some(message) => {
+            let writer = Redis::typed::<_, Result<Option<Vec<u8>>, RedisError>>(instance);
+            run!(writer.request(message))
+        }
+        None => run!(request_read_from::<_, Result<Option<Vec<u8>>, RedisError>>(
+            instance,
+            keyspace.query(key)
+        )),
+    };
+    match reply {
+        Ok(value) => value?
+            .map(|value| keyspace.codec.decode(&value))
+            .transpose(),
+        Err(e) => {
+            error!("query error: {:?}", e);
+            Err(RedisError::NotReady)
+        }
+    }
+}
+
+/// Delete `keys` of the keyspace registered as `keyspace`, returning how many of them existed
+pub fn delete_in(keyspace: &str, keys: &[&str]) -> Result<u64, RedisError> {
+    let _call = accept()?;
+    let keyspace = self::keyspace(keyspace)?;
+    let message = RedisDeleteMany {
+        keys: keys.iter().map(|key| keyspace.key(key)).collect(),
+        caller: None,
+    };
+    let writer = Redis::typed::<_, Result<u64, RedisError>>(keyspace.instance.as_deref());
+    run!(writer.request(message)).unwrap_or_else(|e| {
+        error!("delete error: {:?}", e);
+        Err(RedisError::NotReady)
+    })
+}
+
+/// Stream a large value in chunks of `chunk_size` bytes instead of one reply
+pub fn query_stream(key: String, chunk_size: usize) -> ChunkStream {
+    let (sink, stream) = ChunkSink::channel();
+    let message = RedisStreamQuery {
+        key,
+        chunk_size,
+        sink,
+    };
+    let _call = match accept() {
+        Ok(call) => call,
+        Err(e) => {
+            message.sink.fail(e);
+            return stream;
+        }
+    };
+
+    // Readers stream when there is a read group, the writer otherwise
+    let sent = match Redis::typed_reader::<_, ()>(None).tell_one(message.clone()) {
+        Err(SendError::EmptyRecipient | SendError::NoDistributor(_)) => {
+            Redis::typed::<_, ()>(None).tell_one(message)
+        }
+        sent => sent,
+    };
+    if let Err(e) = sent {
+        error!("query stream error: {:?}", e);
+    }
+    stream
+}
+
+/// Status of the running actor, `RedisState::Uninitialized` when it cannot be asked, e.g. it is
+/// not running
+pub fn status() -> RedisStatus {
+    run!(Redis::typed::<_, RedisStatus>(None).request(RedisStatusQuery)).unwrap_or_else(|e| {
+        error!("status error: {:?}", e);
+        RedisStatus::default()
+    })
+}
+
+/// Pool report of the running actor, empty when it cannot be asked, e.g. it is not running
+pub fn pool_stats() -> PoolStatsReport {
+    run!(Redis::typed::<_, PoolStatsReport>(None).request(RedisPoolStats)).unwrap_or_else(|e| {
+        error!("pool stats error: {:?}", e);
+        PoolStatsReport::default()
+    })
+}
+
+/// Hand `control` to the actor ahead of every message already queued and wait until it is
+/// handled, e.g. to move to rotated credentials while it is flooded with data commands
+pub fn send_control(control: RedisControl) -> Result<(), RedisError> {
+    run!(control::send(None, control))
+}
+
+/// Liveness of every child of the running actor, `RedisError::NotReady` when it is not running
+/// or was started without `heartbeat_tick`. Answered by a monitor next to the children, so a
+/// child stuck in its handler is reported rather than waited for.
+pub fn liveness() -> Result<LivenessReport, RedisError> {
+    let monitor = RUNNING
+        .lock()
+        .unwrap()
+        .as_ref()
+        .and_then(|(_, actor)| actor.liveness())
+        .ok_or(RedisError::NotReady)?;
+    run!(monitor.request(LivenessQuery)).map_err(|_| RedisError::NotReady)
+}
+
+/// Readiness report for probes, an unreachable actor reports as not ready
+pub fn readiness() -> RedisHealth {
+    let reply = run!(Redis::typed::<_, RedisHealth>(None).request(RedisHealthQuery));
+    reply.unwrap_or_default()
+}
+
+/// Whether the actor is initialized, has pooled connections and answers PING
+pub fn is_healthy() -> bool {
+    readiness().is_healthy()
 }
 
 #[cfg(test)]
 mod tests {
-    use std::{thread::sleep, time::Duration};
+    use actors::base::testkit::runtime;
+    use aggregates::redis::backend::{Backend, MemoryBackend};
 
     use super::*;
 
-    #[tokio::test]
-    async fn it_works() {
-        init_redis(vec!["redis://127.0.0.1:30006".to_owned()]);
-        sleep(Duration::from_secs(5));
+    #[test]
+    fn invalid_urls_are_refused_before_starting() {
+        assert_eq!(
+            Some(RedisInitError::Config(RedisError::EmptyUrls)),
+            init_redis(vec![]).err()
+        );
+        assert!(matches!(
+            init_redis(vec!["not a url".to_owned()]),
+            Err(RedisInitError::Config(RedisError::MalformedUrl { .. }))
+        ));
+    }
+
+    #[test]
+    fn it_works_in_memory() {
+        use aggregates::redis::bigkeys::{BigKey, KeySize};
+
+        let _runtime = runtime().enter();
+        let redis = Redis {
+            urls: vec!["redis://127.0.0.1:30006".to_owned()],
+            backend: Backend::memory(MemoryBackend::default()),
+            coalesce_queries: true,
+            ..Default::default()
+        };
+        init_redis_and_wait(redis.clone(), Duration::from_secs(5)).unwrap();
+        insert("hello".to_owned(), "hi");
+
+        assert_eq!(Bytes::from("hi"), query("hello".to_owned()));
+        assert_eq!(Ok(None), try_query("missing".to_owned()));
+        assert_eq!(Ok(()), try_insert("confirmed".to_owned(), "yes"));
+        assert_eq!(
+            Ok(Some(Bytes::from("yes"))),
+            try_query("confirmed".to_owned())
+        );
+
+        let values = query_many(vec!["hello".to_owned(), "missing".to_owned()]).unwrap();
+        assert_eq!(Some(&Some(b"hi".to_vec())), values.get("hello"));
+        assert_eq!(Some(&None), values.get("missing"));
+
+        assert_eq!(Ok(true), exists("hello".to_owned()));
+        assert_eq!(
+            Ok(HashMap::from([
+                ("hello".to_owned(), true),
+                ("missing".to_owned(), false)
+            ])),
+            exists_many(vec!["hello".to_owned(), "missing".to_owned()])
+        );
+
+        insert("backup:1".to_owned(), "hi");
+        let mut exported = vec![];
+        assert_eq!(
+            1,
+            runtime()
+                .block_on(export_to("backup:*", &mut exported))
+                .unwrap()
+        );
+        assert_eq!(
+            "{\"key\":\"backup:1\",\"ttl_ms\":null,\"kind\":\"string\",\"value\":\"aGk=\"}\n",
+            String::from_utf8(exported.clone()).unwrap()
+        );
+
+        insert("backup:1".to_owned(), "changed");
+        let mut batches = 0;
+        let progress = runtime()
+            .block_on(import_from(&exported[..], ConflictPolicy::Replace, |_| {
+                batches += 1
+            }))
+            .unwrap();
+        assert_eq!((1, 0, 1), (progress.imported, progress.skipped, batches));
+        assert_eq!(Bytes::from("hi"), query("backup:1".to_owned()));
+
+        insert("stale:1".to_owned(), "a");
+        insert("stale:2".to_owned(), "b");
+        assert_eq!(Ok(2), delete_by_pattern("stale:*"));
+        assert_eq!(Bytes::new(), query("stale:1".to_owned()));
+
+        insert("blob:small".to_owned(), "a");
+        insert("blob:large".to_owned(), "a".repeat(64));
+        let big = find_big_keys(RedisBigKeyScan {
+            pattern: "blob:*".to_owned(),
+            max_bytes: 16,
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(2, big.scanned);
+        assert_eq!(
+            vec![BigKey {
+                key: "blob:large".to_owned(),
+                size: KeySize::Bytes(64)
+            }],
+            big.keys
+        );
+        assert!(matches!(
+            node_command(NodeTarget::Slot(0), vec!["DBSIZE".to_owned()]),
+            Err(RedisError::Command(_))
+        ));
+        assert_eq!(PoolStatsReport::default(), pool_stats());
+        let mut stream = query_stream("hello".to_owned(), 1);
+        assert!(matches!(
+            runtime().block_on(stream.recv()),
+            Some(Err(RedisError::Command(_)))
+        ));
+        insert("session".to_owned(), "a");
+        assert_eq!(Ok(Ttl::NoExpiry), ttl("session".to_owned()));
+        insert_with_ttl("token".to_owned(), "t", Duration::from_secs(60));
+        assert!(
+            matches!(ttl("token".to_owned()), Ok(Ttl::Expires(ttl)) if ttl > Duration::from_secs(59))
+        );
+        assert_eq!(Ok(Ttl::NoKey), ttl("missing".to_owned()));
+        assert_eq!(Ok(true), expire("session".to_owned(), Duration::ZERO));
+        assert_eq!(Bytes::new(), query("session".to_owned()));
+        assert_eq!(Ok(false), expire("session".to_owned(), Duration::ZERO));
+
+        let moved = vec!["redis://127.0.0.1:30008".to_owned()];
+        assert_eq!(Ok(()), reconnect(moved.clone(), Duration::from_secs(5)));
+        assert_eq!(moved, status().urls);
+        assert_eq!(
+            Err(RedisInitError::Config(RedisError::EmptyUrls)),
+            reconnect(vec![], Duration::from_secs(5))
+        );
+        assert_eq!(
+            Ok(()),
+            reconnect(redis.urls.clone(), Duration::from_secs(5))
+        );
+
+        insert("gone".to_owned(), "a");
+        assert_eq!(
+            Ok(1),
+            delete_many(vec!["gone".to_owned(), "never".to_owned()])
+        );
+
+        insert_json("numbers".to_owned(), &vec![1, 2, 3]).unwrap();
+        assert_eq!(
+            vec![1, 2, 3],
+            query_json::<Vec<u8>>("numbers".to_owned()).unwrap()
+        );
+
+        register_keyspace(
+            "sessions",
+            Keyspace {
+                prefix: "session:".to_owned(),
+                ttl: Some(Duration::from_secs(60)),
+                ..Default::default()
+            },
+        );
+        insert_in("sessions", "42", &("user", 7)).unwrap();
+        assert_eq!(
+            Ok(Some(("user".to_owned(), 7))),
+            query_in::<(String, u8)>("sessions", "42")
+        );
+        assert!(matches!(ttl("session:42".to_owned()), Ok(Ttl::Expires(_))));
+        register_keyspace(
+            "carts",
+            Keyspace {
+                prefix: "cart:".to_owned(),
+                ttl: Some(Duration::from_secs(60)),
+                sliding: true,
+                ..Default::default()
+            },
+        );
+        insert_in("carts", "7", &1).unwrap();
+        assert_eq!(
+            Ok(true),
+            expire("cart:7".to_owned(), Duration::from_secs(5))
+        );
+        assert_eq!(Ok(Some(1)), query_in::<u8>("carts", "7"));
+        assert!(
+            matches!(ttl("cart:7".to_owned()), Ok(Ttl::Expires(ttl)) if ttl > Duration::from_secs(59))
+        );
+        assert_eq!(
+            Ok(Some(Bytes::from("1"))),
+            try_query_sliding("cart:7".to_owned(), Duration::from_secs(5))
+        );
+        assert!(
+            matches!(ttl("cart:7".to_owned()), Ok(Ttl::Expires(ttl)) if ttl <= Duration::from_secs(5))
+        );
+        assert_eq!(Ok(1), delete_in("sessions", &["42"]));
+        assert_eq!(Ok(None), query_in::<(String, u8)>("sessions", "42"));
+        assert_eq!(
+            Err(RedisError::UnknownKeyspace("queues".to_owned())),
+            query_in::<String>("queues", "1")
+        );
+
+        let gcra = Gcra {
+            limit: 1,
+            period: Duration::from_secs(60),
+            burst: 2,
+        };
+        let limited = || rate_limit("limit:login".to_owned(), gcra, 1).unwrap();
+        assert!(limited().allowed);
+        assert!(limited().allowed);
+        let refused = limited();
+        assert!(!refused.allowed && refused.retry_after.is_some());
+
+        #[cfg(feature = "messagepack")]
+        {
+            insert_msgpack("packed".to_owned(), &("a", 1)).unwrap();
+            assert_eq!(
+                ("a".to_owned(), 1),
+                query_msgpack::<(String, u8)>("packed".to_owned()).unwrap()
+            );
+        }
+
+        #[cfg(feature = "prost")]
+        {
+            #[derive(Clone, PartialEq, prost::Message)]
+            struct Greeting {
+                #[prost(string, tag = "1")]
+                text: String,
+            }
+
+            let greeting = Greeting {
+                text: "hi".to_owned(),
+            };
+            insert_proto("proto".to_owned(), &greeting);
+            assert_eq!(greeting, query_proto("proto".to_owned()).unwrap());
+        }
+
+        #[cfg(feature = "admin-http")]
+        {
+            use std::{
+                io::{Read, Write},
+                net::TcpStream,
+            };
+
+            let _admin = init_admin("127.0.0.1:19187").unwrap();
+            thread::sleep(Duration::from_millis(200));
+            let mut stream = TcpStream::connect("127.0.0.1:19187").unwrap();
+            stream.write_all(b"GET /topology HTTP/1.1\r\n\r\n").unwrap();
+            let mut reply = String::new();
+            stream.read_to_string(&mut reply).unwrap();
+            assert!(reply.starts_with("HTTP/1.1 200 OK"));
+            assert!(reply.ends_with("\r\n\r\n[]"));
+        }
+
+        #[cfg(feature = "tower")]
+        {
+            use service::{RedisRequest, RedisResponse, RedisService};
+            use tower::Service;
+
+            let mut service = RedisService;
+            let set = RedisRequest::Set {
+                key: "served".to_owned(),
+                value: Bytes::from("yes"),
+            };
+            let get = RedisRequest::MultiGet {
+                keys: vec!["served".to_owned(), "missing".to_owned()],
+            };
+            runtime().block_on(async {
+                assert_eq!(RedisResponse::Done, service.call(set).await.unwrap());
+                assert_eq!(
+                    RedisResponse::Values(vec![Some(Bytes::from("yes")), None]),
+                    service.call(get).await.unwrap()
+                );
+            });
+        }
+
+        assert!(init_redis_with(redis.clone()).is_ok());
+        assert_eq!(
+            Some(RedisInitError::AlreadyRunning),
+            init_redis_with(Redis {
+                readers: 2,
+                ..redis.clone()
+            })
+            .err()
+        );
+        shutdown_redis(Duration::from_secs(5)).unwrap();
+        let redis = Redis {
+            backend: Backend::memory(MemoryBackend::default()),
+            ..redis
+        };
+        init_redis_and_wait(redis.clone(), Duration::from_secs(5)).unwrap();
+        assert_eq!(Ok(None), try_query("hello".to_owned()));
+
+        #[cfg(feature = "graceful-drain")]
+        {
+            use aggregates::redis::backend::RedisBackend;
+
+            // Queued before the drain, written before the actor stops
+            insert("queued".to_owned(), "1");
+            drain::drain(Duration::from_secs(5)).unwrap();
+            assert_eq!(
+                Err(RedisError::Draining),
+                try_insert("late".to_owned(), "1")
+            );
+            let mut backend = redis.backend.as_memory().unwrap().clone();
+            assert_eq!(Some(b"1".to_vec()), backend.get("queued").unwrap());
+            assert!(!has_recipients(Distributor::named("redis_actor")));
+        }
+    }
+
+    #[test]
+    fn named_instances_keep_to_their_own_distributors() {
+        let _runtime = runtime().enter();
+        let start = |name: &str| {
+            let redis = Redis {
+                urls: vec!["redis://127.0.0.1:30006".to_owned()],
+                backend: Backend::memory(MemoryBackend::default()),
+                pending_writes: Some(Default::default()),
+                ..Default::default()
+            };
+            let actor = Actor::builder()
+                .with_name(name)
+                .with_state_inner(redis)
+                .run()
+                .unwrap();
+            while !has_recipients(Redis::distributor_named(Some(name))) {
+                thread::sleep(READY_POLL);
+            }
+            actor
+        };
+        let ask = |name: &str, key: &str, value: Option<&str>| -> Option<Vec<u8>> {
+            let distributor = Redis::distributor_named(Some(name));
+            run!(async {
+                if let Some(value) = value {
+                    let insert = RedisInsert {
+                        key: key.to_owned(),
+                        value: Bytes::from(value.to_owned()),
+                        ttl: None,
+                        caller: None,
+                    };
+                    let written: Result<(), RedisError> =
+                        distributor.request(insert).await.unwrap().unwrap();
+                    written.unwrap();
+                }
+                let query = RedisQuery {
+                    key: key.to_owned(),
+                };
+                let value: Result<Option<Vec<u8>>, RedisError> =
+                    distributor.request(query).await.unwrap().unwrap();
+                value.unwrap()
+            })
+        };
+        let (_first, _second) = (start("first"), start("second"));
+
+        assert_eq!(Some(b"1".to_vec()), ask("first", "key", Some("1")));
+        assert_eq!(None, ask("second", "key", None));
+        assert_eq!(Some(b"2".to_vec()), ask("second", "key", Some("2")));
+        assert_eq!(Some(b"1".to_vec()), ask("first", "key", None));
+    }
+
+    #[test]
+    fn warm_up_fills_a_cold_cache() {
+        use std::sync::Arc;
+
+        use aggregates::redis::{
+            backend::RedisBackend,
+            warmup::{WarmupKeys, WarmupLoader},
+        };
+
+        struct Database;
+
+        impl WarmupLoader for Database {
+            fn load(&self, key: &str) -> anyhow::Result<Option<Bytes>> {
+                match key {
+                    "user:1" => Ok(Some(Bytes::from("ada"))),
+                    "user:2" => Ok(Some(Bytes::from("grace"))),
+                    "user:3" => anyhow::bail!("database down"),
+                    _ => Ok(None),
+                }
+            }
+        }
+
+        let _runtime = runtime().enter();
+        let backend = MemoryBackend::default();
+        let redis = Redis {
+            urls: vec!["redis://127.0.0.1:30006".to_owned()],
+            backend: Backend::memory(backend.clone()),
+            ..Default::default()
+        };
+        let _actor = start(redis, Some("warmup")).unwrap();
+        let mut cached = backend.clone();
+        cached.set("user:2", b"cached", None).unwrap();
+        cached
+            .set(
+                "warmup:manifest",
+                br#"["user:1","user:2","user:3","user:4"]"#,
+                None,
+            )
+            .unwrap();
+        let _warmup = init_warmup(Warmup {
+            keys: WarmupKeys::Manifest("warmup:manifest".to_owned()),
+            loader: Arc::new(Database),
+            on_start: false,
+            instance: Some("warmup".to_owned()),
+            ..Default::default()
+        })
+        .unwrap();
+        while !has_recipients(Redis::distributor_named(Some("warmup")))
+            || !has_recipients(Warmup::distributor())
+        {
+            thread::sleep(READY_POLL);
+        }
+
+        // Straight to the actor, the drain test refuses public calls
+        let warmup =
+            TypedDistributor::<_, Result<WarmupReport, RedisError>>::new(Warmup::distributor());
+        let report = run!(warmup.request(RedisWarmup)).unwrap().unwrap();
+        assert_eq!(
+            (4, 1, 1, 1),
+            (report.keys, report.present, report.loaded, report.empty)
+        );
+        assert_eq!(vec!["user:3".to_owned()], report.failed);
+        assert!(report.missing.is_empty());
+        assert_eq!(Some(b"ada".to_vec()), cached.get("user:1").unwrap());
+        assert_eq!(Some(b"cached".to_vec()), cached.get("user:2").unwrap());
+    }
+
+    #[test]
+    fn hot_keys_are_reported_every_window() {
+        use aggregates::redis::hotkeys::{HotKeyReport, HotKeysConfig, RedisHotKeysQuery};
+
+        let _runtime = runtime().enter();
+        let redis = Redis {
+            urls: vec!["redis://127.0.0.1:30009".to_owned()],
+            backend: Backend::memory(MemoryBackend::default()),
+            hot_keys: Some(HotKeysConfig {
+                window: Duration::from_millis(200),
+                top: 1,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let _actor = start(redis, Some("hotkeys")).unwrap();
+        let writer = Redis::distributor_named(Some("hotkeys"));
+        while !has_recipients(writer) {
+            thread::sleep(READY_POLL);
+        }
+
+        let queries = TypedDistributor::<_, Result<Option<Vec<u8>>, RedisError>>::new(writer);
+        for key in ["cold", "hot", "hot", "hot"] {
+            let _ = run!(queries.request(RedisQuery {
+                key: key.to_owned()
+            }));
+        }
+        let hot_keys = TypedDistributor::<_, Option<HotKeyReport>>::new(writer);
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let report = loop {
+            // The first window may end before the queries
+            match run!(hot_keys.request(RedisHotKeysQuery)).unwrap() {
+                Some(report) if !report.keys.is_empty() => break report,
+                _ if Instant::now() < deadline => thread::sleep(READY_POLL),
+                _ => panic!("no hot keys reported"),
+            }
+        };
+        assert_eq!("hot", report.keys[0].key);
+        assert_eq!(3, report.keys[0].accesses);
+        assert_eq!(1, report.keys.len());
+    }
+
+    #[test]
+    fn oversized_values_are_refused() {
+        use aggregates::redis::{
+            backend::RedisBackend,
+            error::{ErrorStats, RedisErrorStatsQuery},
+            limit::ValueLimit,
+            multi::RedisMultiInsert,
+        };
+
+        let _runtime = runtime().enter();
+        let backend = MemoryBackend::default();
+        let redis = Redis {
+            urls: vec!["redis://127.0.0.1:30010".to_owned()],
+            backend: Backend::memory(backend.clone()),
+            value_limit: Some(ValueLimit {
+                max_bytes: 4,
+                ..Default::default()
+            }),
+            // Inserts sent before the actor is marked connected are held
+            pending_writes: Some(Default::default()),
+            ..Default::default()
+        };
+        let _actor = start(redis, Some("limited")).unwrap();
+        let writer = Redis::typed::<_, Result<(), RedisError>>(Some("limited"));
+        while !has_recipients(writer.distributor()) {
+            thread::sleep(READY_POLL);
+        }
+
+        let insert = |value: &'static str| RedisInsert {
+            key: "limited".to_owned(),
+            value: Bytes::from(value),
+            ttl: None,
+            caller: None,
+        };
+        assert_eq!(Ok(()), run!(writer.request(insert("tiny"))).unwrap());
+        assert_eq!(
+            Err(RedisError::ValueTooLarge {
+                key: "limited".to_owned(),
+                size: 5,
+                max: 4
+            }),
+            run!(writer.request(insert("large"))).unwrap()
+        );
+        assert_eq!(
+            Some(b"tiny".to_vec()),
+            backend.clone().get("limited").unwrap()
+        );
+
+        // Nobody waits for told writes, refusals are reported instead
+        Redis::typed::<_, ()>(Some("limited"))
+            .tell_one(insert("large"))
+            .unwrap();
+        let many = RedisMultiInsert {
+            entries: vec![
+                ("limited:large".to_owned(), Bytes::from("large")),
+                ("limited:small".to_owned(), Bytes::from("ok")),
+            ],
+            caller: None,
+        };
+        Redis::typed::<_, ()>(Some("limited"))
+            .tell_one(many)
+            .unwrap();
+        let errors = Redis::typed::<_, ErrorStats>(Some("limited"));
+        // Reports reach the actor through its mailbox
+        let mut refused = None;
+        for _ in 0..100 {
+            let stats = run!(errors.request(RedisErrorStatsQuery)).unwrap();
+            refused = stats.counts.get("ValueLimit").copied();
+            if refused == Some(2) {
+                break;
+            }
+            thread::sleep(READY_POLL);
+        }
+        assert_eq!(Some(2), refused);
+        assert_eq!(
+            Some(b"ok".to_vec()),
+            backend.clone().get("limited:small").unwrap()
+        );
+        assert_eq!(None, backend.clone().get("limited:large").unwrap());
+    }
+
+    #[test]
+    fn chunked_values_are_reassembled_by_multi_key_queries() {
+        use aggregates::redis::{
+            backend::RedisBackend, chunked::ChunkingConfig, multi::RedisMultiInsert,
+        };
+
+        let _runtime = runtime().enter();
+        let backend = MemoryBackend::default();
+        let redis = Redis {
+            urls: vec!["redis://127.0.0.1:30011".to_owned()],
+            backend: Backend::memory(backend.clone()),
+            chunking: Some(ChunkingConfig {
+                threshold: 4,
+                chunk_size: 2,
+            }),
+            // Inserts sent before the actor is marked connected are held
+            pending_writes: Some(Default::default()),
+            ..Default::default()
+        };
+        let _actor = start(redis, Some("chunked")).unwrap();
+        let writer = Redis::typed::<_, ()>(Some("chunked"));
+        while !has_recipients(writer.distributor()) {
+            thread::sleep(READY_POLL);
+        }
+
+        let insert = RedisMultiInsert {
+            entries: vec![
+                ("chunked:large".to_owned(), Bytes::from("abcdefg")),
+                ("chunked:small".to_owned(), Bytes::from("ab")),
+            ],
+            caller: None,
+        };
+        writer.tell_one(insert).unwrap();
+        let query = RedisMultiQuery {
+            keys: vec!["chunked:large".to_owned(), "chunked:small".to_owned()],
+        };
+        let queried = Redis::typed::<_, Result<Vec<Option<Bytes>>, RedisError>>(Some("chunked"));
+        assert_eq!(
+            Ok(vec![Some(Bytes::from("abcdefg")), Some(Bytes::from("ab"))]),
+            run!(queried.request(query)).unwrap()
+        );
+        assert_eq!(
+            Some(b"ab".to_vec()),
+            backend.clone().get("chunked:large:chunk:0").unwrap()
+        );
+    }
+
+    #[test]
+    fn an_unreachable_primary_fails_over() {
+        use aggregates::redis::failover::FailoverConfig;
+
+        let _runtime = runtime().enter();
+        // Nothing listens on either cluster
+        let secondary = vec!["redis://127.0.0.1:30012".to_owned()];
+        let redis = Redis {
+            urls: vec!["redis://127.0.0.1:30011".to_owned()],
+            health_check_interval: Some(Duration::from_millis(20)),
+            failover: Some(FailoverConfig {
+                secondary: secondary.clone(),
+                after: Duration::from_millis(100),
+            }),
+            ..Default::default()
+        };
+        let _actor = start(redis, Some("failing")).unwrap();
+        let status = Redis::typed::<_, RedisStatus>(Some("failing"));
+        while !has_recipients(status.distributor()) {
+            thread::sleep(READY_POLL);
+        }
+
+        let deadline = Instant::now() + Duration::from_secs(15);
+        let status = loop {
+            let status = run!(status.request(RedisStatusQuery)).unwrap();
+            if status.last_failover_at.is_some() || Instant::now() >= deadline {
+                break status;
+            }
+            thread::sleep(READY_POLL);
+        };
+        assert_eq!(secondary, status.urls);
+        assert!(status.last_failover_at.is_some());
+    }
+
+    #[test]
+    fn control_messages_skip_the_queue() {
+        use std::{
+            future::Future,
+            task::{Context, Poll, Waker},
+        };
+
+        let _runtime = runtime().enter();
+        let backend = MemoryBackend::default();
+        let redis = Redis {
+            urls: vec!["redis://127.0.0.1:30006".to_owned()],
+            backend: Backend::memory(backend.clone()),
+            ..Default::default()
+        };
+        let _actor = start(redis, Some("control")).unwrap();
+        let writer = Redis::typed::<_, Result<(), RedisError>>(Some("control"));
+        while !has_recipients(writer.distributor()) {
+            thread::sleep(READY_POLL);
+        }
+
+        // Connected first, the event would otherwise follow the reconnect
+        let status = Redis::typed::<_, RedisStatus>(Some("control"));
+        while run!(status.request(RedisStatusQuery)).unwrap().state != RedisState::Initialized {
+            thread::sleep(READY_POLL);
+        }
+        let urls = vec!["redis://127.0.0.1:30007".to_owned()];
+        let reconnect = RedisControl::Reconnect { urls: urls.clone() };
+        assert_eq!(Ok(()), run!(control::send(Some("control"), reconnect)));
+        assert_eq!(urls, run!(status.request(RedisStatusQuery)).unwrap().urls);
+
+        let insert = |key: &str| RedisInsert {
+            key: key.to_owned(),
+            value: Bytes::from("1"),
+            ttl: None,
+            caller: None,
+        };
+        // The writer stalls on the first insert until the backend is released, the second one
+        // waits in the mailbox
+        let stall = backend.stall();
+        writer.tell_one(insert("stalled")).unwrap();
+        let queued = writer
+            .distributor()
+            .request::<Result<(), RedisError>>(insert("queued"));
+        let mut shutdown = Box::pin(control::send(Some("control"), RedisControl::Shutdown));
+        // Polled once to put the request in the lane
+        let mut context = Context::from_waker(Waker::noop());
+        assert_eq!(Poll::Pending, shutdown.as_mut().poll(&mut context));
+        drop(stall);
+
+        // Shutdown is answered and the insert queued before it never is
+        assert_eq!(Ok(()), run!(shutdown));
+        assert!(matches!(run!(queued), Ok(Err(_))));
+        assert!(backend.keys("queued").is_empty());
+        assert_eq!(
+            Err(RedisError::NotReady),
+            run!(control::send(Some("missing"), RedisControl::Shutdown))
+        );
+    }
+
+    #[test]
+    fn the_tree_reports_every_child() {
+        use actors::base::tree::{ChildStatus, GroupRole};
+
+        let _runtime = runtime().enter();
+        let redis = Redis {
+            urls: vec!["redis://127.0.0.1:30006".to_owned()],
+            backend: Backend::memory(MemoryBackend::default()),
+            readers: 2,
+            ..Default::default()
+        };
+        let actor = start(redis, Some("tree")).unwrap();
+        let all = |status: ChildStatus| {
+            // Children start and stop asynchronously
+            (0..100).any(|_| {
+                let tree = actor.tree();
+                let done = tree
+                    .groups
+                    .iter()
+                    .flat_map(|group| &group.children)
+                    .all(|child| child.status == status);
+                if !done {
+                    thread::sleep(READY_POLL);
+                }
+                done
+            })
+        };
+
+        assert!(all(ChildStatus::Running));
+        let tree = actor.tree();
+        assert_eq!(Some("tree"), tree.instance.as_deref());
+        assert!(tree.actor.ends_with("Redis"));
+        let roles: Vec<_> = tree.groups.iter().map(|group| group.role).collect();
+        assert_eq!(vec![GroupRole::Main, GroupRole::Read], roles);
+        assert_eq!(2, tree.groups[1].children.len());
+        assert!(tree.groups[0]
+            .children
+            .iter()
+            .all(|child| child.restarts == 0));
+        assert!(tree.is_healthy(Duration::from_secs(60)));
+        assert!(supervision_tree().iter().any(|other| other.id == tree.id));
+
+        actor.stop().unwrap();
+        assert!(all(ChildStatus::Stopped));
+        assert!(!actor.tree().is_healthy(Duration::from_secs(60)));
+    }
+
+    #[test]
+    fn liveness_counts_messages_per_tick() {
+        let _runtime = runtime().enter();
+        let redis = Redis {
+            urls: vec!["redis://127.0.0.1:30006".to_owned()],
+            backend: Backend::memory(MemoryBackend::default()),
+            heartbeat_tick: Some(Duration::from_millis(200)),
+            ..Default::default()
+        };
+        let actor = start(redis.clone(), Some("liveness")).unwrap();
+        let monitor = actor.liveness().unwrap();
+        let writer = Redis::typed::<_, Result<(), RedisError>>(Some("liveness"));
+        while !has_recipients(writer.distributor()) {
+            thread::sleep(READY_POLL);
+        }
+
+        for i in 0..5 {
+            let insert = RedisInsert {
+                key: format!("live:{i}"),
+                value: Bytes::from_static(b"v"),
+                ttl: None,
+                caller: None,
+            };
+            run!(writer.request(insert)).unwrap().unwrap();
+        }
+        // Counts move to the last tick once it ends
+        let report = (0..100)
+            .find_map(|_| {
+                let report = run!(monitor.request(LivenessQuery)).unwrap();
+                if report.children.iter().any(|c| c.processed_last_tick >= 5) {
+                    return Some(report);
+                }
+                thread::sleep(READY_POLL);
+                None
+            })
+            .unwrap();
+        assert_eq!(Some("liveness"), report.instance.as_deref());
+        assert_eq!(Duration::from_millis(200), report.tick);
+        assert_eq!(1, report.children.len());
+        assert!(report.stuck(Duration::from_secs(60)).is_empty());
+        actor.stop().unwrap();
+
+        let without = Redis {
+            heartbeat_tick: None,
+            ..redis
+        };
+        let actor = start(without, Some("no-liveness")).unwrap();
+        assert!(actor.liveness().is_none());
+        actor.stop().unwrap();
+    }
+
+    #[test]
+    fn writes_are_mirrored_and_older_keys_backfilled() {
+        use aggregates::redis::{
+            export::{ExportRecord, RecordKind},
+            import::{ImportProgress, RedisImport},
+            mirror::{MirrorConfig, RedisMirrorBackfill},
+        };
+
+        let _runtime = runtime().enter();
+        let start = |name: &str, mirror: Option<MirrorConfig>| {
+            let redis = Redis {
+                urls: vec!["redis://127.0.0.1:30006".to_owned()],
+                backend: Backend::memory(MemoryBackend::default()),
+                pending_writes: Some(Default::default()),
+                mirror,
+                ..Default::default()
+            };
+            let actor = Actor::builder()
+                .with_name(name)
+                .with_state_inner(redis)
+                .run()
+                .unwrap();
+            while !has_recipients(Redis::distributor_named(Some(name))) {
+                thread::sleep(READY_POLL);
+            }
+            actor
+        };
+        let insert = |key: &str| {
+            let insert = RedisInsert {
+                key: key.to_owned(),
+                value: Bytes::from(key.to_owned()),
+                ttl: None,
+                caller: None,
+            };
+            let distributor = Redis::distributor_named(Some("mirror_primary"));
+            let written: Result<(), RedisError> =
+                run!(async { distributor.request(insert).await.unwrap().unwrap() });
+            written.unwrap();
+        };
+        let mirrored = |key: &str, present: bool| {
+            let secondary = Redis::distributor_named(Some("mirror_secondary"));
+            // Replay is asynchronous
+            for _ in 0..100 {
+                let query = RedisQuery {
+                    key: key.to_owned(),
+                };
+                let value: Result<Option<Vec<u8>>, RedisError> =
+                    run!(async { secondary.request(query).await.unwrap().unwrap() });
+                if value.unwrap().is_some() == present {
+                    return true;
+                }
+                thread::sleep(READY_POLL);
+            }
+            false
+        };
+
+        let config = MirrorConfig {
+            instance: "mirror_secondary".to_owned(),
+            capacity: 16,
+        };
+        let _primary = start("mirror_primary", Some(config));
+        // Not replayed, the secondary is not running yet
+        insert("before");
+        let _secondary = start("mirror_secondary", None);
+        insert("after");
+        assert!(mirrored("after", true));
+
+        let backfill = RedisMirrorBackfill {
+            pattern: "*".to_owned(),
+            policy: Default::default(),
+        };
+        let primary = Redis::distributor_named(Some("mirror_primary"));
+        let progress: Result<ImportProgress, RedisError> =
+            run!(async { primary.request(backfill).await.unwrap().unwrap() });
+        assert!(progress.is_ok());
+        assert!(mirrored("before", true));
+
+        let import = RedisImport {
+            records: vec![ExportRecord {
+                key: "imported".to_owned(),
+                ttl_ms: None,
+                kind: RecordKind::String,
+                value: Bytes::from("imported"),
+            }],
+            policy: Default::default(),
+        };
+        let imported: Result<ImportProgress, RedisError> =
+            run!(async { primary.request(import).await.unwrap().unwrap() });
+        assert!(imported.is_ok());
+        assert!(mirrored("imported", true));
+
+        let delete = RedisDeleteByPattern {
+            pattern: "after".to_owned(),
+            ..Default::default()
+        };
+        let deleted: Result<u64, RedisError> =
+            run!(async { primary.request(delete).await.unwrap().unwrap() });
+        assert_eq!(Ok(1), deleted);
+        assert!(mirrored("after", false));
+
+        let unmirrored: Result<ImportProgress, RedisError> = run!(async {
+            Redis::distributor_named(Some("mirror_secondary"))
+                .request(RedisMirrorBackfill::default())
+                .await
+                .unwrap()
+                .unwrap()
+        });
+        assert_eq!(Err(RedisError::MirrorDisabled), unmirrored);
+    }
+
+    #[test]
+    fn the_router_sends_each_key_to_its_cluster() {
+        use aggregates::redis::{
+            router::RouteRule,
+            tenancy::{ForTenant, Tenant},
+        };
+
+        let _runtime = runtime().enter();
+        let cluster = || Redis {
+            urls: vec!["redis://127.0.0.1:30006".to_owned()],
+            backend: Backend::memory(MemoryBackend::default()),
+            pending_writes: Some(Default::default()),
+            ..Default::default()
+        };
+        let router = RedisRouter {
+            clusters: BTreeMap::from([
+                ("routed_eu".to_owned(), cluster()),
+                ("routed_us".to_owned(), cluster()),
+            ]),
+            rules: vec![
+                RouteRule::Prefix {
+                    prefix: "eu:".to_owned(),
+                    cluster: "routed_eu".to_owned(),
+                },
+                RouteRule::Prefix {
+                    prefix: "us:".to_owned(),
+                    cluster: "routed_us".to_owned(),
+                },
+            ],
+            tenants: BTreeMap::from([(
+                "acme".to_owned(),
+                Tenant {
+                    prefix: "acme:".to_owned(),
+                    cluster: Some("routed_us".to_owned()),
+                },
+            )]),
+        };
+        let _router = init_router(router).unwrap();
+        for distributor in [
+            RedisRouter::distributor(),
+            Redis::distributor_named(Some("routed_eu")),
+            Redis::distributor_named(Some("routed_us")),
+        ] {
+            while !has_recipients(distributor) {
+                thread::sleep(READY_POLL);
+            }
+        }
+
+        run!(async {
+            let ask = |key: &str, value: &str| {
+                RedisRouter::distributor().request::<Result<(), RedisError>>(RedisInsert {
+                    key: key.to_owned(),
+                    value: Bytes::from(value.to_owned()),
+                    ttl: None,
+                    caller: None,
+                })
+            };
+            assert_eq!(Ok(()), ask("eu:1", "a").await.unwrap().unwrap());
+            assert_eq!(Ok(()), ask("us:1", "b").await.unwrap().unwrap());
+            assert_eq!(
+                Err(RedisError::NoRoute("apac:1".to_owned())),
+                ask("apac:1", "c").await.unwrap().unwrap()
+            );
+
+            let query = |distributor: Distributor, key: &str| {
+                distributor.request::<Result<Option<Vec<u8>>, RedisError>>(RedisQuery {
+                    key: key.to_owned(),
+                })
+            };
+            assert_eq!(
+                Ok(Some(b"a".to_vec())),
+                query(RedisRouter::distributor(), "eu:1")
+                    .await
+                    .unwrap()
+                    .unwrap()
+            );
+            assert_eq!(
+                Ok(None),
+                query(Redis::distributor_named(Some("routed_eu")), "us:1")
+                    .await
+                    .unwrap()
+                    .unwrap()
+            );
+
+            let delete = RedisDeleteMany {
+                keys: vec!["eu:1".to_owned(), "us:1".to_owned(), "us:2".to_owned()],
+                caller: None,
+            };
+            let deleted: Result<u64, RedisError> = RedisRouter::distributor()
+                .request(delete)
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(Ok(2), deleted);
+
+            let scoped = ForTenant::new(
+                "acme",
+                RedisQuery {
+                    key: "1".to_owned(),
+                },
+            );
+            let written: Result<(), RedisError> = RedisRouter::distributor()
+                .request(ForTenant::new(
+                    "acme",
+                    RedisInsert {
+                        value: Bytes::from("x"),
+                        ..RedisInsert::new("1".to_owned())
+                    },
+                ))
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(Ok(()), written);
+            let value: Result<Option<Vec<u8>>, RedisError> = RedisRouter::distributor()
+                .request(scoped.clone())
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(Ok(Some(b"x".to_vec())), value);
+            assert_eq!(
+                Ok(Some(b"x".to_vec())),
+                query(Redis::distributor_named(Some("routed_us")), "acme:1")
+                    .await
+                    .unwrap()
+                    .unwrap()
+            );
+            assert_eq!(
+                Err(RedisError::TenantKey("acme:1".to_owned())),
+                query(RedisRouter::distributor(), "acme:1")
+                    .await
+                    .unwrap()
+                    .unwrap()
+            );
+            let value: Result<Option<Vec<u8>>, RedisError> = RedisRouter::distributor()
+                .request(ForTenant {
+                    tenant: "globex".to_owned(),
+                    ..scoped
+                })
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(Err(RedisError::UnknownTenant("globex".to_owned())), value);
+        });
+    }
+
+    #[test]
+    #[ignore = "needs a cluster at 127.0.0.1:30006"]
+    fn multi_key_commands_follow_batched_writes() {
+        use aggregates::redis::pipeline::PipelineConfig;
+
+        let _runtime = runtime().enter();
+        let redis = Redis {
+            urls: vec!["redis://127.0.0.1:30006".to_owned()],
+            // Long enough for the insert to still be batched when the delete arrives
+            pipeline: Some(PipelineConfig {
+                window: Duration::from_secs(1),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let _actor = start(redis, Some("pipelined")).unwrap();
+        let status = Redis::typed::<_, RedisStatus>(Some("pipelined"));
+        while run!(status.request(RedisStatusQuery)).unwrap().state != RedisState::Initialized {
+            thread::sleep(READY_POLL);
+        }
+
+        let insert = RedisInsert {
+            key: "pipelined:deleted".to_owned(),
+            value: Bytes::from("1"),
+            ttl: None,
+            caller: None,
+        };
+        Redis::typed::<_, ()>(Some("pipelined"))
+            .tell_one(insert)
+            .unwrap();
+        let delete = RedisDeleteMany {
+            keys: vec!["pipelined:deleted".to_owned()],
+            caller: None,
+        };
+        let deleted = Redis::typed::<_, Result<u64, RedisError>>(Some("pipelined"));
+        assert_eq!(Ok(1), run!(deleted.request(delete)).unwrap());
+        let query = RedisQuery {
+            key: "pipelined:deleted".to_owned(),
+        };
+        let queried = Redis::typed::<_, Result<Option<Vec<u8>>, RedisError>>(Some("pipelined"));
+        assert_eq!(Ok(None), run!(queried.request(query)).unwrap());
+    }
+
+    #[test]
+    #[ignore = "needs a cluster at 127.0.0.1:30006"]
+    fn chunked_values_span_multi_key_commands_and_streams() {
+        use aggregates::redis::{chunked::ChunkingConfig, multi::RedisMultiInsert};
+
+        let _runtime = runtime().enter();
+        let redis = Redis {
+            urls: vec!["redis://127.0.0.1:30006".to_owned()],
+            chunking: Some(ChunkingConfig {
+                threshold: 4,
+                chunk_size: 2,
+            }),
+            ..Default::default()
+        };
+        let _actor = start(redis, Some("chunked-cluster")).unwrap();
+        let status = Redis::typed::<_, RedisStatus>(Some("chunked-cluster"));
+        while run!(status.request(RedisStatusQuery)).unwrap().state != RedisState::Initialized {
+            thread::sleep(READY_POLL);
+        }
+
+        let writer = Redis::typed::<_, ()>(Some("chunked-cluster"));
+        let insert = |value: &'static str| RedisMultiInsert {
+            entries: vec![("chunked:multi".to_owned(), Bytes::from(value))],
+            caller: None,
+        };
+        writer.tell_one(insert("abcdefg")).unwrap();
+        let query = RedisMultiQuery {
+            keys: vec![
+                "chunked:multi".to_owned(),
+                "chunked:multi:chunk:3".to_owned(),
+            ],
+        };
+        let queried =
+            Redis::typed::<_, Result<Vec<Option<Bytes>>, RedisError>>(Some("chunked-cluster"));
+        assert_eq!(
+            Ok(vec![Some(Bytes::from("abcdefg")), Some(Bytes::from("g"))]),
+            run!(queried.request(query.clone())).unwrap()
+        );
+
+        let (sink, mut stream) = ChunkSink::channel();
+        let message = RedisStreamQuery {
+            key: "chunked:multi".to_owned(),
+            chunk_size: 3,
+            sink,
+        };
+        Redis::typed::<_, ()>(Some("chunked-cluster"))
+            .tell_one(message)
+            .unwrap();
+        let mut streamed = vec![];
+        while let Some(chunk) = runtime().block_on(stream.recv()) {
+            streamed.extend(chunk.unwrap());
+        }
+        assert_eq!(b"abcdefg".to_vec(), streamed);
+
+        // Overwriting with a small value removes the chunks
+        writer.tell_one(insert("ab")).unwrap();
+        assert_eq!(
+            Ok(vec![Some(Bytes::from("ab")), None]),
+            run!(queried.request(query)).unwrap()
+        );
+    }
+
+    #[test]
+    #[ignore = "needs a cluster at 127.0.0.1:30006"]
+    fn it_works() {
+        let _runtime = runtime().enter();
+        let redis = Redis {
+            urls: vec!["redis://127.0.0.1:30006".to_owned()],
+            ..Default::default()
+        };
+        init_redis_and_wait(redis, Duration::from_secs(5)).unwrap();
         let expected = "hi".to_owned();
         insert("hello".to_owned(), expected.as_bytes().to_vec());
 
         let query = query("hello".to_owned());
 
-        let res = String::from_utf8(query).unwrap();
+        let res = String::from_utf8(query.to_vec()).unwrap();
         assert_eq!(expected, res);
     }
 }
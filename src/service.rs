@@ -0,0 +1,110 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use bastion::prelude::SendError;
+use bytes::Bytes;
+use thiserror::Error;
+use tower::Service;
+
+use crate::{
+    accept,
+    aggregates::redis::{
+        error::RedisError,
+        multi::{RedisMultiInsert, RedisMultiQuery},
+        Redis, RedisInsert, RedisQuery,
+    },
+    request_read,
+};
+
+/// Command sent through `RedisService`
+#[derive(Debug, Clone, PartialEq)]
+pub enum RedisRequest {
+    Get { key: String },
+    Set { key: String, value: Bytes },
+    MultiGet { keys: Vec<String> },
+    MultiSet { entries: Vec<(String, Bytes)> },
+}
+
+/// Reply of `RedisService`, a `Set` is acknowledged once written and a `MultiSet` once handed
+/// to the actor
+#[derive(Debug, Clone, PartialEq)]
+pub enum RedisResponse {
+    /// `None` for a missing key
+    Value(Option<Bytes>),
+    Values(Vec<Option<Bytes>>),
+    Done,
+}
+
+/// Failure to reach the actor, or of the command itself
+#[derive(Debug, Error)]
+pub enum RedisServiceError {
+    #[error("cannot reach the redis actor: {0}")]
+    Unreachable(SendError),
+    #[error(transparent)]
+    Redis(#[from] RedisError),
+}
+
+/// The actor as a `tower::Service`, so tower middleware can wrap it
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RedisService;
+
+impl Service<RedisRequest> for RedisService {
+    type Response = RedisResponse;
+    type Error = RedisServiceError;
+    type Future = Pin<Box<dyn Future<Output = Result<RedisResponse, RedisServiceError>> + Send>>;
+
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        // The actor's mailbox is unbounded
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: RedisRequest) -> Self::Future {
+        Box::pin(async move {
+            let _call = accept()?;
+            match request {
+                RedisRequest::Get { key } => {
+                    let message = RedisQuery { key };
+                    #[cfg(feature = "otel")]
+                    let message = crate::aggregates::redis::otel::Traced::new(message);
+
+                    let value: Result<Option<Vec<u8>>, RedisError> = request_read(message)
+                        .await
+                        .map_err(RedisServiceError::Unreachable)?;
+                    Ok(RedisResponse::Value(value?.map(Bytes::from)))
+                }
+                RedisRequest::MultiGet { keys } => {
+                    let values: Result<Vec<Option<Bytes>>, RedisError> =
+                        request_read(RedisMultiQuery { keys })
+                            .await
+                            .map_err(RedisServiceError::Unreachable)?;
+                    Ok(RedisResponse::Values(values?))
+                }
+                RedisRequest::Set { key, value } => {
+                    let message = RedisInsert {
+                        key,
+                        value,
+                        ttl: None,
+                        caller: None,
+                    };
+                    let written: Result<(), RedisError> = Redis::distributor()
+                        .request(message)
+                        .await
+                        .unwrap_or_else(|e| Err(SendError::Other(anyhow::anyhow!("{e:?}"))))
+                        .map_err(RedisServiceError::Unreachable)?;
+                    written?;
+                    Ok(RedisResponse::Done)
+                }
+                RedisRequest::MultiSet { entries } => Redis::distributor()
+                    .tell_one(RedisMultiInsert {
+                        entries,
+                        caller: None,
+                    })
+                    .map(|_| RedisResponse::Done)
+                    .map_err(RedisServiceError::Unreachable),
+            }
+        })
+    }
+}
@@ -0,0 +1,296 @@
+//! Redis actor as a plain tokio task.
+//!
+//! The actor owns its connection in a blocking tokio task fed by an mpsc channel, next to a
+//! supervision loop restarting it when it panics. No Bastion runtime is started, for platforms
+//! that cannot run it. It serves the key and value commands of the crate's public API, with the
+//! same names and errors; pipelining, read groups, mirroring and the other extensions need the
+//! Bastion actor.
+
+use std::{sync::Arc, time::Duration};
+
+use bytes::Bytes;
+use tokio::{
+    sync::{mpsc, oneshot, Mutex},
+    task::{self, JoinHandle},
+    time,
+};
+use tracing::{error, warn};
+
+use crate::{
+    actors::machine::MachineContext,
+    aggregates::redis::{
+        backend::RedisBackend,
+        command::{validate_urls, RedisCommand},
+        connection,
+        error::{RedisError, RedisInitError},
+        view::RedisStatus,
+        Redis, Ttl,
+    },
+};
+
+/// Work for the actor, run against its state and connection
+type Job = Box<dyn FnOnce(&mut Served) + Send>;
+
+/// Handle to a Redis actor running as a tokio task, clones share the same actor
+#[derive(Debug, Clone)]
+pub struct TaskActor {
+    jobs: mpsc::UnboundedSender<Job>,
+}
+
+impl TaskActor {
+    /// Start the actor with `redis` and its supervision loop, must be called from within a
+    /// tokio runtime. It stops once every handle is dropped.
+    pub fn spawn(redis: Redis) -> Result<Self, RedisInitError> {
+        validate_urls(&redis.urls)?;
+        let (jobs, queue) = mpsc::unbounded_channel();
+        tokio::spawn(supervise(redis, Arc::new(Mutex::new(queue))));
+        Ok(Self { jobs })
+    }
+
+    /// Store a value, `RedisError::NotReady` when the actor cannot reach Redis
+    pub async fn try_insert(&self, key: String, value: impl Into<Bytes>) -> Result<(), RedisError> {
+        self.try_insert_with_ttl(key, value, None).await
+    }
+
+    /// Store a value expiring after `ttl` when set
+    pub async fn try_insert_with_ttl(
+        &self,
+        key: String,
+        value: impl Into<Bytes>,
+        ttl: impl Into<Option<Duration>>,
+    ) -> Result<(), RedisError> {
+        let (value, ttl) = (value.into(), ttl.into());
+        self.ask(move |conn| conn.set(&key, &value, ttl)).await
+    }
+
+    /// Read a value, `None` for a missing key
+    pub async fn try_query(&self, key: String) -> Result<Option<Bytes>, RedisError> {
+        let value = self.ask(move |conn| conn.get(&key)).await?;
+        Ok(value.map(Bytes::from))
+    }
+
+    /// Whether the key exists
+    pub async fn exists(&self, key: String) -> Result<bool, RedisError> {
+        self.ask(move |conn| conn.exists(&key)).await
+    }
+
+    /// Remaining lifetime of the key
+    pub async fn ttl(&self, key: String) -> Result<Ttl, RedisError> {
+        self.ask(move |conn| conn.ttl(&key)).await
+    }
+
+    /// Set the TTL of an existing key, false when the key does not exist
+    pub async fn expire(&self, key: String, ttl: Duration) -> Result<bool, RedisError> {
+        self.ask(move |conn| conn.expire(&key, ttl)).await
+    }
+
+    /// Delete keys, returns how many existed
+    pub async fn delete_many(&self, keys: Vec<String>) -> Result<u64, RedisError> {
+        self.ask(move |conn| {
+            keys.iter()
+                .try_fold(0, |deleted, key| Ok(deleted + u64::from(conn.del(key)?)))
+        })
+        .await
+    }
+
+    /// Connection status of the actor
+    pub async fn status(&self) -> Result<RedisStatus, RedisError> {
+        self.job(|served| Ok(served.status.clone())).await
+    }
+
+    // Run `command` on the connection, connecting first when there is none
+    async fn ask<T, F>(&self, command: F) -> Result<T, RedisError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut dyn RedisBackend) -> redis::RedisResult<T> + Send + 'static,
+    {
+        self.job(|served| served.run(command)).await
+    }
+
+    // Run `job` on the actor and wait for its result, `NotReady` when the actor is gone
+    async fn job<T, F>(&self, job: F) -> Result<T, RedisError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Served) -> Result<T, RedisError> + Send + 'static,
+    {
+        let (reply, result) = oneshot::channel();
+        self.jobs
+            .send(Box::new(move |served| {
+                // The caller may be gone already
+                let _ = reply.send(job(served));
+            }))
+            .map_err(|_| RedisError::NotReady)?;
+        result.await.unwrap_or(Err(RedisError::NotReady))
+    }
+}
+
+/// State and connection of the running actor
+struct Served {
+    redis: Redis,
+    machine: MachineContext<Redis>,
+    status: RedisStatus,
+    conn: Option<Box<dyn RedisBackend>>,
+}
+
+impl Served {
+    fn new(redis: Redis) -> Self {
+        let machine = MachineContext::new(redis.own_distributor());
+        Self {
+            redis,
+            machine,
+            status: RedisStatus::default(),
+            conn: None,
+        }
+    }
+
+    fn run<T>(
+        &mut self,
+        command: impl FnOnce(&mut dyn RedisBackend) -> redis::RedisResult<T>,
+    ) -> Result<T, RedisError> {
+        let conn = self.connect()?;
+        let result = command(conn);
+        if let Err(e) = &result {
+            if e.is_io_error() || e.is_connection_dropped() {
+                self.conn = None;
+                self.execute(RedisCommand::DisconnectRedisServer {
+                    error: e.to_string(),
+                });
+            }
+        }
+        result.map_err(|e| RedisError::Command(e.to_string()))
+    }
+
+    // Connection, opened when there is none
+    fn connect(&mut self) -> Result<&mut dyn RedisBackend, RedisError> {
+        if self.conn.is_none() {
+            let conn: Box<dyn RedisBackend> = match self.redis.backend.as_memory() {
+                Some(backend) => Box::new(backend.clone()),
+                None => match connection::connect(&self.redis.urls) {
+                    Ok(conn) => Box::new(conn),
+                    Err(e) => {
+                        warn!("[REDIS] Cannot connect: {e}");
+                        self.execute(RedisCommand::DisconnectRedisServer {
+                            error: e.to_string(),
+                        });
+                        return Err(RedisError::NotReady);
+                    }
+                },
+            };
+            self.conn = Some(conn);
+            self.execute(RedisCommand::ConnectRedisServer {
+                urls: self.redis.urls.clone(),
+            });
+        }
+        Ok(self.conn.as_deref_mut().unwrap())
+    }
+
+    fn execute(&mut self, command: RedisCommand) {
+        let status = &mut self.status;
+        if let Err(e) = self
+            .machine
+            .execute_now(&mut self.redis, command, |applied| status.update(applied))
+        {
+            error!("[REDIS] Rejected command: {e}");
+        }
+    }
+}
+
+// Run the actor until every handle is dropped, restarting it after the delays of
+// `redis.reconnect` when a job panics. Jobs still queued are served by the restarted actor.
+async fn supervise(redis: Redis, queue: Arc<Mutex<mpsc::UnboundedReceiver<Job>>>) {
+    let mut restarts = 0;
+    loop {
+        let actor: JoinHandle<()> = {
+            let (redis, queue) = (redis.clone(), queue.clone());
+            task::spawn_blocking(move || {
+                let mut served = Served::new(redis);
+                let mut queue = queue.blocking_lock();
+                while let Some(job) = queue.blocking_recv() {
+                    job(&mut served);
+                }
+            })
+        };
+        match actor.await {
+            Ok(()) => return,
+            Err(e) => {
+                let delay = redis.reconnect.delay(restarts);
+                restarts += 1;
+                error!(
+                    restarts,
+                    "[REDIS] Actor task failed, restarting in {delay:?}: {e}"
+                );
+                time::sleep(delay).await;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        actors::base::testkit::runtime,
+        aggregates::redis::{
+            backend::{Backend, MemoryBackend},
+            RedisState,
+        },
+    };
+
+    use super::*;
+
+    fn memory() -> Redis {
+        Redis {
+            urls: vec!["redis://127.0.0.1:30006".to_owned()],
+            backend: Backend::memory(MemoryBackend::default()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn commands_run_without_bastion() {
+        runtime().block_on(async {
+            let actor = TaskActor::spawn(memory()).unwrap();
+
+            actor.try_insert("task:1".to_owned(), "v").await.unwrap();
+            let ttl = Duration::from_secs(60);
+            actor
+                .try_insert_with_ttl("task:2".to_owned(), "w", ttl)
+                .await
+                .unwrap();
+            assert_eq!(
+                Some(Bytes::from("v")),
+                actor.try_query("task:1".to_owned()).await.unwrap()
+            );
+            assert!(matches!(
+                actor.ttl("task:2".to_owned()).await.unwrap(),
+                Ttl::Expires(left) if left <= ttl
+            ));
+            assert_eq!(
+                2,
+                actor
+                    .delete_many(vec!["task:1".to_owned(), "task:2".to_owned()])
+                    .await
+                    .unwrap()
+            );
+            assert!(!actor.exists("task:1".to_owned()).await.unwrap());
+            assert_eq!(RedisState::Initialized, actor.status().await.unwrap().state);
+        });
+    }
+
+    #[test]
+    fn a_panicking_job_restarts_the_actor() {
+        runtime().block_on(async {
+            let actor = TaskActor::spawn(memory()).unwrap();
+            actor.try_insert("task:3".to_owned(), "v").await.unwrap();
+
+            let failed = actor
+                .job(|_| -> Result<(), RedisError> { panic!("handler bug") })
+                .await;
+            assert_eq!(Err(RedisError::NotReady), failed);
+            // The backend outlives the restart, the state starts over
+            assert_eq!(
+                Some(Bytes::from("v")),
+                actor.try_query("task:3".to_owned()).await.unwrap()
+            );
+        });
+    }
+}